@@ -1,6 +1,13 @@
 use crate::filter::LabelFilter;
 use crate::message::HighlightID;
+use crate::physical::BitTiming;
+use crate::protocol::canopen::eds::NodeEds;
+use crate::protocol::isotp::IsoTpChannel;
+use crate::protocol::uds::DidName;
+use crate::protocol::xcp::XcpChannel;
+use crate::value_table::ValueTable;
 use std::fs;
+use std::io::Read;
 use std::path::PathBuf;
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
@@ -8,16 +15,28 @@ pub(crate) struct Config {
     pub file_path: Option<PathBuf>,
     pub highlight_ids: Vec<HighlightID>,
     pub label_filters: Vec<LabelFilter>,
-}
-
-impl Config {
-    pub fn new(file_path: Option<PathBuf>, highlight_ids: Vec<HighlightID>, filter_labels: Vec<LabelFilter>) -> Self {
-        Self {
-            file_path,
-            highlight_ids,
-            label_filters: filter_labels,
-        }
-    }
+    #[serde(default)]
+    pub value_tables: Vec<ValueTable>,
+    #[serde(default)]
+    pub isotp_channels: Vec<IsoTpChannel>,
+    #[serde(default)]
+    pub did_names: Vec<DidName>,
+    #[serde(default)]
+    pub j1939_columns: bool,
+    #[serde(default)]
+    pub j1939_definitions: Option<PathBuf>,
+    #[serde(default)]
+    pub canopen_enabled: bool,
+    #[serde(default)]
+    pub canopen_eds: Vec<NodeEds>,
+    #[serde(default)]
+    pub nmea2000_definitions: Option<PathBuf>,
+    #[serde(default)]
+    pub xcp_channels: Vec<XcpChannel>,
+    #[serde(default)]
+    pub sample_channel: String,
+    #[serde(default)]
+    pub bit_timing: BitTiming,
 }
 
 pub(crate) fn write_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
@@ -28,6 +47,70 @@ pub(crate) fn write_config(config: &Config) -> Result<(), Box<dyn std::error::Er
 
 pub(crate) fn read_config() -> Result<Config, Box<dyn std::error::Error>> {
     let file = fs::File::open("config.json")?;
-    let config = serde_json::from_reader(&file)?;
+    parse_config(file)
+}
+
+fn parse_config(reader: impl Read) -> Result<Config, Box<dyn std::error::Error>> {
+    let mut config: Config = serde_json::from_reader(reader)?;
+    // The label editor doesn't make filters that can't be evaluated, but the file can be edited
+    config.label_filters.retain(|lf| {
+        let valid = lf.filter.is_valid();
+        if !valid {
+            eprintln!(
+                "Error loading label {}: sequence rules can't be nested under Any",
+                lf.label.name
+            );
+        }
+        valid
+    });
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::multiplexed_label;
+    use crate::filter::{ChangedBytes, FilterType, MessageFilter, Occurrence};
+
+    fn rule(filter_type: FilterType) -> MessageFilter {
+        MessageFilter::new(None, None, filter_type)
+    }
+
+    #[test]
+    fn invalid_labels() {
+        let second = rule(FilterType::Occurrence(Occurrence { number: 2 }));
+        let changed = rule(FilterType::Changed(ChangedBytes { mask: Vec::new() }));
+        let filters = vec![
+            rule(FilterType::Any(vec![
+                rule(FilterType::Basic),
+                second.clone(),
+            ])),
+            rule(FilterType::Not(Box::new(rule(FilterType::All(vec![
+                rule(FilterType::Any(vec![changed])),
+            ]))))),
+            // Sequence rules are fine elsewhere, and Any is fine under them
+            rule(FilterType::All(vec![
+                second,
+                rule(FilterType::Any(vec![rule(FilterType::Basic)])),
+            ])),
+        ];
+        let labels: Vec<_> = filters
+            .into_iter()
+            .map(|filter| LabelFilter {
+                filter,
+                ..multiplexed_label()
+            })
+            .collect();
+        let json = serde_json::json!({
+            "file_path": null,
+            "highlight_ids": [],
+            "label_filters": labels,
+        });
+        let config = parse_config(json.to_string().as_bytes()).unwrap();
+        assert_eq!(config.label_filters.len(), 1);
+        assert!(matches!(
+            config.label_filters[0].filter.filter_type(),
+            FilterType::All(_)
+        ));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::message::{is_extended_id, Message};
+
+const CRC15_POLYNOMIAL: u32 = 0x4599;
+const CRC17_POLYNOMIAL: u32 = 0x1685B;
+const CRC21_POLYNOMIAL: u32 = 0x102899;
+// Bits of the same level after which the transmitter inserts a stuff bit
+const STUFF_WIDTH: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CrcStatus {
+    Match,
+    Mismatch { computed: u32 },
+    // No CRC captured, or a payload length no frame format has
+    Unknown,
+}
+
+fn push_bits(bits: &mut Vec<bool>, value: u32, width: usize) {
+    for bit in (0..width).rev() {
+        bits.push((value >> bit) & 1 != 0);
+    }
+}
+
+fn crc(bits: &[bool], polynomial: u32, width: usize, initial: u32) -> u32 {
+    let top = 1 << (width - 1);
+    let mask = (1 << width) - 1;
+    let mut crc = initial;
+    for bit in bits {
+        let feedback = (crc & top != 0) ^ bit;
+        crc = (crc << 1) & mask;
+        if feedback {
+            crc ^= polynomial;
+        }
+    }
+    crc
+}
+
+// CRC of the unstuffed bits of a classical frame from SOF to the end of the data field
+pub(crate) fn crc15(bits: &[bool]) -> u32 {
+    crc(bits, CRC15_POLYNOMIAL, 15, 0)
+}
+
+// Insert a complementary bit after every run of five equal bits, returning how many were added
+fn stuff(bits: &[bool]) -> (Vec<bool>, usize) {
+    let mut stuffed = Vec::with_capacity(bits.len() + bits.len() / STUFF_WIDTH);
+    let mut count = 0;
+    let mut run = 0;
+    let mut last = None;
+    for bit in bits {
+        if last == Some(*bit) {
+            run += 1;
+        } else {
+            run = 1;
+        }
+        stuffed.push(*bit);
+        last = Some(*bit);
+        if run == STUFF_WIDTH {
+            // The stuff bit starts the next run
+            stuffed.push(!bit);
+            last = Some(!bit);
+            run = 1;
+            count += 1;
+        }
+    }
+    (stuffed, count)
+}
+
+// DLC of a CAN FD frame with this many data bytes
+fn fd_dlc(length: usize) -> Option<u32> {
+    let dlc = match length {
+        0..=8 => length as u32,
+        12 => 9,
+        16 => 10,
+        20 => 11,
+        24 => 12,
+        32 => 13,
+        48 => 14,
+        64 => 15,
+        _ => return None,
+    };
+    Some(dlc)
+}
+
+// Start of frame and arbitration field, the same up to the bit after the ID in both formats
+fn arbitration_bits(id: u32, extended: bool) -> Vec<bool> {
+    let mut bits = vec![false];
+    match extended {
+        false => push_bits(&mut bits, id, 11),
+        true => {
+            push_bits(&mut bits, id >> 18, 11);
+            // SRR and IDE
+            bits.push(true);
+            bits.push(true);
+            push_bits(&mut bits, id & 0x3FFFF, 18);
+        }
+    }
+    bits
+}
+
+// The unstuffed bits of a classical data frame from SOF to the end of the data field
+fn classical_bits(id: u32, extended: bool, data: &[u8]) -> Vec<bool> {
+    let mut bits = arbitration_bits(id, extended);
+    // RTR, then IDE and r0 in base frames or r1 and r0 in extended frames
+    bits.push(false);
+    bits.push(false);
+    bits.push(false);
+    push_bits(&mut bits, data.len() as u32, 4);
+    for byte in data {
+        push_bits(&mut bits, *byte as u32, 8);
+    }
+    bits
+}
+
+// The unstuffed bits of a CAN FD frame from SOF to the end of the data field
+fn fd_bits(id: u32, extended: bool, data: &[u8], dlc: u32, brs: bool, esi: bool) -> Vec<bool> {
+    let mut bits = arbitration_bits(id, extended);
+    // RRS, then IDE in base frames, FDF and res
+    bits.push(false);
+    if !extended {
+        bits.push(false);
+    }
+    bits.push(true);
+    bits.push(false);
+    bits.push(brs);
+    bits.push(esi);
+    push_bits(&mut bits, dlc, 4);
+    for byte in data {
+        push_bits(&mut bits, *byte as u32, 8);
+    }
+    bits
+}
+
+// CAN FD CRCs cover the dynamic stuff bits and the stuff count field: the count modulo 8 in
+// Gray code followed by an even parity bit
+fn fd_crc(bits: &[bool], length: usize) -> u32 {
+    let (mut stuffed, count) = stuff(bits);
+    let gray = (count % 8) as u32;
+    let gray = gray ^ (gray >> 1);
+    push_bits(&mut stuffed, gray, 3);
+    stuffed.push(gray.count_ones() % 2 == 1);
+    match length > 16 {
+        false => crc(&stuffed, CRC17_POLYNOMIAL, 17, 1 << 16),
+        true => crc(&stuffed, CRC21_POLYNOMIAL, 21, 1 << 20),
+    }
+}
+
+// Whether a frame is CAN FD can't be told from the capture directly, so it is inferred from the
+// payload length and the width of the captured CRC.
+fn is_fd(message: &Message) -> bool {
+    message.data.len() > 8 || message.crc.len() > 2
+}
+
+// Compare the captured CRC with one computed from the frame contents. Frames are assumed to be
+// data frames. The BRS and ESI bits of CAN FD frames aren't captured, so every combination is
+// tried and the computed CRC shown on a mismatch is the one for BRS set and ESI clear.
+pub(crate) fn check(message: &Message) -> CrcStatus {
+    let id = match message.id_u32() {
+        Some(id) if !message.crc.is_empty() && message.crc.len() <= 4 => id,
+        _ => return CrcStatus::Unknown,
+    };
+    // The format follows the captured ID width, so extended frames with small IDs keep their IDE
+    let extended = is_extended_id(&message.id);
+    let captured = message
+        .crc
+        .iter()
+        .fold(0u32, |acc, b| (acc << 8) | *b as u32);
+
+    let candidates = match is_fd(message) {
+        false => vec![crc15(&classical_bits(id, extended, &message.data))],
+        true => {
+            let dlc = match fd_dlc(message.data.len()) {
+                Some(dlc) => dlc,
+                None => return CrcStatus::Unknown,
+            };
+            [(true, false), (false, false), (true, true), (false, true)]
+                .iter()
+                .map(|(brs, esi)| {
+                    let bits = fd_bits(id, extended, &message.data, dlc, *brs, *esi);
+                    fd_crc(&bits, message.data.len())
+                })
+                .collect()
+        }
+    };
+    match candidates.contains(&captured) {
+        true => CrcStatus::Match,
+        false => CrcStatus::Mismatch {
+            computed: candidates[0],
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frame CRCs below come from a separate bit string implementation using GF(2) long
+    // division, itself checked against the catalogued CRC-15/CAN, CRC-17/CAN-FD and
+    // CRC-21/CAN-FD check values
+    fn message(id: Vec<u8>, data: Vec<u8>, crc: u32, crc_bytes: usize) -> Message {
+        Message {
+            timestamp: 0.0,
+            id,
+            data,
+            crc: crc.to_be_bytes()[4 - crc_bytes..].to_vec(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    fn bytes(text: &str) -> Vec<bool> {
+        let mut bits = Vec::new();
+        for byte in text.bytes() {
+            push_bits(&mut bits, byte as u32, 8);
+        }
+        bits
+    }
+
+    #[test]
+    fn polynomials() {
+        assert_eq!(crc15(&bytes("123456789")), 0x059E);
+        assert_eq!(crc(&bytes("123456789"), CRC17_POLYNOMIAL, 17, 0), 0x04F03);
+        assert_eq!(crc(&bytes("123456789"), CRC21_POLYNOMIAL, 21, 0), 0x0ED841);
+    }
+
+    #[test]
+    fn stuffing() {
+        let bits = [
+            false, false, false, false, false, false, true, true, true, true, true,
+        ];
+        let (stuffed, count) = stuff(&bits);
+        assert_eq!(count, 2);
+        assert_eq!(stuffed.len(), 13);
+        assert!(stuffed[5]);
+        assert!(!stuffed[12]);
+        let zeros = fd_bits(0x000, false, &[0; 16], 10, true, false);
+        assert_eq!(stuff(&zeros).1, 27);
+        let counting: Vec<u8> = (0..12).collect();
+        let counting = fd_bits(0x7FF, false, &counting, 9, true, false);
+        assert_eq!(stuff(&counting).1, 13);
+    }
+
+    #[test]
+    fn classical_frames() {
+        let data = vec![0x11, 0x22, 0x33, 0x44];
+        let base = message(vec![0x01, 0x23], data.clone(), 0x11DA, 2);
+        assert_eq!(check(&base), CrcStatus::Match);
+        // The same ID in the extended format
+        let extended = message(vec![0x00, 0x00, 0x01, 0x23], data.clone(), 0x6848, 2);
+        assert_eq!(check(&extended), CrcStatus::Match);
+        let wrong = message(vec![0x00, 0x00, 0x01, 0x23], data, 0x11DA, 2);
+        assert_eq!(check(&wrong), CrcStatus::Mismatch { computed: 0x6848 });
+
+        let pgn = message(vec![0x18, 0xFE, 0xF1, 0x00], vec![0xFF; 8], 0x177A, 2);
+        assert_eq!(check(&pgn), CrcStatus::Match);
+    }
+
+    #[test]
+    fn fd_frames() {
+        let crc17 = message(vec![0x07, 0xFF], (0..12).collect(), 0x06B77, 3);
+        assert_eq!(check(&crc17), CrcStatus::Match);
+        let zeros = message(vec![0x00, 0x00], vec![0; 16], 0x17EC7, 3);
+        assert_eq!(check(&zeros), CrcStatus::Match);
+
+        let mut data = vec![0xAA; 32];
+        data.extend([0x00; 32]);
+        let crc21 = message(vec![0x01, 0xAB, 0xCD, 0xEF], data, 0x14048D, 3);
+        assert_eq!(check(&crc21), CrcStatus::Match);
+    }
+
+    #[test]
+    fn unknown_frames() {
+        let no_crc = message(vec![0x01, 0x23], vec![0x11], 0, 0);
+        assert_eq!(check(&no_crc), CrcStatus::Unknown);
+        let odd_length = message(vec![0x01, 0x23], vec![0; 10], 0x12345, 3);
+        assert_eq!(check(&odd_length), CrcStatus::Unknown);
+    }
+}
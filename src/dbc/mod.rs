@@ -0,0 +1,406 @@
+pub(crate) mod reader;
+pub(crate) mod writer;
+
+use std::collections::HashMap;
+
+use crate::filter::{FilterType, LabelFilter, MessageFilter};
+use crate::label::Label;
+use crate::message::{id_u32, is_extended_id, HighlightID, IdPattern, Message};
+use crate::signal::{Multiplexor, MuxRange, Signal};
+use crate::value_table::{find_table, ValueTable};
+
+// Bit 31 of a BO_ ID marks a 29-bit extended frame.
+const EXTENDED_ID_FLAG: u32 = 0x8000_0000;
+const DEFAULT_DLC: usize = 8;
+// BA_ attribute holding the highlight color of a message
+const COLOR_ATTRIBUTE: &str = "CanProtolyserColor";
+// Highlight color of imported messages that have none
+const IMPORT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+// A BO_ entry assembled from the workspace, before it is written out.
+pub(crate) struct DbcMessage {
+    pub id: u32,
+    pub extended: bool,
+    pub name: String,
+    pub dlc: usize,
+    pub color: Option<[f32; 3]>,
+    pub signals: Vec<DbcSignal>,
+    pub comments: Vec<String>,
+}
+
+impl DbcMessage {
+    fn unique_signal_name(&self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut suffix = 1;
+        while self.signals.iter().any(|s| s.name == unique) {
+            suffix += 1;
+            unique = format!("{}_{}", name, suffix);
+        }
+        unique
+    }
+
+    // Add the selector of a multiplexor as a switch signal, along with any selectors it is
+    // multiplexed by itself. Selectors are shared by name between signals of the message.
+    fn add_selector(&mut self, mux: &Multiplexor) -> DbcMux {
+        let multiplexed_by = mux
+            .selector
+            .multiplexor
+            .as_ref()
+            .map(|parent| self.add_selector(parent));
+        let name = identifier(&mux.name);
+        let switch = match self
+            .signals
+            .iter()
+            .find(|s| s.multiplexer_switch && s.name == name)
+        {
+            Some(existing) => existing.name.clone(),
+            None => {
+                let switch = self.unique_signal_name(&name);
+                self.signals.push(DbcSignal {
+                    name: switch.clone(),
+                    signal: mux.selector.as_ref().clone(),
+                    value_table: None,
+                    multiplexer_switch: true,
+                    multiplexed_by,
+                });
+                switch
+            }
+        };
+        DbcMux {
+            switch,
+            values: mux.values.clone(),
+        }
+    }
+
+    // Simple multiplexing allows a single switch with one value per multiplexed signal,
+    // anything else needs SG_MUL_VAL_ entries.
+    pub(crate) fn extended_multiplexing(&self) -> bool {
+        let switches = self.signals.iter().filter(|s| s.multiplexer_switch).count();
+        switches > 1
+            || self.signals.iter().any(|s| match &s.multiplexed_by {
+                Some(mux) => match mux.values.as_slice() {
+                    [range] => range.min != range.max,
+                    _ => true,
+                },
+                None => false,
+            })
+    }
+
+    // The multiplexor a switch signal of the message gives, with the switches selecting it.
+    // Nesting is limited by the number of signals, in case switches select each other.
+    fn multiplexor(&self, mux: &DbcMux, depth: usize) -> Option<Multiplexor> {
+        let switch = self.signals.iter().find(|s| s.name == mux.switch)?;
+        let mut selector = switch.signal.clone();
+        if let Some(parent) = &switch.multiplexed_by {
+            selector.multiplexor = Some(self.multiplexor(parent, depth.checked_sub(1)?)?);
+        }
+        Some(Multiplexor {
+            name: switch.name.clone(),
+            selector: Box::new(selector),
+            values: mux.values.clone(),
+        })
+    }
+
+    pub(crate) fn id_bytes(&self) -> Vec<u8> {
+        match self.extended {
+            true => self.id.to_be_bytes().to_vec(),
+            false => (self.id as u16).to_be_bytes().to_vec(),
+        }
+    }
+
+    pub(crate) fn dbc_id(&self) -> u32 {
+        match self.extended {
+            true => self.id | EXTENDED_ID_FLAG,
+            false => self.id,
+        }
+    }
+}
+
+pub(crate) struct DbcMux {
+    pub switch: String,
+    pub values: Vec<MuxRange>,
+}
+
+impl DbcMux {
+    pub(crate) fn first_value(&self) -> u64 {
+        self.values.first().map_or(0, |range| range.min)
+    }
+}
+
+pub(crate) struct DbcSignal {
+    pub name: String,
+    pub signal: Signal,
+    pub value_table: Option<ValueTable>,
+    pub multiplexer_switch: bool,
+    pub multiplexed_by: Option<DbcMux>,
+}
+
+pub(crate) struct DbcDatabase {
+    pub messages: Vec<DbcMessage>,
+    pub value_tables: Vec<ValueTable>,
+    pub comments: Vec<String>,
+}
+
+impl DbcDatabase {
+    // Collect highlight IDs and label rules into DBC messages, with label signals as SG_
+    // entries. Rules that have no DBC equivalent are kept as comments on the message they
+    // apply to, or on the network if they match any ID.
+    pub(crate) fn from_workspace(
+        highlight_ids: &[HighlightID],
+        label_filters: &Vec<LabelFilter>,
+        value_tables: &[ValueTable],
+        messages: Option<&Vec<Message>>,
+    ) -> Self {
+        let mut dlcs: HashMap<(u32, bool), usize> = HashMap::new();
+        if let Some(messages) = messages {
+            for msg in messages {
+                if let Some(id) = msg.id_u32() {
+                    let dlc = dlcs.entry((id, is_extended_id(&msg.id))).or_insert(0);
+                    *dlc = (*dlc).max(msg.data.len());
+                }
+            }
+        }
+        let dlc_for = |id: u32, extended: bool| *dlcs.get(&(id, extended)).unwrap_or(&DEFAULT_DLC);
+
+        let mut db = Self {
+            messages: Vec::new(),
+            value_tables: value_tables.to_vec(),
+            comments: Vec::new(),
+        };
+
+        for h_id in highlight_ids {
+            // Masks and ranges don't name a single message
+            let (id, extended) = match h_id.id().exact() {
+                Some(id) => match id_u32(id) {
+                    Some(value) => (value, is_extended_id(id)),
+                    None => continue,
+                },
+                None => continue,
+            };
+            if db.message_mut(id, extended).is_some() {
+                continue;
+            }
+            let name = db.unique_name(&identifier(h_id.name()), id);
+            db.messages.push(DbcMessage {
+                id,
+                extended,
+                name,
+                dlc: dlc_for(id, extended),
+                color: Some(*h_id.color()),
+                signals: Vec::new(),
+                comments: Vec::new(),
+            });
+        }
+
+        for label_filter in label_filters {
+            let rule = format!(
+                "Label \"{}\" (speed {}): {}",
+                label_filter.label.name,
+                label_filter.filter.speed_string(),
+                label_filter.filter.description()
+            );
+            let (id, extended) = match label_filter.filter.id() {
+                None => {
+                    db.comments.push(rule);
+                    continue;
+                }
+                Some(IdPattern::Exact(id)) => match id_u32(id) {
+                    Some(value) => (value, is_extended_id(id)),
+                    // DBC IDs are at most 29 bits
+                    None => {
+                        db.comments
+                            .push(format!("{} for ID {}", rule, hex::encode(id)));
+                        continue;
+                    }
+                },
+                Some(pattern) => {
+                    db.comments.push(format!("{} for IDs {}", rule, pattern));
+                    continue;
+                }
+            };
+            if db.message_mut(id, extended).is_none() {
+                let name = db.unique_name(&format!("MSG_{:X}", id), id);
+                db.messages.push(DbcMessage {
+                    id,
+                    extended,
+                    name,
+                    dlc: dlc_for(id, extended),
+                    color: None,
+                    signals: Vec::new(),
+                    comments: Vec::new(),
+                });
+            }
+            let msg = db.message_mut(id, extended).unwrap();
+            msg.comments.push(rule);
+            if let Some(signal) = &label_filter.signal {
+                let name = msg.unique_signal_name(&identifier(&label_filter.label.name));
+                let value_table = signal
+                    .value_table
+                    .as_ref()
+                    .and_then(|name| find_table(value_tables, name))
+                    .cloned();
+                let multiplexed_by = signal.multiplexor.as_ref().map(|mux| msg.add_selector(mux));
+                msg.signals.push(DbcSignal {
+                    name,
+                    signal: signal.clone(),
+                    value_table,
+                    multiplexer_switch: false,
+                    multiplexed_by,
+                });
+            }
+        }
+
+        db
+    }
+
+    // Highlight IDs, label rules and value tables for the messages of an imported DBC file. Each
+    // message is highlighted and each of its signals becomes a label on its ID, except switch
+    // signals which become the multiplexors of the signals they select.
+    pub(crate) fn into_workspace(self) -> (Vec<HighlightID>, Vec<LabelFilter>, Vec<ValueTable>) {
+        let mut value_tables = self.value_tables;
+        let mut highlight_ids = Vec::new();
+        let mut label_filters = Vec::new();
+        for msg in &self.messages {
+            let id = IdPattern::Exact(msg.id_bytes());
+            let color = msg.color.unwrap_or(IMPORT_COLOR);
+            highlight_ids.push(HighlightID::new(id.clone(), msg.name.clone(), color));
+            for sig in msg.signals.iter().filter(|s| !s.multiplexer_switch) {
+                let mut signal = sig.signal.clone();
+                if let Some(table) = &sig.value_table {
+                    if find_table(&value_tables, &table.name).is_none() {
+                        value_tables.push(table.clone());
+                    }
+                    signal.value_table = Some(table.name.clone());
+                }
+                signal.multiplexor = sig
+                    .multiplexed_by
+                    .as_ref()
+                    .and_then(|mux| msg.multiplexor(mux, msg.signals.len()));
+                label_filters.push(LabelFilter {
+                    label: Label {
+                        name: sig.name.clone(),
+                        color,
+                    },
+                    filter: MessageFilter::new(Some(id.clone()), None, FilterType::Basic),
+                    signal: Some(signal),
+                });
+            }
+        }
+        (highlight_ids, label_filters, value_tables)
+    }
+
+    fn message_mut(&mut self, id: u32, extended: bool) -> Option<&mut DbcMessage> {
+        self.messages
+            .iter_mut()
+            .find(|m| m.id == id && m.extended == extended)
+    }
+
+    fn unique_name(&self, name: &str, id: u32) -> String {
+        match self.messages.iter().any(|m| m.name == name) {
+            true => format!("{}_{:X}", name, id),
+            false => name.to_string(),
+        }
+    }
+}
+
+// DBC object names must be C identifiers.
+pub(crate) fn identifier(name: &str) -> String {
+    let mut s: String = name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    if s.is_empty() || s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.insert(0, '_');
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label_filter(name: &str, id: Option<IdPattern>) -> LabelFilter {
+        LabelFilter {
+            label: Label {
+                name: name.to_string(),
+                color: [1.0, 0.0, 0.0],
+            },
+            filter: MessageFilter::new(id, None, FilterType::Basic),
+            signal: Some(Signal::default()),
+        }
+    }
+
+    #[test]
+    fn extended_flag_follows_id_width() {
+        let highlight_ids = vec![
+            HighlightID::new(IdPattern::Exact(vec![0x01, 0x23]), "Base".into(), [0.0; 3]),
+            HighlightID::new(
+                IdPattern::Exact(vec![0x00, 0x00, 0x01, 0x23]),
+                "Extended".into(),
+                [0.0; 3],
+            ),
+            HighlightID::new(
+                IdPattern::Exact(vec![0x18, 0xFE, 0xF1, 0x00]),
+                "J1939".into(),
+                [0.0; 3],
+            ),
+        ];
+        let db = DbcDatabase::from_workspace(&highlight_ids, &Vec::new(), &[], None);
+        let ids: Vec<u32> = db.messages.iter().map(|m| m.dbc_id()).collect();
+        assert_eq!(ids, vec![0x123, 0x8000_0123, 0x98FE_F100]);
+    }
+
+    #[test]
+    fn dlc_is_kept_per_id_format() {
+        let message = |id: Vec<u8>, len: usize| Message {
+            timestamp: 0.0,
+            id,
+            data: vec![0; len],
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        };
+        let messages = vec![
+            message(vec![0x01, 0x23], 2),
+            message(vec![0x00, 0x00, 0x01, 0x23], 6),
+        ];
+        let labels = vec![
+            label_filter("Base", Some(IdPattern::Exact(vec![0x01, 0x23]))),
+            label_filter("Extended", Some(IdPattern::Exact(vec![0, 0, 0x01, 0x23]))),
+        ];
+        let db = DbcDatabase::from_workspace(&Vec::new(), &labels, &[], Some(&messages));
+        let dlcs: Vec<(bool, usize)> = db.messages.iter().map(|m| (m.extended, m.dlc)).collect();
+        assert_eq!(dlcs, vec![(false, 2), (true, 6)]);
+    }
+
+    #[test]
+    fn unrepresentable_rules_become_comments() {
+        let labels = vec![
+            label_filter("Any", None),
+            label_filter("Wide", Some(IdPattern::Exact(vec![1, 2, 3, 4, 5]))),
+            label_filter(
+                "Range",
+                Some(IdPattern::Range {
+                    first: 0x100,
+                    last: 0x1FF,
+                    width: 2,
+                }),
+            ),
+        ];
+        let db = DbcDatabase::from_workspace(&Vec::new(), &labels, &[], None);
+        assert!(db.messages.is_empty());
+        assert_eq!(db.comments.len(), 3);
+        assert!(db.comments[1].contains("Label \"Wide\""));
+        assert!(db.comments[1].ends_with("for ID 0102030405"));
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier("Engine speed"), "Engine_speed");
+        assert_eq!(identifier("1st"), "_1st");
+        assert_eq!(identifier(""), "_");
+    }
+}
@@ -0,0 +1,649 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::iter::Peekable;
+use std::path::Path;
+use std::vec::IntoIter;
+
+use crate::dbc::{DbcDatabase, DbcMessage, DbcMux, DbcSignal, COLOR_ATTRIBUTE, EXTENDED_ID_FLAG};
+use crate::signal::{ByteOrder, MuxRange, Signal, ValueType};
+use crate::value_table::{ValueTable, ValueTableEntry};
+
+// Statements ending with a semicolon that have nothing to import
+const IGNORED_STATEMENTS: [&str; 11] = [
+    "BA_DEF_",
+    "BA_DEF_DEF_",
+    "BA_DEF_REL_",
+    "BA_DEF_DEF_REL_",
+    "BA_REL_",
+    "BO_TX_BU_",
+    "EV_",
+    "ENVVAR_DATA_",
+    "SGTYPE_",
+    "SIG_GROUP_",
+    "SIG_TYPE_REF_",
+];
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    // Keywords, names and numbers
+    Word(String),
+    Text(String),
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.extend(chars.next()),
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string".into()),
+                    }
+                }
+                tokens.push(Token::Text(s));
+            }
+            c if is_word_char(c) => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.next_if(|c| is_word_char(*c)) {
+                    s.push(c);
+                }
+                tokens.push(Token::Word(s));
+            }
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '+' | '-')
+}
+
+fn is_word(token: &Token, word: &str) -> bool {
+    matches!(token, Token::Word(w) if w == word)
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn word(&mut self) -> Result<String, Box<dyn Error>> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) => Ok(word),
+            other => Err(format!("expected a name or number, found {:?}", other).into()),
+        }
+    }
+
+    fn text(&mut self) -> Result<String, Box<dyn Error>> {
+        match self.tokens.next() {
+            Some(Token::Text(text)) => Ok(text),
+            other => Err(format!("expected a string, found {:?}", other).into()),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, Box<dyn Error>> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| format!("invalid number {}", word).into())
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), Box<dyn Error>> {
+        match self.tokens.next() {
+            Some(Token::Punct(c)) if c == punct => Ok(()),
+            other => Err(format!("expected '{}', found {:?}", punct, other).into()),
+        }
+    }
+
+    fn next_is_word(&mut self, word: &str) -> bool {
+        self.tokens.peek().map_or(false, |t| is_word(t, word))
+    }
+
+    fn next_is_punct(&mut self, punct: char) -> bool {
+        self.tokens.peek() == Some(&Token::Punct(punct))
+    }
+
+    fn skip_statement(&mut self) {
+        for token in self.tokens.by_ref() {
+            if token == Token::Punct(';') {
+                break;
+            }
+        }
+    }
+
+    // Value descriptions up to the end of the statement
+    fn value_descriptions(&mut self) -> Result<Vec<ValueTableEntry>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        while !self.next_is_punct(';') {
+            let value = self.number::<f64>()? as i64;
+            entries.push(ValueTableEntry {
+                value,
+                name: self.text()?,
+            });
+        }
+        self.expect(';')?;
+        Ok(entries)
+    }
+}
+
+// A signal as written in its SG_ line, before multiplexing is resolved
+struct ParsedSignal {
+    signal: DbcSignal,
+    mux_value: Option<u64>,
+}
+
+struct ParsedMessage {
+    message: DbcMessage,
+    signals: Vec<ParsedSignal>,
+    // Signal name, switch name and ranges from SG_MUL_VAL_
+    extended_mux: Vec<(String, String, Vec<MuxRange>)>,
+}
+
+fn split_id(dbc_id: u32) -> (u32, bool) {
+    (dbc_id & !EXTENDED_ID_FLAG, dbc_id & EXTENDED_ID_FLAG != 0)
+}
+
+fn message(messages: &mut [ParsedMessage], dbc_id: u32) -> Option<&mut ParsedMessage> {
+    let (id, extended) = split_id(dbc_id);
+    messages
+        .iter_mut()
+        .find(|m| m.message.id == id && m.message.extended == extended)
+}
+
+fn parse_signal(parser: &mut Parser) -> Result<ParsedSignal, Box<dyn Error>> {
+    let name = parser.word()?;
+    let (multiplexer_switch, mux_value) = match parser.next_is_punct(':') {
+        true => (false, None),
+        false => {
+            // M, m<value> or m<value>M
+            let mux = parser.word()?;
+            let switch = mux.ends_with('M');
+            let value = mux.trim_end_matches('M');
+            match value.strip_prefix('m').map(str::parse) {
+                Some(Ok(value)) => (switch, Some(value)),
+                None if value.is_empty() => (switch, None),
+                _ => return Err(format!("invalid multiplexer {}", mux).into()),
+            }
+        }
+    };
+    parser.expect(':')?;
+    let start_bit = parser.number()?;
+    parser.expect('|')?;
+    let length = parser.number()?;
+    parser.expect('@')?;
+    let format = parser.word()?;
+    let byte_order = match format.chars().next() {
+        Some('0') => ByteOrder::Motorola,
+        Some('1') => ByteOrder::Intel,
+        _ => return Err(format!("invalid byte order in {}", format).into()),
+    };
+    let value_type = match format.chars().nth(1) {
+        Some('+') => ValueType::Unsigned,
+        Some('-') => ValueType::Signed,
+        _ => return Err(format!("invalid value type in {}", format).into()),
+    };
+    parser.expect('(')?;
+    let scale = parser.number()?;
+    parser.expect(',')?;
+    let offset = parser.number()?;
+    parser.expect(')')?;
+    parser.expect('[')?;
+    parser.number::<f64>()?;
+    parser.expect('|')?;
+    parser.number::<f64>()?;
+    parser.expect(']')?;
+    let unit = parser.text()?;
+    // Receiving nodes
+    parser.word()?;
+    while parser.next_is_punct(',') {
+        parser.expect(',')?;
+        parser.word()?;
+    }
+    Ok(ParsedSignal {
+        signal: DbcSignal {
+            name,
+            signal: Signal {
+                start_bit,
+                length,
+                byte_order,
+                value_type,
+                scale,
+                offset,
+                unit,
+                ..Default::default()
+            },
+            value_table: None,
+            multiplexer_switch,
+            multiplexed_by: None,
+        },
+        mux_value,
+    })
+}
+
+fn parse_color(s: &str) -> Option<[f32; 3]> {
+    let bytes = hex::decode(s.strip_prefix('#')?).ok()?;
+    match bytes.as_slice() {
+        [r, g, b] => Some([*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0]),
+        _ => None,
+    }
+}
+
+// Read the messages, signals, value tables and comments of a DBC file. Switch signals of
+// simple multiplexing select the signals marked with a value, SG_MUL_VAL_ entries override
+// that and give nested multiplexing.
+pub(crate) fn parse_dbc(text: &str) -> Result<DbcDatabase, Box<dyn Error>> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?.into_iter().peekable(),
+    };
+    let mut messages: Vec<ParsedMessage> = Vec::new();
+    let mut value_tables = Vec::new();
+    let mut comments = Vec::new();
+    let mut signal_tables: HashMap<(u32, String), Vec<ValueTableEntry>> = HashMap::new();
+
+    while let Some(token) = parser.tokens.next() {
+        let keyword = match token {
+            Token::Word(word) => word,
+            _ => continue,
+        };
+        match keyword.as_str() {
+            // The symbol list runs up to the bit timing section
+            "NS_" => while parser.tokens.next_if(|t| !is_word(t, "BS_")).is_some() {},
+            "BO_" => {
+                let (id, extended) = split_id(parser.number()?);
+                let name = parser.word()?;
+                parser.expect(':')?;
+                let dlc = parser.number()?;
+                parser.word()?;
+                messages.push(ParsedMessage {
+                    message: DbcMessage {
+                        id,
+                        extended,
+                        name,
+                        dlc,
+                        color: None,
+                        signals: Vec::new(),
+                        comments: Vec::new(),
+                    },
+                    signals: Vec::new(),
+                    extended_mux: Vec::new(),
+                });
+            }
+            "SG_" => {
+                let signal = parse_signal(&mut parser)?;
+                match messages.last_mut() {
+                    Some(msg) => msg.signals.push(signal),
+                    None => return Err("signal outside of a message".into()),
+                }
+            }
+            "CM_" => match parser.tokens.peek() {
+                Some(Token::Text(_)) => {
+                    comments.extend(parser.text()?.lines().map(str::to_string));
+                    parser.expect(';')?;
+                }
+                Some(token) if is_word(token, "BO_") => {
+                    parser.word()?;
+                    let dbc_id = parser.number()?;
+                    let comment = parser.text()?;
+                    if let Some(msg) = message(&mut messages, dbc_id) {
+                        msg.message
+                            .comments
+                            .extend(comment.lines().map(str::to_string));
+                    }
+                    parser.expect(';')?;
+                }
+                _ => parser.skip_statement(),
+            },
+            "VAL_TABLE_" => {
+                let name = parser.word()?;
+                value_tables.push(ValueTable {
+                    name,
+                    entries: parser.value_descriptions()?,
+                });
+            }
+            "VAL_" => {
+                let dbc_id = parser.word()?;
+                // Environment variables have no message ID
+                let dbc_id = match dbc_id.parse() {
+                    Ok(dbc_id) => dbc_id,
+                    Err(_) => {
+                        parser.skip_statement();
+                        continue;
+                    }
+                };
+                let signal = parser.word()?;
+                signal_tables.insert((dbc_id, signal), parser.value_descriptions()?);
+            }
+            "SIG_VALTYPE_" => {
+                let dbc_id = parser.number()?;
+                let name = parser.word()?;
+                parser.expect(':')?;
+                let float_type = parser.number::<u8>()?;
+                parser.expect(';')?;
+                let signal = message(&mut messages, dbc_id)
+                    .and_then(|msg| msg.signals.iter_mut().find(|s| s.signal.name == name));
+                if let (Some(signal), 1 | 2) = (signal, float_type) {
+                    signal.signal.signal.value_type = ValueType::Float;
+                }
+            }
+            "SG_MUL_VAL_" => {
+                let dbc_id = parser.number()?;
+                let name = parser.word()?;
+                let switch = parser.word()?;
+                let mut values = Vec::new();
+                loop {
+                    let range = parser.word()?;
+                    let (min, max) = range
+                        .split_once('-')
+                        .and_then(|(min, max)| Some((min.parse().ok()?, max.parse().ok()?)))
+                        .ok_or_else(|| format!("invalid multiplexer range {}", range))?;
+                    values.push(MuxRange { min, max });
+                    match parser.next_is_punct(',') {
+                        true => parser.expect(',')?,
+                        false => break,
+                    }
+                }
+                parser.expect(';')?;
+                if let Some(msg) = message(&mut messages, dbc_id) {
+                    msg.extended_mux.push((name, switch, values));
+                }
+            }
+            "BA_" => {
+                let attribute = parser.text()?;
+                if attribute != COLOR_ATTRIBUTE || !parser.next_is_word("BO_") {
+                    parser.skip_statement();
+                    continue;
+                }
+                parser.word()?;
+                let dbc_id = parser.number()?;
+                let color = parse_color(&parser.text()?);
+                parser.expect(';')?;
+                if let Some(msg) = message(&mut messages, dbc_id) {
+                    msg.message.color = color;
+                }
+            }
+            keyword if IGNORED_STATEMENTS.contains(&keyword) => parser.skip_statement(),
+            _ => {}
+        }
+    }
+
+    let mut db = DbcDatabase {
+        messages: Vec::new(),
+        value_tables,
+        comments,
+    };
+    for parsed in messages {
+        let mut msg = parsed.message;
+        let dbc_id = msg.dbc_id();
+        let simple_switch = parsed
+            .signals
+            .iter()
+            .find(|s| s.signal.multiplexer_switch)
+            .map(|s| s.signal.name.clone());
+        for parsed_signal in parsed.signals {
+            let mut signal = parsed_signal.signal;
+            let extended = parsed
+                .extended_mux
+                .iter()
+                .find(|(name, _, _)| *name == signal.name);
+            signal.multiplexed_by = match (extended, parsed_signal.mux_value, &simple_switch) {
+                (Some((_, switch, values)), _, _) => Some(DbcMux {
+                    switch: switch.clone(),
+                    values: values.clone(),
+                }),
+                (None, Some(value), Some(switch)) => Some(DbcMux {
+                    switch: switch.clone(),
+                    values: vec![MuxRange {
+                        min: value,
+                        max: value,
+                    }],
+                }),
+                _ => None,
+            };
+            if let Some(entries) = signal_tables.remove(&(dbc_id, signal.name.clone())) {
+                // Use a value table of the same entries if there is one
+                signal.value_table = Some(
+                    db.value_tables
+                        .iter()
+                        .find(|table| table.entries == entries)
+                        .cloned()
+                        .unwrap_or_else(|| ValueTable {
+                            name: format!("{}_{}", msg.name, signal.name),
+                            entries,
+                        }),
+                );
+            }
+            msg.signals.push(signal);
+        }
+        db.messages.push(msg);
+    }
+    Ok(db)
+}
+
+pub(crate) fn read_dbc(path: &Path) -> Result<DbcDatabase, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    parse_dbc(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use crate::dbc::writer::write_dbc;
+    use crate::filter::{FilterType, LabelFilter, MessageFilter};
+    use crate::label::Label;
+    use crate::message::{HighlightID, IdPattern};
+    use crate::signal::Multiplexor;
+
+    const DBC: &str = r##"VERSION ""
+
+NS_ :
+	CM_
+	BA_DEF_
+	VAL_
+
+BS_:
+
+BU_: ECU Tester
+
+VAL_TABLE_ Gear 0 "Park" 1 "Reverse" 2 "Neutral" 3 "Drive" ;
+
+BO_ 256 Engine: 8 ECU
+ SG_ Mode M : 0|8@1+ (1,0) [0|0] "" Tester
+ SG_ Rpm m0 : 8|16@1+ (0.25,0) [0|16383.75] "rpm" Tester,ECU
+ SG_ Temp m1 : 15|8@0- (1,-40) [-168|87] "degC" Tester
+ SG_ Gear : 32|8@1+ (1,0) [0|3] "" Tester
+
+BO_ 2566844672 Extended: 4 ECU
+ SG_ Ratio : 0|32@1- (1,0) [0|0] "" Tester
+
+BA_DEF_ BO_ "CanProtolyserColor" STRING ;
+BA_DEF_ BO_ "GenMsgCycleTime" INT 0 10000;
+BA_ "GenMsgCycleTime" BO_ 256 100;
+BA_ "CanProtolyserColor" BO_ 256 "#ff8000";
+CM_ "Bench capture";
+CM_ BO_ 256 "Engine state
+Sent every 100 ms";
+CM_ SG_ 256 Rpm "Crankshaft speed";
+VAL_ 256 Gear 0 "Park" 1 "Reverse" 2 "Neutral" 3 "Drive" ;
+VAL_ 256 Mode 0 "Running" 1 "Cooling" ;
+SIG_VALTYPE_ 2566844672 Ratio : 1;
+"##;
+
+    #[test]
+    fn parse() {
+        let db = parse_dbc(DBC).unwrap();
+        assert_eq!(db.comments, vec!["Bench capture"]);
+        assert_eq!(db.value_tables.len(), 1);
+        assert_eq!(db.value_tables[0].entries[3].name, "Drive");
+
+        let engine = &db.messages[0];
+        assert_eq!((engine.id, engine.extended, engine.dlc), (0x100, false, 8));
+        assert_eq!(engine.color, Some([1.0, 128.0 / 255.0, 0.0]));
+        assert_eq!(engine.comments, vec!["Engine state", "Sent every 100 ms"]);
+        let names: Vec<&str> = engine.signals.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Mode", "Rpm", "Temp", "Gear"]);
+        assert!(engine.signals[0].multiplexer_switch);
+        let temp = &engine.signals[2];
+        assert_eq!(temp.multiplexed_by.as_ref().unwrap().switch, "Mode");
+        assert_eq!(temp.multiplexed_by.as_ref().unwrap().first_value(), 1);
+        assert_eq!(temp.signal.byte_order, ByteOrder::Motorola);
+        assert_eq!(temp.signal.value_type, ValueType::Signed);
+        assert_eq!((temp.signal.start_bit, temp.signal.length), (15, 8));
+        assert_eq!((temp.signal.scale, temp.signal.offset), (1.0, -40.0));
+        // VAL_ entries of a known table refer to it
+        assert_eq!(engine.signals[3].value_table.as_ref().unwrap().name, "Gear");
+        assert_eq!(
+            engine.signals[0].value_table.as_ref().unwrap().name,
+            "Engine_Mode"
+        );
+
+        let extended = &db.messages[1];
+        assert_eq!((extended.id, extended.extended), (0x18FE_F100, true));
+        assert_eq!(extended.signals[0].signal.value_type, ValueType::Float);
+    }
+
+    #[test]
+    fn into_workspace() {
+        let (highlight_ids, label_filters, value_tables) = parse_dbc(DBC).unwrap().into_workspace();
+        assert_eq!(highlight_ids.len(), 2);
+        assert_eq!(highlight_ids[0].id(), &IdPattern::Exact(vec![0x01, 0x00]));
+        assert_eq!(
+            highlight_ids[1].id(),
+            &IdPattern::Exact(vec![0x18, 0xFE, 0xF1, 0x00])
+        );
+        // The switch selects the other signals rather than being a label itself
+        let names: Vec<&str> = label_filters
+            .iter()
+            .map(|lf| lf.label.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Rpm", "Temp", "Gear", "Ratio"]);
+        let rpm = label_filters[0].signal.as_ref().unwrap();
+        let mux = rpm.multiplexor.as_ref().unwrap();
+        assert_eq!(mux.name, "Mode");
+        assert_eq!(mux.selector.length, 8);
+        assert_eq!(mux.values, vec![MuxRange { min: 0, max: 0 }]);
+        assert!(label_filters[2]
+            .signal
+            .as_ref()
+            .unwrap()
+            .multiplexor
+            .is_none());
+        let names: Vec<&str> = value_tables.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["Gear"]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_dbc("CM_ \"unterminated;").is_err());
+        assert!(parse_dbc(" SG_ Orphan : 0|8@1+ (1,0) [0|0] \"\" X").is_err());
+        assert!(parse_dbc("BO_ 1 A: 8 X\n SG_ Bad : 0|8@2+ (1,0) [0|0] \"\" X").is_err());
+        assert!(parse_dbc("BO_ 1 A: 8 X\n SG_ Bad m : 0|8@1+ (1,0) [0|0] \"\" X").is_err());
+    }
+
+    fn signal(start_bit: u16, length: u16) -> Signal {
+        Signal {
+            start_bit,
+            length,
+            ..Default::default()
+        }
+    }
+
+    fn label(name: &str, id: Vec<u8>, signal: Signal) -> LabelFilter {
+        LabelFilter {
+            label: Label {
+                name: name.to_string(),
+                color: [1.0, 1.0, 1.0],
+            },
+            filter: MessageFilter::new(Some(IdPattern::Exact(id)), None, FilterType::Basic),
+            signal: Some(signal),
+        }
+    }
+
+    // Signals, nested multiplexing, value tables and IDs survive an export and import
+    #[test]
+    fn round_trip() {
+        let state = ValueTable {
+            name: "State".to_string(),
+            entries: ValueTable::parse_entries("0=Off; 1=On").unwrap(),
+        };
+        let page = Multiplexor {
+            name: "Page".to_string(),
+            selector: Box::new(signal(0, 4)),
+            values: vec![MuxRange { min: 2, max: 3 }],
+        };
+        let nested = Multiplexor {
+            name: "Sub".to_string(),
+            selector: Box::new(Signal {
+                multiplexor: Some(page.clone()),
+                ..signal(4, 4)
+            }),
+            values: vec![MuxRange { min: 1, max: 1 }, MuxRange { min: 5, max: 7 }],
+        };
+        let highlight_ids = vec![HighlightID::new(
+            IdPattern::Exact(vec![0x01, 0x23]),
+            "Engine".to_string(),
+            [1.0, 0.0, 0.0],
+        )];
+        let labels = vec![
+            label(
+                "Speed",
+                vec![0x01, 0x23],
+                Signal {
+                    byte_order: ByteOrder::Motorola,
+                    value_type: ValueType::Signed,
+                    scale: 0.5,
+                    offset: -10.0,
+                    unit: "km/h".to_string(),
+                    multiplexor: Some(page),
+                    ..signal(15, 12)
+                },
+            ),
+            label(
+                "Switch",
+                vec![0x01, 0x23],
+                Signal {
+                    value_table: Some("State".to_string()),
+                    multiplexor: Some(nested),
+                    ..signal(16, 1)
+                },
+            ),
+            label(
+                "Ratio",
+                vec![0x00, 0x00, 0x01, 0x23],
+                Signal {
+                    value_type: ValueType::Float,
+                    ..signal(0, 32)
+                },
+            ),
+        ];
+        let tables = vec![state];
+        let db = DbcDatabase::from_workspace(&highlight_ids, &labels, &tables, None);
+        let path = env::temp_dir().join(format!("can_decode_round_trip_{}.dbc", process::id()));
+        write_dbc(&path, &db).unwrap();
+        let imported = read_dbc(&path);
+        fs::remove_file(&path).unwrap();
+        let (imported_ids, imported_labels, imported_tables) = imported.unwrap().into_workspace();
+
+        assert_eq!(imported_tables, tables);
+        assert_eq!(imported_ids[0].id(), highlight_ids[0].id());
+        assert_eq!(imported_ids[0].name(), "Engine");
+        assert_eq!(imported_ids[0].color(), &[1.0, 0.0, 0.0]);
+        assert_eq!(imported_ids[1].id(), labels[2].filter.id().unwrap());
+        assert_eq!(imported_labels.len(), labels.len());
+        for (imported, label) in imported_labels.iter().zip(&labels) {
+            assert_eq!(imported.label.name, label.label.name);
+            assert_eq!(imported.filter.id(), label.filter.id());
+            assert_eq!(imported.signal, label.signal);
+        }
+    }
+}
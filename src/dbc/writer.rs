@@ -0,0 +1,207 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::dbc::{identifier, DbcDatabase, DbcMessage, DbcSignal, COLOR_ATTRIBUTE};
+use crate::signal::{ByteOrder, ValueType};
+use crate::value_table::ValueTable;
+
+const NODE_NAME: &str = "Vector__XXX";
+
+// DBC strings cannot contain double quotes.
+fn dbc_string(s: &str) -> String {
+    s.replace('"', "'")
+}
+
+fn color_hex(color: &[f32; 3]) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        (color[0] * 255.0) as u8,
+        (color[1] * 255.0) as u8,
+        (color[2] * 255.0) as u8
+    )
+}
+
+fn value_descriptions(table: &ValueTable) -> String {
+    table
+        .entries
+        .iter()
+        .map(|entry| format!("{} \"{}\"", entry.value, dbc_string(&entry.name)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_signal(out: &mut String, sig: &DbcSignal) -> std::fmt::Result {
+    let signal = &sig.signal;
+    let byte_order = match signal.byte_order {
+        ByteOrder::Motorola => 0,
+        ByteOrder::Intel => 1,
+    };
+    let sign = match signal.value_type {
+        ValueType::Unsigned => '+',
+        ValueType::Signed | ValueType::Float => '-',
+    };
+    let multiplex = match (sig.multiplexer_switch, &sig.multiplexed_by) {
+        (true, Some(mux)) => format!(" m{}M", mux.first_value()),
+        (true, None) => " M".to_string(),
+        (false, Some(mux)) => format!(" m{}", mux.first_value()),
+        (false, None) => String::new(),
+    };
+    writeln!(
+        out,
+        " SG_ {}{} : {}|{}@{}{} ({},{}) [0|0] \"{}\" {}",
+        sig.name,
+        multiplex,
+        signal.start_bit,
+        signal.length,
+        byte_order,
+        sign,
+        signal.scale,
+        signal.offset,
+        dbc_string(&signal.unit),
+        NODE_NAME
+    )
+}
+
+fn write_message(out: &mut String, msg: &DbcMessage) -> std::fmt::Result {
+    writeln!(
+        out,
+        "BO_ {} {}: {} {}",
+        msg.dbc_id(),
+        msg.name,
+        msg.dlc,
+        NODE_NAME
+    )?;
+    for sig in &msg.signals {
+        write_signal(out, sig)?;
+    }
+    writeln!(out)
+}
+
+fn dbc_to_string(db: &DbcDatabase) -> Result<String, std::fmt::Error> {
+    let mut out = String::new();
+
+    writeln!(out, "VERSION \"\"")?;
+    writeln!(out)?;
+    writeln!(out, "NS_ :")?;
+    for symbol in [
+        "CM_",
+        "BA_DEF_",
+        "BA_",
+        "BA_DEF_DEF_",
+        "VAL_",
+        "VAL_TABLE_",
+        "SIG_VALTYPE_",
+        "SG_MUL_VAL_",
+    ] {
+        writeln!(out, "\t{}", symbol)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "BS_:")?;
+    writeln!(out)?;
+    writeln!(out, "BU_:")?;
+    writeln!(out)?;
+
+    for table in &db.value_tables {
+        writeln!(
+            out,
+            "VAL_TABLE_ {} {} ;",
+            identifier(&table.name),
+            value_descriptions(table)
+        )?;
+    }
+    if !db.value_tables.is_empty() {
+        writeln!(out)?;
+    }
+
+    for msg in &db.messages {
+        write_message(&mut out, msg)?;
+    }
+
+    if !db.comments.is_empty() {
+        writeln!(out, "CM_ \"{}\";", dbc_string(&db.comments.join("\n")))?;
+    }
+    for msg in db.messages.iter().filter(|m| !m.comments.is_empty()) {
+        writeln!(
+            out,
+            "CM_ BO_ {} \"{}\";",
+            msg.dbc_id(),
+            dbc_string(&msg.comments.join("\n"))
+        )?;
+    }
+
+    writeln!(out, "BA_DEF_ BO_ \"{}\" STRING ;", COLOR_ATTRIBUTE)?;
+    writeln!(out, "BA_DEF_DEF_ \"{}\" \"\";", COLOR_ATTRIBUTE)?;
+    for msg in &db.messages {
+        if let Some(color) = &msg.color {
+            writeln!(
+                out,
+                "BA_ \"{}\" BO_ {} \"{}\";",
+                COLOR_ATTRIBUTE,
+                msg.dbc_id(),
+                color_hex(color)
+            )?;
+        }
+    }
+
+    for msg in &db.messages {
+        for sig in &msg.signals {
+            if let Some(table) = &sig.value_table {
+                writeln!(
+                    out,
+                    "VAL_ {} {} {} ;",
+                    msg.dbc_id(),
+                    sig.name,
+                    value_descriptions(table)
+                )?;
+            }
+        }
+    }
+
+    for msg in &db.messages {
+        for sig in &msg.signals {
+            if sig.signal.value_type == ValueType::Float {
+                let float_type = match sig.signal.length {
+                    32 => 1,
+                    _ => 2,
+                };
+                writeln!(
+                    out,
+                    "SIG_VALTYPE_ {} {} : {};",
+                    msg.dbc_id(),
+                    sig.name,
+                    float_type
+                )?;
+            }
+        }
+    }
+
+    for msg in db.messages.iter().filter(|m| m.extended_multiplexing()) {
+        for sig in &msg.signals {
+            if let Some(mux) = &sig.multiplexed_by {
+                let ranges = mux
+                    .values
+                    .iter()
+                    .map(|range| format!("{}-{}", range.min, range.max))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                writeln!(
+                    out,
+                    "SG_MUL_VAL_ {} {} {} {};",
+                    msg.dbc_id(),
+                    sig.name,
+                    mux.switch,
+                    ranges
+                )?;
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+pub(crate) fn write_dbc(path: &Path, db: &DbcDatabase) -> Result<(), Box<dyn Error>> {
+    fs::write(path, dbc_to_string(db)?)?;
+    Ok(())
+}
@@ -0,0 +1,892 @@
+use std::fmt;
+
+use crate::message::Message;
+use crate::signal::ByteOrder;
+
+// A small expression language over messages, such as
+// `id == 0x18FEF100 && len >= 4 && u16le(data, 2) > 3000 && !ack`.
+//
+// Fields are id, len, ack, time and speed, data[i] is a data byte, u16le(data, i) and the like
+// read integers and floats, and bit(data, i, b) is bit b of byte i. Operators and their
+// precedence are as in Rust. Reading past the end of the data makes the comparison it is in
+// false.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionError {
+    // Byte offset in the source
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+fn error<T>(position: usize, message: impl Into<String>) -> Result<T, ExpressionError> {
+    Err(ExpressionError {
+        position,
+        message: message.into(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Int(i128),
+    Float(f64),
+    Text(String),
+    Ident(String),
+    Symbol(&'static str),
+    End,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+// Longest first, so that e.g. <= isn't read as <
+const SYMBOLS: [&str; 23] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "<", ">", "!", "+", "-", "*", "/", "%", "&",
+    "|", "^", "(", ")", "[", "]",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let bytes = source.as_bytes();
+    let mut position = 0;
+    while position < bytes.len() {
+        let c = bytes[position];
+        let start = position;
+        if c.is_ascii_whitespace() {
+            position += 1;
+            continue;
+        }
+        let kind = if c.is_ascii_digit()
+            || (c == b'.' && bytes.get(position + 1).map_or(false, u8::is_ascii_digit))
+        {
+            while position < bytes.len()
+                && (bytes[position].is_ascii_alphanumeric() || bytes[position] == b'.')
+            {
+                // Exponents can be signed
+                let exponent =
+                    matches!(bytes[position], b'e' | b'E') && !source[start..].starts_with("0x");
+                position += 1;
+                if exponent && matches!(bytes.get(position), Some(b'+' | b'-')) {
+                    position += 1;
+                }
+            }
+            parse_number(&source[start..position], start)?
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while position < bytes.len()
+                && (bytes[position].is_ascii_alphanumeric() || bytes[position] == b'_')
+            {
+                position += 1;
+            }
+            TokenKind::Ident(source[start..position].to_string())
+        } else if c == b'"' {
+            let end = match source[start + 1..].find('"') {
+                Some(end) => start + 1 + end,
+                None => return error(start, "unterminated string"),
+            };
+            position = end + 1;
+            TokenKind::Text(source[start + 1..end].to_string())
+        } else if c == b',' {
+            position += 1;
+            TokenKind::Symbol(",")
+        } else {
+            match SYMBOLS
+                .iter()
+                .find(|symbol| source[start..].starts_with(*symbol))
+            {
+                Some(symbol) => {
+                    position += symbol.len();
+                    TokenKind::Symbol(symbol)
+                }
+                None => {
+                    let c = source[start..].chars().next().unwrap_or_default();
+                    return error(start, format!("unexpected character '{}'", c));
+                }
+            }
+        };
+        tokens.push(Token {
+            kind,
+            position: start,
+        });
+    }
+    tokens.push(Token {
+        kind: TokenKind::End,
+        position: source.len(),
+    });
+    Ok(tokens)
+}
+
+fn parse_number(text: &str, position: usize) -> Result<TokenKind, ExpressionError> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok().map(TokenKind::Int),
+        None => match text.parse::<i128>() {
+            Ok(value) => Some(TokenKind::Int(value)),
+            Err(_) => text.parse::<f64>().ok().map(TokenKind::Float),
+        },
+    };
+    match parsed {
+        Some(kind) => Ok(kind),
+        None => error(position, format!("invalid number {}", text)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Int,
+    Float,
+    Bool,
+    Text,
+}
+
+impl Type {
+    fn is_number(&self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Type::Int => "an integer",
+            Type::Float => "a float",
+            Type::Bool => "true or false",
+            Type::Text => "text",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+// Binary operators from the loosest binding to the tightest
+const PRECEDENCE: [&[(&str, Operator)]; 9] = [
+    &[("||", Operator::Or)],
+    &[("&&", Operator::And)],
+    &[
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessEqual),
+        (">=", Operator::GreaterEqual),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+    ],
+    &[("|", Operator::BitOr)],
+    &[("^", Operator::BitXor)],
+    &[("&", Operator::BitAnd)],
+    &[("<<", Operator::ShiftLeft), (">>", Operator::ShiftRight)],
+    &[("+", Operator::Add), ("-", Operator::Subtract)],
+    &[
+        ("*", Operator::Multiply),
+        ("/", Operator::Divide),
+        ("%", Operator::Remainder),
+    ],
+];
+const COMPARISON_LEVEL: usize = 2;
+// How deeply parentheses, calls and operations nest, which bounds the recursion of parsing and
+// evaluation
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReadType {
+    Unsigned,
+    Signed,
+    Float,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Read {
+    read_type: ReadType,
+    width: usize,
+    byte_order: ByteOrder,
+}
+
+impl Read {
+    // Names such as u8, i16le, u32be or f64le
+    fn from_name(name: &str) -> Option<Self> {
+        let (name, byte_order) = match name.strip_suffix("le") {
+            Some(name) => (name, Some(ByteOrder::Intel)),
+            None => match name.strip_suffix("be") {
+                Some(name) => (name, Some(ByteOrder::Motorola)),
+                None => (name, None),
+            },
+        };
+        let read_type = match name.chars().next()? {
+            'u' => ReadType::Unsigned,
+            'i' => ReadType::Signed,
+            'f' => ReadType::Float,
+            _ => return None,
+        };
+        let width = match (read_type, &name[1..]) {
+            (ReadType::Float, "32") => 4,
+            (ReadType::Float, "64") => 8,
+            (ReadType::Float, _) => return None,
+            (_, "8") => 1,
+            (_, "16") => 2,
+            (_, "32") => 4,
+            (_, "64") => 8,
+            _ => return None,
+        };
+        // Single bytes have no byte order, everything else needs one
+        let byte_order = match (width, byte_order) {
+            (1, None) => ByteOrder::Intel,
+            (1, Some(_)) | (_, None) => return None,
+            (_, Some(byte_order)) => byte_order,
+        };
+        Some(Self {
+            read_type,
+            width,
+            byte_order,
+        })
+    }
+
+    fn value_type(&self) -> Type {
+        match self.read_type {
+            ReadType::Float => Type::Float,
+            _ => Type::Int,
+        }
+    }
+
+    fn read(&self, data: &[u8], offset: i128) -> Option<Value> {
+        let offset = usize::try_from(offset).ok()?;
+        let bytes = data.get(offset..offset.checked_add(self.width)?)?;
+        let raw = match self.byte_order {
+            ByteOrder::Intel => bytes
+                .iter()
+                .rev()
+                .fold(0u64, |acc, b| (acc << 8) | *b as u64),
+            ByteOrder::Motorola => bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64),
+        };
+        let unused = 64 - 8 * self.width as u32;
+        let value = match (self.read_type, self.width) {
+            (ReadType::Unsigned, _) => Value::Int(raw as i128),
+            (ReadType::Signed, _) => Value::Int(((raw << unused) as i64 >> unused) as i128),
+            (ReadType::Float, 4) => Value::Float(f32::from_bits(raw as u32) as f64),
+            (ReadType::Float, _) => Value::Float(f64::from_bits(raw)),
+        };
+        Some(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+    Id,
+    Length,
+    Ack,
+    Time,
+    Speed,
+    Byte(Box<Node>),
+    Read(Read, Box<Node>),
+    Bit(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    Text(String),
+}
+
+impl Value {
+    fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(value) => Some(*value as f64),
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+// A parsed node with its type and where it starts in the source
+struct Typed {
+    node: Node,
+    value_type: Type,
+    position: usize,
+    // Levels of nodes down to the deepest leaf
+    depth: usize,
+}
+
+fn typed(
+    node: Node,
+    value_type: Type,
+    position: usize,
+    depth: usize,
+) -> Result<Typed, ExpressionError> {
+    match depth > MAX_DEPTH {
+        true => error(position, "expression nested too deeply"),
+        false => Ok(Typed {
+            node,
+            value_type,
+            position,
+            depth,
+        }),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+    // Unary expressions being parsed, each of which a nested expression goes through
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].clone();
+        if token.kind != TokenKind::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        match self.peek().kind {
+            TokenKind::Symbol(s) if s == symbol => {
+                self.index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ExpressionError> {
+        match self.eat(symbol) {
+            true => Ok(()),
+            false => error(self.peek().position, format!("expected '{}'", symbol)),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Typed, ExpressionError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let (operator, position) = match &self.peek().kind {
+                TokenKind::Symbol(symbol) => {
+                    match PRECEDENCE[level].iter().find(|(s, _)| s == symbol) {
+                        Some((_, operator)) => (*operator, self.peek().position),
+                        None => return Ok(left),
+                    }
+                }
+                _ => return Ok(left),
+            };
+            self.index += 1;
+            let right = self.binary(level + 1)?;
+            let value_type = operator_type(operator, &left, &right, position)?;
+            let depth = left.depth.max(right.depth) + 1;
+            let node = Node::Binary(operator, Box::new(left.node), Box::new(right.node));
+            left = typed(node, value_type, left.position, depth)?;
+            // Comparisons don't chain
+            if level == COMPARISON_LEVEL {
+                return match &self.peek().kind {
+                    TokenKind::Symbol(symbol)
+                        if PRECEDENCE[level].iter().any(|(s, _)| s == symbol) =>
+                    {
+                        error(self.peek().position, "comparisons can't be chained")
+                    }
+                    _ => Ok(left),
+                };
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Typed, ExpressionError> {
+        if self.depth == MAX_DEPTH {
+            return error(self.peek().position, "expression nested too deeply");
+        }
+        self.depth += 1;
+        let typed = self.prefixed();
+        self.depth -= 1;
+        typed
+    }
+
+    fn prefixed(&mut self) -> Result<Typed, ExpressionError> {
+        let position = self.peek().position;
+        if self.eat("!") {
+            let operand = self.unary()?;
+            expect_type(&operand, Type::Bool)?;
+            let node = Node::Not(Box::new(operand.node));
+            return typed(node, Type::Bool, position, operand.depth + 1);
+        }
+        if self.eat("-") {
+            let operand = self.unary()?;
+            if !operand.value_type.is_number() {
+                return error(operand.position, "expected a number");
+            }
+            let node = Node::Negate(Box::new(operand.node));
+            return typed(node, operand.value_type, position, operand.depth + 1);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Typed, ExpressionError> {
+        let token = self.next();
+        let leaf = |node, value_type| typed(node, value_type, token.position, 1);
+        match &token.kind {
+            TokenKind::Int(value) => leaf(Node::Int(*value), Type::Int),
+            TokenKind::Float(value) => leaf(Node::Float(*value), Type::Float),
+            TokenKind::Text(text) => leaf(Node::Text(text.clone()), Type::Text),
+            TokenKind::Symbol("(") => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            TokenKind::Ident(name) => match name.as_str() {
+                "true" => leaf(Node::Bool(true), Type::Bool),
+                "false" => leaf(Node::Bool(false), Type::Bool),
+                "id" => leaf(Node::Id, Type::Int),
+                "len" => leaf(Node::Length, Type::Int),
+                "ack" => leaf(Node::Ack, Type::Bool),
+                "time" => leaf(Node::Time, Type::Float),
+                "speed" => leaf(Node::Speed, Type::Text),
+                "data" => {
+                    self.expect("[")?;
+                    let index = self.integer()?;
+                    self.expect("]")?;
+                    let depth = index.depth + 1;
+                    let node = Node::Byte(Box::new(index.node));
+                    typed(node, Type::Int, token.position, depth)
+                }
+                "bit" => {
+                    self.data_argument()?;
+                    let byte = self.integer()?;
+                    self.expect(",")?;
+                    let bit = self.integer()?;
+                    self.expect(")")?;
+                    let depth = byte.depth.max(bit.depth) + 1;
+                    let node = Node::Bit(Box::new(byte.node), Box::new(bit.node));
+                    typed(node, Type::Int, token.position, depth)
+                }
+                name => match Read::from_name(name) {
+                    Some(read) => {
+                        self.data_argument()?;
+                        let offset = self.integer()?;
+                        self.expect(")")?;
+                        let value_type = read.value_type();
+                        let node = Node::Read(read, Box::new(offset.node));
+                        typed(node, value_type, token.position, offset.depth + 1)
+                    }
+                    None => error(token.position, format!("unknown name {}", name)),
+                },
+            },
+            TokenKind::End => error(token.position, "expected a value"),
+            _ => error(token.position, "unexpected symbol"),
+        }
+    }
+
+    // The opening of a call such as u16le(data, 2) up to the offset
+    fn data_argument(&mut self) -> Result<(), ExpressionError> {
+        self.expect("(")?;
+        match &self.peek().kind {
+            TokenKind::Ident(name) if name == "data" => self.index += 1,
+            _ => return error(self.peek().position, "expected data"),
+        }
+        self.expect(",")
+    }
+
+    fn integer(&mut self) -> Result<Typed, ExpressionError> {
+        let typed = self.binary(0)?;
+        expect_type(&typed, Type::Int)?;
+        Ok(typed)
+    }
+}
+
+fn expect_type(typed: &Typed, value_type: Type) -> Result<(), ExpressionError> {
+    match typed.value_type == value_type {
+        true => Ok(()),
+        false => error(
+            typed.position,
+            format!(
+                "expected {}, not {}",
+                value_type.name(),
+                typed.value_type.name()
+            ),
+        ),
+    }
+}
+
+fn operator_type(
+    operator: Operator,
+    left: &Typed,
+    right: &Typed,
+    position: usize,
+) -> Result<Type, ExpressionError> {
+    use Operator::*;
+    match operator {
+        Or | And => {
+            expect_type(left, Type::Bool)?;
+            expect_type(right, Type::Bool)?;
+            Ok(Type::Bool)
+        }
+        Equal | NotEqual => {
+            let comparable = (left.value_type.is_number() && right.value_type.is_number())
+                || left.value_type == right.value_type;
+            match comparable {
+                true => Ok(Type::Bool),
+                false => error(
+                    position,
+                    format!(
+                        "can't compare {} with {}",
+                        left.value_type.name(),
+                        right.value_type.name()
+                    ),
+                ),
+            }
+        }
+        Less | LessEqual | Greater | GreaterEqual => {
+            for operand in [left, right] {
+                if !operand.value_type.is_number() {
+                    return error(operand.position, "expected a number");
+                }
+            }
+            Ok(Type::Bool)
+        }
+        BitOr | BitXor | BitAnd | ShiftLeft | ShiftRight => {
+            expect_type(left, Type::Int)?;
+            expect_type(right, Type::Int)?;
+            Ok(Type::Int)
+        }
+        Add | Subtract | Multiply | Divide | Remainder => {
+            for operand in [left, right] {
+                if !operand.value_type.is_number() {
+                    return error(operand.position, "expected a number");
+                }
+            }
+            match (left.value_type, right.value_type) {
+                (Type::Int, Type::Int) => Ok(Type::Int),
+                _ => Ok(Type::Float),
+            }
+        }
+    }
+}
+
+impl Node {
+    fn int(&self, message: &Message) -> Option<i128> {
+        match self.eval(message)? {
+            Value::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Missing data gives None, which makes a comparison false
+    fn eval(&self, message: &Message) -> Option<Value> {
+        let value = match self {
+            Node::Int(value) => Value::Int(*value),
+            Node::Float(value) => Value::Float(*value),
+            Node::Bool(value) => Value::Bool(*value),
+            Node::Text(text) => Value::Text(text.clone()),
+            Node::Id => Value::Int(message.id_u32()? as i128),
+            Node::Length => Value::Int(message.data.len() as i128),
+            Node::Ack => Value::Bool(message.ack),
+            Node::Time => Value::Float(message.timestamp),
+            Node::Speed => Value::Text(message.speed.clone()),
+            Node::Byte(index) => {
+                let index = usize::try_from(index.int(message)?).ok()?;
+                Value::Int(*message.data.get(index)? as i128)
+            }
+            Node::Read(read, offset) => read.read(&message.data, offset.int(message)?)?,
+            Node::Bit(byte, bit) => {
+                let byte = usize::try_from(byte.int(message)?).ok()?;
+                let bit = bit.int(message)?;
+                if !(0..8).contains(&bit) {
+                    return None;
+                }
+                Value::Int(((message.data.get(byte)? >> bit) & 1) as i128)
+            }
+            Node::Not(operand) => match operand.eval(message)? {
+                Value::Bool(value) => Value::Bool(!value),
+                _ => return None,
+            },
+            Node::Negate(operand) => match operand.eval(message)? {
+                Value::Int(value) => Value::Int(value.checked_neg()?),
+                Value::Float(value) => Value::Float(-value),
+                _ => return None,
+            },
+            Node::Binary(Operator::And, left, right) => {
+                Value::Bool(left.is_true(message) && right.is_true(message))
+            }
+            Node::Binary(Operator::Or, left, right) => {
+                Value::Bool(left.is_true(message) || right.is_true(message))
+            }
+            Node::Binary(operator, left, right) => {
+                binary(*operator, left.eval(message)?, right.eval(message)?)?
+            }
+        };
+        Some(value)
+    }
+
+    fn is_true(&self, message: &Message) -> bool {
+        self.eval(message) == Some(Value::Bool(true))
+    }
+}
+
+fn binary(operator: Operator, left: Value, right: Value) -> Option<Value> {
+    use Operator::*;
+    let value = match (operator, left, right) {
+        (Equal, Value::Int(l), Value::Int(r)) => Value::Bool(l == r),
+        (NotEqual, Value::Int(l), Value::Int(r)) => Value::Bool(l != r),
+        (Less, Value::Int(l), Value::Int(r)) => Value::Bool(l < r),
+        (LessEqual, Value::Int(l), Value::Int(r)) => Value::Bool(l <= r),
+        (Greater, Value::Int(l), Value::Int(r)) => Value::Bool(l > r),
+        (GreaterEqual, Value::Int(l), Value::Int(r)) => Value::Bool(l >= r),
+        (BitOr, Value::Int(l), Value::Int(r)) => Value::Int(l | r),
+        (BitXor, Value::Int(l), Value::Int(r)) => Value::Int(l ^ r),
+        (BitAnd, Value::Int(l), Value::Int(r)) => Value::Int(l & r),
+        (ShiftLeft, Value::Int(l), Value::Int(r)) => Value::Int(l.checked_shl(r.try_into().ok()?)?),
+        (ShiftRight, Value::Int(l), Value::Int(r)) => {
+            Value::Int(l.checked_shr(r.try_into().ok()?)?)
+        }
+        (Add, Value::Int(l), Value::Int(r)) => Value::Int(l.checked_add(r)?),
+        (Subtract, Value::Int(l), Value::Int(r)) => Value::Int(l.checked_sub(r)?),
+        (Multiply, Value::Int(l), Value::Int(r)) => Value::Int(l.checked_mul(r)?),
+        (Divide, Value::Int(l), Value::Int(r)) => Value::Int(l.checked_div(r)?),
+        (Remainder, Value::Int(l), Value::Int(r)) => Value::Int(l.checked_rem(r)?),
+        (Equal, l, r) if l.as_float().is_none() => Value::Bool(l == r),
+        (NotEqual, l, r) if l.as_float().is_none() => Value::Bool(l != r),
+        // Mixed integer and float operands
+        (operator, l, r) => {
+            let (l, r) = (l.as_float()?, r.as_float()?);
+            match operator {
+                Equal => Value::Bool(l == r),
+                NotEqual => Value::Bool(l != r),
+                Less => Value::Bool(l < r),
+                LessEqual => Value::Bool(l <= r),
+                Greater => Value::Bool(l > r),
+                GreaterEqual => Value::Bool(l >= r),
+                Add => Value::Float(l + r),
+                Subtract => Value::Float(l - r),
+                Multiply => Value::Float(l * r),
+                Divide => Value::Float(l / r),
+                Remainder => Value::Float(l % r),
+                _ => return None,
+            }
+        }
+    };
+    Some(value)
+}
+
+// A parsed expression that is true or false for each message. It is stored as its source.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+impl Expression {
+    pub(crate) fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            index: 0,
+            depth: 0,
+        };
+        let root = parser.binary(0)?;
+        let end = parser.peek();
+        if end.kind != TokenKind::End {
+            return error(end.position, "expected an operator");
+        }
+        expect_type(&root, Type::Bool)?;
+        Ok(Self {
+            source: source.to_string(),
+            root: root.node,
+        })
+    }
+
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub(crate) fn matches(&self, message: &Message) -> bool {
+        self.root.is_true(message)
+    }
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self {
+            source: "true".to_string(),
+            root: Node::Bool(true),
+        }
+    }
+}
+
+impl TryFrom<String> for Expression {
+    type Error = ExpressionError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Expression::parse(&source)
+    }
+}
+
+impl From<Expression> for String {
+    fn from(expression: Expression) -> Self {
+        expression.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: Vec<u8>, data: Vec<u8>) -> Message {
+        Message {
+            timestamp: 1.5,
+            id,
+            data,
+            crc: Vec::new(),
+            ack: true,
+            speed: "500k".to_string(),
+        }
+    }
+
+    fn matches(source: &str, message: &Message) -> bool {
+        Expression::parse(source).unwrap().matches(message)
+    }
+
+    fn parse_error(source: &str) -> String {
+        Expression::parse(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn fields() {
+        let m = message(vec![0x18, 0xFE, 0xF1, 0x00], vec![0xB8, 0x0B, 0xFF, 0x80]);
+        assert!(matches("id == 0x18FEF100 && len >= 4 && ack", &m));
+        assert!(matches(
+            "u16le(data, 0) == 3000 && u16be(data, 0) == 0xB80B",
+            &m
+        ));
+        assert!(matches("i8(data, 2) == -1 && i16le(data, 2) == -32513", &m));
+        assert!(matches("data[3] == 128 && bit(data, 3, 7) == 1", &m));
+        assert!(matches("time > 1 && time < 2 && speed == \"500k\"", &m));
+        assert!(matches("!(id < 0x100) && -len == -4", &m));
+        let float = message(vec![0x01], 1.25f32.to_le_bytes().to_vec());
+        assert!(matches(
+            "f32le(data, 0) == 1.25 && f32le(data, 0) * 4 == 5",
+            &float
+        ));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let m = message(vec![0x01, 0x00], vec![0x12, 0x34]);
+        assert!(matches("1 + 2 * 3 == 7 && (1 + 2) * 3 == 9", &m));
+        assert!(matches("data[0] << 8 | data[1] == 0x1234", &m));
+        assert!(matches("data[1] >> 4 & 0xF ^ 1 == 2", &m));
+        assert!(matches("7 / 2 == 3 && 7 % 2 == 1 && 7.0 / 2 == 3.5", &m));
+        assert!(matches(
+            "1e3 == 1000 && .5 + 0.5 == 1 && 2.5e-1 == 0.25",
+            &m
+        ));
+        // Missing data and failed arithmetic make the comparison false
+        assert!(!matches("data[2] == 0", &m));
+        assert!(matches("data[2] == 0 || true", &m));
+        assert!(!matches("1 / 0 == 0", &m));
+        assert!(!matches("1 << 200 == 0", &m));
+        assert!(!matches("bit(data, 0, 8) == 0", &m));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_error("id =="), "expected a value at column 6");
+        assert_eq!(parse_error("id == )"), "unexpected symbol at column 7");
+        assert_eq!(parse_error("id 1"), "expected an operator at column 4");
+        assert_eq!(
+            parse_error("id"),
+            "expected true or false, not an integer at column 1"
+        );
+        assert_eq!(
+            parse_error("len < 1 < 2"),
+            "comparisons can't be chained at column 9"
+        );
+        assert_eq!(parse_error("ack + 1 == 2"), "expected a number at column 1");
+        assert_eq!(
+            parse_error("speed == 1"),
+            "can't compare text with an integer at column 7"
+        );
+        assert_eq!(
+            parse_error("data[1.5] == 0"),
+            "expected an integer, not a float at column 6"
+        );
+        assert_eq!(
+            parse_error("u16(data, 0) == 0"),
+            "unknown name u16 at column 1"
+        );
+        assert_eq!(parse_error("u8(len, 0) == 0"), "expected data at column 4");
+        assert_eq!(
+            parse_error("speed == \"500k"),
+            "unterminated string at column 10"
+        );
+        assert_eq!(parse_error("id == 0x"), "invalid number 0x at column 7");
+        assert_eq!(
+            parse_error("id == 1 # 2"),
+            "unexpected character '#' at column 9"
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        let m = message(vec![0x01, 0x00], vec![0x01]);
+        let nested = |depth| format!("{}true{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(matches(&nested(MAX_DEPTH - 1), &m));
+        assert!(parse_error(&nested(MAX_DEPTH)).starts_with("expression nested too deeply"));
+        assert!(parse_error(&nested(100_000)).starts_with("expression nested too deeply"));
+
+        let negated = format!("{}true", "!".repeat(MAX_DEPTH - 1));
+        assert!(!matches(&negated, &m));
+        let negated = format!("{}true", "!".repeat(100_000));
+        assert!(parse_error(&negated).starts_with("expression nested too deeply"));
+
+        // Long chains of operators nest as deeply as parentheses
+        let sum = |terms| format!("0{} == 1", " + 0".repeat(terms));
+        assert!(!matches(&sum(MAX_DEPTH - 2), &m));
+        assert!(parse_error(&sum(100_000)).starts_with("expression nested too deeply"));
+        let index = |terms| format!("data[{}0] == 1", "0 + ".repeat(terms));
+        assert!(matches(&index(MAX_DEPTH - 3), &m));
+        assert!(parse_error(&index(MAX_DEPTH - 2)).starts_with("expression nested too deeply"));
+    }
+
+    #[test]
+    fn stored_source() {
+        let expression: Expression = serde_json::from_str("\"len > 2\"").unwrap();
+        assert_eq!(expression.source(), "len > 2");
+        assert_eq!(serde_json::to_string(&expression).unwrap(), "\"len > 2\"");
+        assert!(serde_json::from_str::<Expression>("\"len >\"").is_err());
+        assert!(Expression::default().matches(&message(Vec::new(), Vec::new())));
+    }
+}
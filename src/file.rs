@@ -1,10 +1,11 @@
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Mutex, Arc};
 use std::sync::mpsc::Sender;
 use std::thread;
 
 use crate::message::Message;
+use crate::physical::{decode_file, BitTiming, Decoded};
 
 fn parse_file(path: &PathBuf, progress: &Mutex<f32>) -> Result<Vec<Message>, Box<dyn Error>> {
     let reader = csv::Reader::from_path(path)?;
@@ -40,3 +41,20 @@ pub fn load_file_async(path: &PathBuf, progress: Arc<Mutex<f32>>, result: Sender
         }
     });
 }
+
+pub fn decode_samples_async(
+    path: &Path,
+    channel: &str,
+    timing: &BitTiming,
+    samplerate: f64,
+    result: Sender<Result<Decoded, String>>,
+) {
+    let path = path.to_path_buf();
+    let channel = channel.to_string();
+    let timing = timing.clone();
+    thread::spawn(move || {
+        let decoded =
+            decode_file(&path, &channel, &timing, samplerate).map_err(|e| e.to_string());
+        let _ = result.send(decoded);
+    });
+}
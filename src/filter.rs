@@ -1,7 +1,18 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
 use strum::EnumIter;
 
+use crate::crc::{check, CrcStatus};
+use crate::expression::Expression;
 use crate::label::Label;
-use crate::message::{id_string, HighlightID, Message, Speed};
+use crate::message::{id_string, optional_id_pattern_deserializer, HighlightID, IdPattern};
+use crate::message::{Message, Speed};
+use crate::protocol::j1939::J1939Id;
+use crate::signal::{ByteOrder, Signal, SignalValue};
+use crate::util::{hex_to_str, parse_number};
+use crate::value_table::ValueTable;
 
 pub trait SpecialFilter {
     fn filter_specific(&self, message: &Message) -> bool;
@@ -9,6 +20,11 @@ pub trait SpecialFilter {
     fn output_data(&self, _message: &Message) -> Option<Vec<u8>> {
         None
     }
+
+    // Offset in the data where the filter matched, for filters that match at a position
+    fn match_position(&self, _message: &Message) -> Option<usize> {
+        None
+    }
 }
 
 #[derive(Debug, EnumIter, PartialEq, serde::Serialize, serde::Deserialize, Default, Clone)]
@@ -42,30 +58,552 @@ impl SpecialFilter for StartsWithBytes {
     fn output_data(&self, _message: &Message) -> Option<Vec<u8>> {
         match self.output {
             OutputSelection::All => Some(_message.data.clone()),
-            OutputSelection::AfterMatch => {
-                if let Some(index) = _message
-                    .data
-                    .windows(self.bytes.len())
-                    .position(|w| w == &self.bytes)
-                {
-                    Some(_message.data[index + self.bytes.len()..].to_vec())
-                } else {
-                    None
+            // The match is always the prefix
+            OutputSelection::AfterMatch => match self.filter_specific(_message) {
+                true => Some(_message.data[self.bytes.len()..].to_vec()),
+                false => None,
+            },
+        }
+    }
+}
+
+// One byte of a pattern, matching data bytes that equal the value in the bits set in the mask
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub struct PatternByte {
+    pub value: u8,
+    pub mask: u8,
+}
+
+impl PatternByte {
+    fn matches(&self, byte: u8) -> bool {
+        byte & self.mask == self.value & self.mask
+    }
+}
+
+fn parse_nibble(c: char) -> Option<(u8, u8)> {
+    match c {
+        '?' => Some((0, 0)),
+        c => Some((c.to_digit(16)? as u8, 0xF)),
+    }
+}
+
+// Parse a pattern such as "12 ?? 3? F0/F0": bytes in hex separated by whitespace, where ? is
+// any nibble and value/mask compares only the bits set in the mask
+pub(crate) fn parse_pattern(text: &str) -> Option<Vec<PatternByte>> {
+    let pattern: Option<Vec<PatternByte>> = text
+        .split_whitespace()
+        .map(|token| match token.split_once('/') {
+            Some((value, mask)) => Some(PatternByte {
+                value: u8::from_str_radix(value, 16).ok()?,
+                mask: u8::from_str_radix(mask, 16).ok()?,
+            }),
+            None => {
+                let mut chars = token.chars();
+                let (high, high_mask) = parse_nibble(chars.next()?)?;
+                let (low, low_mask) = parse_nibble(chars.next()?)?;
+                if chars.next().is_some() {
+                    return None;
                 }
+                Some(PatternByte {
+                    value: (high << 4) | low,
+                    mask: (high_mask << 4) | low_mask,
+                })
+            }
+        })
+        .collect();
+    pattern.filter(|pattern| !pattern.is_empty())
+}
+
+pub(crate) fn pattern_string(pattern: &[PatternByte]) -> String {
+    let nibble = |value: u8, mask: u8| match mask {
+        0 => "?".to_string(),
+        _ => format!("{:X}", value),
+    };
+    pattern
+        .iter()
+        .map(|byte| match byte.mask {
+            0xFF | 0xF0 | 0x0F | 0x00 => format!(
+                "{}{}",
+                nibble(byte.value >> 4, byte.mask >> 4),
+                nibble(byte.value & 0xF, byte.mask & 0xF)
+            ),
+            mask => format!("{:02X}/{:02X}", byte.value, mask),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Where in the data a pattern has to match
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub enum PatternAnchor {
+    Offset(usize),
+    Anywhere,
+}
+
+impl Default for PatternAnchor {
+    fn default() -> Self {
+        PatternAnchor::Offset(0)
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub struct BytePattern {
+    pub pattern: Vec<PatternByte>,
+    pub anchor: PatternAnchor,
+    #[serde(default)]
+    pub output: OutputSelection,
+}
+
+impl BytePattern {
+    fn matches_at(&self, data: &[u8], offset: usize) -> bool {
+        match data.get(offset..offset + self.pattern.len()) {
+            Some(bytes) => self.pattern.iter().zip(bytes).all(|(p, b)| p.matches(*b)),
+            None => false,
+        }
+    }
+
+    fn description(&self) -> String {
+        let position = match self.anchor {
+            PatternAnchor::Offset(offset) => format!("at byte {}", offset),
+            PatternAnchor::Anywhere => "anywhere".to_string(),
+        };
+        format!("Data matches {} {}", pattern_string(&self.pattern), position)
+    }
+}
+
+impl SpecialFilter for BytePattern {
+    fn filter_specific(&self, message: &Message) -> bool {
+        self.match_position(message).is_some()
+    }
+
+    fn output_data(&self, message: &Message) -> Option<Vec<u8>> {
+        match self.output {
+            OutputSelection::All => Some(message.data.clone()),
+            OutputSelection::AfterMatch => {
+                let end = self.match_position(message)? + self.pattern.len();
+                Some(message.data[end..].to_vec())
             }
         }
     }
+
+    fn match_position(&self, message: &Message) -> Option<usize> {
+        match self.anchor {
+            PatternAnchor::Offset(offset) => match self.matches_at(&message.data, offset) {
+                true => Some(offset),
+                false => None,
+            },
+            PatternAnchor::Anywhere => {
+                (0..message.data.len()).find(|offset| self.matches_at(&message.data, *offset))
+            }
+        }
+    }
+}
+
+#[derive(Debug, EnumIter, PartialEq, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub enum FieldType {
+    #[default]
+    Unsigned,
+    Signed,
+    Float,
+    Bit,
+}
+
+impl FieldType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            FieldType::Unsigned => "Unsigned",
+            FieldType::Signed => "Signed",
+            FieldType::Float => "Float",
+            FieldType::Bit => "Bit",
+        }
+    }
+}
+
+#[derive(Debug, EnumIter, PartialEq, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub enum Comparison {
+    #[default]
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    InRange,
+    InSet,
+}
+
+impl Comparison {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::Greater => ">",
+            Comparison::InRange => "in range",
+            Comparison::InSet => "in set",
+        }
+    }
+}
+
+// A decimal, 0x-prefixed hex or floating point constant
+pub(crate) fn parse_value(text: &str) -> Option<f64> {
+    match parse_number(text) {
+        Some(number) => Some(number as f64),
+        None => text.trim().parse().ok(),
+    }
+}
+
+// A number read from the data or compared with it. Integers are compared exactly, since 64-bit
+// values past 2^53 don't fit in a float; floats are compared as floats.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+}
+
+impl FieldValue {
+    // A decimal or 0x-prefixed hex integer, or a floating point constant
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        if let Some(number) = parse_number(text) {
+            return Some(FieldValue::Unsigned(number));
+        }
+        let text = text.trim();
+        match text.parse() {
+            Ok(number) => Some(FieldValue::Signed(number)),
+            Err(_) => text.parse().ok().map(FieldValue::Float),
+        }
+    }
+
+    fn integer(&self) -> Option<i128> {
+        match self {
+            FieldValue::Unsigned(value) => Some(*value as i128),
+            FieldValue::Signed(value) => Some(*value as i128),
+            FieldValue::Float(_) => None,
+        }
+    }
+
+    fn float(&self) -> f64 {
+        match self {
+            FieldValue::Unsigned(value) => *value as f64,
+            FieldValue::Signed(value) => *value as f64,
+            FieldValue::Float(value) => *value,
+        }
+    }
+}
+
+impl PartialEq for FieldValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for FieldValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.integer(), other.integer()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => self.float().partial_cmp(&other.float()),
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Unsigned(value) => write!(f, "{}", value),
+            FieldValue::Signed(value) => write!(f, "{}", value),
+            FieldValue::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+// Compares a number read from the data with constants, e.g. the little-endian u16 at byte 2
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct FieldCompare {
+    pub field_type: FieldType,
+    pub offset: usize,
+    // Bytes of a number
+    pub width: usize,
+    // Bit of a single bit field, 0 being the least significant bit of the byte
+    pub bit: u8,
+    pub byte_order: ByteOrder,
+    pub comparison: Comparison,
+    // One constant, the inclusive bounds of a range, or the members of a set
+    pub values: Vec<FieldValue>,
+}
+
+impl Default for FieldCompare {
+    fn default() -> Self {
+        Self {
+            field_type: FieldType::default(),
+            offset: 0,
+            width: 1,
+            bit: 0,
+            byte_order: ByteOrder::default(),
+            comparison: Comparison::default(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl FieldCompare {
+    fn value(&self, data: &[u8]) -> Option<FieldValue> {
+        if self.field_type == FieldType::Bit {
+            let bit = (data.get(self.offset)? >> self.bit) & 1;
+            return Some(FieldValue::Unsigned(bit as u64));
+        }
+        let bytes = data.get(self.offset..self.offset + self.width)?;
+        let raw = match self.byte_order {
+            ByteOrder::Intel => bytes.iter().rev().fold(0u64, |acc, b| (acc << 8) | *b as u64),
+            ByteOrder::Motorola => bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64),
+        };
+        match (&self.field_type, self.width) {
+            (FieldType::Unsigned, _) => Some(FieldValue::Unsigned(raw)),
+            (FieldType::Signed, 1..=8) => {
+                let unused = 64 - 8 * self.width as u32;
+                Some(FieldValue::Signed((raw << unused) as i64 >> unused))
+            }
+            (FieldType::Float, 4) => Some(FieldValue::Float(f32::from_bits(raw as u32) as f64)),
+            (FieldType::Float, 8) => Some(FieldValue::Float(f64::from_bits(raw))),
+            _ => None,
+        }
+    }
+
+    fn field_string(&self) -> String {
+        let prefix = match self.field_type {
+            FieldType::Unsigned => "u",
+            FieldType::Signed => "i",
+            FieldType::Float => "f",
+            FieldType::Bit => return format!("bit {} of byte {}", self.bit, self.offset),
+        };
+        let order = match (self.width, &self.byte_order) {
+            (1, _) => "",
+            (_, ByteOrder::Intel) => " LE",
+            (_, ByteOrder::Motorola) => " BE",
+        };
+        format!("{}{}{} at byte {}", prefix, self.width * 8, order, self.offset)
+    }
+
+    fn description(&self) -> String {
+        let values = match self.comparison {
+            Comparison::InRange => self
+                .values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(" to "),
+            _ => self
+                .values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        format!("{} {} {}", self.field_string(), self.comparison.name(), values)
+    }
+}
+
+impl SpecialFilter for FieldCompare {
+    fn filter_specific(&self, message: &Message) -> bool {
+        let value = match self.value(&message.data) {
+            Some(value) => value,
+            None => return false,
+        };
+        match (&self.comparison, self.values.as_slice()) {
+            (Comparison::Equal, [constant]) => value == *constant,
+            (Comparison::NotEqual, [constant]) => value != *constant,
+            (Comparison::Less, [constant]) => value < *constant,
+            (Comparison::Greater, [constant]) => value > *constant,
+            (Comparison::InRange, [min, max]) => *min <= value && value <= *max,
+            (Comparison::InSet, values) => values.contains(&value),
+            _ => false,
+        }
+    }
+
+    fn match_position(&self, message: &Message) -> Option<usize> {
+        match self.filter_specific(message) {
+            true => Some(self.offset),
+            false => None,
+        }
+    }
+}
+
+// Relates a frame to the frames matching another filter within a time
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub struct Related {
+    pub filter: Box<MessageFilter>,
+    pub within_ms: f64,
+}
+
+impl Related {
+    fn within(&self) -> f64 {
+        self.within_ms / 1000.0
+    }
+
+    // Frames that come at most the time after an earlier related frame
+    fn after(&self, base: &[bool], related: &[bool], messages: &[Message]) -> Vec<bool> {
+        let mut last = None;
+        let mut rows = Vec::with_capacity(messages.len());
+        for (index, message) in messages.iter().enumerate() {
+            rows.push(match (base[index], last) {
+                (true, Some(time)) => message.timestamp - time <= self.within(),
+                _ => false,
+            });
+            if related[index] {
+                last = Some(message.timestamp);
+            }
+        }
+        rows
+    }
+
+    // Frames with no later related frame within the time. Frames too close to the end of the
+    // capture to tell don't match.
+    fn not_followed(&self, base: &[bool], related: &[bool], messages: &[Message]) -> Vec<bool> {
+        let end = messages.last().map_or(0.0, |message| message.timestamp);
+        let mut next = None;
+        let mut rows = vec![false; messages.len()];
+        for (index, message) in messages.iter().enumerate().rev() {
+            rows[index] = base[index]
+                && message.timestamp + self.within() <= end
+                && match next {
+                    Some(time) => time - message.timestamp > self.within(),
+                    None => true,
+                };
+            if related[index] {
+                next = Some(message.timestamp);
+            }
+        }
+        rows
+    }
+}
+
+// The nth frame matching the ID and speed, counting from 1
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct Occurrence {
+    pub number: usize,
+}
+
+impl Default for Occurrence {
+    fn default() -> Self {
+        Self { number: 1 }
+    }
+}
+
+// Frames whose payload differs from the previous frame with the same ID and speed. Only the bits
+// set in the mask are compared, or the whole payload if the mask is empty.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub struct ChangedBytes {
+    pub mask: Vec<u8>,
+}
+
+impl ChangedBytes {
+    // Offsets of the bytes that changed. The first frame of an ID changed in every byte.
+    pub(crate) fn changed(&self, previous: Option<&Message>, message: &Message) -> Vec<usize> {
+        let previous = match previous {
+            Some(previous) => &previous.data,
+            None => return (0..message.data.len()).collect(),
+        };
+        let length = match self.mask.is_empty() {
+            true => previous.len().max(message.data.len()),
+            false => self.mask.len(),
+        };
+        (0..length)
+            .filter(|offset| {
+                let mask = self.mask.get(*offset).copied().unwrap_or(0xFF);
+                match (previous.get(*offset), message.data.get(*offset)) {
+                    (Some(a), Some(b)) => a & mask != b & mask,
+                    (None, None) => false,
+                    // Bytes added or removed by a length change
+                    _ => mask != 0,
+                }
+            })
+            .collect()
+    }
+
+    fn description(&self) -> String {
+        match self.mask.is_empty() {
+            true => "Changed from previous frame".to_string(),
+            false => format!("Changed from previous frame in {}", hex_to_str(&self.mask)),
+        }
+    }
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+// Row of the previous frame with the same ID and speed for each row, so the same ID on two buses
+// of a capture is compared per bus
+pub(crate) fn previous_rows(messages: &[Message]) -> Vec<Option<usize>> {
+    let mut last: HashMap<(&[u8], &str), usize> = HashMap::new();
+    messages
+        .iter()
+        .enumerate()
+        .map(|(index, message)| last.insert((message.id.as_slice(), message.speed.as_str()), index))
+        .collect()
+}
+
+// Frames with a time from start to end, in seconds as in the time column
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub struct TimeWindow {
+    pub start: f64,
+    pub end: f64,
+}
+
+// Matches the fields of a 29-bit J1939 ID, so rules keep working when e.g. the source changes.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub struct J1939Fields {
+    pub priority: Option<u8>,
+    pub pgn: Option<u32>,
+    pub source: Option<u8>,
+    pub destination: Option<u8>,
+}
+
+impl J1939Fields {
+    fn description(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(priority) = self.priority {
+            parts.push(format!("priority {}", priority));
+        }
+        if let Some(pgn) = self.pgn {
+            parts.push(format!("PGN {} ({:04X})", pgn, pgn));
+        }
+        if let Some(source) = self.source {
+            parts.push(format!("SA {:02X}", source));
+        }
+        if let Some(destination) = self.destination {
+            parts.push(format!("DA {:02X}", destination));
+        }
+        match parts.is_empty() {
+            true => "Any J1939 frame".to_string(),
+            false => format!("J1939 {}", parts.join(", ")),
+        }
+    }
+}
+
+impl SpecialFilter for J1939Fields {
+    fn filter_specific(&self, message: &Message) -> bool {
+        let id = match J1939Id::from_message(message) {
+            Some(id) => id,
+            None => return false,
+        };
+        // Unset fields match anything
+        self.priority.unwrap_or(id.priority) == id.priority
+            && self.pgn.unwrap_or(id.pgn) == id.pgn
+            && self.source.unwrap_or(id.source) == id.source
+            && self.destination.unwrap_or(id.destination) == id.destination
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub(crate) struct MessageFilter {
-    id: Option<Vec<u8>>,
+    #[serde(default, deserialize_with = "optional_id_pattern_deserializer")]
+    id: Option<IdPattern>,
     speed: Option<Speed>,
     filter_type: FilterType,
 }
 
 impl MessageFilter {
-    pub(crate) fn new(id: Option<Vec<u8>>, speed: Option<Speed>, filter_type: FilterType) -> Self {
+    pub(crate) fn new(
+        id: Option<IdPattern>,
+        speed: Option<Speed>,
+        filter_type: FilterType,
+    ) -> Self {
         Self {
             id,
             speed,
@@ -73,11 +611,11 @@ impl MessageFilter {
         }
     }
 
-    pub(crate) fn id(&self) -> Option<&Vec<u8>> {
+    pub(crate) fn id(&self) -> Option<&IdPattern> {
         self.id.as_ref()
     }
 
-    pub(crate) fn id_string(&self, ids: &Vec<HighlightID>) -> String {
+    pub(crate) fn id_string(&self, ids: &[HighlightID]) -> String {
         match self.id() {
             Some(id) => id_string(id, ids),
             None => String::from("any"),
@@ -105,19 +643,97 @@ impl MessageFilter {
             FilterType::StartsWithBytes(filter) => {
                 format!("Data starts with {}", hex::encode(&filter.bytes))
             }
+            FilterType::J1939(filter) => filter.description(),
+            FilterType::CrcMismatch => "CRC mismatch".to_string(),
+            FilterType::BytePattern(filter) => filter.description(),
+            FilterType::FieldCompare(filter) => filter.description(),
+            FilterType::Expression(expression) => format!("Expression {}", expression.source()),
+            FilterType::After(related) => format!(
+                "Within {} ms after ({})",
+                related.within_ms,
+                related.filter.summary()
+            ),
+            FilterType::NotFollowedBy(related) => format!(
+                "Not followed by ({}) within {} ms",
+                related.filter.summary(),
+                related.within_ms
+            ),
+            FilterType::Occurrence(occurrence) => format!("Occurrence #{}", occurrence.number),
+            FilterType::TimeWindow(window) => {
+                format!("Time from {} s to {} s", window.start, window.end)
+            }
+            FilterType::Changed(changed) => changed.description(),
+            FilterType::All(filters) => format!("All of ({})", summaries(filters)),
+            FilterType::Any(filters) => format!("Any of ({})", summaries(filters)),
+            FilterType::Not(filter) => format!("Not ({})", filter.summary()),
+        }
+    }
+
+    // Description including the ID and speed, for filters nested in a composite
+    fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(id) = &self.id {
+            parts.push(format!("ID {}", id));
+        }
+        if let Some(speed) = &self.speed {
+            parts.push(format!("speed {}", speed));
         }
+        if !matches!(self.filter_type, FilterType::Basic) || parts.is_empty() {
+            parts.push(self.description());
+        }
+        parts.join(", ")
     }
 
     pub(crate) fn output_data(&self, message: &Message) -> Option<Vec<u8>> {
         match self.filter_type() {
             FilterType::Basic => None,
             FilterType::StartsWithBytes(filter) => filter.output_data(message),
+            FilterType::J1939(filter) => filter.output_data(message),
+            FilterType::CrcMismatch => None,
+            FilterType::BytePattern(filter) => filter.output_data(message),
+            FilterType::FieldCompare(_) => None,
+            FilterType::Expression(_) => None,
+            FilterType::After(_) | FilterType::NotFollowedBy(_) => None,
+            FilterType::Occurrence(_) | FilterType::TimeWindow(_) => None,
+            FilterType::Changed(_) => None,
+            // The output of the first nested filter that has one
+            FilterType::All(filters) => filters.iter().find_map(|f| f.output_data(message)),
+            // Any can't nest sequence rules, so the matching nested filters are known here
+            FilterType::Any(filters) => filters
+                .iter()
+                .filter(|f| f.filter(message))
+                .find_map(|f| f.output_data(message)),
+            FilterType::Not(_) => None,
         }
     }
 
-    pub(crate) fn filter(&self, message: &Message) -> bool {
+    pub(crate) fn match_position(&self, message: &Message) -> Option<usize> {
+        match self.filter_type() {
+            FilterType::BytePattern(filter) => filter.match_position(message),
+            FilterType::FieldCompare(filter) => filter.match_position(message),
+            FilterType::All(filters) => filters.iter().find_map(|f| f.match_position(message)),
+            FilterType::Any(filters) => filters
+                .iter()
+                .filter(|f| f.filter(message))
+                .find_map(|f| f.match_position(message)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn match_output(&self, message: &Message) -> Option<MatchOutput> {
+        let output = MatchOutput {
+            output: self.output_data(message),
+            position: self.match_position(message),
+        };
+        match (&output.output, output.position) {
+            (None, None) => None,
+            _ => Some(output),
+        }
+    }
+
+    fn matches_id_speed(&self, message: &Message) -> bool {
         if let Some(id) = &self.id {
-            if &message.id != id {
+            if !id.matches(&message.id) {
                 return false;
             }
         }
@@ -127,39 +743,189 @@ impl MessageFilter {
                 return false;
             }
         }
+        true
+    }
+
+    // Whether any part of the filter depends on other frames of the capture
+    pub(crate) fn is_sequential(&self) -> bool {
+        match &self.filter_type {
+            FilterType::After(_) | FilterType::NotFollowedBy(_) | FilterType::Occurrence(_) => true,
+            FilterType::Changed(_) => true,
+            FilterType::All(filters) | FilterType::Any(filters) => {
+                filters.iter().any(|filter| filter.is_sequential())
+            }
+            FilterType::Not(filter) => filter.is_sequential(),
+            _ => false,
+        }
+    }
+
+    // Whether the filter can be evaluated. Any can't nest sequence rules, since which nested filter
+    // matched a frame is needed for its output.
+    pub(crate) fn is_valid(&self) -> bool {
+        match &self.filter_type {
+            FilterType::All(filters) => filters.iter().all(|filter| filter.is_valid()),
+            FilterType::Any(filters) => filters.iter().all(|filter| !filter.is_sequential()),
+            FilterType::Not(filter) => filter.is_valid(),
+            FilterType::After(related) | FilterType::NotFollowedBy(related) => {
+                related.filter.is_valid()
+            }
+            _ => true,
+        }
+    }
+
+    // Whether each frame of a capture matches. Sequence rules take one pass over the frames
+    // each, in capture order.
+    pub(crate) fn filter_all(&self, messages: &[Message]) -> Vec<bool> {
+        if !self.is_sequential() {
+            return messages.iter().map(|message| self.filter(message)).collect();
+        }
+        let mut rows: Vec<bool> = messages
+            .iter()
+            .map(|message| self.matches_id_speed(message))
+            .collect();
+        let combine = |rows: &mut Vec<bool>, other: Vec<bool>, op: fn(bool, bool) -> bool| {
+            rows.iter_mut()
+                .zip(other)
+                .for_each(|(row, other)| *row = op(*row, other))
+        };
+        match &self.filter_type {
+            FilterType::All(filters) => {
+                for filter in filters {
+                    combine(&mut rows, filter.filter_all(messages), |a, b| a && b);
+                }
+            }
+            FilterType::Any(filters) => {
+                let mut any = vec![false; messages.len()];
+                for filter in filters {
+                    combine(&mut any, filter.filter_all(messages), |a, b| a || b);
+                }
+                combine(&mut rows, any, |a, b| a && b);
+            }
+            FilterType::Not(filter) => {
+                combine(&mut rows, filter.filter_all(messages), |a, b| a && !b);
+            }
+            FilterType::After(related) => {
+                rows = related.after(&rows, &related.filter.filter_all(messages), messages);
+            }
+            FilterType::NotFollowedBy(related) => {
+                let other = related.filter.filter_all(messages);
+                rows = related.not_followed(&rows, &other, messages);
+            }
+            FilterType::Changed(changed) => {
+                for (index, previous) in previous_rows(messages).into_iter().enumerate() {
+                    let previous = previous.map(|previous| &messages[previous]);
+                    rows[index] =
+                        rows[index] && !changed.changed(previous, &messages[index]).is_empty();
+                }
+            }
+            FilterType::Occurrence(occurrence) => {
+                let mut count = 0;
+                for row in rows.iter_mut().filter(|row| **row) {
+                    count += 1;
+                    *row = count == occurrence.number;
+                }
+            }
+            _ => {}
+        }
+        rows
+    }
+
+    // Sequence rules can't tell from a single frame and don't match here, nor do filters negating
+    // them; see filter_all
+    pub(crate) fn filter(&self, message: &Message) -> bool {
+        if !self.matches_id_speed(message) {
+            return false;
+        }
 
         match &self.filter_type {
             FilterType::Basic => true,
             FilterType::StartsWithBytes(filter) => filter.filter_specific(message),
+            FilterType::J1939(filter) => filter.filter_specific(message),
+            FilterType::CrcMismatch => matches!(check(message), CrcStatus::Mismatch { .. }),
+            FilterType::BytePattern(filter) => filter.filter_specific(message),
+            FilterType::FieldCompare(filter) => filter.filter_specific(message),
+            FilterType::Expression(expression) => expression.matches(message),
+            FilterType::After(_) | FilterType::NotFollowedBy(_) => false,
+            FilterType::Occurrence(_) | FilterType::Changed(_) => false,
+            FilterType::TimeWindow(window) => {
+                window.start <= message.timestamp && message.timestamp <= window.end
+            }
+            FilterType::All(filters) => filters.iter().all(|f| f.filter(message)),
+            FilterType::Any(filters) => filters.iter().any(|f| f.filter(message)),
+            FilterType::Not(filter) => !filter.is_sequential() && !filter.filter(message),
         }
     }
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, EnumIter, Clone)]
+fn summaries(filters: &[MessageFilter]) -> String {
+    filters
+        .iter()
+        .map(|filter| filter.summary())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, EnumIter, Default, Clone)]
 pub enum FilterType {
+    #[default]
     Basic,
     StartsWithBytes(StartsWithBytes),
-}
-
-impl Default for FilterType {
-    fn default() -> Self {
-        FilterType::Basic
-    }
+    J1939(J1939Fields),
+    CrcMismatch,
+    BytePattern(BytePattern),
+    FieldCompare(FieldCompare),
+    Expression(Expression),
+    // Rules on a frame's place in the capture
+    After(Related),
+    NotFollowedBy(Related),
+    Occurrence(Occurrence),
+    TimeWindow(TimeWindow),
+    Changed(ChangedBytes),
+    // Composites of other filters, each with its own ID and speed
+    All(Vec<MessageFilter>),
+    Any(Vec<MessageFilter>),
+    Not(Box<MessageFilter>),
 }
 
 impl FilterType {
     pub(crate) fn is_variant(&self, variant: &FilterType) -> bool {
-        match (self, variant) {
-            (FilterType::StartsWithBytes(_), FilterType::StartsWithBytes(_)) => true,
-            (FilterType::Basic, FilterType::Basic) => true,
-            _ => false,
-        }
+        matches!(
+            (self, variant),
+            (FilterType::StartsWithBytes(_), FilterType::StartsWithBytes(_))
+                | (FilterType::Basic, FilterType::Basic)
+                | (FilterType::J1939(_), FilterType::J1939(_))
+                | (FilterType::CrcMismatch, FilterType::CrcMismatch)
+                | (FilterType::BytePattern(_), FilterType::BytePattern(_))
+                | (FilterType::FieldCompare(_), FilterType::FieldCompare(_))
+                | (FilterType::Expression(_), FilterType::Expression(_))
+                | (FilterType::After(_), FilterType::After(_))
+                | (FilterType::NotFollowedBy(_), FilterType::NotFollowedBy(_))
+                | (FilterType::Occurrence(_), FilterType::Occurrence(_))
+                | (FilterType::TimeWindow(_), FilterType::TimeWindow(_))
+                | (FilterType::Changed(_), FilterType::Changed(_))
+                | (FilterType::All(_), FilterType::All(_))
+                | (FilterType::Any(_), FilterType::Any(_))
+                | (FilterType::Not(_), FilterType::Not(_))
+        )
     }
 
     pub(crate) fn name(&self) -> &str {
         match self {
             FilterType::StartsWithBytes(_) => "Starts with bytes",
             FilterType::Basic => "Basic",
+            FilterType::J1939(_) => "J1939 fields",
+            FilterType::CrcMismatch => "CRC mismatch",
+            FilterType::BytePattern(_) => "Byte pattern",
+            FilterType::FieldCompare(_) => "Field comparison",
+            FilterType::Expression(_) => "Expression",
+            FilterType::After(_) => "Within time after",
+            FilterType::NotFollowedBy(_) => "Not followed within time",
+            FilterType::Occurrence(_) => "Nth occurrence",
+            FilterType::TimeWindow(_) => "Time window",
+            FilterType::Changed(_) => "Changed bytes",
+            FilterType::All(_) => "All of",
+            FilterType::Any(_) => "Any of",
+            FilterType::Not(_) => "Not",
         }
     }
 }
@@ -168,9 +934,438 @@ impl FilterType {
 pub(crate) struct LabelFilter {
     pub label: Label,
     pub filter: MessageFilter,
+    #[serde(default)]
+    pub signal: Option<Signal>,
 }
 
-pub(crate) struct FilterResult {
-    pub(crate) label: Label,
+impl LabelFilter {
+    pub(crate) fn description(&self) -> String {
+        match &self.signal {
+            Some(signal) => format!("{}, {}", self.filter.description(), signal.description()),
+            None => self.filter.description(),
+        }
+    }
+
+    // A multiplexed signal only labels frames with its multiplexor value
+    fn is_active(&self, message: &Message) -> bool {
+        match &self.signal {
+            Some(signal) => signal.is_active(&message.data),
+            None => true,
+        }
+    }
+
+    // Whether each frame of a capture is labeled
+    pub(crate) fn filter_all(&self, messages: &[Message]) -> Vec<bool> {
+        let mut rows = self.filter.filter_all(messages);
+        for (row, message) in rows.iter_mut().zip(messages) {
+            *row = *row && self.is_active(message);
+        }
+        rows
+    }
+
+    // The result for a frame the filter is known to match, with the output found along with the
+    // matching rows. Only the signal value is decoded here, since value tables can change.
+    pub(crate) fn matched_result<'a>(
+        &'a self,
+        message: &Message,
+        output: Option<&'a MatchOutput>,
+        value_tables: &[ValueTable],
+    ) -> FilterResult<'a> {
+        FilterResult {
+            label: &self.label,
+            output: output.and_then(|output| output.output.as_deref()),
+            position: output.and_then(|output| output.position),
+            value: self
+                .signal
+                .as_ref()
+                .and_then(|signal| signal.decode(message, value_tables)),
+            changed: None,
+        }
+    }
+}
+
+// What a filter shows for a frame it matches, for filters that show more than their label
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MatchOutput {
     pub(crate) output: Option<Vec<u8>>,
+    pub(crate) position: Option<usize>,
+}
+
+pub(crate) struct FilterResult<'a> {
+    pub(crate) label: &'a Label,
+    pub(crate) output: Option<&'a [u8]>,
+    pub(crate) position: Option<usize>,
+    pub(crate) value: Option<SignalValue>,
+    // Offsets of the changed bytes, for changed bytes rules
+    pub(crate) changed: Option<Vec<usize>>,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::signal::{Multiplexor, MuxRange};
+
+    fn frame(timestamp: f64, id: u16, data: Vec<u8>) -> Message {
+        Message {
+            timestamp,
+            id: id.to_be_bytes().to_vec(),
+            data,
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    fn byte(value: u8, mask: u8) -> PatternByte {
+        PatternByte { value, mask }
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(
+            parse_pattern("12 ?? 3? ?c F0/F0").unwrap(),
+            vec![
+                byte(0x12, 0xFF),
+                byte(0x00, 0x00),
+                byte(0x30, 0xF0),
+                byte(0x0C, 0x0F),
+                byte(0xF0, 0xF0)
+            ]
+        );
+        assert_eq!(parse_pattern("  ab\tCD ").unwrap().len(), 2);
+        for invalid in ["", " ", "1", "123", "G0", "12 x", "F0/", "/F0", "100/FF"] {
+            assert_eq!(parse_pattern(invalid), None, "{:?}", invalid);
+        }
+
+        let pattern = parse_pattern("12 ?? 3? 81/81 0F/0F").unwrap();
+        assert_eq!(pattern_string(&pattern), "12 ?? 3? 81/81 ?F");
+        assert_eq!(parse_pattern(&pattern_string(&pattern)).unwrap(), pattern);
+    }
+
+    #[test]
+    fn pattern_matches() {
+        let filter = |text, anchor| BytePattern {
+            pattern: parse_pattern(text).unwrap(),
+            anchor,
+            output: OutputSelection::AfterMatch,
+        };
+        let message = frame(0.0, 0x123, vec![0x10, 0x12, 0x34, 0x81, 0x00]);
+        let at_one = filter("12 3?", PatternAnchor::Offset(1));
+        assert_eq!(at_one.match_position(&message), Some(1));
+        assert_eq!(at_one.output_data(&message), Some(vec![0x81, 0x00]));
+        assert!(!filter("12 3?", PatternAnchor::Offset(0)).filter_specific(&message));
+
+        let anywhere = filter("80/80 ??", PatternAnchor::Anywhere);
+        assert_eq!(anywhere.match_position(&message), Some(3));
+        assert_eq!(anywhere.output_data(&message), Some(Vec::new()));
+        // The pattern has to fit in the data
+        assert!(!filter("00 ??", PatternAnchor::Anywhere).filter_specific(&message));
+    }
+
+    fn rule(filter_type: FilterType) -> MessageFilter {
+        MessageFilter::new(None, None, filter_type)
+    }
+
+    #[test]
+    fn sequential_composites() {
+        let messages: Vec<_> = (0..4)
+            .map(|i| frame(i as f64, 0x100, vec![i / 2]))
+            .collect();
+        let second = rule(FilterType::Occurrence(Occurrence { number: 2 }));
+        let not_second = rule(FilterType::Not(Box::new(second)));
+        assert_eq!(
+            not_second.filter_all(&messages),
+            vec![true, false, true, true]
+        );
+        // A single frame can't tell, so it doesn't match
+        assert!(messages.iter().all(|message| !not_second.filter(message)));
+
+        let changed = rule(FilterType::Changed(ChangedBytes { mask: Vec::new() }));
+        let starts = rule(FilterType::StartsWithBytes(StartsWithBytes {
+            bytes: vec![1],
+            output: OutputSelection::AfterMatch,
+        }));
+        let all = rule(FilterType::All(vec![changed.clone(), starts.clone()]));
+        assert_eq!(all.filter_all(&messages), vec![false, false, true, false]);
+        let any = rule(FilterType::Any(vec![changed, starts]));
+        assert_eq!(any.filter_all(&messages), vec![true, false, true, true]);
+        let not_all = rule(FilterType::Not(Box::new(all)));
+        assert_eq!(not_all.filter_all(&messages), vec![true, true, false, true]);
+    }
+
+    #[test]
+    fn composite_output() {
+        let starts = |byte| {
+            rule(FilterType::StartsWithBytes(StartsWithBytes {
+                bytes: vec![byte],
+                output: OutputSelection::AfterMatch,
+            }))
+        };
+        let any = rule(FilterType::Any(vec![starts(1), starts(2)]));
+        let message = frame(0.0, 0x100, vec![2, 3]);
+        assert!(any.filter(&message));
+        // The output comes from the nested filter that matched
+        assert_eq!(any.output_data(&message), Some(vec![3]));
+        assert_eq!(any.match_position(&message), None);
+        let not = rule(FilterType::Not(Box::new(starts(1))));
+        assert!(not.filter(&message));
+        assert_eq!(not.output_data(&message), None);
+    }
+
+    #[test]
+    fn previous_rows_per_bus() {
+        let on = |speed: &str, id, data| Message {
+            speed: speed.to_string(),
+            ..frame(0.0, id, data)
+        };
+        let messages = vec![
+            on("500k", 0x100, vec![1]),
+            on("250k", 0x100, vec![2]),
+            on("500k", 0x200, vec![1]),
+            on("500k", 0x100, vec![1]),
+            on("250k", 0x100, vec![2]),
+        ];
+        assert_eq!(
+            previous_rows(&messages),
+            vec![None, None, None, Some(0), Some(1)]
+        );
+        // Alternating buses don't make frames look changed
+        let changed = rule(FilterType::Changed(ChangedBytes { mask: Vec::new() }));
+        assert_eq!(
+            changed.filter_all(&messages),
+            vec![true, true, true, false, false]
+        );
+    }
+
+    // A label on ID 100 with a signal multiplexed on the low nibble of the first byte being 2
+    pub(crate) fn multiplexed_label() -> LabelFilter {
+        let selector = Signal {
+            length: 4,
+            ..Signal::default()
+        };
+        LabelFilter {
+            label: Label {
+                name: "Page 2".to_string(),
+                color: [1.0, 1.0, 1.0],
+            },
+            filter: MessageFilter::new(IdPattern::parse("0100"), None, FilterType::Basic),
+            signal: Some(Signal {
+                start_bit: 8,
+                multiplexor: Some(Multiplexor {
+                    name: "Page".to_string(),
+                    selector: Box::new(selector),
+                    values: MuxRange::parse_list("2").unwrap(),
+                }),
+                ..Signal::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn multiplexed_labels() {
+        let label = multiplexed_label();
+        let messages = vec![
+            frame(0.0, 0x100, vec![0x01, 7]),
+            frame(1.0, 0x100, vec![0x12, 7]),
+            frame(2.0, 0x200, vec![0x02, 7]),
+            frame(3.0, 0x100, vec![0x22, 7]),
+        ];
+        assert_eq!(
+            label.filter.filter_all(&messages),
+            vec![true, true, false, true]
+        );
+        // Frames have to match the filter and the multiplexor
+        assert_eq!(label.filter_all(&messages), vec![false, true, false, true]);
+        let result = label.matched_result(&messages[1], None, &[]);
+        assert_eq!(result.value.map(|value| value.raw), Some(7.0));
+    }
+
+    fn field(
+        field_type: FieldType,
+        offset: usize,
+        width: usize,
+        byte_order: ByteOrder,
+        comparison: Comparison,
+        values: &[&str],
+    ) -> FieldCompare {
+        FieldCompare {
+            field_type,
+            offset,
+            width,
+            bit: 0,
+            byte_order,
+            comparison,
+            values: values
+                .iter()
+                .map(|value| FieldValue::parse(value).unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn field_values() {
+        let message = frame(
+            0.0,
+            0x100,
+            vec![0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0],
+        );
+        let value = |field_type, offset, width, byte_order| {
+            field(
+                field_type,
+                offset,
+                width,
+                byte_order,
+                Comparison::Equal,
+                &[],
+            )
+            .value(&message.data)
+            .unwrap()
+            .to_string()
+        };
+        use ByteOrder::{Intel, Motorola};
+        use FieldType::{Float, Signed, Unsigned};
+        assert_eq!(value(Unsigned, 1, 1, Intel), "52");
+        assert_eq!(value(Unsigned, 0, 2, Intel), "13330");
+        assert_eq!(value(Unsigned, 0, 2, Motorola), "4660");
+        assert_eq!(value(Unsigned, 2, 3, Motorola), "5666970");
+        assert_eq!(value(Unsigned, 2, 4, Motorola), "1450744508");
+        assert_eq!(value(Unsigned, 0, 8, Intel), "17356517385562371090");
+        assert_eq!(value(Signed, 7, 1, Intel), "-16");
+        assert_eq!(value(Signed, 6, 2, Motorola), "-8464");
+        assert_eq!(value(Signed, 0, 8, Intel), "-1090226688147180526");
+        assert_eq!(value(Signed, 0, 8, Motorola), "1311768467463790320");
+
+        let floats = frame(
+            0.0,
+            0x100,
+            vec![0x3F, 0xC0, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0xF4, 0xBF],
+        );
+        let value = |width, offset, byte_order| {
+            field(Float, offset, width, byte_order, Comparison::Equal, &[])
+                .value(&floats.data)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(value(4, 0, Motorola), "1.5");
+        assert_eq!(value(8, 4, Intel), "-1.25");
+        // Floats are 4 or 8 bytes
+        assert!(field(Float, 0, 2, Intel, Comparison::Equal, &[])
+            .value(&floats.data)
+            .is_none());
+
+        let bit = |bit| FieldCompare {
+            field_type: FieldType::Bit,
+            bit,
+            ..field(Unsigned, 0, 1, Intel, Comparison::Equal, &["1"])
+        };
+        let matching: Vec<_> = (0..8)
+            .filter(|b| bit(*b).filter_specific(&message))
+            .collect();
+        assert_eq!(matching, vec![1, 4]);
+    }
+
+    #[test]
+    fn field_comparisons() {
+        let message = frame(0.0, 0x100, vec![0x00, 0x20, 0, 0, 0, 0, 0, 0x01]);
+        let matches = |comparison, values: &[&str]| {
+            field(
+                FieldType::Unsigned,
+                0,
+                8,
+                ByteOrder::Motorola,
+                comparison,
+                values,
+            )
+            .filter_specific(&message)
+        };
+        // Past 2^53, where neighbouring integers have the same float
+        assert!(matches(Comparison::Equal, &["0x20000000000001"]));
+        assert!(!matches(Comparison::Equal, &["0x20000000000000"]));
+        assert!(matches(Comparison::NotEqual, &["0x20000000000000"]));
+        assert!(!matches(Comparison::NotEqual, &["9007199254740993"]));
+        assert!(matches(Comparison::Greater, &["0x20000000000000"]));
+        assert!(!matches(Comparison::Less, &["0x20000000000001"]));
+        assert!(matches(Comparison::Less, &["1e16"]));
+        assert!(matches(
+            Comparison::InRange,
+            &["9007199254740993", "9007199254740993"]
+        ));
+        assert!(!matches(Comparison::InRange, &["0", "0x20000000000000"]));
+        assert!(matches(
+            Comparison::InSet,
+            &["1", "0x20000000000001", "2.5"]
+        ));
+        assert!(!matches(
+            Comparison::InSet,
+            &["0x20000000000000", "0x20000000000002"]
+        ));
+
+        let signed = |comparison, values: &[&str]| {
+            field(
+                FieldType::Signed,
+                7,
+                1,
+                ByteOrder::Intel,
+                comparison,
+                values,
+            )
+            .filter_specific(&frame(0.0, 0x100, vec![0, 0, 0, 0, 0, 0, 0, 0xFE]))
+        };
+        assert!(signed(Comparison::Equal, &["-2"]));
+        assert!(signed(Comparison::Less, &["-1.5"]));
+        assert!(signed(Comparison::InRange, &["-3", "0"]));
+        assert!(!signed(Comparison::Greater, &["0xFE"]));
+
+        // Fields past the end of the payload never match
+        for comparison in [Comparison::Equal, Comparison::NotEqual, Comparison::Less] {
+            let past_end = field(
+                FieldType::Unsigned,
+                7,
+                2,
+                ByteOrder::Intel,
+                comparison,
+                &["0"],
+            );
+            assert!(!past_end.filter_specific(&message));
+        }
+        let past_end = FieldCompare {
+            field_type: FieldType::Bit,
+            ..field(
+                FieldType::Unsigned,
+                8,
+                1,
+                ByteOrder::Intel,
+                Comparison::Equal,
+                &["0"],
+            )
+        };
+        assert!(!past_end.filter_specific(&message));
+    }
+
+    #[test]
+    fn field_constants() {
+        assert_eq!(
+            FieldValue::parse(" 0X20000000000001").map(|value| value.to_string()),
+            Some("9007199254740993".to_string())
+        );
+        assert!(matches!(
+            FieldValue::parse("-5"),
+            Some(FieldValue::Signed(-5))
+        ));
+        assert!(matches!(FieldValue::parse("2.5"), Some(FieldValue::Float(value)) if value == 2.5));
+        assert!(FieldValue::parse("0x").is_none());
+        assert!(FieldValue::parse("five").is_none());
+
+        // Configs from before integers were kept exact have float constants
+        let values: Vec<FieldValue> =
+            serde_json::from_str("[1.0, 9007199254740993, -3, 2.5]").unwrap();
+        assert!(matches!(values[0], FieldValue::Float(_)));
+        assert!(matches!(values[1], FieldValue::Unsigned(9007199254740993)));
+        assert!(matches!(values[2], FieldValue::Signed(-3)));
+        assert_eq!(values[0], FieldValue::Unsigned(1));
+        assert_eq!(
+            serde_json::to_string(&values).unwrap(),
+            "[1.0,9007199254740993,-3,2.5]"
+        );
+    }
 }
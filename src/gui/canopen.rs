@@ -0,0 +1,154 @@
+use crate::egui;
+use egui_extras::{Size, TableBuilder};
+
+use super::dialog::eds_from_dialog;
+use super::TableGui;
+
+impl TableGui {
+    pub(super) fn canopen_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(
+                &mut self.canopen_state.enabled,
+                "Decode CANopen frames in message table",
+            )
+            .changed()
+        {
+            self.save_state();
+        }
+
+        ui.collapsing("Object dictionaries", |ui| {
+            self.eds_ui(ui);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Decode").clicked() {
+                if let Some(messages) = self.message_loader.messages() {
+                    self.canopen_state.decode(messages);
+                }
+            }
+            ui.label(format!(
+                "{} SDO transfers",
+                self.canopen_state.transfers.len()
+            ));
+        });
+        ui.separator();
+
+        self.sdo_transfer_table(ui);
+    }
+
+    fn eds_ui(&mut self, ui: &mut egui::Ui) {
+        let mut index_to_remove: Option<usize> = None;
+        for (index, eds) in self.canopen_state.eds_files.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("Node {}: {}", eds.node, eds.path.display()));
+                if ui.button("Delete").clicked() {
+                    index_to_remove = Some(index);
+                }
+            });
+            if let Some(error) = self.canopen_state.eds_errors.get(&eds.node) {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        }
+        if let Some(index) = index_to_remove {
+            self.canopen_state.remove_eds(index);
+            self.save_state();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Node ID:");
+            TableGui::validated_text_edit(ui, &mut self.canopen_state.edit_node, 30.0);
+            if ui.button("Load EDS/DCF...").clicked() {
+                match eds_from_dialog() {
+                    Ok(Some(path)) => {
+                        if self.canopen_state.add_eds(path).is_ok() {
+                            self.save_state();
+                        }
+                    }
+                    Ok(None) => {} // User cancelled
+                    Err(e) => {
+                        eprintln!("Error opening EDS: {}", e);
+                    }
+                }
+            }
+        });
+        ui.label("Leave the node ID empty to use the one in a DCF");
+    }
+
+    fn sdo_transfer_table(&mut self, ui: &mut egui::Ui) {
+        ui.push_id("sdo_transfer_table", |ui| {
+            let table = TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(50.0).at_least(30.0))
+                .column(Size::initial(80.0).at_least(40.0))
+                .column(Size::initial(160.0).at_least(40.0))
+                .column(Size::initial(200.0).at_least(60.0))
+                .column(Size::remainder().at_least(60.0));
+
+            let mut selected_transfer = self.canopen_state.selected_transfer;
+
+            table
+                .header(20.0, |mut header| {
+                    for heading in [
+                        "#", "Time", "Node", "Access", "Mode", "Object", "Value", "Errors",
+                    ] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(
+                        TableGui::BUTTON_HEIGHT,
+                        self.canopen_state.transfers.len(),
+                        |row_index, mut row| {
+                            let transfer = &self.canopen_state.transfers[row_index];
+                            row.col(|ui| {
+                                if ui
+                                    .selectable_label(
+                                        selected_transfer == Some(row_index),
+                                        row_index.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    selected_transfer = Some(row_index);
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.3}", transfer.timestamp));
+                            });
+                            row.col(|ui| {
+                                ui.label(transfer.node.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.label(transfer.direction.name());
+                            });
+                            row.col(|ui| {
+                                ui.label(transfer.mode.name());
+                            });
+                            row.col(|ui| {
+                                ui.label(self.canopen_state.object_name(transfer));
+                            });
+                            row.col(|ui| {
+                                ui.label(self.canopen_state.transfer_value(transfer));
+                            });
+                            row.col(|ui| {
+                                let errors = transfer
+                                    .errors
+                                    .iter()
+                                    .map(|e| e.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                ui.colored_label(egui::Color32::RED, errors);
+                            });
+                        },
+                    );
+                });
+
+            self.canopen_state.selected_transfer = selected_transfer;
+        });
+    }
+}
@@ -17,17 +17,60 @@ impl fmt::Display for DialogError {
     }
 }
 
+impl From<Error> for DialogError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::ImplementationError(msg) => DialogError {
+                err: Error::ImplementationError(msg.clone()),
+                msg: Some(msg),
+            },
+            e => DialogError { err: e, msg: None },
+        }
+    }
+}
+
 pub(crate) fn csv_from_dialog() -> Result<Option<PathBuf>, DialogError> {
-    match FileDialog::new()
+    Ok(FileDialog::new()
         .add_filter("CSV", &["csv"])
-        .show_open_single_file()
-    {
-        Ok(Some(path)) => Ok(Some(path)),
-        Ok(None) => Ok(None),
-        Err(Error::ImplementationError(msg)) => Err(DialogError {
-            err: Error::ImplementationError(msg.clone()),
-            msg: Some(msg),
-        }),
-        Err(e) => Err(DialogError { err: e, msg: None }),
-    }
+        .show_open_single_file()?)
+}
+
+pub(crate) fn samples_from_dialog() -> Result<Option<PathBuf>, DialogError> {
+    Ok(FileDialog::new()
+        .add_filter("Logic analyzer export", &["csv", "vcd", "sr"])
+        .add_filter("sigrok annotations", &["txt"])
+        .show_open_single_file()?)
+}
+
+pub(crate) fn json_from_dialog() -> Result<Option<PathBuf>, DialogError> {
+    Ok(FileDialog::new()
+        .add_filter("JSON", &["json"])
+        .show_open_single_file()?)
+}
+
+pub(crate) fn eds_from_dialog() -> Result<Option<PathBuf>, DialogError> {
+    Ok(FileDialog::new()
+        .add_filter("EDS/DCF", &["eds", "dcf"])
+        .show_open_single_file()?)
+}
+
+pub(crate) fn dbc_from_dialog() -> Result<Option<PathBuf>, DialogError> {
+    Ok(FileDialog::new()
+        .add_filter("DBC", &["dbc"])
+        .show_open_single_file()?)
+}
+
+pub(crate) fn dbc_save_dialog() -> Result<Option<PathBuf>, DialogError> {
+    Ok(FileDialog::new()
+        .set_filename("workspace.dbc")
+        .add_filter("DBC", &["dbc"])
+        .show_save_single_file()?)
+}
+
+pub(crate) fn firmware_save_dialog(filename: &str) -> Result<Option<PathBuf>, DialogError> {
+    Ok(FileDialog::new()
+        .set_filename(filename)
+        .add_filter("Binary", &["bin"])
+        .add_filter("Intel HEX", &["hex"])
+        .show_save_single_file()?)
 }
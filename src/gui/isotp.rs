@@ -0,0 +1,307 @@
+use std::path::Path;
+
+use crate::egui::{self, ComboBox};
+use egui_extras::{Size, TableBuilder};
+use strum::IntoEnumIterator;
+
+use crate::protocol::firmware::FirmwareRegion;
+use crate::protocol::isotp::Addressing;
+use crate::protocol::uds::UdsKind;
+use crate::util::hex_to_str;
+
+use super::dialog::firmware_save_dialog;
+use super::state::{EditDidNameState, EditIsoTpChannelState};
+use super::TableGui;
+
+impl TableGui {
+    pub(super) fn isotp_ui(&mut self, ui: &mut egui::Ui) {
+        let mut index_to_remove: Option<usize> = None;
+        for (index, channel) in self.isotp_state.channels.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{}: {} / {} ({})",
+                    channel.name,
+                    hex_to_str(&channel.tx_id),
+                    hex_to_str(&channel.rx_id),
+                    channel.addressing.name()
+                ));
+                if ui.button("Delete").clicked() {
+                    index_to_remove = Some(index);
+                }
+            });
+        }
+        if let Some(index) = index_to_remove {
+            self.isotp_state.channels.remove(index);
+            self.save_state();
+        }
+
+        self.isotp_channel_edit_line(ui);
+        ui.collapsing("DID names", |ui| {
+            self.did_names_ui(ui);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Reassemble").clicked() {
+                if let Some(messages) = self.message_loader.messages() {
+                    self.isotp_state.reassemble(messages);
+                }
+            }
+            ui.label(format!("{} PDUs", self.isotp_state.pdus.len()));
+        });
+        if !self.isotp_state.firmware.is_empty() {
+            ui.collapsing("Firmware downloads", |ui| {
+                self.firmware_ui(ui);
+            });
+        }
+        ui.separator();
+
+        self.isotp_pdu_table(ui);
+    }
+
+    fn isotp_channel_edit_line(&mut self, ui: &mut egui::Ui) {
+        let edit_state = &mut self.isotp_state.edit_state;
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            TableGui::validated_text_edit(ui, &mut edit_state.name, 60.0);
+            ui.label("Tx ID:");
+            TableGui::validated_text_edit(ui, &mut edit_state.tx_id, 60.0);
+            ui.label("Rx ID:");
+            TableGui::validated_text_edit(ui, &mut edit_state.rx_id, 60.0);
+        });
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("isotp_addressing")
+                .selected_text(edit_state.addressing.name())
+                .show_ui(ui, |ui| {
+                    for addressing in Addressing::iter() {
+                        if ui
+                            .selectable_label(
+                                edit_state.addressing == addressing,
+                                addressing.name(),
+                            )
+                            .clicked()
+                        {
+                            edit_state.addressing = addressing;
+                        }
+                    }
+                });
+            if edit_state.addressing != Addressing::Normal {
+                ui.label("Tx address:");
+                TableGui::validated_text_edit(ui, &mut edit_state.tx_address, 25.0);
+                ui.label("Rx address:");
+                TableGui::validated_text_edit(ui, &mut edit_state.rx_address, 25.0);
+            }
+            ui.label("Timeout (ms):");
+            TableGui::validated_text_edit(ui, &mut edit_state.timeout_ms, 50.0);
+        });
+        if ui.button("Add").clicked() {
+            if let Ok(channel) = self.isotp_state.edit_state.validate() {
+                self.isotp_state.channels.push(channel);
+                self.isotp_state.edit_state = EditIsoTpChannelState::default();
+                self.save_state();
+            }
+        }
+    }
+
+    fn did_names_ui(&mut self, ui: &mut egui::Ui) {
+        let mut index_to_remove: Option<usize> = None;
+        for (index, did_name) in self.isotp_state.did_names.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{:04X}: {}", did_name.did, did_name.name));
+                if ui.button("Delete").clicked() {
+                    index_to_remove = Some(index);
+                }
+            });
+        }
+        let mut changed = false;
+        if let Some(index) = index_to_remove {
+            self.isotp_state.did_names.remove(index);
+            changed = true;
+        }
+
+        let edit_state = &mut self.isotp_state.edit_did_state;
+        let mut add_clicked = false;
+        ui.horizontal(|ui| {
+            ui.label("DID:");
+            TableGui::validated_text_edit(ui, &mut edit_state.did, 40.0);
+            ui.label("Name:");
+            TableGui::validated_text_edit(ui, &mut edit_state.name, 120.0);
+            add_clicked = ui.button("Add").clicked();
+        });
+        if add_clicked {
+            if let Ok(did_name) = self.isotp_state.edit_did_state.validate() {
+                self.isotp_state.did_names.push(did_name);
+                self.isotp_state.edit_did_state = EditDidNameState::default();
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.isotp_state.decode_uds();
+            self.save_state();
+        }
+    }
+
+    fn firmware_ui(&mut self, ui: &mut egui::Ui) {
+        for (index, region) in self.isotp_state.firmware.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let name = self
+                    .isotp_state
+                    .channels
+                    .get(region.channel)
+                    .map_or("?", |channel| channel.name.as_str());
+                ui.label(format!(
+                    "{:.3} {}: {:08X} ({} of {} bytes)",
+                    region.timestamp,
+                    name,
+                    region.address,
+                    region.received(),
+                    region.size
+                ));
+                if ui.button("Save...").clicked() {
+                    let filename = format!("firmware_{:08X}.bin", region.address);
+                    match firmware_save_dialog(&filename) {
+                        Ok(Some(path)) => save_firmware(region, &path),
+                        Ok(None) => {} // User cancelled
+                        Err(e) => {
+                            eprintln!("Error saving firmware: {}", e);
+                        }
+                    }
+                }
+            });
+            if !region.issues.is_empty() {
+                let issues = region
+                    .issues
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                ui.push_id(index, |ui| {
+                    ui.colored_label(egui::Color32::RED, issues);
+                });
+            }
+        }
+    }
+
+    fn isotp_pdu_table(&mut self, ui: &mut egui::Ui) {
+        ui.push_id("isotp_pdu_table", |ui| {
+            let table = TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(200.0).at_least(60.0))
+                .column(Size::initial(80.0).at_least(40.0))
+                .column(Size::initial(100.0).at_least(60.0))
+                .column(Size::initial(250.0).at_least(60.0))
+                .column(Size::remainder().at_least(60.0));
+
+            let mut selected_pdu = self.isotp_state.selected_pdu;
+
+            table
+                .header(20.0, |mut header| {
+                    for heading in [
+                        "#", "Time", "Channel", "Len", "Data", "Rows", "Errors", "UDS", "Latency",
+                    ] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(
+                        TableGui::BUTTON_HEIGHT,
+                        self.isotp_state.pdus.len(),
+                        |row_index, mut row| {
+                            let pdu = &self.isotp_state.pdus[row_index];
+                            row.col(|ui| {
+                                if ui
+                                    .selectable_label(
+                                        selected_pdu == Some(row_index),
+                                        row_index.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    selected_pdu = Some(row_index);
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.3}", pdu.timestamp));
+                            });
+                            row.col(|ui| {
+                                let name = self
+                                    .isotp_state
+                                    .channels
+                                    .get(pdu.channel)
+                                    .map_or("?", |channel| channel.name.as_str());
+                                ui.label(format!("{} {}", name, pdu.direction.name()));
+                            });
+                            row.col(|ui| {
+                                ui.label(pdu.length.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.label(hex_to_str(&pdu.data));
+                            });
+                            row.col(|ui| {
+                                let first = pdu.rows.first().copied().unwrap_or(0);
+                                let last = pdu.rows.last().copied().unwrap_or(0);
+                                ui.label(match first == last {
+                                    true => first.to_string(),
+                                    false => format!("{}-{}", first, last),
+                                });
+                            });
+                            row.col(|ui| {
+                                let errors = pdu
+                                    .errors
+                                    .iter()
+                                    .map(|e| e.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                ui.colored_label(egui::Color32::RED, errors);
+                            });
+                            let uds = self.isotp_state.pdu_uds(row_index);
+                            row.col(|ui| {
+                                if let Some(message) = uds {
+                                    match message.kind {
+                                        UdsKind::NegativeResponse(_) => {
+                                            ui.colored_label(
+                                                egui::Color32::RED,
+                                                &message.description,
+                                            );
+                                        }
+                                        _ => {
+                                            ui.label(&message.description);
+                                        }
+                                    }
+                                }
+                            });
+                            row.col(|ui| {
+                                if let Some(latency) = uds.and_then(|message| message.latency_ms) {
+                                    ui.label(format!("{:.1} ms", latency));
+                                }
+                            });
+                        },
+                    );
+                });
+
+            self.isotp_state.selected_pdu = selected_pdu;
+        });
+    }
+}
+
+// Intel HEX for .hex files, raw binary otherwise
+fn save_firmware(region: &FirmwareRegion, path: &Path) {
+    let result = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("hex") => region.write_intel_hex(path),
+        _ => region.write_bin(path),
+    };
+    match result {
+        Ok(_) => {
+            println!("Wrote firmware to {}", path.display());
+        }
+        Err(e) => {
+            eprintln!("Error saving firmware: {}", e);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::egui;
+use egui_extras::{Size, TableBuilder};
+
+use crate::protocol::j1939::GLOBAL_ADDRESS;
+use crate::util::hex_to_str;
+
+use super::dialog::json_from_dialog;
+use super::TableGui;
+
+// Address as shown in tables, with the global address spelled out
+pub(super) fn address_text(address: u8) -> String {
+    match address {
+        GLOBAL_ADDRESS => "global".to_string(),
+        address => format!("{:02X}", address),
+    }
+}
+
+impl TableGui {
+    // PGN number with its name from the definition file, if any
+    pub(super) fn pgn_text(&self, pgn: u32) -> String {
+        match self.j1939_state.definition(pgn) {
+            Some(definition) => format!("{} ({})", definition.name, pgn),
+            None => pgn.to_string(),
+        }
+    }
+
+    pub(super) fn j1939_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(
+                &mut self.j1939_state.show_columns,
+                "Show J1939 columns in message table",
+            )
+            .changed()
+        {
+            self.save_state();
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Load definitions...").clicked() {
+                match json_from_dialog() {
+                    Ok(Some(path)) => {
+                        self.j1939_state.load_definitions(path);
+                        self.save_state();
+                    }
+                    Ok(None) => {} // User cancelled
+                    Err(e) => {
+                        self.j1939_state.definitions_error = Some(e.to_string());
+                    }
+                }
+            }
+            match &self.j1939_state.definitions_path {
+                Some(path) => {
+                    ui.label(format!(
+                        "{} ({} PGNs)",
+                        path.display(),
+                        self.j1939_state.definitions.len()
+                    ));
+                }
+                None => {
+                    ui.label("No definitions loaded");
+                }
+            }
+        });
+        if let Some(error) = &self.j1939_state.definitions_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Reassemble").clicked() {
+                if let Some(messages) = self.message_loader.messages() {
+                    self.j1939_state.reassemble(messages);
+                }
+            }
+            ui.label(format!(
+                "{} transport transfers",
+                self.j1939_state.transfers.len()
+            ));
+        });
+        ui.separator();
+
+        self.j1939_transfer_table(ui);
+    }
+
+    fn j1939_transfer_table(&mut self, ui: &mut egui::Ui) {
+        ui.push_id("j1939_transfer_table", |ui| {
+            let table = TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(50.0).at_least(30.0))
+                .column(Size::initial(120.0).at_least(40.0))
+                .columns(Size::initial(40.0).at_least(30.0), 3)
+                .column(Size::initial(200.0).at_least(60.0))
+                .column(Size::initial(100.0).at_least(60.0))
+                .column(Size::remainder().at_least(60.0));
+
+            let mut selected_transfer = self.j1939_state.selected_transfer;
+
+            table
+                .header(20.0, |mut header| {
+                    for heading in [
+                        "#", "Time", "Type", "PGN", "SA", "DA", "Len", "Data", "Errors", "SPNs",
+                    ] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(
+                        TableGui::BUTTON_HEIGHT,
+                        self.j1939_state.transfers.len(),
+                        |row_index, mut row| {
+                            let transfer = &self.j1939_state.transfers[row_index];
+                            row.col(|ui| {
+                                if ui
+                                    .selectable_label(
+                                        selected_transfer == Some(row_index),
+                                        row_index.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    selected_transfer = Some(row_index);
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.3}", transfer.timestamp));
+                            });
+                            row.col(|ui| {
+                                ui.label(transfer.kind.name());
+                            });
+                            row.col(|ui| {
+                                ui.label(self.pgn_text(transfer.pgn));
+                            });
+                            row.col(|ui| {
+                                ui.label(address_text(transfer.source));
+                            });
+                            row.col(|ui| {
+                                ui.label(address_text(transfer.destination));
+                            });
+                            row.col(|ui| {
+                                ui.label(transfer.length.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.label(hex_to_str(&transfer.data));
+                            });
+                            row.col(|ui| {
+                                let errors = transfer
+                                    .errors
+                                    .iter()
+                                    .map(|e| e.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                ui.colored_label(egui::Color32::RED, errors);
+                            });
+                            row.col(|ui| {
+                                if let Some(definition) = self.j1939_state.definition(transfer.pgn)
+                                {
+                                    let values = definition
+                                        .decode(&transfer.data)
+                                        .iter()
+                                        .map(|value| value.to_string())
+                                        .collect::<Vec<_>>()
+                                        .join(", ");
+                                    ui.label(values);
+                                }
+                            });
+                        },
+                    );
+                });
+
+            self.j1939_state.selected_transfer = selected_transfer;
+        });
+    }
+}
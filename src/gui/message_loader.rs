@@ -8,7 +8,11 @@ use crate::message::{Message, Speed};
 
 pub struct MessageLoader {
     state: MessageLoaderState,
-    known_speeds: HashSet<Speed>
+    known_speeds: HashSet<Speed>,
+    // Messages were decoded from logic analyzer samples rather than loaded from a CSV
+    decoded: bool,
+    // CSV loaded before the decoded messages replaced it, which stays the one to reload
+    last_csv_path: Option<PathBuf>,
 }
 
 pub enum MessageLoaderState {
@@ -20,7 +24,8 @@ pub enum MessageLoaderState {
         loader_channel: (Sender<Option<Vec<Message>>>, Receiver<Option<Vec<Message>>>),
     },
     Loaded {
-        messages: Vec<Message>,
+        // Shared with background work on the capture
+        messages: Arc<Vec<Message>>,
         file_path: PathBuf,
     },
     Error {
@@ -46,6 +51,8 @@ impl MessageLoader {
         Self {
             state: MessageLoaderState::FileNotSelected,
             known_speeds: HashSet::new(),
+            decoded: false,
+            last_csv_path: None,
         }
     }
 
@@ -57,6 +64,8 @@ impl MessageLoader {
         Self {
             state: MessageLoaderState::FileSelected(file_path),
             known_speeds: HashSet::new(),
+            decoded: false,
+            last_csv_path: None,
         }
     }
 
@@ -64,7 +73,16 @@ impl MessageLoader {
         self.state.file_path()
     }
 
+    // Path to reload the messages from, the CSV before any decoded messages
+    pub fn csv_path(&self) -> Option<&PathBuf> {
+        match self.decoded {
+            true => self.last_csv_path.as_ref(),
+            false => self.file_path(),
+        }
+    }
+
     pub fn replace_file_path(&mut self, file_path: Option<PathBuf>) {
+        self.decoded = false;
         match file_path {
             None => self.state = MessageLoaderState::FileNotSelected,
             Some(path) => self.state = MessageLoaderState::FileSelected(path),
@@ -79,6 +97,20 @@ impl MessageLoader {
         }
     }
 
+    pub fn messages(&self) -> Option<&Vec<Message>> {
+        match &self.state {
+            MessageLoaderState::Loaded { messages, .. } => Some(messages),
+            _ => None,
+        }
+    }
+
+    pub fn shared_messages(&self) -> Option<Arc<Vec<Message>>> {
+        match &self.state {
+            MessageLoaderState::Loaded { messages, .. } => Some(messages.clone()),
+            _ => None,
+        }
+    }
+
     pub fn known_speeds(&self) -> &HashSet<Speed> {
         &self.known_speeds
     }
@@ -90,6 +122,18 @@ impl MessageLoader {
         }
     }
 
+    pub fn set_decoded(&mut self, messages: Vec<Message>, file_path: PathBuf) {
+        if !self.decoded {
+            self.last_csv_path = self.file_path().cloned();
+        }
+        self.known_speeds = messages.iter().map(|m| m.speed.clone()).collect();
+        self.state = MessageLoaderState::Loaded {
+            messages: Arc::new(messages),
+            file_path,
+        };
+        self.decoded = true;
+    }
+
     pub fn handle_file_loading(&mut self) {
         match &self.state {
             MessageLoaderState::Error { .. } => (),
@@ -126,7 +170,7 @@ impl MessageLoader {
                         // Load succeeded
                         self.known_speeds = messages.iter().map(|m| m.speed.clone()).collect();
                         self.state = MessageLoaderState::Loaded {
-                            messages,
+                            messages: Arc::new(messages),
                             file_path: file_path.clone(),
                         };
                     }
@@ -1,8 +1,16 @@
+mod canopen;
 mod dialog;
+mod isotp;
+mod j1939;
 mod message_loader;
+mod nmea2000;
+mod obd;
+mod physical;
+mod quick_filter;
 mod state;
 mod util;
 mod widgets;
+mod xcp;
 
 use std::collections::HashSet;
 
@@ -10,22 +18,32 @@ use crate::egui::{self, Align, Color32, ComboBox, Layout, ProgressBar, TextEdit}
 use egui_extras::{Size, StripBuilder, TableBuilder};
 use strum::IntoEnumIterator;
 
-use crate::filter::{FilterType, OutputSelection};
-use crate::message::{id_string, HighlightID, Message};
+use crate::crc::{check, CrcStatus};
+use crate::expression::Expression;
+use crate::filter::{Comparison, FieldType, FilterType, OutputSelection};
+use crate::message::{id_string, HighlightID, IdPattern, Message};
+use crate::protocol::canopen::decode_frame;
+use crate::protocol::j1939::J1939Id;
+use crate::signal::{ByteOrder, ValueType};
 use crate::util::{bytes_to_string, hex_to_str};
+use crate::value_table::ValueTable;
 
 pub(crate) use state::{EditFilterOptionsState, TableGui};
 
-use self::dialog::csv_from_dialog;
+use self::dialog::{csv_from_dialog, dbc_from_dialog, dbc_save_dialog};
+use self::j1939::address_text;
 use self::message_loader::{MessageLoader, MessageLoaderState};
-use self::state::{EditFilterLabelState, Field};
-use self::util::{ack_color, speed_color};
+use self::state::{
+    EditFilterLabelState, EditMessageFilterState, EditMultiplexorState, EditSignalState,
+    EditValueTableState, Field,
+};
+use self::util::{ack_color, crc_color, signal_value_color, speed_color};
 use self::widgets::{color_chip, colored_label};
 
-pub fn id_text(id_field: &Field<String>, ids: &Vec<HighlightID>) -> String {
-    match id_field.as_bytes(true) {
-        Err(_) => id_field.value.clone(),
-        Ok(id_bytes) => id_string(&id_bytes, ids),
+pub fn id_text(id_field: &Field<String>, ids: &[HighlightID]) -> String {
+    match IdPattern::parse(&id_field.value) {
+        None => id_field.value.clone(),
+        Some(id) => id_string(&id, ids),
     }
 }
 
@@ -42,14 +60,107 @@ pub fn speed_text(speed_field: &Field<String>) -> String {
 impl eframe::App for TableGui {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.message_loader.handle_file_loading();
+        if let Some(messages) = self.physical_state.poll() {
+            if let Some(path) = self.physical_state.path.clone() {
+                self.message_loader.set_decoded(messages, path);
+                self.clear_results();
+            }
+        }
+        if self.message_loader.messages().is_none() {
+            self.clear_results();
+        }
+        if let Some(messages) = self.message_loader.shared_messages() {
+            let quick_filter_changed = self.quick_filter_state.update_rows(&messages);
+            let labels_changed = self.filter_label_state.update_rows(&messages);
+            if quick_filter_changed || labels_changed {
+                self.view_filter_state.invalidate();
+            }
+            self.view_filter_state.update_rows(
+                &messages,
+                &self.filter_label_state,
+                self.quick_filter_state.rows(),
+            );
+        }
+        self.view_filter_state.poll();
 
         egui::SidePanel::left("side_panel")
             .default_width(500.0)
             .show(ctx, |ui| {
                 self.left_pane_ui(ui);
             });
+        let mut value_tables_open = self.value_table_state.window_open;
+        egui::Window::new("Value tables")
+            .open(&mut value_tables_open)
+            .show(ctx, |ui| {
+                self.value_tables_ui(ui);
+            });
+        self.value_table_state.window_open = value_tables_open;
+
+        let mut isotp_open = self.isotp_state.window_open;
+        egui::Window::new("Diagnostics (ISO-TP / UDS)")
+            .open(&mut isotp_open)
+            .default_width(700.0)
+            .show(ctx, |ui| {
+                self.isotp_ui(ui);
+            });
+        self.isotp_state.window_open = isotp_open;
+
+        let mut j1939_open = self.j1939_state.window_open;
+        egui::Window::new("J1939")
+            .open(&mut j1939_open)
+            .default_width(700.0)
+            .show(ctx, |ui| {
+                self.j1939_ui(ui);
+            });
+        self.j1939_state.window_open = j1939_open;
+
+        let mut canopen_open = self.canopen_state.window_open;
+        egui::Window::new("CANopen")
+            .open(&mut canopen_open)
+            .default_width(700.0)
+            .show(ctx, |ui| {
+                self.canopen_ui(ui);
+            });
+        self.canopen_state.window_open = canopen_open;
+
+        let mut obd_open = self.obd_state.window_open;
+        egui::Window::new("OBD-II")
+            .open(&mut obd_open)
+            .default_width(700.0)
+            .show(ctx, |ui| {
+                self.obd_ui(ui);
+            });
+        self.obd_state.window_open = obd_open;
+
+        let mut nmea2000_open = self.nmea2000_state.window_open;
+        egui::Window::new("NMEA 2000")
+            .open(&mut nmea2000_open)
+            .default_width(700.0)
+            .show(ctx, |ui| {
+                self.nmea2000_ui(ui);
+            });
+        self.nmea2000_state.window_open = nmea2000_open;
+
+        let mut xcp_open = self.xcp_state.window_open;
+        egui::Window::new("XCP / CCP")
+            .open(&mut xcp_open)
+            .default_width(700.0)
+            .show(ctx, |ui| {
+                self.xcp_ui(ui);
+            });
+        self.xcp_state.window_open = xcp_open;
+
+        let mut physical_open = self.physical_state.window_open;
+        egui::Window::new("Logic analyzer")
+            .open(&mut physical_open)
+            .default_width(600.0)
+            .show(ctx, |ui| {
+                self.physical_ui(ui);
+            });
+        self.physical_state.window_open = physical_open;
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.vertical(|ui| {
+            ui.horizontal(|ui| {
                 let response = ui.button("Open...");
                 if response.clicked() {
                     match csv_from_dialog() {
@@ -64,8 +175,54 @@ impl eframe::App for TableGui {
                     }
                     return;
                 }
+                if ui.button("Samples...").clicked() {
+                    self.physical_state.window_open = !self.physical_state.window_open;
+                }
+                if ui.button("Value tables...").clicked() {
+                    self.value_table_state.window_open = !self.value_table_state.window_open;
+                }
+                if ui.button("Diagnostics...").clicked() {
+                    self.isotp_state.window_open = !self.isotp_state.window_open;
+                }
+                if ui.button("J1939...").clicked() {
+                    self.j1939_state.window_open = !self.j1939_state.window_open;
+                }
+                if ui.button("CANopen...").clicked() {
+                    self.canopen_state.window_open = !self.canopen_state.window_open;
+                }
+                if ui.button("OBD-II...").clicked() {
+                    self.obd_state.window_open = !self.obd_state.window_open;
+                }
+                if ui.button("NMEA 2000...").clicked() {
+                    self.nmea2000_state.window_open = !self.nmea2000_state.window_open;
+                }
+                if ui.button("XCP/CCP...").clicked() {
+                    self.xcp_state.window_open = !self.xcp_state.window_open;
+                }
+                if ui.button("Import DBC...").clicked() {
+                    match dbc_from_dialog() {
+                        Ok(Some(path)) => {
+                            self.import_dbc(&path);
+                            self.save_state();
+                        }
+                        Ok(None) => {} // User cancelled
+                        Err(e) => {
+                            eprintln!("Error importing DBC: {}", e);
+                        }
+                    }
+                }
+                if ui.button("Export DBC...").clicked() {
+                    match dbc_save_dialog() {
+                        Ok(Some(path)) => self.export_dbc(&path),
+                        Ok(None) => {} // User cancelled
+                        Err(e) => {
+                            eprintln!("Error exporting DBC: {}", e);
+                        }
+                    }
+                }
             });
 
+            self.quick_filter_ui(ui);
             ui.separator();
 
             // Leave room for the source code link after the table demo:
@@ -84,6 +241,19 @@ impl eframe::App for TableGui {
 impl TableGui {
     const BUTTON_HEIGHT: f32 = 20.0;
 
+    // Drop protocol results that no longer match the loaded messages
+    fn clear_results(&mut self) {
+        self.isotp_state.clear_results();
+        self.j1939_state.clear_results();
+        self.canopen_state.clear_results();
+        self.obd_state.clear_results();
+        self.nmea2000_state.clear_results();
+        self.xcp_state.clear_results();
+        self.quick_filter_state.clear_results();
+        self.filter_label_state.clear_results();
+        self.view_filter_state.clear_results();
+    }
+
     fn left_pane_ui(&mut self, ui: &mut egui::Ui) {
         StripBuilder::new(ui)
             .size(Size::relative(0.3))
@@ -118,6 +288,7 @@ impl TableGui {
 
         let mut index_to_remove: Option<usize> = None;
         let mut index_to_copy: Option<usize> = None;
+        let mut view_toggled: Option<(usize, bool)> = None;
 
         table
             .header(20.0, |mut header| {
@@ -141,17 +312,29 @@ impl TableGui {
                     self.filter_label_state.data.len(),
                     |row_index, mut row| {
                         let label_filter = &self.filter_label_state.data[row_index];
-                        let filter = &label_filter.filter;
 
                         match self.filter_label_state.editing_index() {
                             None => {
                                 // No row being edited
                                 row.col(|ui| {
+                                    let mut in_view = self.filter_label_state.in_view(row_index);
+                                    if ui
+                                        .checkbox(&mut in_view, "")
+                                        .on_hover_text("Use as view filter")
+                                        .changed()
+                                    {
+                                        view_toggled = Some((row_index, in_view));
+                                    }
                                     colored_label(
                                         ui,
                                         label_filter.label.color32(),
                                         &label_filter.label.name,
                                     );
+                                    if self.filter_label_state.failed(row_index) {
+                                        ui.colored_label(Color32::RED, "failed").on_hover_text(
+                                            "Evaluating the label over the capture failed",
+                                        );
+                                    }
                                 });
                                 row.col(|ui| {
                                     ui.label(
@@ -164,7 +347,7 @@ impl TableGui {
                                     ui.label(&label_filter.filter.speed_string());
                                 });
                                 row.col(|ui| {
-                                    ui.label(filter.description());
+                                    ui.label(label_filter.description());
                                 });
                                 row.col(|ui| {
                                     if ui.button("Edit").clicked() {
@@ -192,6 +375,11 @@ impl TableGui {
                                         label_filter.label.color32(),
                                         &label_filter.label.name,
                                     );
+                                    if self.filter_label_state.failed(row_index) {
+                                        ui.colored_label(Color32::RED, "failed").on_hover_text(
+                                            "Evaluating the label over the capture failed",
+                                        );
+                                    }
                                 });
                                 row.col(|ui| {
                                     ui.label(
@@ -204,7 +392,7 @@ impl TableGui {
                                     ui.label(&label_filter.filter.speed_string());
                                 });
                                 row.col(|ui| {
-                                    ui.label(filter.description());
+                                    ui.label(label_filter.description());
                                 });
                             }
                         }
@@ -213,89 +401,52 @@ impl TableGui {
             });
 
         if index_to_remove.is_some() {
-            self.filter_label_state
-                .data
-                .remove(index_to_remove.unwrap());
+            self.filter_label_state.remove(index_to_remove.unwrap());
             self.save_state();
         }
         if index_to_copy.is_some() {
-            let index = index_to_copy.unwrap();
-            let label_filter = self.filter_label_state.data[index].clone();
-            self.filter_label_state.data.insert(index + 1, label_filter);
+            self.filter_label_state.duplicate(index_to_copy.unwrap());
             self.save_state();
         }
+        if let Some((index, in_view)) = view_toggled {
+            self.filter_label_state.set_in_view(index, in_view);
+        }
     }
 
     fn edit_label_ui(&mut self, ui: &mut egui::Ui) {
-        let rule_select_text = &self
-            .filter_label_state
-            .edit_state
-            .filter_type
-            .name()
-            .to_owned();
-
+        let edit_state = &mut self.filter_label_state.edit_state;
         ui.horizontal(|ui| {
             ui.label("Label:");
             ui.add(
-                TextEdit::singleline(&mut self.filter_label_state.edit_state.name.value)
+                TextEdit::singleline(&mut edit_state.name.value)
                     .desired_width(80.0)
-                    .text_color_opt(match self.filter_label_state.edit_state.name.valid {
+                    .text_color_opt(match edit_state.name.valid {
                         true => None,
                         false => Some(Color32::RED),
                     }),
             );
-            ui.color_edit_button_rgb(&mut self.filter_label_state.edit_state.color.value);
+            ui.color_edit_button_rgb(&mut edit_state.color.value);
             ui.label("Rule:");
-            ComboBox::from_id_source("add_label_rule")
-                .selected_text(rule_select_text)
-                .show_ui(ui, |ui| {
-                    for rule in FilterType::iter() {
-                        if ui
-                            .selectable_label(
-                                self.filter_label_state
-                                    .edit_state
-                                    .filter_type
-                                    .is_variant(&rule),
-                                rule.name(),
-                            )
-                            .clicked()
-                        {
-                            self.filter_label_state.edit_state.filter_options =
-                                EditFilterOptionsState::from_filter_type(&rule);
-                            self.filter_label_state.edit_state.filter_type = rule;
-                        }
-                    }
-                });
+            TableGui::rule_combo_box(
+                ui,
+                &mut edit_state.filter_type,
+                &mut edit_state.filter_options,
+            );
         });
-        match (
-            &self.filter_label_state.edit_state.filter_type,
-            &mut self.filter_label_state.edit_state.filter_options,
-        ) {
-            (FilterType::Basic, EditFilterOptionsState::Empty) => {
-                TableGui::basic_filter_edit_line(
-                    ui,
-                    &mut self.filter_label_state.edit_state.id,
-                    &mut self.filter_label_state.edit_state.speed,
-                    &self.highlight_id_state.data,
-                    self.message_loader.known_speeds(),
-                );
-            }
-            (
-                FilterType::StartsWithBytes(_),
-                EditFilterOptionsState::OneStringFieldOneOutputSelection(field, output),
-            ) => {
-                TableGui::basic_filter_edit_line(
-                    ui,
-                    &mut self.filter_label_state.edit_state.id,
-                    &mut self.filter_label_state.edit_state.speed,
-                    &self.highlight_id_state.data,
-                    self.message_loader.known_speeds(),
-                );
-                TableGui::one_string_edit_line(ui, &"Starts with".to_string(), field);
-                TableGui::output_selection_edit_line(ui, output);
-            }
-            _ => {}
-        }
+        TableGui::filter_edit_lines(
+            ui,
+            &mut edit_state.id,
+            &mut edit_state.speed,
+            &edit_state.filter_type,
+            &mut edit_state.filter_options,
+            &self.highlight_id_state.data,
+            self.message_loader.known_speeds(),
+        );
+        TableGui::signal_edit_lines(
+            ui,
+            &mut self.filter_label_state.edit_state.signal,
+            &self.value_table_state.data,
+        );
 
         ui.horizontal(|ui| match &self.filter_label_state.editing_index() {
             Some(_) => {
@@ -308,7 +459,7 @@ impl TableGui {
                 if ui.button("Add").clicked() {
                     match self.filter_label_state.edit_state.validate() {
                         Ok(filter) => {
-                            self.filter_label_state.data.push(filter);
+                            self.filter_label_state.add(filter);
                             self.filter_label_state.edit_state = EditFilterLabelState::default();
                             self.save_state();
                         }
@@ -319,17 +470,268 @@ impl TableGui {
         });
     }
 
+    fn rule_combo_box(
+        ui: &mut egui::Ui,
+        filter_type: &mut FilterType,
+        filter_options: &mut EditFilterOptionsState,
+    ) {
+        ComboBox::from_id_source("add_label_rule")
+            .selected_text(filter_type.name().to_owned())
+            .show_ui(ui, |ui| {
+                for rule in FilterType::iter() {
+                    if ui
+                        .selectable_label(filter_type.is_variant(&rule), rule.name())
+                        .clicked()
+                    {
+                        *filter_options = EditFilterOptionsState::from_filter_type(&rule);
+                        *filter_type = rule;
+                    }
+                }
+            });
+    }
+
+    // Edit lines for the options of a rule, with nested filters of composites indented below it
+    fn filter_edit_lines(
+        ui: &mut egui::Ui,
+        id_field: &mut Field<String>,
+        speed_field: &mut Field<String>,
+        filter_type: &FilterType,
+        filter_options: &mut EditFilterOptionsState,
+        highlight_ids: &[HighlightID],
+        known_speeds: &HashSet<String>,
+    ) {
+        TableGui::basic_filter_edit_line(
+            ui,
+            id_field,
+            speed_field,
+            highlight_ids,
+            known_speeds,
+        );
+        match (filter_type, filter_options) {
+            (
+                FilterType::StartsWithBytes(_),
+                EditFilterOptionsState::OneStringFieldOneOutputSelection(field, output),
+            ) => {
+                TableGui::one_string_edit_line(ui, &"Starts with".to_string(), field);
+                TableGui::output_selection_edit_line(ui, output);
+            }
+            (FilterType::BytePattern(_), EditFilterOptionsState::Pattern(pattern)) => {
+                ui.horizontal(|ui| {
+                    ui.label("Pattern:")
+                        .on_hover_text("Hex bytes, ? for any nibble, value/mask for bits");
+                    TableGui::validated_text_edit(ui, &mut pattern.pattern, 150.0);
+                    ui.checkbox(&mut pattern.anywhere, "Anywhere");
+                    if !pattern.anywhere {
+                        ui.label("Offset:");
+                        TableGui::validated_text_edit(ui, &mut pattern.offset, 30.0);
+                    }
+                });
+                TableGui::output_selection_edit_line(ui, &mut pattern.output);
+            }
+            (FilterType::FieldCompare(_), EditFilterOptionsState::Field(field)) => {
+                ui.horizontal(|ui| {
+                    ComboBox::from_id_source("add_label_field_type")
+                        .selected_text(field.field_type.name())
+                        .show_ui(ui, |ui| {
+                            for field_type in FieldType::iter() {
+                                if ui
+                                    .selectable_label(
+                                        field.field_type == field_type,
+                                        field_type.name(),
+                                    )
+                                    .clicked()
+                                {
+                                    field.field_type = field_type;
+                                }
+                            }
+                        });
+                    ui.label("Byte:");
+                    TableGui::validated_text_edit(ui, &mut field.offset, 30.0);
+                    match field.field_type {
+                        FieldType::Bit => {
+                            ui.label("Bit:").on_hover_text("0 is the least significant bit");
+                            TableGui::validated_text_edit(ui, &mut field.bit, 20.0);
+                        }
+                        _ => {
+                            ui.label("Bytes:");
+                            TableGui::validated_text_edit(ui, &mut field.width, 20.0);
+                            ComboBox::from_id_source("add_label_field_byte_order")
+                                .selected_text(field.byte_order.name())
+                                .show_ui(ui, |ui| {
+                                    for byte_order in ByteOrder::iter() {
+                                        if ui
+                                            .selectable_label(
+                                                field.byte_order == byte_order,
+                                                byte_order.name(),
+                                            )
+                                            .clicked()
+                                        {
+                                            field.byte_order = byte_order;
+                                        }
+                                    }
+                                });
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ComboBox::from_id_source("add_label_comparison")
+                        .selected_text(field.comparison.name())
+                        .show_ui(ui, |ui| {
+                            for comparison in Comparison::iter() {
+                                if ui
+                                    .selectable_label(
+                                        field.comparison == comparison,
+                                        comparison.name(),
+                                    )
+                                    .clicked()
+                                {
+                                    field.comparison = comparison;
+                                }
+                            }
+                        });
+                    match field.comparison {
+                        Comparison::InRange => {
+                            TableGui::validated_text_edit(ui, &mut field.value, 60.0);
+                            ui.label("to");
+                            TableGui::validated_text_edit(ui, &mut field.max, 60.0);
+                        }
+                        Comparison::InSet => {
+                            TableGui::validated_text_edit(ui, &mut field.value, 150.0);
+                        }
+                        _ => TableGui::validated_text_edit(ui, &mut field.value, 60.0),
+                    }
+                });
+            }
+            (FilterType::Expression(_), EditFilterOptionsState::OneStringField(field)) => {
+                ui.horizontal(|ui| {
+                    ui.label("Expression:").on_hover_text(
+                        "e.g. id == 0x18FEF100 && len >= 4 && u16le(data, 2) > 3000 && !ack",
+                    );
+                    TableGui::validated_text_edit(ui, field, 300.0);
+                });
+                if !field.valid {
+                    if let Err(error) = Expression::parse(&field.value) {
+                        ui.colored_label(Color32::RED, error.to_string());
+                    }
+                }
+            }
+            (FilterType::J1939(_), EditFilterOptionsState::J1939(fields)) => {
+                ui.horizontal(|ui| {
+                    ui.label("Priority:");
+                    TableGui::validated_text_edit(ui, &mut fields.priority, 20.0);
+                    ui.label("PGN:");
+                    TableGui::validated_text_edit(ui, &mut fields.pgn, 60.0);
+                    ui.label("SA:");
+                    TableGui::validated_text_edit(ui, &mut fields.source, 40.0);
+                    ui.label("DA:");
+                    TableGui::validated_text_edit(ui, &mut fields.destination, 40.0);
+                });
+            }
+            (filter_type, EditFilterOptionsState::Children(children)) => {
+                // Not always has exactly one nested filter
+                let fixed = matches!(filter_type, FilterType::Not(_));
+                TableGui::nested_filter_edit_lines(
+                    ui,
+                    children,
+                    fixed,
+                    highlight_ids,
+                    known_speeds,
+                );
+                if matches!(filter_type, FilterType::Any(_))
+                    && children.iter().any(|child| child.is_sequential())
+                {
+                    ui.colored_label(
+                        Color32::RED,
+                        "Any of can't nest rules on a frame's place in the capture",
+                    );
+                }
+            }
+            (_, EditFilterOptionsState::Related(within, children)) => {
+                ui.horizontal(|ui| {
+                    ui.label("Within ms:");
+                    TableGui::validated_text_edit(ui, within, 60.0);
+                });
+                ui.label("Of frames matching:");
+                TableGui::nested_filter_edit_lines(ui, children, true, highlight_ids, known_speeds);
+            }
+            (FilterType::Occurrence(_), EditFilterOptionsState::OneStringField(field)) => {
+                ui.horizontal(|ui| {
+                    ui.label("Occurrence:")
+                        .on_hover_text("Counts the frames matching the ID and speed from 1");
+                    TableGui::validated_text_edit(ui, field, 60.0);
+                });
+            }
+            (FilterType::Changed(_), EditFilterOptionsState::OneStringField(mask)) => {
+                ui.horizontal(|ui| {
+                    ui.label("Mask:")
+                        .on_hover_text("Hex bits to compare per byte, empty for the whole payload");
+                    TableGui::validated_text_edit(ui, mask, 150.0);
+                });
+            }
+            (FilterType::TimeWindow(_), EditFilterOptionsState::TwoStringFields(start, end)) => {
+                ui.horizontal(|ui| {
+                    ui.label("From s:");
+                    TableGui::validated_text_edit(ui, start, 80.0);
+                    ui.label("To s:");
+                    TableGui::validated_text_edit(ui, end, 80.0);
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn nested_filter_edit_lines(
+        ui: &mut egui::Ui,
+        children: &mut Vec<EditMessageFilterState>,
+        fixed: bool,
+        highlight_ids: &[HighlightID],
+        known_speeds: &HashSet<String>,
+    ) {
+        let mut index_to_remove = None;
+        for (index, child) in children.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Rule:");
+                    TableGui::rule_combo_box(ui, &mut child.filter_type, &mut child.filter_options);
+                    if !fixed && ui.button("Remove").clicked() {
+                        index_to_remove = Some(index);
+                    }
+                });
+                ui.indent("nested_filter", |ui| {
+                    TableGui::filter_edit_lines(
+                        ui,
+                        &mut child.id,
+                        &mut child.speed,
+                        &child.filter_type,
+                        &mut child.filter_options,
+                        highlight_ids,
+                        known_speeds,
+                    );
+                });
+            });
+        }
+        if let Some(index) = index_to_remove {
+            children.remove(index);
+        }
+        if !fixed && ui.button("Add nested filter").clicked() {
+            children.push(EditMessageFilterState::default());
+        }
+    }
+
     fn basic_filter_edit_line(
         ui: &mut egui::Ui,
         id_field: &mut Field<String>,
         speed_field: &mut Field<String>,
-        highlight_ids: &Vec<HighlightID>,
+        highlight_ids: &[HighlightID],
         known_speeds: &HashSet<String>,
     ) {
-        let current_id_data = id_field.validate_bytes(false);
+        let current_id = IdPattern::parse(&id_field.value);
 
         ui.horizontal(|ui| {
-            ui.label("ID:");
+            ui.label("ID:").on_hover_text(
+                "Hex ID, code/mask such as 18FEF100/1FFFFF00, or range such as 700-77F. \
+                Only IDs as wide as written match.",
+            );
             ComboBox::from_id_source("add_label_id")
                 .selected_text(id_text(id_field, highlight_ids))
                 .show_ui(ui, |ui| {
@@ -342,18 +744,16 @@ impl TableGui {
                     for h_id in highlight_ids {
                         if ui
                             .selectable_label(
-                                match current_id_data {
-                                    Ok(ref id_data) => id_data.clone() == h_id.id().clone(),
-                                    Err(_) => false,
-                                },
+                                current_id.as_ref() == Some(h_id.id()),
                                 h_id.name(),
                             )
                             .clicked()
                         {
-                            id_field.value = hex_to_str(h_id.id());
+                            id_field.value = h_id.id().to_string();
                         }
                     }
                 });
+            TableGui::validated_text_edit(ui, id_field, 120.0);
 
             ui.label("Speed:");
             ComboBox::from_id_source("add_label_speed")
@@ -396,6 +796,216 @@ impl TableGui {
         });
     }
 
+    fn validated_text_edit(ui: &mut egui::Ui, field: &mut Field<String>, width: f32) {
+        ui.add(
+            TextEdit::singleline(&mut field.value)
+                .desired_width(width)
+                .text_color_opt(match field.valid {
+                    true => None,
+                    false => Some(Color32::RED),
+                }),
+        );
+    }
+
+    fn signal_edit_lines(
+        ui: &mut egui::Ui,
+        signal: &mut EditSignalState,
+        value_tables: &Vec<ValueTable>,
+    ) {
+        ui.checkbox(&mut signal.enabled, "Decode signal");
+        if !signal.enabled {
+            return;
+        }
+        TableGui::bit_field_edit_line(ui, signal, 0);
+        ui.horizontal(|ui| {
+            ui.label("Scale:");
+            TableGui::validated_text_edit(ui, &mut signal.scale, 50.0);
+            ui.label("Offset:");
+            TableGui::validated_text_edit(ui, &mut signal.offset, 50.0);
+            ui.label("Unit:");
+            TableGui::validated_text_edit(ui, &mut signal.unit, 40.0);
+            ui.label("Values:");
+            ComboBox::from_id_source("add_label_value_table")
+                .selected_text(signal.value_table.as_deref().unwrap_or("none"))
+                .show_ui(ui, |ui| {
+                    if ui
+                        .selectable_label(signal.value_table.is_none(), "none")
+                        .clicked()
+                    {
+                        signal.value_table = None;
+                    }
+                    for table in value_tables {
+                        if ui
+                            .selectable_label(
+                                signal.value_table.as_ref() == Some(&table.name),
+                                &table.name,
+                            )
+                            .clicked()
+                        {
+                            signal.value_table = Some(table.name.clone());
+                        }
+                    }
+                });
+        });
+        TableGui::multiplexor_edit_lines(ui, &mut signal.multiplexor, 0);
+    }
+
+    // Start bit, length, byte order and type of a signal or selector at the given nesting depth
+    fn bit_field_edit_line(ui: &mut egui::Ui, signal: &mut EditSignalState, depth: usize) {
+        ui.horizontal(|ui| {
+            ui.label("Start bit:");
+            TableGui::validated_text_edit(ui, &mut signal.start_bit, 30.0);
+            ui.label("Length:");
+            TableGui::validated_text_edit(ui, &mut signal.length, 30.0);
+            ComboBox::from_id_source(("add_label_byte_order", depth))
+                .selected_text(signal.byte_order.name())
+                .show_ui(ui, |ui| {
+                    for byte_order in ByteOrder::iter() {
+                        if ui
+                            .selectable_label(signal.byte_order == byte_order, byte_order.name())
+                            .clicked()
+                        {
+                            signal.byte_order = byte_order;
+                        }
+                    }
+                });
+            if depth > 0 {
+                // Selectors are always raw unsigned values
+                return;
+            }
+            ComboBox::from_id_source("add_label_value_type")
+                .selected_text(signal.value_type.name())
+                .show_ui(ui, |ui| {
+                    for value_type in ValueType::iter() {
+                        if ui
+                            .selectable_label(signal.value_type == value_type, value_type.name())
+                            .clicked()
+                        {
+                            signal.value_type = value_type;
+                        }
+                    }
+                });
+        });
+    }
+
+    fn multiplexor_edit_lines(
+        ui: &mut egui::Ui,
+        multiplexor: &mut Option<Box<EditMultiplexorState>>,
+        depth: usize,
+    ) {
+        let mut multiplexed = multiplexor.is_some();
+        let text = match depth {
+            0 => "Multiplexed",
+            _ => "Selector multiplexed",
+        };
+        if ui.checkbox(&mut multiplexed, text).changed() {
+            *multiplexor = match multiplexed {
+                true => Some(Box::default()),
+                false => None,
+            };
+        }
+        if let Some(mux) = multiplexor {
+            ui.indent(("add_label_multiplexor", depth), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Selector:");
+                    TableGui::validated_text_edit(ui, &mut mux.name, 80.0);
+                    ui.label("Values:");
+                    TableGui::validated_text_edit(ui, &mut mux.values, 60.0);
+                });
+                TableGui::bit_field_edit_line(ui, &mut mux.selector, depth + 1);
+                TableGui::multiplexor_edit_lines(ui, &mut mux.selector.multiplexor, depth + 1);
+            });
+        }
+    }
+
+    fn value_tables_ui(&mut self, ui: &mut egui::Ui) {
+        let table = TableBuilder::new(ui)
+            .striped(true)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Size::initial(80.0).at_least(40.0))
+            .column(Size::remainder().at_least(120.0))
+            .column(Size::exact(110.0));
+
+        let mut index_to_remove: Option<usize> = None;
+
+        table
+            .header(20.0, |mut header| {
+                header.col(|ui| {
+                    ui.heading("Name");
+                });
+                header.col(|ui| {
+                    ui.heading("Values");
+                });
+                header.col(|_| {});
+            })
+            .body(|body| {
+                body.rows(
+                    TableGui::BUTTON_HEIGHT,
+                    self.value_table_state.data.len(),
+                    |row_index, mut row| {
+                        let editing_index = *self.value_table_state.editing_index();
+                        if editing_index == Some(row_index) {
+                            row.col(|ui| {
+                                ui.label("editing...");
+                            });
+                            return;
+                        }
+                        let value_table = &self.value_table_state.data[row_index];
+                        row.col(|ui| {
+                            ui.label(&value_table.name);
+                        });
+                        row.col(|ui| {
+                            ui.label(value_table.entries_string());
+                        });
+                        row.col(|ui| {
+                            if editing_index.is_none() {
+                                if ui.button("Edit").clicked() {
+                                    self.value_table_state.edit(row_index);
+                                }
+                                if ui.button("Delete").clicked() {
+                                    index_to_remove = Some(row_index);
+                                }
+                            }
+                        });
+                    },
+                );
+            });
+
+        if let Some(index) = index_to_remove {
+            self.value_table_state.data.remove(index);
+            self.save_state();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            TableGui::validated_text_edit(ui, &mut self.value_table_state.edit_state.name, 80.0);
+            ui.label("Values:");
+            TableGui::validated_text_edit(
+                ui,
+                &mut self.value_table_state.edit_state.entries,
+                200.0,
+            );
+            match &self.value_table_state.editing_index() {
+                Some(_) => {
+                    if ui.button("Save").clicked() {
+                        self.value_table_state.commit();
+                        self.save_state();
+                    }
+                }
+                None => {
+                    if ui.button("Add").clicked() {
+                        if let Ok(table) = self.value_table_state.edit_state.validate() {
+                            self.value_table_state.data.push(table);
+                            self.value_table_state.edit_state = EditValueTableState::default();
+                            self.save_state();
+                        }
+                    }
+                }
+            }
+        });
+        ui.label("Values are written as value=name, separated by semicolons, e.g. 0=Off; 1=Run");
+    }
+
     fn labels_ui(&mut self, ui: &mut egui::Ui) {
         ui.push_id("labels_ui", |ui| {
             StripBuilder::new(ui)
@@ -508,7 +1118,7 @@ impl TableGui {
                                         // No row being edited
                                         let msg = &mut self.highlight_id_state.data[row_index];
                                         row.col(|ui| {
-                                            ui.label(hex_to_str(msg.id()));
+                                            ui.label(msg.id().to_string());
                                         });
                                         row.col(|ui| {
                                             ui.label(msg.name());
@@ -535,7 +1145,7 @@ impl TableGui {
                                         // Row while another is being edited
                                         let msg = &mut self.highlight_id_state.data[row_index];
                                         row.col(|ui| {
-                                            ui.label(hex_to_str(msg.id()));
+                                            ui.label(msg.id().to_string());
                                         });
                                         row.col(|ui| {
                                             ui.label(msg.name());
@@ -572,11 +1182,19 @@ impl TableGui {
     }
 
     fn table_from_messages_ui(&self, ui: &mut egui::Ui, messages: &Vec<Message>) {
-        let table = TableBuilder::new(ui)
+        let show_j1939 = self.j1939_state.show_columns;
+        let mut table = TableBuilder::new(ui)
             .striped(true)
             .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
             .column(Size::initial(70.0).at_least(30.0))
-            .column(Size::initial(80.0).at_least(40.0))
+            .column(Size::initial(80.0).at_least(40.0));
+        if show_j1939 {
+            table = table
+                .column(Size::initial(30.0).at_least(20.0))
+                .column(Size::initial(110.0).at_least(50.0))
+                .columns(Size::initial(45.0).at_least(30.0), 2);
+        }
+        let mut table = table
             .column(Size::initial(160.0).at_least(90.0))
             .column(Size::initial(80.0).at_least(90.0))
             .columns(Size::initial(40.0).at_least(40.0), 2)
@@ -584,6 +1202,18 @@ impl TableGui {
             .column(Size::remainder().at_least(60.0))
             .resizable(false);
 
+        // Rows keep their index in the messages, which protocol results refer to
+        let rows = self
+            .view_filter_state
+            .rows()
+            .or_else(|| self.quick_filter_state.rows());
+        // Keep the message at the top of the table there when the view filter changes the rows
+        if let Some(anchor) = self.view_filter_state.take_scroll_anchor() {
+            let index = rows.map_or(anchor, |rows| rows.partition_point(|row| *row < anchor));
+            table = table.scroll_to_row(index, Some(Align::TOP));
+        }
+        let mut top_row = None;
+
         table
             .header(20.0, |mut header| {
                 header.col(|ui| {
@@ -592,6 +1222,13 @@ impl TableGui {
                 header.col(|ui| {
                     ui.heading("ID");
                 });
+                if show_j1939 {
+                    for heading in ["Prio", "PGN", "SA", "DA"] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                }
                 header.col(|ui| {
                     ui.heading("Data");
                 });
@@ -614,8 +1251,10 @@ impl TableGui {
             .body(|body| {
                 body.rows(
                     TableGui::BUTTON_HEIGHT,
-                    messages.len(),
-                    |row_index, mut row| {
+                    rows.map_or(messages.len(), |rows| rows.len()),
+                    |index, mut row| {
+                        let row_index = rows.map_or(index, |rows| rows[index]);
+                        top_row.get_or_insert(row_index);
                         let msg = &messages[row_index];
                         row.col(|ui| {
                             ui.label(std::format!("{:.3}", msg.timestamp));
@@ -628,6 +1267,30 @@ impl TableGui {
                                 ui.label(hex_to_str(&msg.id));
                             }
                         });
+                        let j1939_id = J1939Id::from_message(msg);
+                        if show_j1939 {
+                            match &j1939_id {
+                                Some(id) => {
+                                    row.col(|ui| {
+                                        ui.label(id.priority.to_string());
+                                    });
+                                    row.col(|ui| {
+                                        ui.label(self.pgn_text(id.pgn));
+                                    });
+                                    row.col(|ui| {
+                                        ui.label(address_text(id.source));
+                                    });
+                                    row.col(|ui| {
+                                        ui.label(address_text(id.destination));
+                                    });
+                                }
+                                None => {
+                                    for _ in 0..4 {
+                                        row.col(|_| {});
+                                    }
+                                }
+                            }
+                        }
                         row.col(|ui| {
                             ui.label(hex_to_str(&msg.data));
                         });
@@ -635,7 +1298,12 @@ impl TableGui {
                             ui.label(bytes_to_string(&msg.data));
                         });
                         row.col(|ui| {
-                            ui.label(hex_to_str(&msg.crc));
+                            let status = check(msg);
+                            let response =
+                                ui.colored_label(crc_color(&status), hex_to_str(&msg.crc));
+                            if let CrcStatus::Mismatch { computed } = status {
+                                response.on_hover_text(format!("Computed {:X}", computed));
+                            }
                         });
                         row.col(|ui| {
                             ui.colored_label(ack_color(msg.ack), msg.ack.to_string());
@@ -644,31 +1312,173 @@ impl TableGui {
                             ui.colored_label(speed_color(&msg.speed), msg.speed.to_string());
                         });
                         row.col(|ui| {
+                            if let Some(pdu) = self.isotp_state.row_pdu(row_index) {
+                                let color = match self.isotp_state.selected_pdu == Some(pdu) {
+                                    true => Color32::LIGHT_BLUE,
+                                    false => Color32::GRAY,
+                                };
+                                colored_label(ui, color, &format!("ISO-TP #{}", pdu));
+                            }
+                            if let Some(index) = self.xcp_state.row_packet(row_index) {
+                                let color = match self.xcp_state.selected_packet == Some(index) {
+                                    true => Color32::LIGHT_BLUE,
+                                    false => Color32::GRAY,
+                                };
+                                let text = self.xcp_state.packets[index].description.clone();
+                                colored_label(ui, color, &text);
+                            }
+                            if let Some(index) = self.obd_state.row_message(row_index) {
+                                let color = match self.obd_state.selected_message == Some(index) {
+                                    true => Color32::LIGHT_BLUE,
+                                    false => Color32::GRAY,
+                                };
+                                let text = format!(
+                                    "OBD #{}: {}",
+                                    index, self.obd_state.messages[index].description
+                                );
+                                colored_label(ui, color, &text);
+                            }
+                            if let Some(transfer) = self.j1939_state.row_transfer(row_index) {
+                                let color =
+                                    match self.j1939_state.selected_transfer == Some(transfer) {
+                                        true => Color32::LIGHT_BLUE,
+                                        false => Color32::GRAY,
+                                    };
+                                colored_label(ui, color, &format!("J1939 TP #{}", transfer));
+                            }
+                            if self.canopen_state.enabled {
+                                // Frames of a decoded SDO transfer are shown as the transfer,
+                                // since block segments can't be told apart on their own
+                                match (
+                                    self.canopen_state.row_transfer(row_index),
+                                    self.canopen_state.row_pdo(row_index),
+                                ) {
+                                    (Some(index), _) => {
+                                        let transfer = &self.canopen_state.transfers[index];
+                                        let selected =
+                                            self.canopen_state.selected_transfer == Some(index);
+                                        let color = match selected {
+                                            true => Color32::LIGHT_BLUE,
+                                            false => Color32::GRAY,
+                                        };
+                                        colored_label(
+                                            ui,
+                                            color,
+                                            &format!(
+                                                "SDO #{}: {} {}",
+                                                index,
+                                                transfer.direction.name(),
+                                                self.canopen_state.object_name(transfer)
+                                            ),
+                                        );
+                                    }
+                                    (None, Some(pdo)) => {
+                                        let values = pdo
+                                            .values
+                                            .iter()
+                                            .map(|v| format!("{}={}", v.name, v.value))
+                                            .collect::<Vec<_>>()
+                                            .join(", ");
+                                        colored_label(
+                                            ui,
+                                            Color32::GRAY,
+                                            &format!("{} {}: {}", pdo.name, pdo.node, values),
+                                        );
+                                    }
+                                    (None, None) => {
+                                        if let Some(frame) = decode_frame(msg) {
+                                            colored_label(ui, Color32::GRAY, &frame.to_string());
+                                        }
+                                    }
+                                }
+                            }
+                            if let Some(definition) =
+                                j1939_id.and_then(|id| self.j1939_state.definition(id.pgn))
+                            {
+                                let values = definition
+                                    .decode(&msg.data)
+                                    .iter()
+                                    .map(|value| value.to_string())
+                                    .collect::<Vec<_>>();
+                                let text = match values.is_empty() {
+                                    true => definition.name.clone(),
+                                    false => format!("{}: {}", definition.name, values.join(", ")),
+                                };
+                                colored_label(ui, Color32::GRAY, &text);
+                            }
+                            if let Some(index) = self.nmea2000_state.row_packet(row_index) {
+                                let packet = &self.nmea2000_state.packets[index];
+                                let color =
+                                    match self.nmea2000_state.selected_packet == Some(index) {
+                                        true => Color32::LIGHT_BLUE,
+                                        false => Color32::GRAY,
+                                    };
+                                let text = match self.nmea2000_state.definition(packet.pgn) {
+                                    Some(definition) => {
+                                        format!("N2K #{}: {}", index, definition.name)
+                                    }
+                                    None => format!("N2K #{}", index),
+                                };
+                                colored_label(ui, color, &text);
+                            } else if let Some(definition) = j1939_id
+                                .and_then(|id| self.nmea2000_state.single_frame_definition(id.pgn))
+                            {
+                                let values = definition
+                                    .decode(&msg.data)
+                                    .iter()
+                                    .map(|value| value.to_string())
+                                    .collect::<Vec<_>>();
+                                let text = match values.is_empty() {
+                                    true => definition.name.clone(),
+                                    false => format!("{}: {}", definition.name, values.join(", ")),
+                                };
+                                colored_label(ui, Color32::GRAY, &text);
+                            }
                             self.filter_label_state
-                                .matching_labels(msg)
+                                .matching_labels(messages, row_index, &self.value_table_state.data)
                                 .iter()
-                                .for_each(|result| match &result.output {
-                                    Some(data) => {
+                                .for_each(|result| match (&result.value, &result.output) {
+                                    (Some(value), _) => {
                                         colored_label(
                                             ui,
-                                            result.label.color32(),
-                                            &(result.label.name.clone()
-                                                + ": "
-                                                + &hex_to_str(&data)),
+                                            signal_value_color(value, result.label.color32()),
+                                            &format!("{}: {}", result.label.name, value),
                                         );
                                     }
-                                    None => {
+                                    (None, Some(data)) => {
+                                        let name = match result.position {
+                                            Some(position) => {
+                                                format!("{} @{}", result.label.name, position)
+                                            }
+                                            None => result.label.name.clone(),
+                                        };
                                         colored_label(
                                             ui,
                                             result.label.color32(),
-                                            &result.label.name,
+                                            &(name + ": " + &hex_to_str(&data)),
                                         );
                                     }
+                                    (None, None) => {
+                                        let text = match &result.changed {
+                                            Some(offsets) => format!(
+                                                "{}: bytes {}",
+                                                result.label.name,
+                                                offsets
+                                                    .iter()
+                                                    .map(|offset| offset.to_string())
+                                                    .collect::<Vec<_>>()
+                                                    .join(", ")
+                                            ),
+                                            None => result.label.name.clone(),
+                                        };
+                                        colored_label(ui, result.label.color32(), &text);
+                                    }
                                 });
                         });
                     },
                 );
             });
+        self.view_filter_state.top_row.set(top_row);
     }
 
     fn messages_ui(&self, ui: &mut egui::Ui) {
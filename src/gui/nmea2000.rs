@@ -0,0 +1,160 @@
+use crate::egui;
+use egui_extras::{Size, TableBuilder};
+
+use crate::util::hex_to_str;
+
+use super::dialog::json_from_dialog;
+use super::j1939::address_text;
+use super::TableGui;
+
+impl TableGui {
+    // PGN number with its name from the NMEA 2000 definition file, if any
+    pub(super) fn nmea2000_pgn_text(&self, pgn: u32) -> String {
+        match self.nmea2000_state.definition(pgn) {
+            Some(definition) => format!("{} ({})", definition.name, pgn),
+            None => pgn.to_string(),
+        }
+    }
+
+    pub(super) fn nmea2000_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Load definitions...").clicked() {
+                match json_from_dialog() {
+                    Ok(Some(path)) => {
+                        self.nmea2000_state.load_definitions(path);
+                        self.save_state();
+                    }
+                    Ok(None) => {} // User cancelled
+                    Err(e) => {
+                        self.nmea2000_state.definitions_error = Some(e.to_string());
+                    }
+                }
+            }
+            match &self.nmea2000_state.definitions_path {
+                Some(path) => {
+                    ui.label(format!(
+                        "{} ({} PGNs)",
+                        path.display(),
+                        self.nmea2000_state.definitions.len()
+                    ));
+                }
+                None => {
+                    ui.label("No definitions loaded");
+                }
+            }
+        });
+        if let Some(error) = &self.nmea2000_state.definitions_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Reassemble").clicked() {
+                if let Some(messages) = self.message_loader.messages() {
+                    self.nmea2000_state.reassemble(messages);
+                }
+            }
+            ui.label(format!(
+                "{} fast packets",
+                self.nmea2000_state.packets.len()
+            ));
+        });
+        ui.separator();
+
+        self.fast_packet_table(ui);
+    }
+
+    fn fast_packet_table(&mut self, ui: &mut egui::Ui) {
+        ui.push_id("fast_packet_table", |ui| {
+            let table = TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(120.0).at_least(40.0))
+                .columns(Size::initial(40.0).at_least(30.0), 4)
+                .column(Size::initial(200.0).at_least(60.0))
+                .column(Size::initial(100.0).at_least(60.0))
+                .column(Size::remainder().at_least(60.0));
+
+            let mut selected_packet = self.nmea2000_state.selected_packet;
+
+            table
+                .header(20.0, |mut header| {
+                    for heading in [
+                        "#", "Time", "PGN", "SA", "DA", "Seq", "Len", "Data", "Errors", "Fields",
+                    ] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(
+                        TableGui::BUTTON_HEIGHT,
+                        self.nmea2000_state.packets.len(),
+                        |row_index, mut row| {
+                            let packet = &self.nmea2000_state.packets[row_index];
+                            row.col(|ui| {
+                                if ui
+                                    .selectable_label(
+                                        selected_packet == Some(row_index),
+                                        row_index.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    selected_packet = Some(row_index);
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.3}", packet.timestamp));
+                            });
+                            row.col(|ui| {
+                                ui.label(self.nmea2000_pgn_text(packet.pgn));
+                            });
+                            row.col(|ui| {
+                                ui.label(address_text(packet.source));
+                            });
+                            row.col(|ui| {
+                                ui.label(address_text(packet.destination));
+                            });
+                            row.col(|ui| {
+                                ui.label(packet.sequence.to_string());
+                            });
+                            row.col(|ui| {
+                                ui.label(match packet.length {
+                                    Some(length) => length.to_string(),
+                                    None => "?".to_string(),
+                                });
+                            });
+                            row.col(|ui| {
+                                ui.label(hex_to_str(&packet.data));
+                            });
+                            row.col(|ui| {
+                                let errors = packet
+                                    .errors
+                                    .iter()
+                                    .map(|e| e.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                ui.colored_label(egui::Color32::RED, errors);
+                            });
+                            row.col(|ui| {
+                                if let Some(definition) = self.nmea2000_state.definition(packet.pgn)
+                                {
+                                    let values = definition
+                                        .decode(&packet.data)
+                                        .iter()
+                                        .map(|value| value.to_string())
+                                        .collect::<Vec<_>>()
+                                        .join(", ");
+                                    ui.label(values);
+                                }
+                            });
+                        },
+                    );
+                });
+
+            self.nmea2000_state.selected_packet = selected_packet;
+        });
+    }
+}
@@ -0,0 +1,94 @@
+use crate::egui;
+use egui_extras::{Size, TableBuilder};
+
+use super::TableGui;
+
+impl TableGui {
+    pub(super) fn obd_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Requests on 7DF and 7E0-7E7, responses on 7E8-7EF");
+        ui.horizontal(|ui| {
+            if ui.button("Decode").clicked() {
+                if let Some(messages) = self.message_loader.messages() {
+                    self.obd_state.decode(messages);
+                }
+            }
+            ui.label(format!("{} OBD-II messages", self.obd_state.messages.len()));
+        });
+        ui.separator();
+
+        self.obd_message_table(ui);
+    }
+
+    fn obd_message_table(&mut self, ui: &mut egui::Ui) {
+        ui.push_id("obd_message_table", |ui| {
+            let table = TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(40.0))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(300.0).at_least(60.0))
+                .column(Size::remainder().at_least(60.0));
+
+            let mut selected_message = self.obd_state.selected_message;
+
+            table
+                .header(20.0, |mut header| {
+                    for heading in ["#", "Time", "ID", "Type", "Mode", "Description", "Errors"] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(
+                        TableGui::BUTTON_HEIGHT,
+                        self.obd_state.messages.len(),
+                        |row_index, mut row| {
+                            let message = &self.obd_state.messages[row_index];
+                            let pdu = &self.obd_state.pdus[message.pdu];
+                            row.col(|ui| {
+                                if ui
+                                    .selectable_label(
+                                        selected_message == Some(row_index),
+                                        row_index.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    selected_message = Some(row_index);
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.3}", pdu.timestamp));
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:03X}", message.id));
+                            });
+                            row.col(|ui| {
+                                ui.label(message.kind.name());
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:02X}", message.mode));
+                            });
+                            row.col(|ui| {
+                                ui.label(&message.description);
+                            });
+                            row.col(|ui| {
+                                let errors = pdu
+                                    .errors
+                                    .iter()
+                                    .map(|e| e.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                ui.colored_label(egui::Color32::RED, errors);
+                            });
+                        },
+                    );
+                });
+
+            self.obd_state.selected_message = selected_message;
+        });
+    }
+}
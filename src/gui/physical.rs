@@ -0,0 +1,105 @@
+use crate::egui::{self, TextEdit};
+use egui_extras::{Size, TableBuilder};
+
+use super::dialog::samples_from_dialog;
+use super::TableGui;
+
+impl TableGui {
+    pub(super) fn physical_ui(&mut self, ui: &mut egui::Ui) {
+        let state = &mut self.physical_state;
+        ui.horizontal(|ui| {
+            if ui.button("Open samples...").clicked() {
+                match samples_from_dialog() {
+                    Ok(Some(path)) => state.path = Some(path),
+                    Ok(None) => {} // User cancelled
+                    Err(e) => state.decode_error = Some(e.to_string()),
+                }
+            }
+            match &state.path {
+                Some(path) => ui.label(path.display().to_string()),
+                None => ui.label("No file selected"),
+            };
+        });
+        ui.horizontal(|ui| {
+            ui.label("Channel:");
+            ui.add(
+                TextEdit::singleline(&mut state.channel)
+                    .desired_width(80.0)
+                    .hint_text("first"),
+            );
+            ui.label("Bitrate:");
+            TableGui::validated_text_edit(ui, &mut state.bitrate, 70.0);
+            ui.label("Sample point %:");
+            TableGui::validated_text_edit(ui, &mut state.sample_point, 40.0);
+            ui.label("SJW %:")
+                .on_hover_text("Synchronization jump width, the most a bit edge resynchronizes");
+            TableGui::validated_text_edit(ui, &mut state.sjw, 40.0);
+            ui.label("Annotation sample rate:")
+                .on_hover_text("Samples per second of sigrok annotation exports");
+            TableGui::validated_text_edit(ui, &mut state.samplerate, 70.0);
+        });
+
+        let mut decode_started = false;
+        ui.horizontal(|ui| {
+            match state.is_decoding() {
+                true => {
+                    ui.label("Decoding...");
+                }
+                false => {
+                    let button = ui.add_enabled(state.path.is_some(), egui::Button::new("Decode"));
+                    if button.clicked() {
+                        decode_started = state.decode().is_ok();
+                    }
+                }
+            }
+            ui.label(format!("{} errors", state.errors.len()));
+        });
+        if let Some(error) = &state.decode_error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        if decode_started {
+            self.save_state();
+        }
+        ui.separator();
+
+        self.physical_error_table(ui);
+    }
+
+    fn physical_error_table(&mut self, ui: &mut egui::Ui) {
+        ui.push_id("physical_error_table", |ui| {
+            let table = TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Size::initial(90.0).at_least(50.0))
+                .column(Size::initial(90.0).at_least(50.0))
+                .column(Size::remainder().at_least(60.0));
+
+            table
+                .header(20.0, |mut header| {
+                    for heading in ["Time", "Frame", "Error"] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(
+                        TableGui::BUTTON_HEIGHT,
+                        self.physical_state.errors.len(),
+                        |row_index, mut row| {
+                            let error = &self.physical_state.errors[row_index];
+                            row.col(|ui| {
+                                ui.label(format!("{:.6}", error.time));
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.6}", error.frame_time));
+                            });
+                            row.col(|ui| {
+                                ui.colored_label(egui::Color32::RED, error.kind.to_string());
+                            });
+                        },
+                    );
+                });
+        });
+    }
+}
@@ -0,0 +1,69 @@
+use crate::egui::{self, Color32, ComboBox, TextEdit};
+use strum::IntoEnumIterator;
+
+use super::state::ViewMode;
+use super::TableGui;
+
+impl TableGui {
+    pub(super) fn quick_filter_ui(&mut self, ui: &mut egui::Ui) {
+        let total = self.message_loader.messages().map_or(0, |messages| messages.len());
+        let state = &mut self.quick_filter_state;
+        ui.horizontal(|ui| {
+            ui.label("Filter:").on_hover_text(
+                "e.g. id == 0x18FEF100 && len >= 4 && u16le(data, 2) > 3000 && !ack",
+            );
+            let response = ui.add(
+                TextEdit::singleline(&mut state.text)
+                    .desired_width(400.0)
+                    .hint_text("expression, Enter to apply")
+                    .text_color_opt(state.error.as_ref().map(|_| Color32::RED)),
+            );
+            if response.changed() {
+                state.error = None;
+            }
+            let entered = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+            let toggled = ui
+                .checkbox(&mut state.changed_only, "Changed only")
+                .on_hover_text("Hide frames equal to the previous frame with the same ID and speed")
+                .changed();
+            ui.add_enabled_ui(state.changed_only, |ui| {
+                ui.label("Mask:")
+                    .on_hover_text("Hex bits to compare per byte, empty for the whole payload");
+                TableGui::validated_text_edit(ui, &mut state.change_mask, 120.0);
+            });
+            if entered || toggled || ui.button("Apply").clicked() {
+                state.apply();
+            }
+            if ui.button("Clear").clicked() {
+                state.clear();
+            }
+            let view = &mut self.view_filter_state;
+            let mut mode = view.mode();
+            ui.label("Labels:")
+                .on_hover_text("Tick labels in the labels table to use them as view filter");
+            ComboBox::from_id_source("view_mode")
+                .selected_text(mode.name())
+                .show_ui(ui, |ui| {
+                    for option in ViewMode::iter() {
+                        ui.selectable_value(&mut mode, option, option.name());
+                    }
+                });
+            if mode != view.mode() {
+                view.set_mode(mode);
+            }
+            let updating = state.is_updating() || view.is_updating();
+            if updating || self.filter_label_state.is_updating() {
+                ui.spinner();
+            }
+            match (&state.error, view.rows().or_else(|| state.rows())) {
+                (Some(error), _) => {
+                    ui.colored_label(Color32::RED, error.to_string());
+                }
+                (None, Some(rows)) => {
+                    ui.label(format!("{} of {} messages", rows.len(), total));
+                }
+                (None, None) => {}
+            }
+        });
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::gui::state::{Field, ParseError};
+use crate::message::Message;
+use crate::protocol::canopen::eds::{read_eds, NodeEds, ObjectDictionary};
+use crate::protocol::canopen::pdo::{decode_pdos, DecodedPdo};
+use crate::protocol::canopen::{decode_sdo, SdoTransfer};
+
+#[derive(Default)]
+pub(crate) struct CanOpenState {
+    pub(crate) enabled: bool,
+    pub(crate) eds_files: Vec<NodeEds>,
+    // Object dictionary of each node with a readable EDS/DCF
+    dictionaries: HashMap<u8, ObjectDictionary>,
+    pub(crate) eds_errors: HashMap<u8, String>,
+    pub(crate) edit_node: Field<String>,
+    pub(crate) transfers: Vec<SdoTransfer>,
+    // SDO transfer index for each message row that is part of a transfer
+    row_transfers: HashMap<usize, usize>,
+    row_pdos: HashMap<usize, DecodedPdo>,
+    pub(crate) selected_transfer: Option<usize>,
+    pub(crate) window_open: bool,
+}
+
+impl CanOpenState {
+    pub(crate) fn from_data(enabled: bool, eds_files: Vec<NodeEds>) -> Self {
+        let mut state = Self {
+            enabled,
+            ..Default::default()
+        };
+        for eds in eds_files {
+            state.load_eds(eds);
+        }
+        state
+    }
+
+    fn load_eds(&mut self, eds: NodeEds) {
+        match read_eds(&eds.path, eds.node) {
+            Ok(dictionary) => {
+                self.dictionaries.insert(eds.node, dictionary);
+                self.eds_errors.remove(&eds.node);
+            }
+            Err(e) => {
+                self.dictionaries.remove(&eds.node);
+                self.eds_errors.insert(eds.node, e.to_string());
+            }
+        }
+        self.eds_files.retain(|existing| existing.node != eds.node);
+        self.eds_files.push(eds);
+        self.eds_files.sort_by_key(|eds| eds.node);
+    }
+
+    // Assign a file to the node in the edit field, or to the node-ID in a DCF if that is empty
+    pub(crate) fn add_eds(&mut self, path: PathBuf) -> Result<(), ParseError> {
+        let node = match self.edit_node.validate_optional_number(127)? {
+            Some(node) => node as u8,
+            None => match read_eds(&path, 0).ok().and_then(|d| d.node_id) {
+                Some(node) => node,
+                None => {
+                    self.edit_node.valid = false;
+                    return Err(ParseError {});
+                }
+            },
+        };
+        self.load_eds(NodeEds { node, path });
+        self.edit_node = Field::default();
+        Ok(())
+    }
+
+    pub(crate) fn remove_eds(&mut self, index: usize) {
+        let eds = self.eds_files.remove(index);
+        self.dictionaries.remove(&eds.node);
+        self.eds_errors.remove(&eds.node);
+    }
+
+    pub(crate) fn decode(&mut self, messages: &[Message]) {
+        self.transfers = decode_sdo(messages);
+        self.row_transfers = self
+            .transfers
+            .iter()
+            .enumerate()
+            .flat_map(|(index, transfer)| transfer.rows.iter().map(move |row| (*row, index)))
+            .collect();
+        self.row_pdos = decode_pdos(messages, &self.transfers, &self.dictionaries);
+        self.selected_transfer = None;
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        if !self.transfers.is_empty() || !self.row_pdos.is_empty() {
+            self.transfers.clear();
+            self.row_transfers.clear();
+            self.row_pdos.clear();
+            self.selected_transfer = None;
+        }
+    }
+
+    pub(crate) fn row_transfer(&self, row: usize) -> Option<usize> {
+        self.row_transfers.get(&row).copied()
+    }
+
+    pub(crate) fn row_pdo(&self, row: usize) -> Option<&DecodedPdo> {
+        self.row_pdos.get(&row)
+    }
+
+    // Object name from the node's dictionary, or the index and subindex
+    pub(crate) fn object_name(&self, transfer: &SdoTransfer) -> String {
+        self.dictionaries
+            .get(&transfer.node)
+            .and_then(|d| d.name(transfer.index, transfer.subindex))
+            .unwrap_or_else(|| format!("{:04X}sub{}", transfer.index, transfer.subindex))
+    }
+
+    pub(crate) fn transfer_value(&self, transfer: &SdoTransfer) -> String {
+        match self
+            .dictionaries
+            .get(&transfer.node)
+            .and_then(|d| d.data_type(transfer.index, transfer.subindex))
+        {
+            Some(data_type) if !transfer.data.is_empty() => data_type.format(&transfer.data),
+            _ => transfer.value_string(),
+        }
+    }
+}
@@ -1,21 +1,36 @@
+use std::sync::Arc;
+
+use crate::expression::Expression;
 use crate::filter::{
-    FilterResult, FilterType, LabelFilter, MessageFilter, OutputSelection, StartsWithBytes,
+    parse_pattern, parse_value, pattern_string, BytePattern, ChangedBytes, Comparison,
+    FieldCompare, FieldType, FieldValue, FilterResult, FilterType, J1939Fields, LabelFilter,
+    MessageFilter, Occurrence, OutputSelection, PatternAnchor, Related, StartsWithBytes,
+    TimeWindow,
 };
-use crate::gui::state::{Field, ParseError};
+use crate::gui::state::label_index::{LabelIndex, LabelRows};
+use crate::gui::state::{EditSignalState, Field, ParseError};
 use crate::label::Label;
 use crate::message::Message;
-use crate::util::{empty_str_as_none, empty_vec_as_none, hex_to_str};
+use crate::signal::ByteOrder;
+use crate::util::{empty_str_as_none, hex_to_str};
+use crate::value_table::ValueTable;
 
 #[derive(Default)]
 pub(crate) struct FilterLabelState {
     pub(crate) data: Vec<LabelFilter>,
     editing_index: Option<usize>,
     pub(crate) edit_state: EditFilterLabelState,
+    index: LabelIndex,
+    // Labels selected for the view filter, by label index
+    in_view: Vec<bool>,
+    view_changed: bool,
 }
 
 impl FilterLabelState {
     pub(crate) fn from_data(data: Vec<LabelFilter>) -> Self {
         Self {
+            index: LabelIndex::new(data.len()),
+            in_view: vec![false; data.len()],
             data,
             ..Default::default()
         }
@@ -36,20 +51,92 @@ impl FilterLabelState {
                 self.data[index] = highlight_id;
                 self.editing_index = None;
                 self.edit_state = EditFilterLabelState::default();
+                self.index.invalidate(index);
             }
         }
     }
 
-    pub(crate) fn matching_labels(&self, message: &Message) -> Vec<FilterResult> {
+    pub(crate) fn add(&mut self, label_filter: LabelFilter) {
+        self.data.push(label_filter);
+        self.in_view.push(false);
+        self.index.insert(self.data.len() - 1, None);
+    }
+
+    // The label may have been selected for the view filter, whose rows then change even if the
+    // other labels' rows don't
+    pub(crate) fn remove(&mut self, index: usize) {
+        self.data.remove(index);
+        self.in_view.remove(index);
+        self.index.remove(index);
+        self.view_changed = true;
+    }
+
+    pub(crate) fn duplicate(&mut self, index: usize) {
+        self.data.insert(index + 1, self.data[index].clone());
+        self.in_view.insert(index + 1, self.in_view[index]);
+        self.index.insert(index + 1, Some(index));
+        self.view_changed = true;
+    }
+
+    pub(crate) fn in_view(&self, index: usize) -> bool {
+        self.in_view[index]
+    }
+
+    pub(crate) fn set_in_view(&mut self, index: usize, in_view: bool) {
+        self.in_view[index] = in_view;
+        self.view_changed = true;
+    }
+
+    // Rows of the labels selected for the view filter, once they're all up to date
+    pub(crate) fn view_rows(&self) -> Option<Vec<Arc<LabelRows>>> {
+        (0..self.data.len())
+            .filter(|index| self.in_view[*index])
+            .map(|index| self.index.current_rows(index))
+            .collect()
+    }
+
+    pub(crate) fn is_updating(&self) -> bool {
+        self.index.is_updating()
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        self.index.clear();
+    }
+
+    // Evaluate the labels over the capture after it or the labels changed. Returns whether the
+    // matching rows or the selection for the view filter changed.
+    pub(crate) fn update_rows(&mut self, messages: &Arc<Vec<Message>>) -> bool {
+        let view_changed = std::mem::take(&mut self.view_changed);
+        let updated = self.index.update(messages, &self.data);
+        updated || view_changed
+    }
+
+    pub(crate) fn matching_labels(
+        &self,
+        messages: &[Message],
+        row: usize,
+        value_tables: &[ValueTable],
+    ) -> Vec<FilterResult<'_>> {
+        let message = &messages[row];
         self.data
             .iter()
-            .filter(|lf| lf.filter.filter(message))
-            .map(|lf| FilterResult {
-                label: lf.label.clone(),
-                output: lf.filter.output_data(message),
+            .enumerate()
+            .filter_map(|(index, lf)| {
+                let rows = self.index.rows(index).filter(|rows| rows.contains(row))?;
+                let mut result = lf.matched_result(message, rows.output(row), value_tables);
+                if let FilterType::Changed(changed) = lf.filter.filter_type() {
+                    let previous = self.index.previous_row(row).map(|p| &messages[p]);
+                    result.changed = Some(changed.changed(previous, message));
+                }
+                Some(result)
             })
             .collect()
     }
+
+    // Whether evaluating the label over the capture failed
+    pub(crate) fn failed(&self, index: usize) -> bool {
+        self.index.failed(index)
+    }
 }
 
 pub(crate) struct EditFilterLabelState {
@@ -59,6 +146,7 @@ pub(crate) struct EditFilterLabelState {
     pub filter_options: EditFilterOptionsState,
     pub name: Field<String>,
     pub color: Field<[f32; 3]>,
+    pub signal: EditSignalState,
 }
 
 impl Default for EditFilterLabelState {
@@ -73,6 +161,7 @@ impl Default for EditFilterLabelState {
                 value: [255.0, 255.0, 255.0],
                 valid: true,
             },
+            signal: EditSignalState::default(),
         }
     }
 }
@@ -80,7 +169,7 @@ impl Default for EditFilterLabelState {
 impl EditFilterLabelState {
     pub(crate) fn from_data(data: &LabelFilter) -> Self {
         Self {
-            id: Field::with_value(data.filter.id().map_or("".to_string(), |id| hex_to_str(id))),
+            id: Field::with_value(data.filter.id().map_or("".to_string(), |id| id.to_string())),
             speed: Field::with_value(
                 data.filter
                     .speed()
@@ -90,50 +179,392 @@ impl EditFilterLabelState {
             filter_options: EditFilterOptionsState::from_filter_type(data.filter.filter_type()),
             name: Field::with_value(data.label.name.clone()),
             color: Field::with_value(data.label.color),
+            signal: EditSignalState::from_option(data.signal.as_ref()),
         }
     }
 
     pub(crate) fn validate(&mut self) -> Result<LabelFilter, ParseError> {
-        let id = empty_vec_as_none(self.id.validate_bytes(true)?);
+        let id = self.id.validate_id_pattern()?;
         let speed = empty_str_as_none(self.speed.validate_string(true)?);
         let name = self.name.validate_string(false)?;
         let color = self.color.value;
-        let filter_type = match (&self.filter_type, &mut self.filter_options) {
-            (FilterType::Basic, EditFilterOptionsState::Empty) => FilterType::Basic,
-            (
-                FilterType::StartsWithBytes(_),
-                EditFilterOptionsState::OneStringFieldOneOutputSelection(field, output),
-            ) => FilterType::StartsWithBytes(StartsWithBytes {
-                bytes: field.validate_bytes(false)?,
-                output: output.clone(),
-            }),
-            _ => return Err(ParseError {}),
-        };
+        let signal = self.signal.validate()?;
+        let filter_type = validate_filter_type(&self.filter_type, &mut self.filter_options)?;
         Ok(LabelFilter {
             label: Label { name, color },
             filter: MessageFilter::new(id, speed, filter_type),
+            signal,
         })
     }
 }
 
+fn validate_filter_type(
+    filter_type: &FilterType,
+    options: &mut EditFilterOptionsState,
+) -> Result<FilterType, ParseError> {
+    let filter_type = match (filter_type, options) {
+        (FilterType::Basic, EditFilterOptionsState::Empty) => FilterType::Basic,
+        (
+            FilterType::StartsWithBytes(_),
+            EditFilterOptionsState::OneStringFieldOneOutputSelection(field, output),
+        ) => FilterType::StartsWithBytes(StartsWithBytes {
+            bytes: field.validate_bytes(false)?,
+            output: output.clone(),
+        }),
+        (FilterType::J1939(_), EditFilterOptionsState::J1939(fields)) => {
+            FilterType::J1939(fields.validate()?)
+        }
+        (FilterType::CrcMismatch, EditFilterOptionsState::Empty) => FilterType::CrcMismatch,
+        (FilterType::BytePattern(_), EditFilterOptionsState::Pattern(pattern)) => {
+            FilterType::BytePattern(pattern.validate()?)
+        }
+        (FilterType::FieldCompare(_), EditFilterOptionsState::Field(field)) => {
+            FilterType::FieldCompare(field.validate()?)
+        }
+        (FilterType::Expression(_), EditFilterOptionsState::OneStringField(field)) => {
+            let expression = Expression::parse(&field.value);
+            field.valid = expression.is_ok();
+            FilterType::Expression(expression.map_err(|_| ParseError {})?)
+        }
+        (FilterType::After(_), EditFilterOptionsState::Related(within, children)) => {
+            FilterType::After(validate_related(within, children)?)
+        }
+        (FilterType::NotFollowedBy(_), EditFilterOptionsState::Related(within, children)) => {
+            FilterType::NotFollowedBy(validate_related(within, children)?)
+        }
+        (FilterType::Occurrence(_), EditFilterOptionsState::OneStringField(field)) => {
+            let number = validate_bounded(field, |number: usize| number > 0)?;
+            FilterType::Occurrence(Occurrence { number })
+        }
+        (FilterType::TimeWindow(_), EditFilterOptionsState::TwoStringFields(start, end)) => {
+            let window = TimeWindow {
+                start: validate_constant(start)?,
+                end: validate_constant(end)?,
+            };
+            if window.start > window.end {
+                end.valid = false;
+                return Err(ParseError {});
+            }
+            FilterType::TimeWindow(window)
+        }
+        (FilterType::Changed(_), EditFilterOptionsState::OneStringField(mask)) => {
+            FilterType::Changed(ChangedBytes {
+                mask: mask.validate_bytes(true)?,
+            })
+        }
+        (FilterType::All(_), EditFilterOptionsState::Children(children)) => {
+            FilterType::All(validate_children(children)?)
+        }
+        (FilterType::Any(_), EditFilterOptionsState::Children(children)) => {
+            // Which nested filter matched a frame is needed for its output, so they have to be
+            // decidable from the frame alone
+            let filters = validate_children(children)?;
+            if filters.iter().any(|filter| filter.is_sequential()) {
+                return Err(ParseError {});
+            }
+            FilterType::Any(filters)
+        }
+        (FilterType::Not(_), EditFilterOptionsState::Children(children)) => {
+            match validate_children(children)?.pop() {
+                Some(filter) if children.len() == 1 => FilterType::Not(Box::new(filter)),
+                _ => return Err(ParseError {}),
+            }
+        }
+        _ => return Err(ParseError {}),
+    };
+    Ok(filter_type)
+}
+
+fn validate_related(
+    within: &mut Field<String>,
+    children: &mut [EditMessageFilterState],
+) -> Result<Related, ParseError> {
+    let within_ms = validate_bounded(within, |within: f64| within >= 0.0);
+    let filter = match validate_children(children)?.pop() {
+        Some(filter) if children.len() == 1 => filter,
+        _ => return Err(ParseError {}),
+    };
+    Ok(Related {
+        filter: Box::new(filter),
+        within_ms: within_ms?,
+    })
+}
+
+fn validate_children(
+    children: &mut [EditMessageFilterState],
+) -> Result<Vec<MessageFilter>, ParseError> {
+    // Validate every child so that all invalid fields get marked
+    let filters: Vec<_> = children.iter_mut().map(|child| child.validate()).collect();
+    match filters.is_empty() {
+        true => Err(ParseError {}),
+        false => filters.into_iter().collect(),
+    }
+}
+
 #[derive(Default)]
 pub(crate) enum EditFilterOptionsState {
     #[default]
     Empty,
     OneStringField(Field<String>),
     OneStringFieldOneOutputSelection(Field<String>, OutputSelection),
+    J1939(EditJ1939FieldsState),
+    Pattern(EditBytePatternState),
+    Field(EditFieldCompareState),
+    TwoStringFields(Field<String>, Field<String>),
+    // The time in milliseconds and the single filter of the related frames
+    Related(Field<String>, Vec<EditMessageFilterState>),
+    // Nested filters of a composite
+    Children(Vec<EditMessageFilterState>),
 }
 
 impl EditFilterOptionsState {
     pub(crate) fn from_filter_type(filter_type: &FilterType) -> Self {
         match filter_type {
-            FilterType::Basic => Self::Empty,
+            FilterType::Basic | FilterType::CrcMismatch => Self::Empty,
             FilterType::StartsWithBytes(StartsWithBytes { bytes, output }) => {
                 Self::OneStringFieldOneOutputSelection(
                     Field::with_value(hex_to_str(bytes)),
                     output.clone(),
                 )
             }
+            FilterType::J1939(fields) => Self::J1939(EditJ1939FieldsState::from_data(fields)),
+            FilterType::BytePattern(pattern) => {
+                Self::Pattern(EditBytePatternState::from_data(pattern))
+            }
+            FilterType::FieldCompare(field) => {
+                Self::Field(EditFieldCompareState::from_data(field))
+            }
+            FilterType::Expression(expression) => {
+                Self::OneStringField(Field::with_value(expression.source().to_string()))
+            }
+            FilterType::After(related) | FilterType::NotFollowedBy(related) => Self::Related(
+                Field::with_value(related.within_ms.to_string()),
+                vec![EditMessageFilterState::from_data(&related.filter)],
+            ),
+            FilterType::Occurrence(occurrence) => {
+                Self::OneStringField(Field::with_value(occurrence.number.to_string()))
+            }
+            FilterType::Changed(changed) => {
+                Self::OneStringField(Field::with_value(hex_to_str(&changed.mask)))
+            }
+            FilterType::TimeWindow(window) => Self::TwoStringFields(
+                Field::with_value(window.start.to_string()),
+                Field::with_value(window.end.to_string()),
+            ),
+            FilterType::All(filters) | FilterType::Any(filters) => {
+                Self::Children(filters.iter().map(EditMessageFilterState::from_data).collect())
+            }
+            FilterType::Not(filter) => {
+                Self::Children(vec![EditMessageFilterState::from_data(filter)])
+            }
         }
     }
 }
+
+// A filter nested in a composite, which has the same ID, speed and rule as a label's filter
+#[derive(Default)]
+pub(crate) struct EditMessageFilterState {
+    pub id: Field<String>,
+    pub speed: Field<String>,
+    pub filter_type: FilterType,
+    pub filter_options: EditFilterOptionsState,
+}
+
+impl EditMessageFilterState {
+    fn from_data(data: &MessageFilter) -> Self {
+        Self {
+            id: Field::with_value(data.id().map_or("".to_string(), |id| id.to_string())),
+            speed: Field::with_value(data.speed().map_or("".to_string(), |s| s.to_string())),
+            filter_type: data.filter_type().clone(),
+            filter_options: EditFilterOptionsState::from_filter_type(data.filter_type()),
+        }
+    }
+
+    // Whether the rule or one nested in it depends on other frames, as for MessageFilter
+    pub(crate) fn is_sequential(&self) -> bool {
+        match (&self.filter_type, &self.filter_options) {
+            (FilterType::After(_) | FilterType::NotFollowedBy(_), _) => true,
+            (FilterType::Occurrence(_) | FilterType::Changed(_), _) => true,
+            (_, EditFilterOptionsState::Children(children)) => {
+                children.iter().any(|child| child.is_sequential())
+            }
+            _ => false,
+        }
+    }
+
+    fn validate(&mut self) -> Result<MessageFilter, ParseError> {
+        let id = self.id.validate_id_pattern()?;
+        let speed = empty_str_as_none(self.speed.validate_string(true)?);
+        let filter_type = validate_filter_type(&self.filter_type, &mut self.filter_options)?;
+        Ok(MessageFilter::new(id, speed, filter_type))
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct EditJ1939FieldsState {
+    pub priority: Field<String>,
+    pub pgn: Field<String>,
+    pub source: Field<String>,
+    pub destination: Field<String>,
+}
+
+impl EditJ1939FieldsState {
+    fn from_data(data: &J1939Fields) -> Self {
+        let address =
+            |address: Option<u8>| address.map_or("".to_string(), |a| format!("0x{:02X}", a));
+        Self {
+            priority: Field::with_value(data.priority.map_or("".to_string(), |p| p.to_string())),
+            pgn: Field::with_value(data.pgn.map_or("".to_string(), |p| p.to_string())),
+            source: Field::with_value(address(data.source)),
+            destination: Field::with_value(address(data.destination)),
+        }
+    }
+
+    fn validate(&mut self) -> Result<J1939Fields, ParseError> {
+        Ok(J1939Fields {
+            priority: self.priority.validate_optional_number(7)?.map(|p| p as u8),
+            pgn: self.pgn.validate_optional_number(0x3FFFF)?.map(|p| p as u32),
+            source: self.source.validate_optional_number(0xFF)?.map(|a| a as u8),
+            destination: self
+                .destination
+                .validate_optional_number(0xFF)?
+                .map(|a| a as u8),
+        })
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct EditBytePatternState {
+    pub pattern: Field<String>,
+    pub anywhere: bool,
+    pub offset: Field<String>,
+    pub output: OutputSelection,
+}
+
+impl EditBytePatternState {
+    fn from_data(data: &BytePattern) -> Self {
+        let (anywhere, offset) = match data.anchor {
+            PatternAnchor::Offset(offset) => (false, offset),
+            PatternAnchor::Anywhere => (true, 0),
+        };
+        Self {
+            pattern: Field::with_value(pattern_string(&data.pattern)),
+            anywhere,
+            offset: Field::with_value(offset.to_string()),
+            output: data.output.clone(),
+        }
+    }
+
+    fn validate(&mut self) -> Result<BytePattern, ParseError> {
+        let pattern = parse_pattern(&self.pattern.value);
+        self.pattern.valid = pattern.is_some();
+        let anchor = match self.anywhere {
+            true => PatternAnchor::Anywhere,
+            false => PatternAnchor::Offset(self.offset.validate_number()?),
+        };
+        Ok(BytePattern {
+            pattern: pattern.ok_or(ParseError {})?,
+            anchor,
+            output: self.output.clone(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct EditFieldCompareState {
+    pub field_type: FieldType,
+    pub offset: Field<String>,
+    pub width: Field<String>,
+    pub bit: Field<String>,
+    pub byte_order: ByteOrder,
+    pub comparison: Comparison,
+    // The constant, the minimum of a range, or the comma separated members of a set
+    pub value: Field<String>,
+    pub max: Field<String>,
+}
+
+impl EditFieldCompareState {
+    fn from_data(data: &FieldCompare) -> Self {
+        let (value, max) = match (&data.comparison, data.values.as_slice()) {
+            (Comparison::InRange, [min, max]) => (min.to_string(), max.to_string()),
+            (_, values) => (
+                values
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                String::new(),
+            ),
+        };
+        Self {
+            field_type: data.field_type.clone(),
+            offset: Field::with_value(data.offset.to_string()),
+            width: Field::with_value(data.width.to_string()),
+            bit: Field::with_value(data.bit.to_string()),
+            byte_order: data.byte_order.clone(),
+            comparison: data.comparison.clone(),
+            value: Field::with_value(value),
+            max: Field::with_value(max),
+        }
+    }
+
+    fn validate(&mut self) -> Result<FieldCompare, ParseError> {
+        let offset = self.offset.validate_number()?;
+        let (width, bit) = match self.field_type {
+            FieldType::Bit => (1, validate_bounded(&mut self.bit, |bit: u8| bit < 8)?),
+            FieldType::Float => (validate_bounded(&mut self.width, |w| w == 4 || w == 8)?, 0),
+            _ => (validate_bounded(&mut self.width, |w| (1..=8).contains(&w))?, 0),
+        };
+        let values = match self.comparison {
+            Comparison::InRange => {
+                let min = validate_field_value(&mut self.value)?;
+                let max = validate_field_value(&mut self.max)?;
+                if min > max {
+                    self.max.valid = false;
+                    return Err(ParseError {});
+                }
+                vec![min, max]
+            }
+            Comparison::InSet => {
+                let values: Option<Vec<FieldValue>> =
+                    self.value.value.split(',').map(FieldValue::parse).collect();
+                self.value.valid = values.is_some();
+                values.ok_or(ParseError {})?
+            }
+            _ => vec![validate_field_value(&mut self.value)?],
+        };
+        Ok(FieldCompare {
+            field_type: self.field_type.clone(),
+            offset,
+            width,
+            bit,
+            byte_order: self.byte_order.clone(),
+            comparison: self.comparison.clone(),
+            values,
+        })
+    }
+}
+
+fn validate_bounded<N: std::str::FromStr + Copy>(
+    field: &mut Field<String>,
+    allowed: impl Fn(N) -> bool,
+) -> Result<N, ParseError> {
+    let result = field.as_number().and_then(|value| match allowed(value) {
+        true => Ok(value),
+        false => Err(ParseError {}),
+    });
+    field.valid = result.is_ok();
+    result
+}
+
+fn validate_constant(field: &mut Field<String>) -> Result<f64, ParseError> {
+    let value = parse_value(&field.value);
+    field.valid = value.is_some();
+    value.ok_or(ParseError {})
+}
+
+fn validate_field_value(field: &mut Field<String>) -> Result<FieldValue, ParseError> {
+    let value = FieldValue::parse(&field.value);
+    field.valid = value.is_some();
+    value.ok_or(ParseError {})
+}
@@ -1,6 +1,6 @@
 use eframe::egui::Color32;
 
-use crate::{message::HighlightID, util::{hex_to_str, remove_whitespace}};
+use crate::message::{HighlightID, IdPattern};
 
 #[derive(Default)]
 pub struct HighlightIDState {
@@ -68,7 +68,7 @@ impl HighlightIDEditState {
 
     pub(crate) fn from_data(data: &HighlightID) -> Self {
         Self {
-            id: hex_to_str(data.id()),
+            id: data.id().to_string(),
             id_valid: true,
             name: data.name().clone(),
             name_valid: true,
@@ -84,26 +84,11 @@ impl HighlightIDEditState {
         Some(HighlightID::new(id, name, color))
     }
 
-    pub fn validate_id(&mut self) -> Option<Vec<u8>> {
-        let mut id = self.id.clone();
-        remove_whitespace(&mut id);
-
-        if id.len() == 0 {
-            self.id_valid = false;
-            return None;
-        }
-        if id.len() % 2 != 0 {
-            self.id.insert(0, '0');
-            return self.validate_id();
-        }
-
-        match hex::decode(&id) {
-            Ok(id) => Some(id),
-            Err(_) => {
-                self.id_valid = false;
-                return None;
-            }
-        }
+    // Hex ID bytes, code/mask such as 18FEF100/1FFFFF00, or a range such as 700-77F
+    pub fn validate_id(&mut self) -> Option<IdPattern> {
+        let id = IdPattern::parse(&self.id);
+        self.id_valid = id.is_some();
+        id
     }
 
     pub fn validate_name(&mut self) -> Option<String> {
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use crate::gui::state::{Field, ParseError};
+use crate::message::Message;
+use crate::protocol::firmware::{self, FirmwareRegion};
+use crate::protocol::isotp::{reassemble, Addressing, IsoTpChannel, IsoTpPdu};
+use crate::protocol::uds::{self, DidName, UdsMessage};
+
+#[derive(Default)]
+pub(crate) struct IsoTpState {
+    pub(crate) channels: Vec<IsoTpChannel>,
+    pub(crate) edit_state: EditIsoTpChannelState,
+    pub(crate) did_names: Vec<DidName>,
+    pub(crate) edit_did_state: EditDidNameState,
+    pub(crate) pdus: Vec<IsoTpPdu>,
+    pub(crate) uds: Vec<UdsMessage>,
+    pub(crate) firmware: Vec<FirmwareRegion>,
+    // PDU index for each message row that is part of a PDU
+    row_pdus: HashMap<usize, usize>,
+    // UDS message index for each PDU that decodes as UDS
+    pdu_uds: HashMap<usize, usize>,
+    pub(crate) selected_pdu: Option<usize>,
+    pub(crate) window_open: bool,
+}
+
+impl IsoTpState {
+    pub(crate) fn from_data(channels: Vec<IsoTpChannel>, did_names: Vec<DidName>) -> Self {
+        Self {
+            channels,
+            did_names,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn reassemble(&mut self, messages: &[Message]) {
+        self.pdus = reassemble(messages, &self.channels);
+        self.row_pdus = self
+            .pdus
+            .iter()
+            .enumerate()
+            .flat_map(|(index, pdu)| pdu.rows.iter().map(move |row| (*row, index)))
+            .collect();
+        self.selected_pdu = None;
+        self.decode_uds();
+    }
+
+    pub(crate) fn decode_uds(&mut self) {
+        self.uds = uds::decode(&self.pdus, &self.did_names);
+        self.pdu_uds = self
+            .uds
+            .iter()
+            .enumerate()
+            .map(|(index, message)| (message.pdu, index))
+            .collect();
+        self.firmware = firmware::extract(&self.pdus, &self.uds);
+    }
+
+    pub(crate) fn pdu_uds(&self, pdu: usize) -> Option<&UdsMessage> {
+        self.pdu_uds.get(&pdu).map(|index| &self.uds[*index])
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        if !self.pdus.is_empty() {
+            self.pdus.clear();
+            self.uds.clear();
+            self.firmware.clear();
+            self.row_pdus.clear();
+            self.pdu_uds.clear();
+            self.selected_pdu = None;
+        }
+    }
+
+    pub(crate) fn row_pdu(&self, row: usize) -> Option<usize> {
+        self.row_pdus.get(&row).copied()
+    }
+}
+
+pub(crate) struct EditIsoTpChannelState {
+    pub name: Field<String>,
+    pub tx_id: Field<String>,
+    pub rx_id: Field<String>,
+    pub addressing: Addressing,
+    pub tx_address: Field<String>,
+    pub rx_address: Field<String>,
+    pub timeout_ms: Field<String>,
+}
+
+impl Default for EditIsoTpChannelState {
+    fn default() -> Self {
+        Self {
+            name: Field::default(),
+            tx_id: Field::default(),
+            rx_id: Field::default(),
+            addressing: Addressing::default(),
+            tx_address: Field::default(),
+            rx_address: Field::default(),
+            timeout_ms: Field::with_value("1000".to_string()),
+        }
+    }
+}
+
+fn validate_address(field: &mut Field<String>) -> Result<u8, ParseError> {
+    let bytes = field.validate_bytes(true)?;
+    match bytes.as_slice() {
+        [] => Ok(0),
+        [address] => Ok(*address),
+        _ => {
+            field.valid = false;
+            Err(ParseError {})
+        }
+    }
+}
+
+impl EditIsoTpChannelState {
+    pub(crate) fn validate(&mut self) -> Result<IsoTpChannel, ParseError> {
+        Ok(IsoTpChannel {
+            name: self.name.validate_string(false)?,
+            tx_id: self.tx_id.validate_bytes(false)?,
+            rx_id: self.rx_id.validate_bytes(false)?,
+            addressing: self.addressing.clone(),
+            tx_address: validate_address(&mut self.tx_address)?,
+            rx_address: validate_address(&mut self.rx_address)?,
+            timeout_ms: self.timeout_ms.validate_number()?,
+        })
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct EditDidNameState {
+    pub did: Field<String>,
+    pub name: Field<String>,
+}
+
+impl EditDidNameState {
+    pub(crate) fn validate(&mut self) -> Result<DidName, ParseError> {
+        let did = self.did.validate_bytes(false)?;
+        let did = match did.as_slice() {
+            [low] => *low as u16,
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            _ => {
+                self.did.valid = false;
+                return Err(ParseError {});
+            }
+        };
+        Ok(DidName {
+            did,
+            name: self.name.validate_string(false)?,
+        })
+    }
+}
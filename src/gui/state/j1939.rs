@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::message::Message;
+use crate::protocol::j1939::{find_definition, read_definitions, reassemble};
+use crate::protocol::j1939::{J1939Transfer, PgnDefinition};
+
+#[derive(Default)]
+pub(crate) struct J1939State {
+    pub(crate) show_columns: bool,
+    pub(crate) definitions_path: Option<PathBuf>,
+    pub(crate) definitions: Vec<PgnDefinition>,
+    pub(crate) definitions_error: Option<String>,
+    pub(crate) transfers: Vec<J1939Transfer>,
+    // Transfer index for each message row that is part of a transfer
+    row_transfers: HashMap<usize, usize>,
+    pub(crate) selected_transfer: Option<usize>,
+    pub(crate) window_open: bool,
+}
+
+impl J1939State {
+    pub(crate) fn from_data(show_columns: bool, definitions_path: Option<PathBuf>) -> Self {
+        let mut state = Self {
+            show_columns,
+            ..Default::default()
+        };
+        if let Some(path) = definitions_path {
+            state.load_definitions(path);
+        }
+        state
+    }
+
+    pub(crate) fn load_definitions(&mut self, path: PathBuf) {
+        match read_definitions(&path) {
+            Ok(definitions) => {
+                self.definitions = definitions;
+                self.definitions_error = None;
+            }
+            Err(e) => {
+                self.definitions.clear();
+                self.definitions_error = Some(e.to_string());
+            }
+        }
+        self.definitions_path = Some(path);
+    }
+
+    pub(crate) fn definition(&self, pgn: u32) -> Option<&PgnDefinition> {
+        find_definition(&self.definitions, pgn)
+    }
+
+    pub(crate) fn reassemble(&mut self, messages: &[Message]) {
+        self.transfers = reassemble(messages);
+        self.row_transfers = self
+            .transfers
+            .iter()
+            .enumerate()
+            .flat_map(|(index, transfer)| transfer.rows.iter().map(move |row| (*row, index)))
+            .collect();
+        self.selected_transfer = None;
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        if !self.transfers.is_empty() {
+            self.transfers.clear();
+            self.row_transfers.clear();
+            self.selected_transfer = None;
+        }
+    }
+
+    pub(crate) fn row_transfer(&self, row: usize) -> Option<usize> {
+        self.row_transfers.get(&row).copied()
+    }
+}
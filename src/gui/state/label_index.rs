@@ -0,0 +1,387 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crate::filter::{previous_rows, FilterType, LabelFilter, MatchOutput};
+use crate::message::Message;
+
+// Captures with at least this many frames have filters evaluated over parts of them in parallel
+const PARALLEL_ROWS: usize = 50_000;
+
+// The rows a label's filter matches, one bit per row, and the output of those that have one
+#[derive(Default)]
+pub(crate) struct LabelRows {
+    bits: Vec<u64>,
+    // By row
+    outputs: Vec<(usize, MatchOutput)>,
+}
+
+impl LabelRows {
+    fn from_matches(matches: &[bool], outputs: Vec<(usize, MatchOutput)>) -> Self {
+        let mut bits = vec![0; (matches.len() + 63) / 64];
+        for (row, _) in matches.iter().enumerate().filter(|(_, matches)| **matches) {
+            bits[row / 64] |= 1 << (row % 64);
+        }
+        Self { bits, outputs }
+    }
+
+    pub(crate) fn contains(&self, row: usize) -> bool {
+        self.bits
+            .get(row / 64)
+            .map_or(false, |bits| bits & (1 << (row % 64)) != 0)
+    }
+
+    pub(crate) fn output(&self, row: usize) -> Option<&MatchOutput> {
+        let index = self
+            .outputs
+            .binary_search_by_key(&row, |(row, _)| *row)
+            .ok()?;
+        Some(&self.outputs[index].1)
+    }
+}
+
+struct IndexUpdate {
+    // Label index, its revision when the update started and its rows
+    rows: Vec<(usize, u64, Arc<LabelRows>)>,
+    previous_rows: Option<Arc<Vec<Option<usize>>>>,
+}
+
+// The matching rows of every label over the capture, found on a worker thread once per capture
+// and again for each label whose filter changed. Rows are kept up while they're being updated.
+#[derive(Default)]
+pub(crate) struct LabelIndex {
+    rows: Vec<Option<Arc<LabelRows>>>,
+    stale: Vec<bool>,
+    // Bumped on each change to a label, so updates of the previous filter are ignored
+    revisions: Vec<u64>,
+    // Row of the previous frame with the same ID and speed, for changed bytes rules
+    previous_rows: Option<Arc<Vec<Option<usize>>>>,
+    receiver: Option<Receiver<IndexUpdate>>,
+    // Labels and their revisions in the update in progress
+    batch: Vec<(usize, u64)>,
+    // Labels whose evaluation failed, which match no rows until they change
+    failed: Vec<bool>,
+}
+
+impl LabelIndex {
+    pub(crate) fn new(count: usize) -> Self {
+        Self {
+            rows: vec![None; count],
+            stale: vec![true; count],
+            revisions: vec![0; count],
+            failed: vec![false; count],
+            ..Default::default()
+        }
+    }
+
+    // Updates in progress refer to labels by index, so any change to the labels drops them and
+    // their labels are updated again
+    pub(crate) fn insert(&mut self, index: usize, copy_of: Option<usize>) {
+        let (rows, stale, failed) = match copy_of {
+            Some(other) => (
+                self.rows[other].clone(),
+                self.stale[other],
+                self.failed[other],
+            ),
+            None => (None, true, false),
+        };
+        self.rows.insert(index, rows);
+        self.stale.insert(index, stale);
+        self.revisions.insert(index, 0);
+        self.failed.insert(index, failed);
+        self.receiver = None;
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) {
+        self.rows.remove(index);
+        self.stale.remove(index);
+        self.revisions.remove(index);
+        self.failed.remove(index);
+        self.receiver = None;
+    }
+
+    // The other labels' rows in an update in progress still apply
+    pub(crate) fn invalidate(&mut self, index: usize) {
+        self.stale[index] = true;
+        self.revisions[index] += 1;
+        self.failed[index] = false;
+    }
+
+    // Drop rows of other messages
+    pub(crate) fn clear(&mut self) {
+        self.rows.iter_mut().for_each(|rows| *rows = None);
+        self.stale.iter_mut().for_each(|stale| *stale = true);
+        self.failed.iter_mut().for_each(|failed| *failed = false);
+        self.previous_rows = None;
+        self.receiver = None;
+    }
+
+    pub(crate) fn is_updating(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    // Take finished results and start updating any stale labels. Returns whether rows changed.
+    pub(crate) fn update(&mut self, messages: &Arc<Vec<Message>>, labels: &[LabelFilter]) -> bool {
+        let updated = self.poll();
+        if self.receiver.is_some() || !self.stale.contains(&true) {
+            return updated;
+        }
+        let filters: Vec<(usize, u64, LabelFilter)> = labels
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.stale[*index])
+            .map(|(index, lf)| (index, self.revisions[index], lf.clone()))
+            .collect();
+        let changes = self.previous_rows.is_none()
+            && filters
+                .iter()
+                .any(|(_, _, lf)| matches!(lf.filter.filter_type(), FilterType::Changed(_)));
+        self.batch = filters
+            .iter()
+            .map(|(index, revision, _)| (*index, *revision))
+            .collect();
+        let messages = messages.clone();
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let update = IndexUpdate {
+                rows: evaluate(&filters, &messages),
+                previous_rows: match changes {
+                    true => Some(Arc::new(previous_rows(&messages))),
+                    false => None,
+                },
+            };
+            let _ = sender.send(update);
+        });
+        self.receiver = Some(receiver);
+        updated
+    }
+
+    fn poll(&mut self) -> bool {
+        let update = match self.receiver.as_ref().map(|receiver| receiver.try_recv()) {
+            None | Some(Err(TryRecvError::Empty)) => return false,
+            Some(Err(TryRecvError::Disconnected)) => {
+                // The update's labels aren't tried again until they change
+                eprintln!("Error evaluating labels over the capture");
+                self.receiver = None;
+                for (index, revision) in std::mem::take(&mut self.batch) {
+                    if self.revisions[index] == revision {
+                        self.rows[index] = Some(Arc::new(LabelRows::default()));
+                        self.stale[index] = false;
+                        self.failed[index] = true;
+                    }
+                }
+                return true;
+            }
+            Some(Ok(update)) => update,
+        };
+        self.receiver = None;
+        for (index, revision, rows) in update.rows {
+            if self.revisions[index] == revision {
+                self.rows[index] = Some(rows);
+                self.stale[index] = false;
+            }
+        }
+        if update.previous_rows.is_some() {
+            self.previous_rows = update.previous_rows;
+        }
+        true
+    }
+
+    // Rows of a label, kept up while they're being updated
+    pub(crate) fn rows(&self, label: usize) -> Option<&LabelRows> {
+        self.rows.get(label)?.as_deref()
+    }
+
+    pub(crate) fn failed(&self, label: usize) -> bool {
+        self.failed[label]
+    }
+
+    // Rows of a label, unless they're missing or out of date
+    pub(crate) fn current_rows(&self, label: usize) -> Option<Arc<LabelRows>> {
+        match self.stale[label] {
+            true => None,
+            false => self.rows[label].clone(),
+        }
+    }
+
+    pub(crate) fn previous_row(&self, row: usize) -> Option<usize> {
+        *self.previous_rows.as_ref()?.get(row)?
+    }
+}
+
+// Rows matching each label's filter. Filters are evaluated on a worker per core, and those that
+// look at one frame at a time also over parts of large captures.
+fn evaluate(
+    filters: &[(usize, u64, LabelFilter)],
+    messages: &[Message],
+) -> Vec<(usize, u64, Arc<LabelRows>)> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let parts = (threads / filters.len().max(1)).max(1);
+    let part_size = match messages.len() >= PARALLEL_ROWS {
+        true => (messages.len() + parts - 1) / parts,
+        false => messages.len(),
+    }
+    .max(1);
+    // Each job is a filter and the rows it's evaluated over, in order of the filters' rows
+    let mut jobs = Vec::new();
+    for (filter, (_, _, lf)) in filters.iter().enumerate() {
+        match lf.filter.is_sequential() {
+            true => jobs.push((filter, 0..messages.len())),
+            false => jobs.extend(
+                (0..messages.len())
+                    .step_by(part_size)
+                    .map(|start| (filter, start..messages.len().min(start + part_size))),
+            ),
+        }
+    }
+    let next = AtomicUsize::new(0);
+    let mut done: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(jobs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    while let Some((filter, rows)) = jobs.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        let part = &messages[rows.clone()];
+                        let (matches, outputs) = evaluate_part(&filters[*filter].2, part);
+                        let outputs: Vec<_> = outputs
+                            .into_iter()
+                            .map(|(row, output)| (rows.start + row, output))
+                            .collect();
+                        done.push((*filter, rows.start, matches, outputs));
+                    }
+                    done
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    done.sort_by_key(|(filter, start, _, _)| (*filter, *start));
+    let mut matches = vec![Vec::new(); filters.len()];
+    let mut outputs = vec![Vec::new(); filters.len()];
+    for (filter, _, part_matches, part_outputs) in done {
+        matches[filter].extend(part_matches);
+        outputs[filter].extend(part_outputs);
+    }
+    filters
+        .iter()
+        .zip(matches.into_iter().zip(outputs))
+        .map(|(&(index, revision, _), (matches, outputs))| {
+            let rows = LabelRows::from_matches(&matches, outputs);
+            (index, revision, Arc::new(rows))
+        })
+        .collect()
+}
+
+// The rows of a part of the capture a label matches, and the output of those that have one.
+// Labels with a signal show its value instead.
+fn evaluate_part(lf: &LabelFilter, messages: &[Message]) -> (Vec<bool>, Vec<(usize, MatchOutput)>) {
+    let matches = lf.filter_all(messages);
+    let outputs = match lf.signal {
+        Some(_) => Vec::new(),
+        None => matches
+            .iter()
+            .enumerate()
+            .filter(|(_, matches)| **matches)
+            .filter_map(|(row, _)| Some((row, lf.filter.match_output(&messages[row])?)))
+            .collect(),
+    };
+    (matches, outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::tests::multiplexed_label;
+    use crate::filter::{MessageFilter, Occurrence, OutputSelection, StartsWithBytes};
+
+    #[test]
+    fn evaluate_parts() {
+        // Enough frames to be split into parts, and more jobs than workers
+        let messages: Vec<_> = (0..PARALLEL_ROWS + 10)
+            .map(|row| Message {
+                timestamp: row as f64,
+                id: vec![0x01, (row % 3) as u8],
+                data: vec![(row % 5) as u8, 0],
+                crc: Vec::new(),
+                ack: true,
+                speed: String::new(),
+            })
+            .collect();
+        let mut labels = vec![multiplexed_label(); 20];
+        // The fifth frame of the ID is the first with the multiplexor value
+        let id = labels[0].filter.id().cloned();
+        let fifth = FilterType::Occurrence(Occurrence { number: 5 });
+        labels.push(LabelFilter {
+            filter: MessageFilter::new(id, None, fifth),
+            ..multiplexed_label()
+        });
+        // Shows the bytes after the match instead of a signal
+        let starts_with = FilterType::StartsWithBytes(StartsWithBytes {
+            bytes: vec![2],
+            output: OutputSelection::AfterMatch,
+        });
+        labels.push(LabelFilter {
+            filter: MessageFilter::new(None, None, starts_with),
+            signal: None,
+            ..multiplexed_label()
+        });
+        let filters: Vec<_> = labels
+            .iter()
+            .enumerate()
+            .map(|(index, lf)| (index + 1, 7, lf.clone()))
+            .collect();
+        let rows = evaluate(&filters, &messages);
+        assert_eq!(rows.len(), labels.len());
+        for (label, (index, revision, rows)) in rows.into_iter().enumerate() {
+            assert_eq!((index, revision), (label + 1, 7));
+            let matches = labels[label].filter_all(&messages);
+            assert!(matches.iter().any(|matches| *matches));
+            for (row, matches) in matches.into_iter().enumerate() {
+                assert_eq!(rows.contains(row), matches, "label {} row {}", index, row);
+                let output = match (matches, &labels[label].signal) {
+                    (true, None) => labels[label].filter.match_output(&messages[row]),
+                    _ => None,
+                };
+                assert_eq!(
+                    rows.output(row),
+                    output.as_ref(),
+                    "label {} row {}",
+                    index,
+                    row
+                );
+            }
+            if labels[label].signal.is_none() {
+                let output = rows.output(2).and_then(|output| output.output.as_deref());
+                assert_eq!(output, Some(&[0][..]));
+            }
+        }
+    }
+
+    #[test]
+    fn failed_update() {
+        let mut index = LabelIndex::new(3);
+        // An update of the first and last labels whose worker stopped without a result, after
+        // the last label changed
+        let (sender, receiver) = channel();
+        drop(sender);
+        index.receiver = Some(receiver);
+        index.batch = vec![(0, 0), (2, 0)];
+        index.invalidate(2);
+        assert!(index.poll());
+        assert!(index.failed(0) && !index.is_updating());
+        assert!(!index.rows(0).unwrap().contains(0));
+        assert!(index.current_rows(0).is_some());
+        // The others are still to be evaluated
+        assert!(!index.failed(1) && !index.failed(2));
+        assert!(index.current_rows(1).is_none() && index.current_rows(2).is_none());
+        // Until the label changes
+        index.invalidate(0);
+        assert!(!index.failed(0));
+        assert!(index.current_rows(0).is_none());
+    }
+}
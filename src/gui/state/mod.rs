@@ -1,15 +1,53 @@
 use eframe::egui::Color32;
 
+mod canopen;
 mod filter;
 mod highlight_id;
+mod isotp;
+mod j1939;
+mod label_index;
+mod nmea2000;
+mod obd;
+mod physical;
+mod quick_filter;
+mod signal;
+mod value_table;
+mod view_filter;
+mod xcp;
+
+use std::path::Path;
+use std::str::FromStr;
 
 use crate::config::{write_config, Config};
+use crate::dbc::reader::read_dbc;
+use crate::dbc::writer::write_dbc;
+use crate::dbc::DbcDatabase;
 use crate::gui::MessageLoader;
-use crate::util::remove_whitespace;
+use crate::message::IdPattern;
+use crate::physical::BitTiming;
+use crate::util::{parse_number, remove_whitespace};
+use crate::value_table::find_table;
 
+use self::canopen::CanOpenState;
 use self::filter::FilterLabelState;
-pub(crate) use self::filter::{EditFilterLabelState, EditFilterOptionsState};
+pub(crate) use self::filter::{
+    EditFilterLabelState, EditFilterOptionsState, EditMessageFilterState,
+};
 use self::highlight_id::HighlightIDState;
+use self::isotp::IsoTpState;
+pub(crate) use self::isotp::{EditDidNameState, EditIsoTpChannelState};
+use self::j1939::J1939State;
+use self::nmea2000::Nmea2000State;
+use self::obd::ObdState;
+use self::physical::PhysicalState;
+use self::quick_filter::QuickFilterState;
+pub(crate) use self::signal::{EditMultiplexorState, EditSignalState};
+pub(crate) use self::value_table::EditValueTableState;
+use self::value_table::ValueTableState;
+use self::view_filter::ViewFilterState;
+pub(crate) use self::view_filter::ViewMode;
+pub(crate) use self::xcp::EditXcpChannelState;
+use self::xcp::XcpState;
 
 #[derive(Debug, Clone)]
 pub struct ParseError {}
@@ -91,12 +129,55 @@ impl Field<String> {
         self.valid = result.is_ok();
         result
     }
+
+    // Empty for any ID, otherwise hex ID bytes, code/mask or a range
+    pub fn validate_id_pattern(&mut self) -> Result<Option<IdPattern>, ParseError> {
+        let result = match self.value.trim().is_empty() {
+            true => Ok(None),
+            false => IdPattern::parse(&self.value).map(Some).ok_or(ParseError {}),
+        };
+        self.valid = result.is_ok();
+        result
+    }
+
+    pub fn as_number<N: FromStr>(&self) -> Result<N, ParseError> {
+        self.value.trim().parse::<N>().map_err(|_| ParseError {})
+    }
+
+    pub fn validate_number<N: FromStr>(&mut self) -> Result<N, ParseError> {
+        let result = self.as_number();
+        self.valid = result.is_ok();
+        result
+    }
+
+    // Empty for none, otherwise a decimal or 0x-prefixed hex number up to max
+    pub fn validate_optional_number(&mut self, max: u64) -> Result<Option<u64>, ParseError> {
+        let result = match self.value.trim().is_empty() {
+            true => Ok(None),
+            false => match parse_number(&self.value) {
+                Some(number) if number <= max => Ok(Some(number)),
+                _ => Err(ParseError {}),
+            },
+        };
+        self.valid = result.is_ok();
+        result
+    }
 }
 
 pub(crate) struct TableGui {
     pub message_loader: MessageLoader,
     pub highlight_id_state: HighlightIDState,
     pub filter_label_state: FilterLabelState,
+    pub value_table_state: ValueTableState,
+    pub isotp_state: IsoTpState,
+    pub j1939_state: J1939State,
+    pub canopen_state: CanOpenState,
+    pub obd_state: ObdState,
+    pub nmea2000_state: Nmea2000State,
+    pub xcp_state: XcpState,
+    pub physical_state: PhysicalState,
+    pub quick_filter_state: QuickFilterState,
+    pub view_filter_state: ViewFilterState,
 }
 
 impl TableGui {
@@ -105,6 +186,16 @@ impl TableGui {
             message_loader: MessageLoader::new(),
             highlight_id_state: HighlightIDState::default(),
             filter_label_state: FilterLabelState::default(),
+            value_table_state: ValueTableState::default(),
+            isotp_state: IsoTpState::default(),
+            j1939_state: J1939State::default(),
+            canopen_state: CanOpenState::default(),
+            obd_state: ObdState::default(),
+            nmea2000_state: Nmea2000State::default(),
+            xcp_state: XcpState::default(),
+            physical_state: PhysicalState::from_data(String::new(), BitTiming::default()),
+            quick_filter_state: QuickFilterState::default(),
+            view_filter_state: ViewFilterState::default(),
         }
     }
 
@@ -116,15 +207,36 @@ impl TableGui {
             },
             highlight_id_state: HighlightIDState::from_data(config.highlight_ids),
             filter_label_state: FilterLabelState::from_data(config.label_filters),
+            value_table_state: ValueTableState::from_data(config.value_tables),
+            isotp_state: IsoTpState::from_data(config.isotp_channels, config.did_names),
+            j1939_state: J1939State::from_data(config.j1939_columns, config.j1939_definitions),
+            canopen_state: CanOpenState::from_data(config.canopen_enabled, config.canopen_eds),
+            obd_state: ObdState::default(),
+            nmea2000_state: Nmea2000State::from_data(config.nmea2000_definitions),
+            xcp_state: XcpState::from_data(config.xcp_channels),
+            physical_state: PhysicalState::from_data(config.sample_channel, config.bit_timing),
+            quick_filter_state: QuickFilterState::default(),
+            view_filter_state: ViewFilterState::default(),
         }
     }
 
     pub fn save_state(&self) {
-        let config = Config::new(
-            self.message_loader.file_path().cloned(),
-            self.highlight_id_state.data.clone(),
-            self.filter_label_state.data.clone(),
-        );
+        let config = Config {
+            file_path: self.message_loader.csv_path().cloned(),
+            highlight_ids: self.highlight_id_state.data.clone(),
+            label_filters: self.filter_label_state.data.clone(),
+            value_tables: self.value_table_state.data.clone(),
+            isotp_channels: self.isotp_state.channels.clone(),
+            did_names: self.isotp_state.did_names.clone(),
+            j1939_columns: self.j1939_state.show_columns,
+            j1939_definitions: self.j1939_state.definitions_path.clone(),
+            canopen_enabled: self.canopen_state.enabled,
+            canopen_eds: self.canopen_state.eds_files.clone(),
+            nmea2000_definitions: self.nmea2000_state.definitions_path.clone(),
+            xcp_channels: self.xcp_state.channels.clone(),
+            sample_channel: self.physical_state.channel.clone(),
+            bit_timing: self.physical_state.timing.clone(),
+        };
         match write_config(&config) {
             Ok(_) => {
                 println!("Wrote config");
@@ -134,4 +246,54 @@ impl TableGui {
             }
         }
     }
+
+    // Add the messages and signals of a DBC file to the workspace. IDs that are already
+    // highlighted, value tables of the same name and labels with the same ID and name are kept,
+    // so importing a file again doesn't add them twice.
+    pub fn import_dbc(&mut self, path: &Path) {
+        let (highlight_ids, label_filters, value_tables) = match read_dbc(path) {
+            Ok(db) => db.into_workspace(),
+            Err(e) => {
+                eprintln!("Error importing DBC: {}", e);
+                return;
+            }
+        };
+        for h_id in highlight_ids {
+            if !self.highlight_id_state.data.iter().any(|h| h.id() == h_id.id()) {
+                self.highlight_id_state.data.push(h_id);
+            }
+        }
+        for table in value_tables {
+            if find_table(&self.value_table_state.data, &table.name).is_none() {
+                self.value_table_state.data.push(table);
+            }
+        }
+        for label_filter in label_filters {
+            let exists = self.filter_label_state.data.iter().any(|lf| {
+                lf.filter.id() == label_filter.filter.id()
+                    && lf.label.name == label_filter.label.name
+            });
+            if !exists {
+                self.filter_label_state.add(label_filter);
+            }
+        }
+        println!("Imported DBC from {}", path.display());
+    }
+
+    pub fn export_dbc(&self, path: &Path) {
+        let db = DbcDatabase::from_workspace(
+            &self.highlight_id_state.data,
+            &self.filter_label_state.data,
+            &self.value_table_state.data,
+            self.message_loader.messages(),
+        );
+        match write_dbc(path, &db) {
+            Ok(_) => {
+                println!("Wrote DBC to {}", path.display());
+            }
+            Err(e) => {
+                eprintln!("Error exporting DBC: {}", e);
+            }
+        }
+    }
 }
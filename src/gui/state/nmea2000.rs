@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::message::Message;
+use crate::protocol::nmea2000::{find_definition, is_fast_packet, read_definitions, reassemble};
+use crate::protocol::nmea2000::{FastPacket, Nmea2000Definition};
+
+#[derive(Default)]
+pub(crate) struct Nmea2000State {
+    pub(crate) definitions_path: Option<PathBuf>,
+    pub(crate) definitions: Vec<Nmea2000Definition>,
+    pub(crate) definitions_error: Option<String>,
+    pub(crate) packets: Vec<FastPacket>,
+    // Fast packet index for each message row that is part of a packet
+    row_packets: HashMap<usize, usize>,
+    pub(crate) selected_packet: Option<usize>,
+    pub(crate) window_open: bool,
+}
+
+impl Nmea2000State {
+    pub(crate) fn from_data(definitions_path: Option<PathBuf>) -> Self {
+        let mut state = Self::default();
+        if let Some(path) = definitions_path {
+            state.load_definitions(path);
+        }
+        state
+    }
+
+    pub(crate) fn load_definitions(&mut self, path: PathBuf) {
+        match read_definitions(&path) {
+            Ok(definitions) => {
+                self.definitions = definitions;
+                self.definitions_error = None;
+            }
+            Err(e) => {
+                self.definitions.clear();
+                self.definitions_error = Some(e.to_string());
+            }
+        }
+        self.definitions_path = Some(path);
+    }
+
+    pub(crate) fn definition(&self, pgn: u32) -> Option<&Nmea2000Definition> {
+        find_definition(&self.definitions, pgn)
+    }
+
+    // Definition of a PGN that fits in one frame, so it can be decoded per message
+    pub(crate) fn single_frame_definition(&self, pgn: u32) -> Option<&Nmea2000Definition> {
+        match is_fast_packet(&self.definitions, pgn) {
+            true => None,
+            false => self.definition(pgn),
+        }
+    }
+
+    pub(crate) fn reassemble(&mut self, messages: &[Message]) {
+        self.packets = reassemble(messages, &self.definitions);
+        self.row_packets = self
+            .packets
+            .iter()
+            .enumerate()
+            .flat_map(|(index, packet)| packet.rows.iter().map(move |row| (*row, index)))
+            .collect();
+        self.selected_packet = None;
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        if !self.packets.is_empty() {
+            self.packets.clear();
+            self.row_packets.clear();
+            self.selected_packet = None;
+        }
+    }
+
+    pub(crate) fn row_packet(&self, row: usize) -> Option<usize> {
+        self.row_packets.get(&row).copied()
+    }
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use crate::message::Message;
+use crate::protocol::isotp::{reassemble, IsoTpPdu};
+use crate::protocol::obd::{self, ObdMessage};
+
+#[derive(Default)]
+pub(crate) struct ObdState {
+    pub(crate) pdus: Vec<IsoTpPdu>,
+    pub(crate) messages: Vec<ObdMessage>,
+    // OBD-II message index for each message row that is part of one
+    row_messages: HashMap<usize, usize>,
+    pub(crate) selected_message: Option<usize>,
+    pub(crate) window_open: bool,
+}
+
+impl ObdState {
+    pub(crate) fn decode(&mut self, messages: &[Message]) {
+        self.pdus = reassemble(messages, &obd::channels());
+        self.messages = obd::decode(&self.pdus);
+        self.row_messages = self
+            .messages
+            .iter()
+            .enumerate()
+            .flat_map(|(index, message)| {
+                self.pdus[message.pdu]
+                    .rows
+                    .iter()
+                    .map(move |row| (*row, index))
+            })
+            .collect();
+        self.selected_message = None;
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        if !self.pdus.is_empty() {
+            self.pdus.clear();
+            self.messages.clear();
+            self.row_messages.clear();
+            self.selected_message = None;
+        }
+    }
+
+    pub(crate) fn row_message(&self, row: usize) -> Option<usize> {
+        self.row_messages.get(&row).copied()
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use crate::file::decode_samples_async;
+use crate::gui::state::{Field, ParseError};
+use crate::message::Message;
+use crate::physical::{BitTiming, Decoded, PhysicalError};
+
+#[derive(Default)]
+pub(crate) struct PhysicalState {
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) channel: String,
+    pub(crate) bitrate: Field<String>,
+    // Sample point in percent of the bit time
+    pub(crate) sample_point: Field<String>,
+    // Synchronization jump width in percent of the bit time
+    pub(crate) sjw: Field<String>,
+    // Sample rate in Hz of annotation exports, which don't include it
+    pub(crate) samplerate: Field<String>,
+    pub(crate) timing: BitTiming,
+    pub(crate) errors: Vec<PhysicalError>,
+    pub(crate) decode_error: Option<String>,
+    receiver: Option<Receiver<Result<Decoded, String>>>,
+    pub(crate) window_open: bool,
+}
+
+impl PhysicalState {
+    pub(crate) fn from_data(channel: String, timing: BitTiming) -> Self {
+        Self {
+            channel,
+            bitrate: Field::with_value(timing.bitrate.to_string()),
+            sample_point: Field::with_value((timing.sample_point * 100.0).to_string()),
+            sjw: Field::with_value((timing.sjw * 100.0).to_string()),
+            samplerate: Field::with_value("1000000".to_string()),
+            timing,
+            ..Default::default()
+        }
+    }
+
+    fn validate(&mut self) -> Result<BitTiming, ParseError> {
+        let bitrate = self.bitrate.validate_number::<u32>()?;
+        let sample_point = self.sample_point.validate_number::<f64>()?;
+        let sjw = self.sjw.validate_number::<f64>()?;
+        self.bitrate.valid = bitrate > 0;
+        self.sample_point.valid = sample_point > 0.0 && sample_point < 100.0;
+        self.sjw.valid = (0.0..=50.0).contains(&sjw);
+        match self.bitrate.valid && self.sample_point.valid && self.sjw.valid {
+            true => Ok(BitTiming {
+                bitrate,
+                sample_point: sample_point / 100.0,
+                sjw: sjw / 100.0,
+            }),
+            false => Err(ParseError {}),
+        }
+    }
+
+    pub(crate) fn is_decoding(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    // Start decoding the selected file in the background
+    pub(crate) fn decode(&mut self) -> Result<(), ParseError> {
+        let timing = self.validate()?;
+        let samplerate = self.samplerate.validate_number::<f64>()?;
+        let path = self.path.as_ref().ok_or(ParseError {})?;
+        let (sender, receiver) = channel();
+        decode_samples_async(path, &self.channel, &timing, samplerate, sender);
+        self.timing = timing;
+        self.receiver = Some(receiver);
+        self.errors.clear();
+        self.decode_error = None;
+        Ok(())
+    }
+
+    // The decoded messages, once decoding has finished
+    pub(crate) fn poll(&mut self) -> Option<Vec<Message>> {
+        let result = match self.receiver.as_ref()?.try_recv() {
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err("Decoding ended unexpectedly".to_string()),
+            Ok(result) => result,
+        };
+        self.receiver = None;
+        match result {
+            Ok(decoded) => {
+                self.errors = decoded.errors;
+                Some(decoded.messages)
+            }
+            Err(e) => {
+                self.decode_error = Some(e);
+                None
+            }
+        }
+    }
+}
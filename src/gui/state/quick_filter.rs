@@ -0,0 +1,153 @@
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use crate::expression::{Expression, ExpressionError};
+use crate::filter::{previous_rows, ChangedBytes};
+use crate::message::Message;
+
+use super::Field;
+
+// The expression typed into the bar above the message table, and the rows it lets through.
+// Rows are found in the background, and the previous rows stay up until they're ready.
+#[derive(Default)]
+pub(crate) struct QuickFilterState {
+    pub(crate) text: String,
+    pub(crate) error: Option<ExpressionError>,
+    // Only show frames that changed from the previous frame with the same ID and speed
+    pub(crate) changed_only: bool,
+    pub(crate) change_mask: Field<String>,
+    expression: Option<Expression>,
+    changed: Option<ChangedBytes>,
+    // Matching message rows, or None to show every row
+    rows: Option<Vec<usize>>,
+    receiver: Option<Receiver<Vec<usize>>>,
+    stale: bool,
+}
+
+impl QuickFilterState {
+    // Parse the text and mask, keeping the current filter for any that doesn't parse
+    pub(crate) fn apply(&mut self) {
+        match self.text.trim().is_empty() {
+            true => {
+                self.expression = None;
+                self.error = None;
+            }
+            false => match Expression::parse(&self.text) {
+                Ok(expression) => {
+                    self.expression = Some(expression);
+                    self.error = None;
+                }
+                Err(error) => self.error = Some(error),
+            },
+        }
+        match self.changed_only {
+            true => {
+                if let Ok(mask) = self.change_mask.validate_bytes(true) {
+                    self.changed = Some(ChangedBytes { mask });
+                }
+            }
+            false => self.changed = None,
+        }
+        self.stale = true;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.text.clear();
+        self.error = None;
+        self.changed_only = false;
+        self.change_mask = Field::default();
+        self.expression = None;
+        self.changed = None;
+        self.rows = None;
+        self.receiver = None;
+        self.stale = true;
+    }
+
+    // Drop rows of other messages
+    pub(crate) fn clear_results(&mut self) {
+        self.rows = None;
+        self.receiver = None;
+        self.stale = true;
+    }
+
+    pub(crate) fn is_updating(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    // Take finished rows and start finding them again if the filter changed. Returns whether the
+    // rows changed.
+    pub(crate) fn update_rows(&mut self, messages: &Arc<Vec<Message>>) -> bool {
+        let updated = self.poll();
+        if !self.stale {
+            return updated;
+        }
+        self.stale = false;
+        if self.expression.is_none() && self.changed.is_none() {
+            self.receiver = None;
+            self.rows = None;
+            return true;
+        }
+        let expression = self.expression.clone();
+        let changed = self.changed.clone();
+        let messages = messages.clone();
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let rows = matching_rows(expression.as_ref(), changed.as_ref(), &messages);
+            // The receiver is gone if the filter changed again in the meantime
+            let _ = sender.send(rows);
+        });
+        self.receiver = Some(receiver);
+        updated
+    }
+
+    fn poll(&mut self) -> bool {
+        let rows = match self.receiver.as_ref().map(|receiver| receiver.try_recv()) {
+            None | Some(Err(TryRecvError::Empty)) => return false,
+            Some(Err(TryRecvError::Disconnected)) => None,
+            Some(Ok(rows)) => Some(rows),
+        };
+        self.receiver = None;
+        match rows {
+            Some(rows) => {
+                self.rows = Some(rows);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn rows(&self) -> Option<&Vec<usize>> {
+        self.rows.as_ref()
+    }
+}
+
+fn matching_rows(
+    expression: Option<&Expression>,
+    changed: Option<&ChangedBytes>,
+    messages: &[Message],
+) -> Vec<usize> {
+    let previous = match changed {
+        Some(_) => previous_rows(messages),
+        None => Vec::new(),
+    };
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(index, message)| {
+            let expression_matches = match expression {
+                Some(expression) => expression.matches(message),
+                None => true,
+            };
+            expression_matches
+                && match changed {
+                    Some(changed) => {
+                        let previous = previous[*index].map(|row| &messages[row]);
+                        !changed.changed(previous, message).is_empty()
+                    }
+                    None => true,
+                }
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
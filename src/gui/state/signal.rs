@@ -0,0 +1,115 @@
+use crate::gui::state::{Field, ParseError};
+use crate::signal::{ByteOrder, Multiplexor, MuxRange, Signal, ValueType};
+
+pub(crate) struct EditSignalState {
+    pub enabled: bool,
+    pub start_bit: Field<String>,
+    pub length: Field<String>,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    pub scale: Field<String>,
+    pub offset: Field<String>,
+    pub unit: Field<String>,
+    pub value_table: Option<String>,
+    pub multiplexor: Option<Box<EditMultiplexorState>>,
+}
+
+impl Default for EditSignalState {
+    fn default() -> Self {
+        let mut state = Self::from_data(&Signal::default());
+        state.enabled = false;
+        state
+    }
+}
+
+impl EditSignalState {
+    pub(crate) fn from_data(signal: &Signal) -> Self {
+        Self {
+            enabled: true,
+            start_bit: Field::with_value(signal.start_bit.to_string()),
+            length: Field::with_value(signal.length.to_string()),
+            byte_order: signal.byte_order.clone(),
+            value_type: signal.value_type.clone(),
+            scale: Field::with_value(signal.scale.to_string()),
+            offset: Field::with_value(signal.offset.to_string()),
+            unit: Field::with_value(signal.unit.clone()),
+            value_table: signal.value_table.clone(),
+            multiplexor: signal
+                .multiplexor
+                .as_ref()
+                .map(|mux| Box::new(EditMultiplexorState::from_data(mux))),
+        }
+    }
+
+    pub(crate) fn from_option(signal: Option<&Signal>) -> Self {
+        match signal {
+            Some(signal) => Self::from_data(signal),
+            None => Self::default(),
+        }
+    }
+
+    pub(crate) fn validate(&mut self) -> Result<Option<Signal>, ParseError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let multiplexor = match &mut self.multiplexor {
+            Some(mux) => Some(mux.validate()?),
+            None => None,
+        };
+        let signal = Signal {
+            start_bit: self.start_bit.validate_number()?,
+            length: self.length.validate_number()?,
+            byte_order: self.byte_order.clone(),
+            value_type: self.value_type.clone(),
+            scale: self.scale.validate_number()?,
+            offset: self.offset.validate_number()?,
+            unit: self.unit.validate_string(true)?,
+            value_table: self.value_table.clone(),
+            multiplexor,
+        };
+        if !signal.is_valid() {
+            self.length.valid = false;
+            return Err(ParseError {});
+        }
+        Ok(Some(signal))
+    }
+}
+
+pub(crate) struct EditMultiplexorState {
+    pub name: Field<String>,
+    pub selector: EditSignalState,
+    pub values: Field<String>,
+}
+
+impl Default for EditMultiplexorState {
+    fn default() -> Self {
+        Self {
+            name: Field::default(),
+            selector: EditSignalState::from_data(&Signal::default()),
+            values: Field::default(),
+        }
+    }
+}
+
+impl EditMultiplexorState {
+    pub(crate) fn from_data(mux: &Multiplexor) -> Self {
+        Self {
+            name: Field::with_value(mux.name.clone()),
+            selector: EditSignalState::from_data(&mux.selector),
+            values: Field::with_value(MuxRange::list_string(&mux.values)),
+        }
+    }
+
+    pub(crate) fn validate(&mut self) -> Result<Multiplexor, ParseError> {
+        let name = self.name.validate_string(false)?;
+        let values = MuxRange::parse_list(&self.values.value);
+        self.values.valid = values.is_some();
+        let values = values.ok_or(ParseError {})?;
+        let selector = self.selector.validate()?.ok_or(ParseError {})?;
+        Ok(Multiplexor {
+            name,
+            selector: Box::new(selector),
+            values,
+        })
+    }
+}
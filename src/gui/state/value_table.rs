@@ -0,0 +1,63 @@
+use crate::gui::state::{Field, ParseError};
+use crate::value_table::ValueTable;
+
+#[derive(Default)]
+pub(crate) struct ValueTableState {
+    pub(crate) data: Vec<ValueTable>,
+    editing_index: Option<usize>,
+    pub(crate) edit_state: EditValueTableState,
+    pub(crate) window_open: bool,
+}
+
+impl ValueTableState {
+    pub(crate) fn from_data(data: Vec<ValueTable>) -> Self {
+        Self {
+            data,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn editing_index(&self) -> &Option<usize> {
+        &self.editing_index
+    }
+
+    pub(crate) fn edit(&mut self, index: usize) {
+        self.editing_index = Some(index);
+        self.edit_state = EditValueTableState::from_data(&self.data[index]);
+    }
+
+    pub(crate) fn commit(&mut self) {
+        if let Some(index) = self.editing_index {
+            if let Ok(table) = self.edit_state.validate() {
+                self.data[index] = table;
+                self.editing_index = None;
+                self.edit_state = EditValueTableState::default();
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct EditValueTableState {
+    pub name: Field<String>,
+    pub entries: Field<String>,
+}
+
+impl EditValueTableState {
+    pub(crate) fn from_data(data: &ValueTable) -> Self {
+        Self {
+            name: Field::with_value(data.name.clone()),
+            entries: Field::with_value(data.entries_string()),
+        }
+    }
+
+    pub(crate) fn validate(&mut self) -> Result<ValueTable, ParseError> {
+        let name = self.name.validate_string(false)?;
+        let entries = ValueTable::parse_entries(&self.entries.value);
+        self.entries.valid = entries.is_some();
+        Ok(ValueTable {
+            name,
+            entries: entries.ok_or(ParseError {})?,
+        })
+    }
+}
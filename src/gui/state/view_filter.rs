@@ -0,0 +1,244 @@
+use std::cell::Cell;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+use strum::EnumIter;
+
+use crate::message::Message;
+
+use super::filter::FilterLabelState;
+
+#[derive(Debug, EnumIter, PartialEq, Default, Clone, Copy)]
+pub(crate) enum ViewMode {
+    #[default]
+    All,
+    // Rows matching any of the selected labels
+    Include,
+    // Rows matching none of the selected labels
+    Exclude,
+}
+
+impl ViewMode {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ViewMode::All => "All rows",
+            ViewMode::Include => "Only selected labels",
+            ViewMode::Exclude => "Hide selected labels",
+        }
+    }
+}
+
+// The rows of the message table left by the quick filter and the labels selected as a view
+// filter. Rows are found in the background, and the previous rows stay up until they're ready.
+#[derive(Default)]
+pub(crate) struct ViewFilterState {
+    mode: ViewMode,
+    rows: Option<Vec<usize>>,
+    receiver: Option<Receiver<Vec<usize>>>,
+    stale: bool,
+    // Message row at the top of the table, kept at the top when the rows change
+    pub(crate) top_row: Cell<Option<usize>>,
+    scroll_anchor: Cell<Option<usize>>,
+}
+
+impl ViewFilterState {
+    pub(crate) fn mode(&self) -> ViewMode {
+        self.mode
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: ViewMode) {
+        self.mode = mode;
+        self.stale = true;
+    }
+
+    pub(crate) fn is_updating(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    // Drop rows of other messages
+    pub(crate) fn clear_results(&mut self) {
+        self.rows = None;
+        self.receiver = None;
+        self.top_row.set(None);
+        self.stale = true;
+    }
+
+    // The quick filter or the labels changed, so the rows need updating
+    pub(crate) fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    // Start finding the rows among the quick filter's, or all rows if it has none, once the
+    // selected labels are up to date
+    pub(crate) fn update_rows(
+        &mut self,
+        messages: &[Message],
+        labels: &FilterLabelState,
+        candidates: Option<&Vec<usize>>,
+    ) {
+        if !self.stale {
+            return;
+        }
+        if self.mode == ViewMode::All {
+            self.stale = false;
+            self.receiver = None;
+            self.set_rows(None);
+            return;
+        }
+        let label_rows = match labels.view_rows() {
+            Some(label_rows) => label_rows,
+            None => return,
+        };
+        self.stale = false;
+        if label_rows.is_empty() {
+            self.receiver = None;
+            self.set_rows(None);
+            return;
+        }
+        let include = self.mode == ViewMode::Include;
+        let candidates = candidates
+            .cloned()
+            .unwrap_or_else(|| (0..messages.len()).collect());
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let rows = candidates
+                .into_iter()
+                .filter(|row| label_rows.iter().any(|rows| rows.contains(*row)) == include)
+                .collect();
+            // The receiver is gone if the rows were invalidated again in the meantime
+            let _ = sender.send(rows);
+        });
+        self.receiver = Some(receiver);
+    }
+
+    pub(crate) fn poll(&mut self) {
+        let rows = match self.receiver.as_ref().map(|receiver| receiver.try_recv()) {
+            None | Some(Err(TryRecvError::Empty)) => return,
+            Some(Err(TryRecvError::Disconnected)) => None,
+            Some(Ok(rows)) => Some(rows),
+        };
+        self.receiver = None;
+        if rows.is_some() {
+            self.set_rows(rows);
+        }
+    }
+
+    fn set_rows(&mut self, rows: Option<Vec<usize>>) {
+        self.rows = rows;
+        self.scroll_anchor.set(self.top_row.get());
+    }
+
+    pub(crate) fn rows(&self) -> Option<&Vec<usize>> {
+        self.rows.as_ref()
+    }
+
+    // Message row to scroll back to the top, once after the rows changed
+    pub(crate) fn take_scroll_anchor(&self) -> Option<usize> {
+        self.scroll_anchor.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::filter::{FilterType, LabelFilter, MessageFilter};
+    use crate::label::Label;
+    use crate::message::IdPattern;
+
+    fn frame(id: u16) -> Message {
+        Message {
+            timestamp: 0.0,
+            id: id.to_be_bytes().to_vec(),
+            data: vec![0],
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    fn label(id: &str) -> LabelFilter {
+        LabelFilter {
+            label: Label {
+                name: id.to_string(),
+                color: [1.0, 1.0, 1.0],
+            },
+            filter: MessageFilter::new(IdPattern::parse(id), None, FilterType::Basic),
+            signal: None,
+        }
+    }
+
+    // Update the labels and the view as on each frame of the GUI, until neither is updating
+    fn settle(
+        view: &mut ViewFilterState,
+        labels: &mut FilterLabelState,
+        messages: &Arc<Vec<Message>>,
+    ) -> Option<Vec<usize>> {
+        for _ in 0..5000 {
+            if labels.update_rows(messages) {
+                view.invalidate();
+            }
+            view.update_rows(messages, labels, None);
+            view.poll();
+            if !labels.is_updating() && !view.is_updating() && !view.stale {
+                return view.rows().cloned();
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("the view filter didn't settle");
+    }
+
+    #[test]
+    fn modes() {
+        let messages = Arc::new(vec![frame(0x100), frame(0x200), frame(0x300), frame(0x100)]);
+        let mut labels = FilterLabelState::from_data(vec![label("0100"), label("0200")]);
+        let mut view = ViewFilterState::default();
+        assert_eq!(settle(&mut view, &mut labels, &messages), None);
+
+        view.set_mode(ViewMode::Include);
+        // No label selected leaves every row
+        assert_eq!(settle(&mut view, &mut labels, &messages), None);
+        labels.set_in_view(0, true);
+        assert_eq!(settle(&mut view, &mut labels, &messages), Some(vec![0, 3]));
+        labels.set_in_view(1, true);
+        assert_eq!(
+            settle(&mut view, &mut labels, &messages),
+            Some(vec![0, 1, 3])
+        );
+
+        view.set_mode(ViewMode::Exclude);
+        assert_eq!(settle(&mut view, &mut labels, &messages), Some(vec![2]));
+        view.set_mode(ViewMode::All);
+        assert_eq!(settle(&mut view, &mut labels, &messages), None);
+    }
+
+    #[test]
+    fn label_changes() {
+        let messages = Arc::new(vec![frame(0x100), frame(0x200), frame(0x300), frame(0x100)]);
+        let mut labels = FilterLabelState::from_data(vec![label("0100"), label("0200")]);
+        let mut view = ViewFilterState::default();
+        view.set_mode(ViewMode::Include);
+        labels.set_in_view(0, true);
+        assert_eq!(settle(&mut view, &mut labels, &messages), Some(vec![0, 3]));
+
+        // The copy is selected too, and keeps its rows when the original changes
+        labels.duplicate(0);
+        assert!(labels.in_view(1));
+        assert_eq!(settle(&mut view, &mut labels, &messages), Some(vec![0, 3]));
+        labels.edit(0);
+        labels.edit_state.id.value = "0300".to_string();
+        labels.commit();
+        assert_eq!(
+            settle(&mut view, &mut labels, &messages),
+            Some(vec![0, 2, 3])
+        );
+
+        labels.remove(1);
+        assert_eq!(settle(&mut view, &mut labels, &messages), Some(vec![2]));
+        // With no selected label left, every row shows again
+        labels.remove(0);
+        assert_eq!(settle(&mut view, &mut labels, &messages), None);
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use crate::gui::state::{Field, ParseError};
+use crate::message::Message;
+use crate::protocol::xcp::{decode, XcpChannel, XcpPacket, XcpProtocol};
+use crate::signal::ByteOrder;
+
+#[derive(Default)]
+pub(crate) struct XcpState {
+    pub(crate) channels: Vec<XcpChannel>,
+    pub(crate) edit_state: EditXcpChannelState,
+    pub(crate) packets: Vec<XcpPacket>,
+    // Packet index for each message row that decodes as XCP or CCP
+    row_packets: HashMap<usize, usize>,
+    pub(crate) selected_packet: Option<usize>,
+    pub(crate) window_open: bool,
+}
+
+impl XcpState {
+    pub(crate) fn from_data(channels: Vec<XcpChannel>) -> Self {
+        Self {
+            channels,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn decode(&mut self, messages: &[Message]) {
+        self.packets = decode(messages, &self.channels);
+        self.row_packets = self
+            .packets
+            .iter()
+            .enumerate()
+            .map(|(index, packet)| (packet.row, index))
+            .collect();
+        self.selected_packet = None;
+    }
+
+    pub(crate) fn clear_results(&mut self) {
+        if !self.packets.is_empty() {
+            self.packets.clear();
+            self.row_packets.clear();
+            self.selected_packet = None;
+        }
+    }
+
+    pub(crate) fn row_packet(&self, row: usize) -> Option<usize> {
+        self.row_packets.get(&row).copied()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct EditXcpChannelState {
+    pub name: Field<String>,
+    pub protocol: XcpProtocol,
+    pub master_id: Field<String>,
+    pub slave_id: Field<String>,
+    pub byte_order: ByteOrder,
+}
+
+impl EditXcpChannelState {
+    pub(crate) fn validate(&mut self) -> Result<XcpChannel, ParseError> {
+        Ok(XcpChannel {
+            name: self.name.validate_string(false)?,
+            protocol: self.protocol,
+            master_id: self.master_id.validate_bytes(false)?,
+            slave_id: self.slave_id.validate_bytes(false)?,
+            byte_order: self.byte_order.clone(),
+        })
+    }
+}
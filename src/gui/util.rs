@@ -1,4 +1,6 @@
+use crate::crc::CrcStatus;
 use crate::message::Speed;
+use crate::signal::SignalValue;
 use eframe::egui::Color32;
 
 pub fn ack_color(ack: bool) -> Color32 {
@@ -9,6 +11,14 @@ pub fn ack_color(ack: bool) -> Color32 {
     }
 }
 
+pub fn crc_color(status: &CrcStatus) -> Color32 {
+    match status {
+        CrcStatus::Match => Color32::GREEN,
+        CrcStatus::Mismatch { .. } => Color32::RED,
+        CrcStatus::Unknown => Color32::WHITE,
+    }
+}
+
 pub fn speed_color(speed: &Speed) -> Color32 {
     match speed.as_str() {
         "1M" => Color32::GREEN,
@@ -18,6 +28,14 @@ pub fn speed_color(speed: &Speed) -> Color32 {
     }
 }
 
+// Values missing from the signal's value table stand out regardless of the label colour
+pub fn signal_value_color(value: &SignalValue, label_color: Color32) -> Color32 {
+    match value.is_undefined() {
+        true => Color32::from_rgb(255, 120, 0),
+        false => label_color,
+    }
+}
+
 fn color_is_light(color: &Color32) -> bool {
     let r = color.r() as f32;
     let g = color.g() as f32;
@@ -0,0 +1,168 @@
+use crate::egui::{self, ComboBox};
+use egui_extras::{Size, TableBuilder};
+use strum::IntoEnumIterator;
+
+use crate::protocol::xcp::{XcpKind, XcpProtocol};
+use crate::signal::ByteOrder;
+use crate::util::hex_to_str;
+
+use super::state::EditXcpChannelState;
+use super::TableGui;
+
+impl TableGui {
+    pub(super) fn xcp_ui(&mut self, ui: &mut egui::Ui) {
+        let mut index_to_remove: Option<usize> = None;
+        for (index, channel) in self.xcp_state.channels.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} ({}): {} / {}",
+                    channel.name,
+                    channel.protocol.name(),
+                    hex_to_str(&channel.master_id),
+                    hex_to_str(&channel.slave_id)
+                ));
+                if ui.button("Delete").clicked() {
+                    index_to_remove = Some(index);
+                }
+            });
+        }
+        if let Some(index) = index_to_remove {
+            self.xcp_state.channels.remove(index);
+            self.save_state();
+        }
+
+        self.xcp_channel_edit_line(ui);
+
+        ui.horizontal(|ui| {
+            if ui.button("Decode").clicked() {
+                if let Some(messages) = self.message_loader.messages() {
+                    self.xcp_state.decode(messages);
+                }
+            }
+            ui.label(format!("{} packets", self.xcp_state.packets.len()));
+        });
+        ui.separator();
+
+        self.xcp_packet_table(ui);
+    }
+
+    fn xcp_channel_edit_line(&mut self, ui: &mut egui::Ui) {
+        let edit_state = &mut self.xcp_state.edit_state;
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            TableGui::validated_text_edit(ui, &mut edit_state.name, 60.0);
+            ui.label("Master ID:");
+            TableGui::validated_text_edit(ui, &mut edit_state.master_id, 60.0);
+            ui.label("Slave ID:");
+            TableGui::validated_text_edit(ui, &mut edit_state.slave_id, 60.0);
+        });
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("xcp_protocol")
+                .selected_text(edit_state.protocol.name())
+                .show_ui(ui, |ui| {
+                    for protocol in XcpProtocol::iter() {
+                        if ui
+                            .selectable_label(edit_state.protocol == protocol, protocol.name())
+                            .clicked()
+                        {
+                            edit_state.protocol = protocol;
+                        }
+                    }
+                });
+            ComboBox::from_id_source("xcp_byte_order")
+                .selected_text(edit_state.byte_order.name())
+                .show_ui(ui, |ui| {
+                    for byte_order in ByteOrder::iter() {
+                        if ui
+                            .selectable_label(
+                                edit_state.byte_order == byte_order,
+                                byte_order.name(),
+                            )
+                            .clicked()
+                        {
+                            edit_state.byte_order = byte_order;
+                        }
+                    }
+                });
+        });
+        if ui.button("Add").clicked() {
+            if let Ok(channel) = self.xcp_state.edit_state.validate() {
+                self.xcp_state.channels.push(channel);
+                self.xcp_state.edit_state = EditXcpChannelState::default();
+                self.save_state();
+            }
+        }
+    }
+
+    fn xcp_packet_table(&mut self, ui: &mut egui::Ui) {
+        ui.push_id("xcp_packet_table", |ui| {
+            let table = TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                .column(Size::initial(40.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(30.0))
+                .column(Size::initial(50.0).at_least(30.0))
+                .column(Size::initial(70.0).at_least(40.0))
+                .column(Size::remainder().at_least(60.0));
+
+            let mut selected_packet = self.xcp_state.selected_packet;
+
+            table
+                .header(20.0, |mut header| {
+                    for heading in ["#", "Time", "Channel", "From", "Type", "Description"] {
+                        header.col(|ui| {
+                            ui.heading(heading);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(
+                        TableGui::BUTTON_HEIGHT,
+                        self.xcp_state.packets.len(),
+                        |row_index, mut row| {
+                            let packet = &self.xcp_state.packets[row_index];
+                            row.col(|ui| {
+                                if ui
+                                    .selectable_label(
+                                        selected_packet == Some(row_index),
+                                        row_index.to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    selected_packet = Some(row_index);
+                                }
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:.3}", packet.timestamp));
+                            });
+                            row.col(|ui| {
+                                let name = self
+                                    .xcp_state
+                                    .channels
+                                    .get(packet.channel)
+                                    .map_or("?", |channel| channel.name.as_str());
+                                ui.label(name);
+                            });
+                            row.col(|ui| {
+                                ui.label(packet.sender.name());
+                            });
+                            row.col(|ui| {
+                                ui.label(packet.kind.name());
+                            });
+                            row.col(|ui| {
+                                match packet.kind {
+                                    XcpKind::Error(_) => {
+                                        ui.colored_label(egui::Color32::RED, &packet.description)
+                                    }
+                                    _ => ui.label(&packet.description),
+                                };
+                            });
+                        },
+                    );
+                });
+
+            self.xcp_state.selected_packet = selected_packet;
+        });
+    }
+}
@@ -1,12 +1,20 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod config;
+mod crc;
+mod dbc;
+mod expression;
 mod file;
 mod filter;
 mod gui;
 mod label;
 mod message;
+mod physical;
+mod protocol;
+mod signal;
 mod util;
+mod value_table;
+mod zip;
 
 use eframe::egui;
 
@@ -1,4 +1,6 @@
-use crate::util::remove_whitespace;
+use std::fmt;
+
+use crate::util::{hex_to_str, remove_whitespace};
 use serde::{Deserialize, Serialize};
 
 fn hex_deserializer<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
@@ -54,29 +56,174 @@ struct MyHex {
 }
 
 impl Message {
-    pub fn match_id(&self, highlight_ids: &Vec<HighlightID>) -> Option<HighlightID> {
-        for id in highlight_ids {
-            if self.id == id.id {
-                return Some(id.clone());
+    pub fn id_u32(&self) -> Option<u32> {
+        id_u32(&self.id)
+    }
+
+    // The most specific matching entry, or the first of equally specific ones
+    pub fn match_id(&self, highlight_ids: &[HighlightID]) -> Option<HighlightID> {
+        highlight_ids
+            .iter()
+            .filter(|id| id.id.matches(&self.id))
+            .min_by_key(|id| id.id.match_count())
+            .cloned()
+    }
+}
+
+// How an ID entry matches message IDs: exactly, by acceptance code and mask as CAN controllers
+// do, or by an inclusive range. Like exact IDs, masks and ranges only match IDs of their width
+// in bytes, as written.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum IdPattern {
+    Exact(Vec<u8>),
+    Mask { code: u32, mask: u32, width: usize },
+    Range { first: u32, last: u32, width: usize },
+}
+
+// The value and width in bytes of a hex number
+fn parse_hex_u32(text: &str) -> Option<(u32, usize)> {
+    let mut text = text.to_string();
+    remove_whitespace(&mut text);
+    let text = match text.get(..2) {
+        Some("0x" | "0X") => &text[2..],
+        _ => &text,
+    };
+    let value = u32::from_str_radix(text, 16).ok()?;
+    Some((value, (text.len() + 1) / 2))
+}
+
+impl IdPattern {
+    // Parse hex bytes, code/mask such as 18FEF100/1FFFFF00, or a range such as 700-77F
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        if let Some((code, mask)) = text.split_once('/') {
+            let ((code, code_width), (mask, mask_width)) =
+                (parse_hex_u32(code)?, parse_hex_u32(mask)?);
+            return Some(IdPattern::Mask {
+                code,
+                mask,
+                width: code_width.max(mask_width),
+            });
+        }
+        if let Some((first, last)) = text.split_once('-') {
+            let ((first, first_width), (last, last_width)) =
+                (parse_hex_u32(first)?, parse_hex_u32(last)?);
+            let width = first_width.max(last_width);
+            return match first <= last {
+                true => Some(IdPattern::Range { first, last, width }),
+                false => None,
+            };
+        }
+        let mut id = text.to_string();
+        remove_whitespace(&mut id);
+        if id.is_empty() {
+            return None;
+        }
+        if id.len() % 2 != 0 {
+            id.insert(0, '0');
+        }
+        hex::decode(&id).ok().map(IdPattern::Exact)
+    }
+
+    pub(crate) fn matches(&self, id: &[u8]) -> bool {
+        match self {
+            IdPattern::Exact(exact) => exact == id,
+            IdPattern::Mask { code, mask, width } => match id_u32(id) {
+                Some(value) => id.len() == *width && value & mask == code & mask,
+                None => false,
+            },
+            IdPattern::Range { first, last, width } => match id_u32(id) {
+                Some(value) => id.len() == *width && *first <= value && value <= *last,
+                None => false,
+            },
+        }
+    }
+
+    // How many IDs match, so the most specific of several matching entries can be picked
+    pub(crate) fn match_count(&self) -> u64 {
+        match self {
+            IdPattern::Exact(_) => 1,
+            IdPattern::Mask { code, mask, .. } => {
+                // Free bits within a base or extended ID
+                let width = match (code | mask) > MAX_BASE_ID {
+                    true => 0x1FFF_FFFF,
+                    false => MAX_BASE_ID,
+                };
+                1 << (!mask & width).count_ones()
             }
+            IdPattern::Range { first, last, .. } => (last - first) as u64 + 1,
+        }
+    }
+
+    pub(crate) fn exact(&self) -> Option<&Vec<u8>> {
+        match self {
+            IdPattern::Exact(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for IdPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdPattern::Exact(id) => write!(f, "{}", hex_to_str(id)),
+            // Padded to their width, like exact IDs
+            IdPattern::Mask { code, mask, width } => {
+                write!(f, "{:02$X}/{:02$X}", code, mask, width * 2)
+            }
+            IdPattern::Range { first, last, width } => {
+                write!(f, "{:02$X}-{:02$X}", first, last, width * 2)
+            }
+        }
+    }
+}
+
+// Configs from before ID patterns store plain ID bytes
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredIdPattern {
+    Bytes(Vec<u8>),
+    Pattern(IdPattern),
+}
+
+impl From<StoredIdPattern> for IdPattern {
+    fn from(stored: StoredIdPattern) -> Self {
+        match stored {
+            StoredIdPattern::Bytes(id) => IdPattern::Exact(id),
+            StoredIdPattern::Pattern(pattern) => pattern,
         }
-        None
     }
 }
 
+pub(crate) fn id_pattern_deserializer<'de, D>(deserializer: D) -> Result<IdPattern, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(StoredIdPattern::deserialize(deserializer)?.into())
+}
+
+pub(crate) fn optional_id_pattern_deserializer<'de, D>(
+    deserializer: D,
+) -> Result<Option<IdPattern>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<StoredIdPattern>::deserialize(deserializer)?.map(IdPattern::from))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HighlightID {
-    id: Vec<u8>,
+    #[serde(deserialize_with = "id_pattern_deserializer")]
+    id: IdPattern,
     name: String,
     color: [f32; 3],
 }
 
 impl HighlightID {
-    pub(crate) fn new(id: Vec<u8>, name: String, color: [f32; 3]) -> Self {
+    pub(crate) fn new(id: IdPattern, name: String, color: [f32; 3]) -> Self {
         Self { id, name, color }
     }
 
-    pub(crate) fn id(&self) -> &Vec<u8> {
+    pub(crate) fn id(&self) -> &IdPattern {
         &self.id
     }
 
@@ -89,12 +236,139 @@ impl HighlightID {
     }
 }
 
-pub(crate) fn id_string(id: &Vec<u8>, ids: &Vec<HighlightID>) -> String {
-    match id.is_empty() {
-        true => "any".to_string(),
-        false => match ids.iter().find(|h_id| h_id.id == *id) {
-            None => hex::encode(id),
-            Some(h_id) => h_id.name.clone(),
+const MAX_BASE_ID: u32 = 0x7FF;
+
+// Interpret big-endian ID bytes as a numeric CAN ID. Fails for IDs wider than 32 bits.
+pub(crate) fn id_u32(id: &[u8]) -> Option<u32> {
+    if id.len() > 4 {
+        return None;
+    }
+    Some(id.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32))
+}
+
+// Extended IDs are stored in 4 bytes, whatever their value
+pub(crate) fn is_extended_id(id: &[u8]) -> bool {
+    id.len() > 2 || id_u32(id).unwrap_or(0) > MAX_BASE_ID
+}
+
+pub(crate) fn id_string(id: &IdPattern, ids: &[HighlightID]) -> String {
+    match ids.iter().find(|h_id| h_id.id == *id) {
+        None => match id {
+            IdPattern::Exact(id) if id.is_empty() => "any".to_string(),
+            IdPattern::Exact(id) => hex::encode(id),
+            pattern => pattern.to_string(),
         },
+        Some(h_id) => h_id.name.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: Vec<u8>) -> Message {
+        Message {
+            timestamp: 0.0,
+            id,
+            data: Vec::new(),
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    #[test]
+    fn parse_patterns() {
+        assert_eq!(
+            IdPattern::parse("123"),
+            Some(IdPattern::Exact(vec![0x01, 0x23]))
+        );
+        assert_eq!(
+            IdPattern::parse(" 18 FE F1 00 "),
+            Some(IdPattern::Exact(vec![0x18, 0xFE, 0xF1, 0x00]))
+        );
+        assert_eq!(
+            IdPattern::parse("18FEF100/1FFFFF00"),
+            Some(IdPattern::Mask {
+                code: 0x18FEF100,
+                mask: 0x1FFFFF00,
+                width: 4
+            })
+        );
+        assert_eq!(
+            IdPattern::parse("0x700 - 0X77F"),
+            Some(IdPattern::Range {
+                first: 0x700,
+                last: 0x77F,
+                width: 2
+            })
+        );
+        for invalid in ["", "  ", "12G", "700/", "/7FF", "77F-700", "700-", "1/2/3"] {
+            assert_eq!(IdPattern::parse(invalid), None, "{:?}", invalid);
+        }
+
+        for text in [
+            "0123",
+            "18FEF100/1FFFFF00",
+            "700-77F",
+            "0-1FFFFFFF",
+            "0x7F/0XFF",
+        ] {
+            let pattern = IdPattern::parse(text).unwrap();
+            assert_eq!(IdPattern::parse(&pattern.to_string()), Some(pattern));
+        }
+        assert_eq!(
+            IdPattern::parse("700-77F").unwrap().to_string(),
+            "0700-077F"
+        );
+    }
+
+    #[test]
+    fn matching() {
+        let exact = IdPattern::parse("123").unwrap();
+        assert!(exact.matches(&[0x01, 0x23]));
+        assert!(!exact.matches(&[0x00, 0x00, 0x01, 0x23]));
+
+        let mask = IdPattern::parse("18FEF100/1FFFFF00").unwrap();
+        assert!(mask.matches(&[0x18, 0xFE, 0xF1, 0x23]));
+        assert!(!mask.matches(&[0x18, 0xFE, 0xF2, 0x23]));
+        assert!(!mask.matches(&[0x01, 0x18, 0xFE, 0xF1, 0x00]));
+        // Masks and ranges match IDs of their width only, like exact IDs
+        let extended = IdPattern::parse("00000700/1FFFFF00").unwrap();
+        assert!(extended.matches(&[0x00, 0x00, 0x07, 0x23]));
+        assert!(!extended.matches(&[0x07, 0x23]));
+        assert_eq!(mask.match_count(), 256);
+        let base_mask = IdPattern::parse("700/700").unwrap();
+        assert_eq!(base_mask.match_count(), 256);
+
+        let range = IdPattern::parse("700-77F").unwrap();
+        assert!(range.matches(&[0x07, 0x00]));
+        assert!(range.matches(&[0x07, 0x7F]));
+        assert!(!range.matches(&[0x07, 0x80]));
+        assert!(!range.matches(&[0x00, 0x00, 0x07, 0x00]));
+        assert!(!range.matches(&[0x07]));
+        assert_eq!(range.match_count(), 128);
+    }
+
+    #[test]
+    fn most_specific_id() {
+        let highlight = |id, name: &str| {
+            HighlightID::new(IdPattern::parse(id).unwrap(), name.to_string(), [1.0; 3])
+        };
+        let ids = [
+            highlight("700-7FF", "Diagnostics"),
+            highlight("7E0/7F0", "OBD"),
+            highlight("7E8", "ECM"),
+        ];
+        let name = |id| message(id).match_id(&ids).map(|h| h.name().clone());
+        assert_eq!(name(vec![0x07, 0xE8]).as_deref(), Some("ECM"));
+        assert_eq!(name(vec![0x07, 0xE9]).as_deref(), Some("OBD"));
+        assert_eq!(name(vec![0x07, 0x10]).as_deref(), Some("Diagnostics"));
+        assert_eq!(name(vec![0x01, 0x23]), None);
+
+        assert_eq!(id_string(ids[1].id(), &ids), "OBD");
+        assert_eq!(id_string(&IdPattern::parse("123").unwrap(), &ids), "0123");
+        assert_eq!(id_string(&IdPattern::Exact(Vec::new()), &ids), "any");
+        assert_eq!(id_string(&IdPattern::parse("1-2").unwrap(), &ids), "01-02");
     }
 }
@@ -0,0 +1,560 @@
+pub(crate) mod samples;
+mod sigrok;
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use crate::crc::crc15;
+use crate::message::Message;
+
+use self::samples::{read_samples, Samples};
+use self::sigrok::read_annotations;
+
+// Bits of the same level after which the transmitter inserts a stuff bit
+const STUFF_WIDTH: usize = 5;
+// Recessive bits that precede every start of frame: ACK delimiter, end of frame and
+// intermission, or error delimiter and intermission, less a bit for clock tolerance
+const IDLE_BITS: f64 = 10.0;
+const MAX_BASE_ID: u32 = 0x7FF;
+const END_OF_FRAME_BITS: usize = 7;
+const DEFAULT_SJW: f64 = 0.125;
+
+fn default_sjw() -> f64 {
+    DEFAULT_SJW
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BitTiming {
+    pub bitrate: u32,
+    // Position of the sample point within a bit, from 0 to 1
+    pub sample_point: f64,
+    // Synchronization jump width, the most one resynchronization moves a bit, from 0 to 1
+    #[serde(default = "default_sjw")]
+    pub sjw: f64,
+}
+
+impl Default for BitTiming {
+    fn default() -> Self {
+        Self {
+            bitrate: 500_000,
+            sample_point: 0.75,
+            sjw: DEFAULT_SJW,
+        }
+    }
+}
+
+impl BitTiming {
+    fn bit_time(&self) -> f64 {
+        1.0 / self.bitrate as f64
+    }
+
+    // Bitrate in the form the speed column uses, such as 500k or 1M
+    pub(crate) fn speed(&self) -> String {
+        match self.bitrate {
+            bitrate if bitrate >= 1_000_000 && bitrate % 1_000_000 == 0 => {
+                format!("{}M", bitrate / 1_000_000)
+            }
+            bitrate => format!("{}k", (bitrate + 500) / 1000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhysicalErrorKind {
+    Stuff,
+    // A fixed-form bit had the wrong level
+    Form { field: &'static str },
+    Crc { received: u32, computed: u32 },
+    Ack,
+    // Frames with the FDF bit set aren't decoded
+    CanFd,
+    // The capture ended within a frame
+    Truncated,
+    // From an imported decoder's annotations
+    Reported(String),
+}
+
+impl fmt::Display for PhysicalErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PhysicalErrorKind::Stuff => write!(f, "stuff error"),
+            PhysicalErrorKind::Form { field } => write!(f, "form error in {}", field),
+            PhysicalErrorKind::Crc { received, computed } => write!(
+                f,
+                "CRC error: received {:04X}, computed {:04X}",
+                received, computed
+            ),
+            PhysicalErrorKind::Ack => write!(f, "ACK error"),
+            PhysicalErrorKind::CanFd => write!(f, "CAN FD frame not decoded"),
+            PhysicalErrorKind::Truncated => write!(f, "capture ends within frame"),
+            PhysicalErrorKind::Reported(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysicalError {
+    // Sample time of the offending bit
+    pub time: f64,
+    // Start of the frame it was found in
+    pub frame_time: f64,
+    pub kind: PhysicalErrorKind,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Decoded {
+    pub messages: Vec<Message>,
+    pub errors: Vec<PhysicalError>,
+}
+
+// Reads bits from the samples, following the bit timing of the transmitter by hard
+// synchronization on the start of frame and resynchronization on every recessive to dominant
+// edge, by at most the synchronization jump width, and removes stuff bits while stuffing is on.
+struct BitReader<'a> {
+    samples: &'a Samples,
+    timing: &'a BitTiming,
+    sof: f64,
+    // Expected start of the next bit and the sample time of the last one
+    bit_start: f64,
+    last_sample: f64,
+    stuffing: bool,
+    run_level: bool,
+    run: usize,
+    // Unstuffed bits from the start of frame, for the CRC
+    bits: Vec<bool>,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(samples: &'a Samples, timing: &'a BitTiming, sof: f64) -> Self {
+        Self {
+            samples,
+            timing,
+            sof,
+            bit_start: sof,
+            last_sample: sof,
+            stuffing: true,
+            run_level: false,
+            run: 0,
+            bits: Vec::new(),
+        }
+    }
+
+    fn error(&self, time: f64, kind: PhysicalErrorKind) -> PhysicalError {
+        PhysicalError {
+            time,
+            frame_time: self.sof,
+            kind,
+        }
+    }
+
+    fn sample(&mut self) -> Result<(bool, f64), PhysicalError> {
+        let bit_time = self.timing.bit_time();
+        let nominal = self.bit_start + self.timing.sample_point * bit_time;
+        // An edge between the last sample point and this one marks the start of this bit
+        if let Some(edge) = self.samples.falling_edge(self.last_sample, nominal) {
+            let jump = self.timing.sjw * bit_time;
+            self.bit_start += (edge - self.bit_start).clamp(-jump, jump);
+        }
+        let time = self.bit_start + self.timing.sample_point * bit_time;
+        if time > self.samples.end() {
+            return Err(self.error(self.samples.end(), PhysicalErrorKind::Truncated));
+        }
+        self.bit_start += bit_time;
+        self.last_sample = time;
+        Ok((self.samples.level_at(time), time))
+    }
+
+    fn bit(&mut self) -> Result<(bool, f64), PhysicalError> {
+        let (level, time) = self.sample()?;
+        if !self.stuffing {
+            return Ok((level, time));
+        }
+        let (level, time) = match self.run == STUFF_WIDTH {
+            false => (level, time),
+            true if level == self.run_level => {
+                return Err(self.error(time, PhysicalErrorKind::Stuff));
+            }
+            true => {
+                // Drop the stuff bit, which starts a new run
+                self.run_level = level;
+                self.run = 1;
+                self.sample()?
+            }
+        };
+        match level == self.run_level {
+            true => self.run += 1,
+            false => {
+                self.run_level = level;
+                self.run = 1;
+            }
+        }
+        self.bits.push(level);
+        Ok((level, time))
+    }
+
+    // Stuffing ends with the CRC sequence, which can still be followed by a stuff bit
+    fn end_stuffing(&mut self) -> Result<(), PhysicalError> {
+        if self.run == STUFF_WIDTH {
+            let (level, time) = self.sample()?;
+            if level == self.run_level {
+                return Err(self.error(time, PhysicalErrorKind::Stuff));
+            }
+        }
+        self.stuffing = false;
+        Ok(())
+    }
+
+    fn bits(&mut self, count: usize) -> Result<u32, PhysicalError> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.bit()?.0 as u32;
+        }
+        Ok(value)
+    }
+
+    // A bit that must be recessive, as in the delimiters and end of frame
+    fn recessive(&mut self, field: &'static str) -> Result<f64, PhysicalError> {
+        match self.bit()? {
+            (true, time) => Ok(time),
+            (false, time) => Err(self.error(time, PhysicalErrorKind::Form { field })),
+        }
+    }
+
+    // Read a classical data or remote frame. CRC and ACK errors still give a message, with the
+    // error added to the list.
+    fn frame(&mut self, errors: &mut Vec<PhysicalError>) -> Result<Message, PhysicalError> {
+        if let (true, time) = self.bit()? {
+            return Err(self.error(
+                time,
+                PhysicalErrorKind::Form {
+                    field: "start of frame",
+                },
+            ));
+        }
+        let mut id = self.bits(11)?;
+        let (rtr, _) = self.bit()?;
+        let (ide, _) = self.bit()?;
+        let (rtr, extended, (fdf, fdf_time)) = match ide {
+            false => (rtr, false, self.bit()?),
+            true => {
+                id = (id << 18) | self.bits(18)?;
+                let rtr = self.bit()?.0;
+                (rtr, true, self.bit()?)
+            }
+        };
+        if fdf {
+            return Err(self.error(fdf_time, PhysicalErrorKind::CanFd));
+        }
+        if extended {
+            // r0
+            self.bit()?;
+        }
+        let dlc = self.bits(4)? as usize;
+        let length = match rtr {
+            true => 0,
+            false => dlc.min(8),
+        };
+        let mut data = Vec::with_capacity(length);
+        for _ in 0..length {
+            data.push(self.bits(8)? as u8);
+        }
+
+        let computed = crc15(&self.bits);
+        let received = self.bits(15)?;
+        let crc_time = self.last_sample;
+        self.end_stuffing()?;
+        self.recessive("CRC delimiter")?;
+        let (ack, ack_time) = self.bit()?;
+        let ack = !ack;
+        self.recessive("ACK delimiter")?;
+
+        let message = Message {
+            timestamp: self.sof,
+            id: match extended || id > MAX_BASE_ID {
+                true => id.to_be_bytes().to_vec(),
+                false => (id as u16).to_be_bytes().to_vec(),
+            },
+            data,
+            crc: (received as u16).to_be_bytes().to_vec(),
+            ack,
+            speed: self.timing.speed(),
+        };
+
+        // Receivers signal CRC and ACK errors with an error frame right after the ACK delimiter,
+        // so the end of frame isn't checked after either
+        if received != computed {
+            errors.push(self.error(crc_time, PhysicalErrorKind::Crc { received, computed }));
+            return Ok(message);
+        }
+        if !ack {
+            errors.push(self.error(ack_time, PhysicalErrorKind::Ack));
+            return Ok(message);
+        }
+        for _ in 0..END_OF_FRAME_BITS {
+            self.recessive("end of frame")?;
+        }
+        Ok(message)
+    }
+}
+
+// Decode classical CAN frames from the samples of a CAN_RX or CAN_TX line. Each frame starts
+// at a recessive to dominant edge after a stretch of bus idle, so decoding picks up again at
+// the next frame after an error, and a capture that starts within a frame skips that frame.
+pub(crate) fn decode(samples: &Samples, timing: &BitTiming) -> Decoded {
+    let mut decoded = Decoded::default();
+    let idle = IDLE_BITS * timing.bit_time();
+    let mut time = f64::MIN;
+
+    while let Some(sof) = samples.falling_edge_after_idle(time, idle) {
+        let mut reader = BitReader::new(samples, timing, sof);
+        match reader.frame(&mut decoded.errors) {
+            Ok(message) => decoded.messages.push(message),
+            Err(error) => {
+                let truncated = error.kind == PhysicalErrorKind::Truncated;
+                decoded.errors.push(error);
+                if truncated {
+                    break;
+                }
+            }
+        }
+        time = reader.last_sample;
+    }
+    decoded
+}
+
+// Decode a logic analyzer export, or read the frames of an annotation export. The sample rate
+// only applies to annotations, which don't record it.
+pub(crate) fn decode_file(
+    path: &Path,
+    channel: &str,
+    timing: &BitTiming,
+    samplerate: f64,
+) -> Result<Decoded, Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    match extension.to_lowercase().as_str() {
+        "txt" => {
+            let (messages, errors) = read_annotations(path, samplerate, &timing.speed())?;
+            Ok(Decoded { messages, errors })
+        }
+        _ => Ok(decode(&read_samples(path, channel)?, timing)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIT_TIME: f64 = 2e-6;
+    // Captures with a pre-trigger start before zero
+    const START: f64 = -100e-6;
+
+    fn push_bits(bits: &mut Vec<bool>, value: u32, width: usize) {
+        for bit in (0..width).rev() {
+            bits.push((value >> bit) & 1 != 0);
+        }
+    }
+
+    // Unstuffed bits of a base data frame from SOF to the end of the data field
+    fn frame_bits(id: u32, data: &[u8]) -> Vec<bool> {
+        let mut bits = vec![false];
+        push_bits(&mut bits, id, 11);
+        // RTR, IDE and r0
+        bits.extend([false, false, false]);
+        push_bits(&mut bits, data.len() as u32, 4);
+        for byte in data {
+            push_bits(&mut bits, *byte as u32, 8);
+        }
+        bits
+    }
+
+    fn stuff(bits: &[bool]) -> Vec<bool> {
+        let mut stuffed = Vec::new();
+        let mut run = 0;
+        for bit in bits {
+            match stuffed.last() == Some(bit) {
+                true => run += 1,
+                false => run = 1,
+            }
+            stuffed.push(*bit);
+            if run == STUFF_WIDTH {
+                stuffed.push(!bit);
+                run = 1;
+            }
+        }
+        stuffed
+    }
+
+    // The stuffed frame up to the CRC delimiter, with the CRC of the unstuffed bits
+    fn stuffed_frame(bits: &[bool], crc: u32) -> Vec<bool> {
+        let mut bits = bits.to_vec();
+        push_bits(&mut bits, crc, 15);
+        stuff(&bits)
+    }
+
+    // Delimiters, ACK, end of frame and intermission
+    fn frame_end(bits: &mut Vec<bool>, ack: bool) {
+        bits.extend([true, !ack, true]);
+        bits.extend([true; END_OF_FRAME_BITS + 3]);
+    }
+
+    // Samples of the bits after 11 bits of bus idle, each lasting a given time
+    fn samples(bits: &[bool], bit_time: f64) -> Samples {
+        let mut samples = Samples::default();
+        samples.push(START, true).unwrap();
+        for (index, bit) in bits.iter().enumerate() {
+            samples
+                .push(START + (11 + index) as f64 * bit_time, *bit)
+                .unwrap();
+        }
+        samples
+            .push(START + (11 + bits.len()) as f64 * bit_time, true)
+            .unwrap();
+        samples
+    }
+
+    fn frame(id: u32, data: &[u8]) -> Vec<bool> {
+        let bits = frame_bits(id, data);
+        let mut bits = stuffed_frame(&bits, crc15(&bits));
+        frame_end(&mut bits, true);
+        bits
+    }
+
+    #[test]
+    fn data_frames() {
+        let mut bits = frame(0x123, &[0xDE, 0xAD, 0xBE, 0xEF]);
+        bits.extend(frame(0x000, &[]));
+        let decoded = decode(&samples(&bits, BIT_TIME), &BitTiming::default());
+        assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+        assert_eq!(decoded.messages.len(), 2);
+        let message = &decoded.messages[0];
+        assert!((message.timestamp - (START + 11.0 * BIT_TIME)).abs() < 1e-12);
+        assert_eq!(message.id, vec![0x01, 0x23]);
+        assert_eq!(message.data, vec![0xDE, 0xAD, 0xBE, 0xEF]);
+        assert!(message.ack);
+        assert_eq!(message.speed, "500k");
+        let crc = crc15(&frame_bits(0x123, &[0xDE, 0xAD, 0xBE, 0xEF])) as u16;
+        assert_eq!(message.crc, crc.to_be_bytes().to_vec());
+        assert_eq!(decoded.messages[1].id, vec![0x00, 0x00]);
+    }
+
+    #[test]
+    fn resynchronization() {
+        // A transmitter 2% slow stays in sync through the edges
+        let bits = frame(0x555, &[0x00, 0xFF, 0x0F]);
+        let slow = samples(&bits, BIT_TIME * 1.02);
+        let decoded = decode(&slow, &BitTiming::default());
+        assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+        assert_eq!(decoded.messages[0].data, vec![0x00, 0xFF, 0x0F]);
+        let fixed = BitTiming {
+            sjw: 0.0,
+            ..BitTiming::default()
+        };
+        let decoded = decode(&slow, &fixed);
+        assert!(decoded.messages.is_empty() || decoded.messages[0].data != [0x00, 0xFF, 0x0F]);
+
+        // A glitch within a recessive bit moves the bit by no more than the jump width
+        let index = (1..bits.len()).find(|i| bits[*i] && !bits[i + 1]).unwrap();
+        let time = |bit: f64| START + (11.0 + bit) * BIT_TIME;
+        let mut glitched = Samples::default();
+        glitched.push(START, true).unwrap();
+        for (bit, level) in bits.iter().enumerate() {
+            glitched.push(time(bit as f64), *level).unwrap();
+            if bit == index {
+                glitched.push(time(bit as f64 + 0.4), false).unwrap();
+                glitched.push(time(bit as f64 + 0.45), true).unwrap();
+            }
+        }
+        glitched.push(time(bits.len() as f64), true).unwrap();
+        let decoded = decode(&glitched, &BitTiming::default());
+        assert!(decoded.errors.is_empty(), "{:?}", decoded.errors);
+        assert_eq!(decoded.messages[0].data, vec![0x00, 0xFF, 0x0F]);
+    }
+
+    #[test]
+    fn stuff_error() {
+        let mut bits = frame(0x000, &[]);
+        // SOF and four ID bits are followed by the first stuff bit
+        assert!(bits[5]);
+        bits.remove(5);
+        let decoded = decode(&samples(&bits, BIT_TIME), &BitTiming::default());
+        assert!(decoded.messages.is_empty());
+        assert_eq!(decoded.errors[0].kind, PhysicalErrorKind::Stuff);
+        let sixth_bit = START + (11.0 + 5.75) * BIT_TIME;
+        assert!((decoded.errors[0].time - sixth_bit).abs() < 1e-12);
+    }
+
+    #[test]
+    fn form_error() {
+        let frame_bits = frame_bits(0x123, &[0x01]);
+        let mut bits = stuffed_frame(&frame_bits, crc15(&frame_bits));
+        let delimiter = bits.len();
+        frame_end(&mut bits, true);
+        bits[delimiter] = false;
+        let decoded = decode(&samples(&bits, BIT_TIME), &BitTiming::default());
+        assert!(decoded.messages.is_empty());
+        assert_eq!(
+            decoded.errors[0].kind,
+            PhysicalErrorKind::Form {
+                field: "CRC delimiter"
+            }
+        );
+    }
+
+    #[test]
+    fn crc_error() {
+        let sent = frame_bits(0x123, &[0x01]);
+        let received = crc15(&sent);
+        // A bit flipped on the bus after the transmitter computed the CRC
+        let mut flipped = sent.clone();
+        let last = flipped.len() - 1;
+        flipped[last] = !flipped[last];
+        let mut bits = stuffed_frame(&flipped, received);
+        frame_end(&mut bits, true);
+        let decoded = decode(&samples(&bits, BIT_TIME), &BitTiming::default());
+        assert_eq!(decoded.messages[0].data, vec![0x00]);
+        assert_eq!(decoded.errors.len(), 1);
+        assert_eq!(
+            decoded.errors[0].kind,
+            PhysicalErrorKind::Crc {
+                received,
+                computed: crc15(&flipped)
+            }
+        );
+    }
+
+    #[test]
+    fn ack_error() {
+        let frame_bits = frame_bits(0x123, &[0x01]);
+        let mut bits = stuffed_frame(&frame_bits, crc15(&frame_bits));
+        frame_end(&mut bits, false);
+        bits.extend(frame(0x124, &[]));
+        let decoded = decode(&samples(&bits, BIT_TIME), &BitTiming::default());
+        assert_eq!(decoded.messages.len(), 2);
+        assert!(!decoded.messages[0].ack);
+        assert_eq!(decoded.errors.len(), 1);
+        assert_eq!(decoded.errors[0].kind, PhysicalErrorKind::Ack);
+    }
+
+    #[test]
+    fn truncated() {
+        let bits = frame(0x123, &[0x01, 0x02]);
+        let decoded = decode(&samples(&bits[..30], BIT_TIME), &BitTiming::default());
+        assert!(decoded.messages.is_empty());
+        assert_eq!(decoded.errors[0].kind, PhysicalErrorKind::Truncated);
+    }
+
+    #[test]
+    fn speeds() {
+        let timing = |bitrate| BitTiming {
+            bitrate,
+            ..BitTiming::default()
+        };
+        assert_eq!(timing(1_000_000).speed(), "1M");
+        assert_eq!(timing(125_000).speed(), "125k");
+        assert_eq!(timing(83_333).speed(), "83k");
+    }
+}
@@ -0,0 +1,334 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::sigrok::read_session;
+
+// The levels of one digital channel, stored as the times the level changed. Recessive is high.
+#[derive(Debug, Default, Clone)]
+pub struct Samples {
+    times: Vec<f64>,
+    levels: Vec<bool>,
+    // Time of the last sample, which may be after the last change
+    end: f64,
+}
+
+impl Samples {
+    pub(super) fn push(&mut self, time: f64, level: bool) -> Result<(), Box<dyn Error>> {
+        // Captures with a pre-trigger start at negative times
+        if !self.times.is_empty() && time < self.end {
+            return Err(format!("time {} is before the previous sample", time).into());
+        }
+        if self.levels.last() != Some(&level) {
+            self.times.push(time);
+            self.levels.push(level);
+        }
+        self.end = time;
+        Ok(())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub(crate) fn end(&self) -> f64 {
+        self.end
+    }
+
+    // Level at a time, taking the first sample's level for anything before it
+    pub(crate) fn level_at(&self, time: f64) -> bool {
+        match self.times.partition_point(|t| *t <= time) {
+            0 => self.levels[0],
+            index => self.levels[index - 1],
+        }
+    }
+
+    // First change from recessive to dominant after start and before end
+    pub(crate) fn falling_edge(&self, start: f64, end: f64) -> Option<f64> {
+        let first = self.times.partition_point(|t| *t <= start).max(1);
+        (first..self.times.len())
+            .take_while(|index| self.times[*index] < end)
+            .find(|index| !self.levels[*index])
+            .map(|index| self.times[index])
+    }
+
+    // First falling edge after a time that follows at least a given time of recessive level
+    pub(crate) fn falling_edge_after_idle(&self, start: f64, idle: f64) -> Option<f64> {
+        let first = self.times.partition_point(|t| *t <= start).max(1);
+        (first..self.times.len())
+            .find(|index| {
+                !self.levels[*index] && self.times[*index] - self.times[index - 1] >= idle
+            })
+            .map(|index| self.times[index])
+    }
+}
+
+fn parse_level(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "0" | "low" | "false" => Some(false),
+        "1" | "high" | "true" => Some(true),
+        _ => None,
+    }
+}
+
+// Read a transition export such as Saleae's digital CSV: the time in seconds in the first
+// column and one column per channel, with a row whenever any channel changes. An empty channel
+// name picks the first channel.
+pub(crate) fn read_csv(path: &Path, channel: &str) -> Result<Samples, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b';'))
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+
+    let mut samples = Samples::default();
+    let mut column = None;
+    for (line, record) in reader.records().enumerate() {
+        let record = record?;
+        let time = match record.get(0).map(|time| time.parse::<f64>()) {
+            Some(Ok(time)) => time,
+            _ if line == 0 => {
+                // Header row
+                column = match channel.is_empty() {
+                    true => Some(1),
+                    false => record
+                        .iter()
+                        .position(|name| name.eq_ignore_ascii_case(channel.trim())),
+                };
+                if column.is_none() {
+                    return Err(format!("no channel named {}", channel).into());
+                }
+                continue;
+            }
+            _ => return Err(format!("invalid time on line {}", line + 1).into()),
+        };
+        let column = *column.get_or_insert(1);
+        let level = match record.get(column).and_then(parse_level) {
+            Some(level) => level,
+            None => return Err(format!("invalid level on line {}", line + 1).into()),
+        };
+        samples.push(time, level)?;
+    }
+
+    if samples.is_empty() {
+        return Err("no samples found".into());
+    }
+    Ok(samples)
+}
+
+// Seconds per unit of a $timescale such as "1ns" or "10 us"
+fn parse_timescale(text: &str) -> Result<f64, Box<dyn Error>> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse()?;
+    let unit = match unit.trim() {
+        "s" => 1.0,
+        "ms" => 1e-3,
+        "us" => 1e-6,
+        "ns" => 1e-9,
+        "ps" => 1e-12,
+        "fs" => 1e-15,
+        unit => return Err(format!("unknown timescale unit {}", unit).into()),
+    };
+    Ok(number * unit)
+}
+
+// Read one signal from a value change dump. An empty channel name picks the first signal.
+// Unknown and high-impedance values count as recessive.
+pub(crate) fn read_vcd(path: &Path, channel: &str) -> Result<Samples, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let mut tokens = text.split_whitespace();
+
+    let mut timescale = 1e-9;
+    let mut code = None;
+    let mut samples = Samples::default();
+    let mut time = 0.0;
+    let mut started = false;
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "$timescale" => {
+                let value: Vec<&str> = tokens.by_ref().take_while(|t| *t != "$end").collect();
+                timescale = parse_timescale(&value.concat())?;
+            }
+            "$var" => {
+                // Type, width, identifier code and reference
+                let var: Vec<&str> = tokens.by_ref().take_while(|t| *t != "$end").collect();
+                let matches = var.len() >= 4
+                    && (channel.is_empty() || var[3].eq_ignore_ascii_case(channel.trim()));
+                if matches && code.is_none() {
+                    code = Some(var[2].to_string());
+                }
+            }
+            "$comment" | "$date" | "$version" | "$scope" | "$upscope" => {
+                tokens.by_ref().find(|t| *t == "$end");
+            }
+            token if token.starts_with('$') => {}
+            token if token.starts_with('#') => {
+                time = token[1..].parse::<f64>()? * timescale;
+                if started {
+                    samples.end = samples.end.max(time);
+                }
+            }
+            token if token.starts_with('b') || token.starts_with('B') => {
+                // Vector value followed by its identifier code; the last bit is used
+                let id = tokens.next().unwrap_or_default();
+                if Some(id) == code.as_deref() {
+                    let level = token
+                        .chars()
+                        .last()
+                        .and_then(|c| parse_level(&c.to_string()));
+                    samples.push(time, level.unwrap_or(true))?;
+                    started = true;
+                }
+            }
+            token if token.starts_with('r') || token.starts_with('R') => {
+                tokens.next();
+            }
+            token => {
+                let (value, id) = token.split_at(1);
+                if Some(id) == code.as_deref() {
+                    samples.push(time, parse_level(value).unwrap_or(true))?;
+                    started = true;
+                }
+            }
+        }
+    }
+
+    match (code, samples.is_empty()) {
+        (None, _) if channel.is_empty() => Err("no signals found".into()),
+        (None, _) => Err(format!("no signal named {}", channel).into()),
+        (Some(_), true) => Err("no value changes found".into()),
+        (Some(_), false) => Ok(samples),
+    }
+}
+
+// Read a CSV or VCD export, going by the file extension
+pub(crate) fn read_samples(path: &Path, channel: &str) -> Result<Samples, Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    match extension.to_lowercase().as_str() {
+        "vcd" => read_vcd(path, channel),
+        "sr" => read_session(path, channel),
+        _ => read_csv(path, channel),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn read_text<T>(
+        name: &str,
+        text: &str,
+        read: impl Fn(&Path) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        let path = env::temp_dir().join(format!("can_decode_{}_{}", process::id(), name));
+        fs::write(&path, text).unwrap();
+        let result = read(&path);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn push() {
+        let mut samples = Samples::default();
+        samples.push(-2.0, true).unwrap();
+        samples.push(-1.0, true).unwrap();
+        samples.push(0.5, false).unwrap();
+        assert!(samples.push(0.25, true).is_err());
+        assert_eq!(samples.end(), 0.5);
+        assert!(samples.level_at(-3.0));
+        assert!(samples.level_at(0.0));
+        assert!(!samples.level_at(1.0));
+        assert_eq!(samples.falling_edge(-2.0, 1.0), Some(0.5));
+        assert_eq!(samples.falling_edge(-2.0, 0.5), None);
+        assert_eq!(samples.falling_edge_after_idle(-3.0, 2.5), Some(0.5));
+        assert_eq!(samples.falling_edge_after_idle(-3.0, 3.0), None);
+    }
+
+    #[test]
+    fn csv() {
+        // Saleae export with a pre-trigger
+        let text = "Time [s],CAN_TX,CAN_RX\n\
+                    -0.000100,1,1\n\
+                    -0.000050,1,0\n\
+                    0.000000,0,0\n\
+                    0.000020,1,1\n";
+        let samples = read_text("samples.csv", text, |path| read_csv(path, "can_rx")).unwrap();
+        assert!(samples.level_at(-0.00006));
+        assert!(!samples.level_at(-0.00001));
+        assert_eq!(samples.falling_edge(-0.0001, 0.0), Some(-0.00005));
+        assert_eq!(samples.end(), 0.00002);
+
+        let samples = read_text("samples.csv", text, |path| read_csv(path, "")).unwrap();
+        assert_eq!(samples.falling_edge(-0.0001, 0.0001), Some(0.0));
+
+        let error = read_text("samples.csv", text, |path| read_csv(path, "CAN_H"));
+        assert_eq!(error.unwrap_err().to_string(), "no channel named CAN_H");
+        let error = read_text("samples.csv", "0.0,1\n0.1,x\n", |path| read_csv(path, ""));
+        assert_eq!(error.unwrap_err().to_string(), "invalid level on line 2");
+        let error = read_text("samples.csv", "0.1,1\n0.0,0\n", |path| read_csv(path, ""));
+        assert!(error.is_err());
+    }
+
+    const VCD: &str = "$date today $end\n\
+                       $version generator $end\n\
+                       $timescale 10 ns $end\n\
+                       $scope module can $end\n\
+                       $var wire 1 ! CAN_RX $end\n\
+                       $var wire 1 \" CAN_TX $end\n\
+                       $upscope $end\n\
+                       $enddefinitions $end\n\
+                       #0\n\
+                       $dumpvars\n\
+                       1!\n\
+                       0\"\n\
+                       $end\n\
+                       #100\n\
+                       0!\n\
+                       #120\n\
+                       b1 \"\n\
+                       #200\n\
+                       x!\n\
+                       #300\n";
+
+    #[test]
+    fn vcd() {
+        let samples = read_text("samples.vcd", VCD, |path| read_vcd(path, "")).unwrap();
+        assert!(samples.level_at(0.5e-6));
+        assert!(!samples.level_at(1.5e-6));
+        // Unknown is recessive
+        assert!(samples.level_at(2.5e-6));
+        assert_eq!(samples.falling_edge(0.0, 3e-6), Some(1e-6));
+        assert!((samples.end() - 3e-6).abs() < 1e-15);
+
+        let samples = read_text("samples.vcd", VCD, |path| read_vcd(path, "can_tx")).unwrap();
+        assert!(!samples.level_at(1e-6));
+        assert!(samples.level_at(1.25e-6));
+
+        let error = read_text("samples.vcd", VCD, |path| read_vcd(path, "CAN_H"));
+        assert_eq!(error.unwrap_err().to_string(), "no signal named CAN_H");
+        let text = VCD.replace("10 ns", "10 ks");
+        let error = read_text("samples.vcd", &text, |path| read_vcd(path, ""));
+        assert_eq!(error.unwrap_err().to_string(), "unknown timescale unit ks");
+        let text = &VCD[..VCD.find("#0").unwrap()];
+        let error = read_text("samples.vcd", text, |path| read_vcd(path, ""));
+        assert_eq!(error.unwrap_err().to_string(), "no value changes found");
+    }
+
+    #[test]
+    fn timescales() {
+        assert_eq!(parse_timescale("1s").unwrap(), 1.0);
+        assert_eq!(parse_timescale("100 us").unwrap(), 100.0 * 1e-6);
+        assert_eq!(parse_timescale("1ps").unwrap(), 1e-12);
+        assert!(parse_timescale("ns").is_err());
+    }
+}
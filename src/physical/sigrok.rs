@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::message::Message;
+use crate::zip::ZipArchive;
+
+use super::samples::Samples;
+use super::{PhysicalError, PhysicalErrorKind};
+
+const MAX_BASE_ID: u32 = 0x7FF;
+
+// Sample rate from the session metadata, such as "1 MHz" or "24000000"
+fn parse_samplerate(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let multiplier = match unit.trim().to_lowercase().as_str() {
+        "" | "hz" => 1.0,
+        "khz" => 1e3,
+        "mhz" => 1e6,
+        "ghz" => 1e9,
+        _ => return None,
+    };
+    Some(number.parse::<f64>().ok()? * multiplier)
+}
+
+// Keys of the first device section of the metadata file
+fn parse_metadata(text: &str) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    let mut in_device = false;
+    for line in text.lines().map(|line| line.trim()) {
+        if line.starts_with('[') {
+            if in_device {
+                break;
+            }
+            in_device = line.starts_with("[device");
+        } else if let (true, Some((key, value))) = (in_device, line.split_once('=')) {
+            metadata.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    metadata
+}
+
+// Chunk number of a logic data file, where version 1 sessions have a single unnumbered file
+fn chunk_number(name: &str, capture_file: &str) -> Option<usize> {
+    match name.strip_prefix(capture_file)? {
+        "" => Some(0),
+        suffix => suffix.strip_prefix('-')?.parse().ok(),
+    }
+}
+
+// Read one channel of a sigrok session archive. The channel is named as in the session, or
+// empty for the first one.
+pub(crate) fn read_session(path: &Path, channel: &str) -> Result<Samples, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let archive = ZipArchive::new(&data)?;
+    let metadata = String::from_utf8(archive.read("metadata")?)?;
+    let metadata = parse_metadata(&metadata);
+
+    let samplerate = metadata
+        .get("samplerate")
+        .and_then(|rate| parse_samplerate(rate))
+        .ok_or("no sample rate in session metadata")?;
+    let unit_size: usize = metadata
+        .get("unitsize")
+        .map_or(Ok(1), |size| size.parse())?;
+    // Channels are listed as probe1=name, counting from 1
+    let probe = (1..=unit_size * 8)
+        .find(|probe| match metadata.get(&format!("probe{}", probe)) {
+            Some(name) => channel.is_empty() || name.eq_ignore_ascii_case(channel.trim()),
+            None => false,
+        })
+        .ok_or_else(|| match channel.is_empty() {
+            true => "no logic channels in session".to_string(),
+            false => format!("no channel named {}", channel),
+        })?;
+    let bit = probe - 1;
+
+    let capture_file = metadata
+        .get("capturefile")
+        .map_or("logic-1", |name| name.as_str());
+    let mut chunks: Vec<(usize, &str)> = archive
+        .entries
+        .iter()
+        .filter_map(|entry| {
+            Some((
+                chunk_number(&entry.name, capture_file)?,
+                entry.name.as_str(),
+            ))
+        })
+        .collect();
+    chunks.sort();
+
+    let mut samples = Samples::default();
+    let mut index = 0;
+    let mut level = None;
+    for (_, name) in chunks {
+        let chunk = archive.read(name)?;
+        for sample in chunk.chunks_exact(unit_size) {
+            let value = sample[bit / 8] & (1 << (bit % 8)) != 0;
+            if level != Some(value) {
+                samples.push(index as f64 / samplerate, value)?;
+                level = Some(value);
+            }
+            index += 1;
+        }
+    }
+    if samples.is_empty() {
+        return Err("no samples in session".into());
+    }
+    samples.push((index - 1) as f64 / samplerate, level.unwrap())?;
+    Ok(samples)
+}
+
+// A frame being put together from annotations
+#[derive(Default)]
+struct AnnotatedFrame {
+    timestamp: f64,
+    id: Option<u32>,
+    extended: bool,
+    data: Vec<u8>,
+    crc: Option<u32>,
+    // CAN FD frames have 17 or 21 bit CRCs
+    wide_crc: bool,
+    ack: bool,
+}
+
+impl AnnotatedFrame {
+    // Frames are kept once they got as far as the CRC, so ACK and end of frame errors still
+    // give a row
+    fn message(&self, speed: &str) -> Option<Message> {
+        let id = self.id?;
+        let crc = self.crc?;
+        Some(Message {
+            timestamp: self.timestamp,
+            id: match self.extended || id > MAX_BASE_ID {
+                true => id.to_be_bytes().to_vec(),
+                false => (id as u16).to_be_bytes().to_vec(),
+            },
+            data: self.data.clone(),
+            crc: match self.wide_crc {
+                true => crc.to_be_bytes()[1..].to_vec(),
+                false => (crc as u16).to_be_bytes().to_vec(),
+            },
+            ack: self.ack,
+            speed: speed.to_string(),
+        })
+    }
+}
+
+// The number in annotation values such as "291 (0x123)" or "0x1cb9"
+fn annotation_number(value: &str) -> Option<u32> {
+    let value = value.split_whitespace().next()?;
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+// Read the output of sigrok's can decoder, as printed by sigrok-cli -A can or exported from
+// PulseView. Lines may start with a sample range, which gives the frame time at the sample
+// rate, and the decoder and row names before the annotation itself. Without sample ranges,
+// frames are timed by their number.
+pub(crate) fn read_annotations(
+    path: &Path,
+    samplerate: f64,
+    speed: &str,
+) -> Result<(Vec<Message>, Vec<PhysicalError>), Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let mut messages = Vec::new();
+    let mut errors = Vec::new();
+    let mut frame: Option<AnnotatedFrame> = None;
+
+    for line in text.lines().map(|line| line.trim()) {
+        let (time, line) = match line.split_once(' ') {
+            Some((range, rest)) if range.contains('-') && range.starts_with(char::is_numeric) => {
+                let start = range.split('-').next().unwrap_or_default();
+                (
+                    start.parse::<f64>().ok().map(|start| start / samplerate),
+                    rest,
+                )
+            }
+            _ => (None, line),
+        };
+        let parts: Vec<&str> = line.split(": ").collect();
+        let field = parts
+            .iter()
+            .position(|part| {
+                let part = part.trim();
+                part == "Start of frame"
+                    || part == "End of frame"
+                    || part.ends_with("Identifier")
+                    || part.starts_with("Data byte ")
+                    || (part.starts_with("CRC-") && part.ends_with("sequence"))
+                    || part == "ACK slot"
+            })
+            .map(|index| (parts[index].trim(), parts.get(index + 1).copied()));
+
+        match field {
+            Some(("Start of frame", _)) => {
+                if let Some(message) = frame.take().and_then(|f| f.message(speed)) {
+                    messages.push(message);
+                }
+                frame = Some(AnnotatedFrame {
+                    timestamp: time.unwrap_or(messages.len() as f64),
+                    ..Default::default()
+                });
+            }
+            Some(("End of frame", _)) => {
+                if let Some(message) = frame.take().and_then(|f| f.message(speed)) {
+                    messages.push(message);
+                }
+            }
+            Some(("ACK slot", Some(value))) => {
+                // Either the bit or ACK / NACK
+                if let Some(frame) = frame.as_mut() {
+                    frame.ack = value.trim() == "0" || value.trim().starts_with("ACK");
+                }
+            }
+            Some((name, Some(value))) => {
+                let (frame, value) = match (frame.as_mut(), annotation_number(value)) {
+                    (Some(frame), Some(value)) => (frame, value),
+                    _ => continue,
+                };
+                match name {
+                    "Identifier" => frame.id = Some(value),
+                    "Extended Identifier" => {
+                        frame.id = Some((frame.id.unwrap_or(0) << 18) | value);
+                        frame.extended = true;
+                    }
+                    "Full Identifier" => {
+                        frame.id = Some(value);
+                        frame.extended = true;
+                    }
+                    name if name.starts_with("Data byte ") => frame.data.push(value as u8),
+                    name if name.starts_with("CRC-") => {
+                        frame.crc = Some(value);
+                        frame.wide_crc = name != "CRC-15 sequence";
+                    }
+                    _ => {}
+                }
+            }
+            _ => {
+                // Decoder warnings, such as stuff errors and bits with the wrong level
+                let lower = line.to_lowercase();
+                if lower.contains("error") || lower.contains("must be") {
+                    errors.push(PhysicalError {
+                        time: time.unwrap_or(0.0),
+                        frame_time: frame.as_ref().map_or(0.0, |frame| frame.timestamp),
+                        kind: PhysicalErrorKind::Reported(
+                            parts.last().unwrap_or(&line).trim().to_string(),
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    if let Some(message) = frame.and_then(|f| f.message(speed)) {
+        messages.push(message);
+    }
+    if messages.is_empty() {
+        return Err("no CAN frames found in annotations".into());
+    }
+    Ok((messages, errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip::tests::SESSION;
+    use std::{env, process};
+
+    fn temp_file(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!("can_decode_{}_{}", process::id(), name));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn session() {
+        let path = temp_file("session.sr", &hex::decode(SESSION).unwrap());
+        let rx = read_session(&path, "");
+        let tx = read_session(&path, "can_tx");
+        let missing = read_session(&path, "CAN_H");
+        fs::remove_file(&path).unwrap();
+
+        let rx = rx.unwrap();
+        assert_eq!(rx.falling_edge(0.0, 1.0), Some(10e-6));
+        // The second falling edge is in the second chunk
+        assert_eq!(rx.falling_edge(10e-6, 1.0), Some(30e-6));
+        assert!(rx.level_at(27e-6));
+        assert!(!rx.level_at(44e-6));
+        assert!(rx.level_at(45e-6));
+        assert_eq!(rx.end(), 63e-6);
+
+        let tx = tx.unwrap();
+        assert_eq!(tx.falling_edge(0.0, 1.0), Some(5e-6));
+        assert!(tx.level_at(8e-6));
+        assert_eq!(missing.unwrap_err().to_string(), "no channel named CAN_H");
+    }
+
+    #[test]
+    fn metadata() {
+        assert_eq!(parse_samplerate("1 MHz"), Some(1e6));
+        assert_eq!(parse_samplerate("24000000"), Some(24e6));
+        assert_eq!(parse_samplerate("12.5 kHz"), Some(12.5e3));
+        assert_eq!(parse_samplerate("fast"), None);
+
+        let metadata = parse_metadata(
+            "[global]\nsigrok version=0.5.2\n\n[device 1]\nsamplerate=1 MHz\nprobe1=RX\n\
+             \n[device 2]\nprobe1=Other\n",
+        );
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["probe1"], "RX");
+
+        assert_eq!(chunk_number("logic-1", "logic-1"), Some(0));
+        assert_eq!(chunk_number("logic-1-12", "logic-1"), Some(12));
+        assert_eq!(chunk_number("logic-10-1", "logic-1"), None);
+        assert_eq!(chunk_number("metadata", "logic-1"), None);
+    }
+
+    // Written after the output of sigrok-cli -A can, not captured from it
+    const ANNOTATIONS: &str = "\
+        1000-1002 can-1: Fields: Start of frame\n\
+        1002-1024 can-1: Fields: Identifier: 291 (0x123)\n\
+        1046-1062 can-1: Fields: Data byte 0: 0x01\n\
+        1062-1078 can-1: Fields: Data byte 1: 0x02\n\
+        1078-1108 can-1: Fields: CRC-15 sequence: 0x1cb9\n\
+        1110-1112 can-1: Fields: ACK slot: 0\n\
+        1114-1128 can-1: Fields: End of frame\n\
+        2000-2002 can-1: Fields: Start of frame\n\
+        2002-2024 can-1: Fields: Identifier: 24 (0x18)\n\
+        2028-2064 can-1: Fields: Extended Identifier: 127231 (0x1f0ff)\n\
+        2090-2120 can-1: Fields: CRC-15 sequence: 0x0123\n\
+        2122-2124 can-1: Fields: ACK slot: 1\n\
+        2126-2128 can-1: Warnings: ACK delimiter must be a recessive bit\n\
+        3000-3002 can-1: Fields: Start of frame\n\
+        3010-3012 can-1: Warnings: Stuff error\n";
+
+    #[test]
+    fn annotations() {
+        let path = temp_file("annotations.txt", ANNOTATIONS.as_bytes());
+        let result = read_annotations(&path, 1e6, "500k");
+        fs::remove_file(&path).unwrap();
+        let (messages, errors) = result.unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].timestamp, 1e-3);
+        assert_eq!(messages[0].id, vec![0x01, 0x23]);
+        assert_eq!(messages[0].data, vec![0x01, 0x02]);
+        assert_eq!(messages[0].crc, vec![0x1C, 0xB9]);
+        assert!(messages[0].ack);
+        assert_eq!(messages[0].speed, "500k");
+        assert_eq!(messages[1].id, vec![0x00, 0x61, 0xF0, 0xFF]);
+        assert!(!messages[1].ack);
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].time, 2.126e-3);
+        assert_eq!(errors[0].frame_time, 2e-3);
+        assert_eq!(
+            errors[0].kind,
+            PhysicalErrorKind::Reported("ACK delimiter must be a recessive bit".to_string())
+        );
+        assert_eq!(errors[1].frame_time, 3e-3);
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::util::{bytes_to_string, hex_to_str, parse_number};
+
+// Basic CANopen data types, by their index in the object dictionary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataType {
+    Boolean,
+    Integer(u16),
+    Unsigned(u16),
+    Real32,
+    Real64,
+    VisibleString,
+    OctetString,
+    Domain,
+}
+
+impl DataType {
+    pub(crate) fn from_index(index: u16) -> Option<Self> {
+        let data_type = match index {
+            0x0001 => DataType::Boolean,
+            0x0002 => DataType::Integer(8),
+            0x0003 => DataType::Integer(16),
+            0x0004 => DataType::Integer(32),
+            0x0005 => DataType::Unsigned(8),
+            0x0006 => DataType::Unsigned(16),
+            0x0007 => DataType::Unsigned(32),
+            0x0008 => DataType::Real32,
+            0x0009 => DataType::VisibleString,
+            0x000A => DataType::OctetString,
+            0x000F => DataType::Domain,
+            0x0010 => DataType::Integer(24),
+            0x0011 => DataType::Real64,
+            0x0012 => DataType::Integer(40),
+            0x0013 => DataType::Integer(48),
+            0x0014 => DataType::Integer(56),
+            0x0015 => DataType::Integer(64),
+            0x0016 => DataType::Unsigned(24),
+            0x0018 => DataType::Unsigned(40),
+            0x0019 => DataType::Unsigned(48),
+            0x001A => DataType::Unsigned(56),
+            0x001B => DataType::Unsigned(64),
+            _ => return None,
+        };
+        Some(data_type)
+    }
+
+    // Format a little-endian value of this type
+    pub(crate) fn format(&self, data: &[u8]) -> String {
+        let raw = data
+            .iter()
+            .take(8)
+            .rev()
+            .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        self.format_raw(raw, data)
+    }
+
+    // Format a value already extracted as raw bits, e.g. from a PDO
+    pub(crate) fn format_raw(&self, raw: u64, data: &[u8]) -> String {
+        match self {
+            DataType::Boolean => (raw != 0).to_string(),
+            DataType::Integer(bits) => {
+                let shift = 64 - (*bits).min(64) as u32;
+                (((raw << shift) as i64) >> shift).to_string()
+            }
+            DataType::Unsigned(_) => raw.to_string(),
+            DataType::Real32 => f32::from_bits(raw as u32).to_string(),
+            DataType::Real64 => f64::from_bits(raw).to_string(),
+            DataType::VisibleString => bytes_to_string(data),
+            DataType::OctetString | DataType::Domain => hex_to_str(data),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OdEntry {
+    pub name: String,
+    pub data_type: Option<DataType>,
+    // ParameterValue of a DCF, otherwise DefaultValue, with $NODEID resolved
+    pub value: Option<u64>,
+}
+
+// The objects of one device, read from its EDS or DCF file.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectDictionary {
+    // Variables by index and subindex; simple variables use subindex 0
+    pub entries: BTreeMap<(u16, u8), OdEntry>,
+    // Names of records and arrays as a whole
+    pub object_names: HashMap<u16, String>,
+    // Node-ID from the DeviceComissioning section of a DCF
+    pub node_id: Option<u8>,
+}
+
+impl ObjectDictionary {
+    pub(crate) fn entry(&self, index: u16, subindex: u8) -> Option<&OdEntry> {
+        self.entries.get(&(index, subindex))
+    }
+
+    // "Object.Sub" for sub-entries of records and arrays, or the variable name
+    pub(crate) fn name(&self, index: u16, subindex: u8) -> Option<String> {
+        let entry = self.entry(index, subindex);
+        match (self.object_names.get(&index), entry) {
+            (Some(object), Some(entry)) => Some(format!("{}.{}", object, entry.name)),
+            (None, Some(entry)) => Some(entry.name.clone()),
+            (Some(object), None) => Some(format!("{}.{}", object, subindex)),
+            (None, None) => None,
+        }
+    }
+
+    pub(crate) fn data_type(&self, index: u16, subindex: u8) -> Option<DataType> {
+        self.entry(index, subindex)
+            .and_then(|entry| entry.data_type)
+    }
+
+    pub(crate) fn value(&self, index: u16, subindex: u8) -> Option<u64> {
+        self.entry(index, subindex).and_then(|entry| entry.value)
+    }
+}
+
+// An EDS or DCF file assigned to a node, as stored in the config.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct NodeEds {
+    pub node: u8,
+    pub path: PathBuf,
+}
+
+// Section names are an index, e.g. [6040], or an index and subindex, e.g. [1018sub1]
+fn parse_section(name: &str) -> Option<(u16, Option<u8>)> {
+    let name = name.to_lowercase();
+    match name.split_once("sub") {
+        Some((index, subindex)) => Some((
+            u16::from_str_radix(index, 16).ok()?,
+            Some(u8::from_str_radix(subindex, 16).ok()?),
+        )),
+        None => Some((u16::from_str_radix(&name, 16).ok()?, None)),
+    }
+}
+
+// Values can be numbers, or sums like $NODEID+0x180
+fn parse_value(value: &str, node: u8) -> Option<u64> {
+    let mut sum = 0u64;
+    for term in value.split('+') {
+        let term = term.trim();
+        sum = sum.wrapping_add(match term.to_uppercase().as_str() {
+            "$NODEID" => node as u64,
+            _ => match term.strip_prefix('-') {
+                Some(negative) => (parse_number(negative)? as i64).wrapping_neg() as u64,
+                None => parse_number(term)?,
+            },
+        });
+    }
+    Some(sum)
+}
+
+type Section = HashMap<String, String>;
+
+fn parse_sections(text: &str) -> Vec<(String, Section)> {
+    let mut sections: Vec<(String, Section)> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name.trim().to_string(), Section::new()));
+        } else if let (Some((key, value)), Some((_, section))) =
+            (line.split_once('='), sections.last_mut())
+        {
+            section.insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    sections
+}
+
+// Build the object dictionary of a device from the text of its EDS or DCF.
+pub(crate) fn parse_eds(text: &str, node: u8) -> Result<ObjectDictionary, Box<dyn Error>> {
+    let sections = parse_sections(text);
+    let mut dictionary = ObjectDictionary {
+        node_id: sections
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("DeviceComissioning"))
+            .and_then(|(_, section)| section.get("nodeid"))
+            .and_then(|id| parse_number(id))
+            .map(|id| id as u8),
+        ..Default::default()
+    };
+
+    for (name, section) in &sections {
+        let (index, subindex) = match parse_section(name) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        let parameter_name = match section.get("parametername") {
+            Some(name) => name.clone(),
+            None => return Err(format!("[{}] has no ParameterName", name).into()),
+        };
+        // Records and arrays only name their sub-entries
+        let has_subs = section
+            .get("subnumber")
+            .and_then(|n| parse_number(n))
+            .unwrap_or(0)
+            > 0;
+        if subindex.is_none() && has_subs {
+            dictionary.object_names.insert(index, parameter_name);
+            continue;
+        }
+        let value = section
+            .get("parametervalue")
+            .or_else(|| section.get("defaultvalue"))
+            .and_then(|value| parse_value(value, node));
+        let data_type = section
+            .get("datatype")
+            .and_then(|t| parse_number(t))
+            .and_then(|t| DataType::from_index(t as u16));
+        dictionary.entries.insert(
+            (index, subindex.unwrap_or(0)),
+            OdEntry {
+                name: parameter_name,
+                data_type,
+                value,
+            },
+        );
+    }
+
+    if dictionary.entries.is_empty() {
+        return Err("no objects found".into());
+    }
+    Ok(dictionary)
+}
+
+pub(crate) fn read_eds(path: &Path, node: u8) -> Result<ObjectDictionary, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    parse_eds(&text, node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DCF: &str = "\
+[FileInfo]
+FileName=io.dcf
+; Comments and blank lines are skipped
+
+[DeviceComissioning]
+NodeID=0x05
+
+[1017]
+ParameterName=Producer heartbeat time
+DataType=0x0006
+DefaultValue=0
+ParameterValue=1000
+
+[1018]
+ParameterName=Identity Object
+SubNumber=2
+ObjectType=0x9
+
+[1018sub0]
+ParameterName=Highest sub-index supported
+DataType=0x0005
+DefaultValue=1
+
+[1018SUB1]
+parametername=Vendor-ID
+DATATYPE=0x0007
+DefaultValue=0x12345678
+
+[1800sub1]
+ParameterName=COB-ID used by TPDO
+DataType=0x0007
+DefaultValue=$NODEID+0x180
+
+[2000]
+ParameterName=Offset
+DataType=0x0003
+DefaultValue=$NODEID+-10
+";
+
+    #[test]
+    fn dictionary() {
+        let dictionary = parse_eds(DCF, 0x20).unwrap();
+        assert_eq!(dictionary.node_id, Some(5));
+        assert_eq!(dictionary.entries.len(), 5);
+        assert_eq!(dictionary.value(0x1017, 0), Some(1000));
+        assert_eq!(
+            dictionary.data_type(0x1017, 0),
+            Some(DataType::Unsigned(16))
+        );
+        assert_eq!(
+            dictionary.name(0x1018, 1).as_deref(),
+            Some("Identity Object.Vendor-ID")
+        );
+        assert_eq!(dictionary.value(0x1018, 1), Some(0x1234_5678));
+        assert_eq!(
+            dictionary.name(0x1018, 4).as_deref(),
+            Some("Identity Object.4")
+        );
+        assert_eq!(dictionary.name(0x1019, 0), None);
+        // $NODEID is the node the file is assigned to
+        assert_eq!(dictionary.value(0x1800, 1), Some(0x1A0));
+        assert_eq!(dictionary.value(0x2000, 0), Some(0x16));
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(parse_eds("[FileInfo]\nFileName=empty.eds\n", 1).is_err());
+        assert!(parse_eds("[1000]\nDataType=0x0007\n", 1).is_err());
+    }
+
+    #[test]
+    fn sections_and_values() {
+        assert_eq!(parse_section("1A00sub2"), Some((0x1A00, Some(2))));
+        assert_eq!(parse_section("6040"), Some((0x6040, None)));
+        assert_eq!(parse_section("DeviceInfo"), None);
+        assert_eq!(parse_section("1018subx"), None);
+        assert_eq!(parse_value("$NODEID+0x180", 0x7F), Some(0x1FF));
+        assert_eq!(parse_value("$nodeid + 0x600", 1), Some(0x601));
+        assert_eq!(parse_value("-1", 1), Some(u64::MAX));
+        assert_eq!(parse_value("", 1), None);
+    }
+
+    #[test]
+    fn format_values() {
+        assert_eq!(DataType::Integer(16).format(&[0xFE, 0xFF]), "-2");
+        assert_eq!(
+            DataType::Integer(24).format(&[0x00, 0x00, 0x80]),
+            "-8388608"
+        );
+        assert_eq!(
+            DataType::Unsigned(32).format(&[0x78, 0x56, 0x34, 0x12]),
+            "305419896"
+        );
+        assert_eq!(DataType::Real32.format(&1.5f32.to_le_bytes()), "1.5");
+        assert_eq!(DataType::Boolean.format(&[0x01]), "true");
+        assert_eq!(DataType::VisibleString.format(b"IO\x01"), "IO_");
+        assert_eq!(DataType::Domain.format(&[0xDE, 0xAD]), "DE AD");
+        assert_eq!(DataType::from_index(0x0017), None);
+    }
+}
@@ -0,0 +1,966 @@
+pub(crate) mod eds;
+pub(crate) mod pdo;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::message::Message;
+use crate::util::hex_to_str;
+
+const SDO_SEGMENT_LENGTH: usize = 7;
+// Size indicated bit of initiate frames, which moves up one in block transfers
+const SEGMENTED_SIZE_FLAG: u8 = 0x01;
+const BLOCK_SIZE_FLAG: u8 = 0x02;
+
+// Communication object, from the function code in the top 4 bits of an 11-bit COB-ID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CobKind {
+    Nmt,
+    Sync,
+    Emcy,
+    Time,
+    Tpdo(u8),
+    Rpdo(u8),
+    // Server to client
+    SdoTx,
+    // Client to server
+    SdoRx,
+    Heartbeat,
+    Lss,
+}
+
+impl fmt::Display for CobKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CobKind::Nmt => write!(f, "NMT"),
+            CobKind::Sync => write!(f, "SYNC"),
+            CobKind::Emcy => write!(f, "EMCY"),
+            CobKind::Time => write!(f, "TIME"),
+            CobKind::Tpdo(number) => write!(f, "TPDO{}", number),
+            CobKind::Rpdo(number) => write!(f, "RPDO{}", number),
+            CobKind::SdoTx => write!(f, "SDO tx"),
+            CobKind::SdoRx => write!(f, "SDO rx"),
+            CobKind::Heartbeat => write!(f, "Heartbeat"),
+            CobKind::Lss => write!(f, "LSS"),
+        }
+    }
+}
+
+// Classify a COB-ID of the predefined connection set, returning the object and node-ID.
+pub(crate) fn classify(cob_id: u32) -> Option<(CobKind, u8)> {
+    if cob_id > 0x7FF {
+        return None;
+    }
+    let node = (cob_id & 0x7F) as u8;
+    let kind = match (cob_id >> 7, node) {
+        (_, _) if cob_id == 0x7E4 || cob_id == 0x7E5 => CobKind::Lss,
+        (0x0, 0) => CobKind::Nmt,
+        (0x1, 0) => CobKind::Sync,
+        (0x1, _) => CobKind::Emcy,
+        (0x2, 0) => CobKind::Time,
+        (0x3, _) => CobKind::Tpdo(1),
+        (0x4, _) => CobKind::Rpdo(1),
+        (0x5, _) => CobKind::Tpdo(2),
+        (0x6, _) => CobKind::Rpdo(2),
+        (0x7, _) => CobKind::Tpdo(3),
+        (0x8, _) => CobKind::Rpdo(3),
+        (0x9, _) => CobKind::Tpdo(4),
+        (0xA, _) => CobKind::Rpdo(4),
+        (0xB, _) => CobKind::SdoTx,
+        (0xC, _) => CobKind::SdoRx,
+        (0xE, _) => CobKind::Heartbeat,
+        _ => return None,
+    };
+    Some((kind, node))
+}
+
+fn nmt_command_name(command: u8) -> &'static str {
+    match command {
+        0x01 => "Start",
+        0x02 => "Stop",
+        0x80 => "Enter pre-operational",
+        0x81 => "Reset node",
+        0x82 => "Reset communication",
+        _ => "Unknown command",
+    }
+}
+
+pub(crate) fn nmt_state_name(state: u8) -> &'static str {
+    match state {
+        0x00 => "Boot-up",
+        0x04 => "Stopped",
+        0x05 => "Operational",
+        0x7F => "Pre-operational",
+        _ => "Unknown state",
+    }
+}
+
+fn emcy_error_name(code: u16) -> &'static str {
+    match code {
+        0x0000 => "Error reset or no error",
+        0x8110 => "CAN overrun",
+        0x8120 => "CAN error passive",
+        0x8130 => "Life guard or heartbeat error",
+        0x8140 => "Recovered from bus off",
+        0x8150 => "CAN-ID collision",
+        0x8210 => "PDO not processed due to length error",
+        0x8220 => "PDO length exceeded",
+        0x8240 => "Unexpected SYNC data length",
+        0x8250 => "RPDO timeout",
+        _ => match code >> 8 {
+            0x10 => "Generic error",
+            0x20 => "Current",
+            0x21 => "Current, device input side",
+            0x22 => "Current inside the device",
+            0x23 => "Current, device output side",
+            0x30 => "Voltage",
+            0x31 => "Mains voltage",
+            0x32 => "Voltage inside the device",
+            0x33 => "Output voltage",
+            0x40 => "Temperature",
+            0x41 => "Ambient temperature",
+            0x42 => "Device temperature",
+            0x50 => "Device hardware",
+            0x60 => "Device software",
+            0x61 => "Internal software",
+            0x62 => "User software",
+            0x63 => "Data set",
+            0x70 => "Additional modules",
+            0x80 => "Monitoring",
+            0x81 => "Communication",
+            0x82 => "Protocol error",
+            0x90 => "External error",
+            0xF0 => "Additional functions",
+            0xFF => "Device specific",
+            _ => "Unknown error",
+        },
+    }
+}
+
+pub(crate) fn sdo_abort_name(code: u32) -> &'static str {
+    match code {
+        0x0503_0000 => "Toggle bit not alternated",
+        0x0504_0000 => "SDO protocol timed out",
+        0x0504_0001 => "Command specifier not valid",
+        0x0504_0002 => "Invalid block size",
+        0x0504_0003 => "Invalid sequence number",
+        0x0504_0004 => "CRC error",
+        0x0504_0005 => "Out of memory",
+        0x0601_0000 => "Unsupported access to an object",
+        0x0601_0001 => "Attempt to read a write only object",
+        0x0601_0002 => "Attempt to write a read only object",
+        0x0602_0000 => "Object does not exist",
+        0x0604_0041 => "Object cannot be mapped to the PDO",
+        0x0604_0042 => "PDO length exceeded",
+        0x0604_0043 => "General parameter incompatibility",
+        0x0604_0047 => "General internal incompatibility",
+        0x0606_0000 => "Hardware error",
+        0x0607_0010 => "Data type does not match",
+        0x0607_0012 => "Data type length too high",
+        0x0607_0013 => "Data type length too low",
+        0x0609_0011 => "Sub-index does not exist",
+        0x0609_0030 => "Invalid value",
+        0x0609_0031 => "Value too high",
+        0x0609_0032 => "Value too low",
+        0x0609_0036 => "Maximum value is less than minimum value",
+        0x060A_0023 => "Resource not available",
+        0x0800_0000 => "General error",
+        0x0800_0020 => "Data cannot be transferred or stored",
+        0x0800_0021 => "Data cannot be transferred because of local control",
+        0x0800_0022 => "Data cannot be transferred in the present device state",
+        0x0800_0023 => "No object dictionary",
+        0x0800_0024 => "No data available",
+        _ => "Unknown abort code",
+    }
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .rev()
+        .fold(0u32, |acc, b| (acc << 8) | *b as u32)
+}
+
+// A decoded CANopen frame, described on its own without any transfer context.
+#[derive(Debug, Clone)]
+pub struct CanOpenFrame {
+    pub kind: CobKind,
+    pub node: u8,
+    pub description: String,
+}
+
+impl fmt::Display for CanOpenFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.node {
+            0 => write!(f, "{}: {}", self.kind, self.description),
+            node => write!(f, "{} {}: {}", self.kind, node, self.description),
+        }
+    }
+}
+
+fn sdo_description(kind: CobKind, data: &[u8]) -> String {
+    let command = match data.first() {
+        Some(command) => *command,
+        None => return "empty".to_string(),
+    };
+    let object = || match data.len() >= 4 {
+        true => format!(
+            " {:04X}sub{}",
+            u16::from_le_bytes([data[1], data[2]]),
+            data[3]
+        ),
+        false => String::new(),
+    };
+    let specifier = command >> 5;
+    if specifier == 4 {
+        let code = le_u32(data.get(4..).unwrap_or(&[]));
+        return format!("Abort{} {:08X} {}", object(), code, sdo_abort_name(code));
+    }
+    // Initiate frames name the object
+    let (name, initiate) = match (kind, specifier) {
+        (CobKind::SdoRx, 0) => ("Download segment", false),
+        (CobKind::SdoRx, 1) => ("Initiate download", true),
+        (CobKind::SdoRx, 2) => ("Initiate upload", true),
+        (CobKind::SdoRx, 3) => ("Upload segment", false),
+        (CobKind::SdoRx, 5) => ("Block upload", false),
+        (CobKind::SdoRx, 6) => ("Block download", false),
+        (CobKind::SdoTx, 0) => ("Upload segment response", false),
+        (CobKind::SdoTx, 1) => ("Download segment response", false),
+        (CobKind::SdoTx, 2) => ("Initiate upload response", true),
+        (CobKind::SdoTx, 3) => ("Initiate download response", true),
+        (CobKind::SdoTx, 5) => ("Block download response", false),
+        (CobKind::SdoTx, 6) => ("Block upload response", false),
+        _ => ("Unknown command", false),
+    };
+    match initiate {
+        true => format!("{}{}", name, object()),
+        false => name.to_string(),
+    }
+}
+
+// Describe a frame by its COB-ID. SDO block segments can only be told apart from commands in
+// the context of a transfer, see `decode_sdo`.
+pub(crate) fn decode_frame(message: &Message) -> Option<CanOpenFrame> {
+    if message.id.len() > 2 {
+        return None;
+    }
+    let (kind, node) = classify(message.id_u32()?)?;
+    let data = &message.data;
+    let description = match kind {
+        CobKind::Nmt => match data.as_slice() {
+            [command, 0, ..] => format!("{} all nodes", nmt_command_name(*command)),
+            [command, node, ..] => format!("{} node {}", nmt_command_name(*command), node),
+            _ => "invalid length".to_string(),
+        },
+        CobKind::Sync => match data.first() {
+            Some(counter) => format!("counter {}", counter),
+            None => String::new(),
+        },
+        CobKind::Emcy => match data.as_slice() {
+            [low, high, register, manufacturer @ ..] => {
+                let code = u16::from_le_bytes([*low, *high]);
+                format!(
+                    "{:04X} {} (register {:02X}, data {})",
+                    code,
+                    emcy_error_name(code),
+                    register,
+                    hex_to_str(manufacturer)
+                )
+            }
+            _ => "invalid length".to_string(),
+        },
+        CobKind::Time => match data.len() >= 6 {
+            true => {
+                let milliseconds = le_u32(&data[..4]) & 0x0FFF_FFFF;
+                let days = u16::from_le_bytes([data[4], data[5]]);
+                format!(
+                    "{} days since 1984, {} ms after midnight",
+                    days, milliseconds
+                )
+            }
+            false => "invalid length".to_string(),
+        },
+        CobKind::Tpdo(_) | CobKind::Rpdo(_) | CobKind::Lss => hex_to_str(data),
+        CobKind::SdoTx | CobKind::SdoRx => sdo_description(kind, data),
+        CobKind::Heartbeat => match data.first() {
+            Some(state) => nmt_state_name(state & 0x7F).to_string(),
+            None => "Node guarding request".to_string(),
+        },
+    };
+    Some(CanOpenFrame {
+        kind,
+        node,
+        description,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SdoDirection {
+    // Client writes to the server's object dictionary
+    Download,
+    // Client reads from the server's object dictionary
+    Upload,
+}
+
+impl SdoDirection {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SdoDirection::Download => "Write",
+            SdoDirection::Upload => "Read",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SdoMode {
+    Expedited,
+    Segmented,
+    Block,
+}
+
+impl SdoMode {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SdoMode::Expedited => "Expedited",
+            SdoMode::Segmented => "Segmented",
+            SdoMode::Block => "Block",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SdoError {
+    Aborted { code: u32 },
+    ToggleError,
+    SequenceError { expected: u8, received: u8 },
+    // Segments after the last acknowledged one are sent again
+    Retransmission { segments: usize },
+    SizeMismatch { indicated: usize, received: usize },
+    UnexpectedFrame,
+    Interrupted,
+    Incomplete,
+}
+
+impl fmt::Display for SdoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdoError::Aborted { code } => {
+                write!(f, "aborted: {:08X} {}", code, sdo_abort_name(*code))
+            }
+            SdoError::ToggleError => write!(f, "toggle bit not alternated"),
+            SdoError::SequenceError { expected, received } => {
+                write!(
+                    f,
+                    "sequence error (expected {}, got {})",
+                    expected, received
+                )
+            }
+            SdoError::Retransmission { segments } => {
+                write!(f, "{} segments retransmitted", segments)
+            }
+            SdoError::SizeMismatch {
+                indicated,
+                received,
+            } => write!(f, "indicated {} bytes, received {}", indicated, received),
+            SdoError::UnexpectedFrame => write!(f, "unexpected frame"),
+            SdoError::Interrupted => write!(f, "interrupted by new transfer"),
+            SdoError::Incomplete => write!(f, "incomplete"),
+        }
+    }
+}
+
+// One read or write of an object dictionary entry, from initiation to the final response.
+#[derive(Debug, Clone)]
+pub struct SdoTransfer {
+    pub node: u8,
+    pub direction: SdoDirection,
+    pub mode: SdoMode,
+    pub index: u16,
+    pub subindex: u8,
+    pub timestamp: f64,
+    pub size: Option<usize>,
+    pub data: Vec<u8>,
+    // Indices into the message list of every frame of this transfer
+    pub rows: Vec<usize>,
+    pub errors: Vec<SdoError>,
+    pub complete: bool,
+}
+
+impl SdoTransfer {
+    // Values up to 4 bytes as unsigned little-endian numbers, longer ones as hex
+    pub(crate) fn value_string(&self) -> String {
+        match self.data.len() {
+            1..=4 => format!("{} ({})", le_u32(&self.data), hex_to_str(&self.data)),
+            _ => hex_to_str(&self.data),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Phase {
+    // Waiting for the response to an initiate or end frame
+    Response,
+    Segmented,
+    // Sub-block segments from the data sender
+    BlockData,
+    // Waiting for the end frame after the last sub-block was acknowledged
+    BlockEnd,
+}
+
+struct Session {
+    transfer: SdoTransfer,
+    phase: Phase,
+    toggle: u8,
+    last_segment: bool,
+    // Segments of the current sub-block, committed when acknowledged
+    block_segments: Vec<Vec<u8>>,
+}
+
+impl Session {
+    fn new(node: u8, direction: SdoDirection, mode: SdoMode, data: &[u8], time: f64) -> Self {
+        Session {
+            transfer: SdoTransfer {
+                node,
+                direction,
+                mode,
+                index: u16::from_le_bytes([data[1], data[2]]),
+                subindex: data[3],
+                timestamp: time,
+                size: None,
+                data: Vec::new(),
+                rows: Vec::new(),
+                errors: Vec::new(),
+                complete: false,
+            },
+            phase: Phase::Response,
+            toggle: 0,
+            last_segment: false,
+            block_segments: Vec::new(),
+        }
+    }
+
+    fn toggle(&mut self, command: u8) {
+        let toggle = (command >> 4) & 1;
+        if toggle != self.toggle && !self.transfer.errors.contains(&SdoError::ToggleError) {
+            self.transfer.errors.push(SdoError::ToggleError);
+        }
+        self.toggle = toggle ^ 1;
+    }
+
+    fn block_segment(&mut self, data: &[u8]) {
+        let sequence = data[0] & 0x7F;
+        let expected = self.block_segments.len() as u8 + 1;
+        if sequence != expected {
+            self.transfer.errors.push(SdoError::SequenceError {
+                expected,
+                received: sequence,
+            });
+        }
+        self.block_segments.push(data[1..].to_vec());
+        if data[0] & 0x80 != 0 {
+            self.last_segment = true;
+        }
+    }
+
+    fn block_ack(&mut self, acknowledged: u8) {
+        let acknowledged = (acknowledged as usize).min(self.block_segments.len());
+        let repeated = self.block_segments.len() - acknowledged;
+        if repeated > 0 {
+            self.transfer
+                .errors
+                .push(SdoError::Retransmission { segments: repeated });
+            // The last segment is among those sent again
+            self.last_segment = false;
+        }
+        for segment in self.block_segments.drain(..acknowledged) {
+            self.transfer.data.extend_from_slice(&segment);
+        }
+        self.block_segments.clear();
+        self.phase = match self.last_segment {
+            true => Phase::BlockEnd,
+            false => Phase::BlockData,
+        };
+    }
+
+    // Drop the unused bytes of the last segment, counted in bits 4-2 of the end frame
+    fn block_end(&mut self, command: u8) {
+        let unused = ((command >> 2) & 0x07) as usize;
+        let length = self.transfer.data.len().saturating_sub(unused);
+        self.transfer.data.truncate(length);
+        self.phase = Phase::Response;
+    }
+
+    // Initiate frames with the expedited bit carry the data, with its size if s is set
+    fn expedited(&mut self, data: &[u8]) {
+        let command = data[0];
+        let length = match command & 0x01 {
+            1 => 4 - ((command >> 2) & 0x03) as usize,
+            _ => 4,
+        };
+        self.transfer.mode = SdoMode::Expedited;
+        self.transfer.data = data[4..(4 + length).min(data.len())].to_vec();
+    }
+
+    fn indicated_size(&mut self, data: &[u8], flag: u8) {
+        if data[0] & flag != 0 {
+            self.transfer.size = Some(le_u32(&data[4..]) as usize);
+        }
+    }
+}
+
+#[derive(Default)]
+struct SdoDecoder {
+    sessions: HashMap<u8, Session>,
+    transfers: Vec<SdoTransfer>,
+}
+
+impl SdoDecoder {
+    fn finish(&mut self, node: u8, complete: bool, error: Option<SdoError>) {
+        if let Some(mut session) = self.sessions.remove(&node) {
+            let transfer = &mut session.transfer;
+            transfer.complete = complete;
+            if let Some(error) = error {
+                transfer.errors.push(error);
+            }
+            if let Some(indicated) = transfer.size {
+                if complete && indicated != transfer.data.len() {
+                    transfer.errors.push(SdoError::SizeMismatch {
+                        indicated,
+                        received: transfer.data.len(),
+                    });
+                }
+            }
+            self.transfers.push(session.transfer);
+        }
+    }
+
+    fn start(&mut self, node: u8, session: Session) {
+        if self.sessions.contains_key(&node) {
+            self.finish(node, false, Some(SdoError::Interrupted));
+        }
+        self.sessions.insert(node, session);
+    }
+
+    fn unexpected(&mut self, node: u8) {
+        if let Some(session) = self.sessions.get_mut(&node) {
+            session.transfer.errors.push(SdoError::UnexpectedFrame);
+        }
+    }
+
+    fn client(&mut self, node: u8, data: &[u8], time: f64) {
+        let command = data[0];
+        let session = self.sessions.get_mut(&node);
+
+        // While the client sends a sub-block every frame is a segment
+        if let Some(session) = session {
+            if session.phase == Phase::BlockData
+                && session.transfer.direction == SdoDirection::Download
+            {
+                session.block_segment(data);
+                return;
+            }
+            match (command >> 5, &session.phase) {
+                (0, Phase::Segmented) if session.transfer.direction == SdoDirection::Download => {
+                    session.toggle(command);
+                    let unused = ((command >> 1) & 0x07) as usize;
+                    let length = SDO_SEGMENT_LENGTH - unused;
+                    session
+                        .transfer
+                        .data
+                        .extend_from_slice(&data[1..(1 + length).min(data.len())]);
+                    session.last_segment = command & 0x01 != 0;
+                    session.phase = Phase::Response;
+                    return;
+                }
+                (3, Phase::Segmented) if session.transfer.direction == SdoDirection::Upload => {
+                    session.toggle(command);
+                    session.phase = Phase::Response;
+                    return;
+                }
+                (5, _) if session.transfer.mode == SdoMode::Block && command & 0x03 != 0 => {
+                    match command & 0x03 {
+                        // Start upload
+                        3 => session.phase = Phase::BlockData,
+                        // Acknowledge a sub-block
+                        2 => session.block_ack(data[1]),
+                        // End response
+                        1 => self.finish(node, true, None),
+                        _ => self.unexpected(node),
+                    }
+                    return;
+                }
+                (6, Phase::BlockEnd) if command & 0x01 == 1 => {
+                    session.block_end(command);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        match command >> 5 {
+            1 => {
+                let mut session =
+                    Session::new(node, SdoDirection::Download, SdoMode::Segmented, data, time);
+                match command & 0x02 {
+                    0 => session.indicated_size(data, SEGMENTED_SIZE_FLAG),
+                    _ => session.expedited(data),
+                }
+                self.start(node, session);
+            }
+            2 => {
+                let session =
+                    Session::new(node, SdoDirection::Upload, SdoMode::Segmented, data, time);
+                self.start(node, session);
+            }
+            4 => self.finish(
+                node,
+                false,
+                Some(SdoError::Aborted {
+                    code: le_u32(&data[4..]),
+                }),
+            ),
+            5 if command & 0x03 == 0 => {
+                let session = Session::new(node, SdoDirection::Upload, SdoMode::Block, data, time);
+                self.start(node, session);
+            }
+            6 if command & 0x01 == 0 => {
+                let mut session =
+                    Session::new(node, SdoDirection::Download, SdoMode::Block, data, time);
+                session.indicated_size(data, BLOCK_SIZE_FLAG);
+                self.start(node, session);
+            }
+            _ => self.unexpected(node),
+        }
+    }
+
+    fn server(&mut self, node: u8, data: &[u8]) {
+        let command = data[0];
+        let session = match self.sessions.get_mut(&node) {
+            Some(session) => session,
+            // Responses without a request in the capture
+            None => return,
+        };
+
+        // While the server sends a sub-block every frame is a segment
+        if session.phase == Phase::BlockData && session.transfer.direction == SdoDirection::Upload {
+            session.block_segment(data);
+            return;
+        }
+
+        match (command >> 5, session.transfer.direction) {
+            (3, SdoDirection::Download) if session.transfer.mode == SdoMode::Expedited => {
+                self.finish(node, true, None)
+            }
+            (3, SdoDirection::Download) => session.phase = Phase::Segmented,
+            (1, SdoDirection::Download) => match session.last_segment {
+                true => self.finish(node, true, None),
+                false => session.phase = Phase::Segmented,
+            },
+            (2, SdoDirection::Upload) => match command & 0x02 {
+                0 => {
+                    session.indicated_size(data, SEGMENTED_SIZE_FLAG);
+                    session.phase = Phase::Segmented;
+                }
+                _ => {
+                    session.expedited(data);
+                    self.finish(node, true, None);
+                }
+            },
+            // The toggle bit is checked on the client's segment requests
+            (0, SdoDirection::Upload) => {
+                let unused = ((command >> 1) & 0x07) as usize;
+                let length = SDO_SEGMENT_LENGTH - unused;
+                session
+                    .transfer
+                    .data
+                    .extend_from_slice(&data[1..(1 + length).min(data.len())]);
+                match command & 0x01 {
+                    1 => self.finish(node, true, None),
+                    _ => session.phase = Phase::Segmented,
+                }
+            }
+            (4, _) => self.finish(
+                node,
+                false,
+                Some(SdoError::Aborted {
+                    code: le_u32(&data[4..]),
+                }),
+            ),
+            (5, SdoDirection::Download) => match command & 0x03 {
+                // Initiate response
+                0 => session.phase = Phase::BlockData,
+                // Acknowledge a sub-block
+                2 => session.block_ack(data[1]),
+                // End response
+                1 => self.finish(node, true, None),
+                _ => self.unexpected(node),
+            },
+            (6, SdoDirection::Upload) => match command & 0x01 {
+                // Initiate response, the client then starts the upload
+                0 => session.indicated_size(data, BLOCK_SIZE_FLAG),
+                // End of the upload
+                _ => session.block_end(command),
+            },
+            _ => self.unexpected(node),
+        }
+    }
+}
+
+// Follow the SDO transfers of every node in a capture, in order of their first frame.
+pub(crate) fn decode_sdo(messages: &[Message]) -> Vec<SdoTransfer> {
+    let mut decoder = SdoDecoder::default();
+
+    for (index, message) in messages.iter().enumerate() {
+        if message.id.len() > 2 || message.data.len() < 8 {
+            continue;
+        }
+        let (kind, node) = match message.id_u32().and_then(classify) {
+            Some(classified) => classified,
+            None => continue,
+        };
+        let finished = decoder.transfers.len();
+        match kind {
+            CobKind::SdoRx => decoder.client(node, &message.data, message.timestamp),
+            CobKind::SdoTx => decoder.server(node, &message.data),
+            _ => continue,
+        }
+        // The frame belongs to the open transfer, or to the one it just finished
+        if let Some(session) = decoder.sessions.get_mut(&node) {
+            session.transfer.rows.push(index);
+        } else if decoder.transfers.len() > finished {
+            if let Some(transfer) = decoder.transfers.last_mut() {
+                transfer.rows.push(index);
+            }
+        }
+    }
+
+    let open: Vec<u8> = decoder.sessions.keys().copied().collect();
+    for node in open {
+        decoder.finish(node, false, Some(SdoError::Incomplete));
+    }
+
+    let mut transfers = decoder.transfers;
+    transfers.sort_by_key(|transfer| transfer.rows.first().copied().unwrap_or(0));
+    transfers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: u16 = 0x605;
+    const SERVER: u16 = 0x585;
+
+    fn frame(id: u16, data: [u8; 8]) -> Message {
+        Message {
+            timestamp: 0.0,
+            id: id.to_be_bytes().to_vec(),
+            data: data.to_vec(),
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    #[test]
+    fn classify_cob_ids() {
+        assert_eq!(classify(0x000), Some((CobKind::Nmt, 0)));
+        assert_eq!(classify(0x080), Some((CobKind::Sync, 0)));
+        assert_eq!(classify(0x085), Some((CobKind::Emcy, 5)));
+        assert_eq!(classify(0x285), Some((CobKind::Tpdo(2), 5)));
+        assert_eq!(classify(0x50A), Some((CobKind::Rpdo(4), 10)));
+        assert_eq!(classify(0x705), Some((CobKind::Heartbeat, 5)));
+        assert_eq!(classify(0x7E5), Some((CobKind::Lss, 0x65)));
+        assert_eq!(classify(0x800), None);
+        // Extended frames are not CANopen
+        let mut message = frame(0x705, [0x05, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            decode_frame(&message).unwrap().to_string(),
+            "Heartbeat 5: Operational"
+        );
+        message.id = vec![0, 0, 0x07, 0x05];
+        assert!(decode_frame(&message).is_none());
+    }
+
+    #[test]
+    fn frame_descriptions() {
+        let describe = |id, data| decode_frame(&frame(id, data)).unwrap().to_string();
+        assert_eq!(
+            describe(0x000, [0x01, 0x00, 0, 0, 0, 0, 0, 0]),
+            "NMT: Start all nodes"
+        );
+        assert_eq!(
+            describe(0x085, [0x10, 0x81, 0x11, 0, 0, 0, 0, 0]),
+            "EMCY 5: 8110 CAN overrun (register 11, data 00 00 00 00 00)"
+        );
+        assert_eq!(
+            describe(CLIENT, [0x40, 0x18, 0x10, 0x01, 0, 0, 0, 0]),
+            "SDO rx 5: Initiate upload 1018sub1"
+        );
+        assert_eq!(
+            describe(SERVER, [0x80, 0x00, 0x20, 0x00, 0x00, 0x00, 0x02, 0x06]),
+            "SDO tx 5: Abort 2000sub0 06020000 Object does not exist"
+        );
+    }
+
+    #[test]
+    fn expedited_and_segmented() {
+        let messages = vec![
+            // Write 1000 to the heartbeat producer time
+            frame(CLIENT, [0x2B, 0x17, 0x10, 0x00, 0xE8, 0x03, 0x00, 0x00]),
+            frame(SERVER, [0x60, 0x17, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            // Read the 10 byte device name
+            frame(CLIENT, [0x40, 0x08, 0x10, 0x00, 0, 0, 0, 0]),
+            frame(SERVER, [0x41, 0x08, 0x10, 0x00, 0x0A, 0, 0, 0]),
+            frame(CLIENT, [0x60, 0, 0, 0, 0, 0, 0, 0]),
+            frame(SERVER, [0x00, b'C', b'A', b'N', b'o', b'p', b'e', b'n']),
+            frame(CLIENT, [0x70, 0, 0, 0, 0, 0, 0, 0]),
+            frame(SERVER, [0x19, b' ', b'I', b'O', 0, 0, 0, 0]),
+        ];
+        let transfers = decode_sdo(&messages);
+        assert_eq!(transfers.len(), 2);
+        let write = &transfers[0];
+        assert_eq!(
+            (write.direction, write.mode),
+            (SdoDirection::Download, SdoMode::Expedited)
+        );
+        assert_eq!((write.index, write.subindex), (0x1017, 0));
+        assert_eq!(write.value_string(), "1000 (E8 03)");
+        assert_eq!(write.rows, vec![0, 1]);
+        let read = &transfers[1];
+        assert_eq!(
+            (read.direction, read.mode),
+            (SdoDirection::Upload, SdoMode::Segmented)
+        );
+        assert_eq!(read.size, Some(10));
+        assert_eq!(read.data, b"CANopen IO");
+        assert!(read.complete && read.errors.is_empty());
+
+        // The second segment request repeats the toggle bit
+        let mut messages = messages;
+        messages[6].data[0] = 0x60;
+        let transfers = decode_sdo(&messages);
+        assert_eq!(transfers[1].errors, vec![SdoError::ToggleError]);
+    }
+
+    // Block download of 10 bytes to 0x1F50 sub 1, with a CRC and the size indicated
+    fn block_download() -> Vec<Message> {
+        vec![
+            frame(CLIENT, [0xC6, 0x50, 0x1F, 0x01, 0x0A, 0x00, 0x00, 0x00]),
+            // Sub-blocks of up to 127 segments
+            frame(SERVER, [0xA4, 0x50, 0x1F, 0x01, 0x7F, 0x00, 0x00, 0x00]),
+            frame(CLIENT, [0x01, 1, 2, 3, 4, 5, 6, 7]),
+            frame(CLIENT, [0x82, 8, 9, 10, 0, 0, 0, 0]),
+            frame(SERVER, [0xA2, 0x02, 0x7F, 0, 0, 0, 0, 0]),
+            // Four unused bytes in the last segment
+            frame(CLIENT, [0xD1, 0x12, 0x34, 0, 0, 0, 0, 0]),
+            frame(SERVER, [0xA1, 0, 0, 0, 0, 0, 0, 0]),
+        ]
+    }
+
+    #[test]
+    fn block_download_transfer() {
+        let transfers = decode_sdo(&block_download());
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!(
+            (transfer.direction, transfer.mode),
+            (SdoDirection::Download, SdoMode::Block)
+        );
+        assert_eq!((transfer.index, transfer.subindex), (0x1F50, 1));
+        assert_eq!(transfer.size, Some(10));
+        assert_eq!(transfer.data, (1..=10).collect::<Vec<u8>>());
+        assert_eq!(transfer.rows, (0..7).collect::<Vec<usize>>());
+        assert!(transfer.complete);
+        assert!(transfer.errors.is_empty());
+    }
+
+    #[test]
+    fn block_download_retransmission() {
+        let mut messages = block_download();
+        // Only the first segment is acknowledged, the second is sent again in a new sub-block
+        messages[4].data[1] = 0x01;
+        messages.insert(5, frame(CLIENT, [0x81, 8, 9, 10, 0, 0, 0, 0]));
+        messages.insert(6, frame(SERVER, [0xA2, 0x01, 0x7F, 0, 0, 0, 0, 0]));
+        let transfers = decode_sdo(&messages);
+        let transfer = &transfers[0];
+        assert_eq!(
+            transfer.errors,
+            vec![SdoError::Retransmission { segments: 1 }]
+        );
+        assert_eq!(transfer.data, (1..=10).collect::<Vec<u8>>());
+        assert!(transfer.complete);
+    }
+
+    #[test]
+    fn block_download_errors() {
+        let mut messages = block_download();
+        messages[3].data[0] = 0x83;
+        // The end frame claims one unused byte too few
+        messages[5].data[0] = 0xCD;
+        let transfer = &decode_sdo(&messages)[0];
+        assert_eq!(
+            transfer.errors,
+            vec![
+                SdoError::SequenceError {
+                    expected: 2,
+                    received: 3
+                },
+                SdoError::SizeMismatch {
+                    indicated: 10,
+                    received: 11
+                },
+            ]
+        );
+
+        let mut messages = block_download();
+        messages.truncate(4);
+        messages.push(frame(
+            SERVER,
+            [0x80, 0x50, 0x1F, 0x01, 0x04, 0x00, 0x04, 0x05],
+        ));
+        let transfer = &decode_sdo(&messages)[0];
+        assert!(!transfer.complete);
+        assert_eq!(
+            transfer.errors,
+            vec![SdoError::Aborted { code: 0x0504_0004 }]
+        );
+        assert_eq!(
+            transfer.errors[0].to_string(),
+            "aborted: 05040004 CRC error"
+        );
+
+        let mut messages = block_download();
+        messages.truncate(5);
+        let transfer = &decode_sdo(&messages)[0];
+        assert_eq!(transfer.errors, vec![SdoError::Incomplete]);
+    }
+
+    #[test]
+    fn block_upload_transfer() {
+        let messages = vec![
+            frame(CLIENT, [0xA4, 0x08, 0x10, 0x00, 0x7F, 0x00, 0, 0]),
+            frame(SERVER, [0xC6, 0x08, 0x10, 0x00, 0x09, 0x00, 0x00, 0x00]),
+            frame(CLIENT, [0xA3, 0, 0, 0, 0, 0, 0, 0]),
+            frame(SERVER, [0x01, 1, 2, 3, 4, 5, 6, 7]),
+            frame(SERVER, [0x82, 8, 9, 0, 0, 0, 0, 0]),
+            frame(CLIENT, [0xA2, 0x02, 0x7F, 0, 0, 0, 0, 0]),
+            // Five unused bytes in the last segment
+            frame(SERVER, [0xD5, 0x12, 0x34, 0, 0, 0, 0, 0]),
+            frame(CLIENT, [0xA1, 0, 0, 0, 0, 0, 0, 0]),
+        ];
+        let transfers = decode_sdo(&messages);
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!(
+            (transfer.direction, transfer.mode),
+            (SdoDirection::Upload, SdoMode::Block)
+        );
+        assert_eq!(transfer.size, Some(9));
+        assert_eq!(transfer.data, (1..=9).collect::<Vec<u8>>());
+        assert_eq!(transfer.rows.len(), 8);
+        assert!(transfer.complete);
+        assert!(transfer.errors.is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use crate::message::Message;
+use crate::protocol::canopen::eds::ObjectDictionary;
+use crate::protocol::canopen::{SdoDirection, SdoTransfer};
+use crate::signal::Signal;
+
+const RPDO_COMMUNICATION: u16 = 0x1400;
+const RPDO_MAPPING: u16 = 0x1600;
+const TPDO_COMMUNICATION: u16 = 0x1800;
+const TPDO_MAPPING: u16 = 0x1A00;
+const MAX_PDOS: u16 = 0x200;
+// COB-ID bit 31 marks a PDO as not valid
+const COB_ID_INVALID: u64 = 0x8000_0000;
+// Mapping indices below this are data type dummies that only take up space
+const FIRST_OBJECT_INDEX: u16 = 0x20;
+
+#[derive(Debug, Clone)]
+struct MappedObject {
+    index: u16,
+    subindex: u8,
+    length: u16,
+}
+
+#[derive(Debug, Clone)]
+struct Pdo {
+    node: u8,
+    name: String,
+    objects: Vec<MappedObject>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PdoValue {
+    pub name: String,
+    pub value: String,
+}
+
+// The contents of one PDO frame, decoded with the mapping in effect at that point.
+#[derive(Debug, Clone)]
+pub struct DecodedPdo {
+    pub node: u8,
+    pub name: String,
+    pub values: Vec<PdoValue>,
+}
+
+// A node's object dictionary together with the values written to it so far.
+struct NodeState<'a> {
+    node: u8,
+    dictionary: &'a ObjectDictionary,
+    written: HashMap<(u16, u8), u64>,
+}
+
+impl<'a> NodeState<'a> {
+    fn value(&self, index: u16, subindex: u8) -> Option<u64> {
+        self.written
+            .get(&(index, subindex))
+            .copied()
+            .or_else(|| self.dictionary.value(index, subindex))
+    }
+
+    fn defines(&self, index: u16) -> bool {
+        self.dictionary
+            .entries
+            .range((index, 0)..=(index, u8::MAX))
+            .next()
+            .is_some()
+            || self.written.keys().any(|(i, _)| *i == index)
+    }
+
+    // COB-ID from the communication parameter, or the predefined one for the first four PDOs
+    fn cob_id(&self, communication: u16, number: u16, base: u32) -> Option<u32> {
+        match self.value(communication + number, 1) {
+            Some(cob_id) if cob_id & COB_ID_INVALID != 0 => None,
+            Some(cob_id) => Some((cob_id & 0x7FF) as u32),
+            None if number < 4 => Some(base + 0x100 * number as u32 + self.node as u32),
+            None => None,
+        }
+    }
+
+    fn pdos(&self, pdos: &mut HashMap<u32, Pdo>) {
+        let kinds = [
+            ("RPDO", RPDO_COMMUNICATION, RPDO_MAPPING, 0x200),
+            ("TPDO", TPDO_COMMUNICATION, TPDO_MAPPING, 0x180),
+        ];
+        for (kind, communication, mapping, base) in kinds {
+            for number in 0..MAX_PDOS {
+                if !self.defines(mapping + number) {
+                    continue;
+                }
+                let cob_id = match self.cob_id(communication, number, base) {
+                    Some(cob_id) => cob_id,
+                    None => continue,
+                };
+                let count = self.value(mapping + number, 0).unwrap_or(0).min(64) as u8;
+                // Entries are index << 16 | subindex << 8 | length in bits
+                let objects = (1..=count)
+                    .filter_map(|sub| self.value(mapping + number, sub))
+                    .map(|entry| MappedObject {
+                        index: (entry >> 16) as u16,
+                        subindex: (entry >> 8) as u8,
+                        length: (entry & 0xFF) as u16,
+                    })
+                    .collect();
+                pdos.insert(
+                    cob_id,
+                    Pdo {
+                        node: self.node,
+                        name: format!("{}{}", kind, number + 1),
+                        objects,
+                    },
+                );
+            }
+        }
+    }
+
+    fn decode(&self, pdo: &Pdo, data: &[u8]) -> Vec<PdoValue> {
+        let mut values = Vec::new();
+        let mut position = 0u16;
+        for object in &pdo.objects {
+            let start = position;
+            position += object.length;
+            if object.index < FIRST_OBJECT_INDEX {
+                continue;
+            }
+            let signal = Signal {
+                start_bit: start,
+                length: object.length,
+                ..Default::default()
+            };
+            let raw = match signal.raw(data) {
+                Some(raw) => raw,
+                None => break,
+            };
+            let bytes = data
+                .get(start as usize / 8..(position as usize + 7) / 8)
+                .unwrap_or(&[]);
+            let value = match self.dictionary.data_type(object.index, object.subindex) {
+                Some(data_type) => data_type.format_raw(raw, bytes),
+                None => raw.to_string(),
+            };
+            values.push(PdoValue {
+                name: self
+                    .dictionary
+                    .name(object.index, object.subindex)
+                    .unwrap_or_else(|| format!("{:04X}sub{}", object.index, object.subindex)),
+                value,
+            });
+        }
+        values
+    }
+}
+
+fn is_pdo_parameter(index: u16) -> bool {
+    (RPDO_COMMUNICATION..TPDO_MAPPING + MAX_PDOS).contains(&index)
+}
+
+// Decode the PDOs of nodes with an object dictionary, by message row. Mappings follow the
+// SDO writes to PDO parameters seen in the capture.
+pub(crate) fn decode_pdos(
+    messages: &[Message],
+    transfers: &[SdoTransfer],
+    dictionaries: &HashMap<u8, ObjectDictionary>,
+) -> HashMap<usize, DecodedPdo> {
+    let mut nodes: HashMap<u8, NodeState> = dictionaries
+        .iter()
+        .map(|(node, dictionary)| {
+            (
+                *node,
+                NodeState {
+                    node: *node,
+                    dictionary,
+                    written: HashMap::new(),
+                },
+            )
+        })
+        .collect();
+
+    // Writes take effect once the transfer's last frame has been seen
+    let mut writes: Vec<(usize, &SdoTransfer)> = transfers
+        .iter()
+        .filter(|t| t.complete && t.direction == SdoDirection::Download)
+        .filter(|t| is_pdo_parameter(t.index) && nodes.contains_key(&t.node))
+        .filter_map(|t| t.rows.last().map(|row| (*row, t)))
+        .collect();
+    writes.sort_by_key(|(row, _)| *row);
+    let mut writes = writes.into_iter().peekable();
+
+    let mut pdos = HashMap::new();
+    for node in nodes.values() {
+        node.pdos(&mut pdos);
+    }
+
+    let mut decoded = HashMap::new();
+    for (row, message) in messages.iter().enumerate() {
+        let mut changed = false;
+        while let Some((_, transfer)) = writes.next_if(|(write_row, _)| *write_row < row) {
+            if let Some(node) = nodes.get_mut(&transfer.node) {
+                let value = transfer
+                    .data
+                    .iter()
+                    .take(8)
+                    .rev()
+                    .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+                node.written
+                    .insert((transfer.index, transfer.subindex), value);
+                changed = true;
+            }
+        }
+        if changed {
+            pdos.clear();
+            for node in nodes.values() {
+                node.pdos(&mut pdos);
+            }
+        }
+
+        if message.id.len() > 2 {
+            continue;
+        }
+        let pdo = match message.id_u32().and_then(|id| pdos.get(&id)) {
+            Some(pdo) => pdo,
+            None => continue,
+        };
+        let values = nodes[&pdo.node].decode(pdo, &message.data);
+        decoded.insert(
+            row,
+            DecodedPdo {
+                node: pdo.node,
+                name: pdo.name.clone(),
+                values,
+            },
+        );
+    }
+    decoded
+}
@@ -0,0 +1,503 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::path::Path;
+
+use crate::protocol::isotp::IsoTpPdu;
+use crate::protocol::uds::{UdsKind, UdsMessage};
+
+const REQUEST_DOWNLOAD: u8 = 0x34;
+const TRANSFER_DATA: u8 = 0x36;
+const REQUEST_TRANSFER_EXIT: u8 = 0x37;
+// Fill value for missing blocks in binary output, as for erased flash
+const FILL_BYTE: u8 = 0xFF;
+const HEX_RECORD_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadIssue {
+    Rejected { nrc: u8 },
+    BlockRejected { counter: u8, nrc: u8 },
+    Retry { counter: u8 },
+    OutOfOrder { counter: u8 },
+    Gap { address: u32, length: usize },
+    SizeMismatch { expected: usize, received: usize },
+    // Block that would end past the 32-bit address space
+    AddressOverflow { counter: u8 },
+    MissingTransferExit,
+    // Data is compressed or encrypted, and is written out as transferred
+    Encoded { data_format: u8 },
+}
+
+impl fmt::Display for DownloadIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadIssue::Rejected { nrc } => write!(f, "download rejected (NRC {:02X})", nrc),
+            DownloadIssue::BlockRejected { counter, nrc } => {
+                write!(f, "block {:02X} rejected (NRC {:02X})", counter, nrc)
+            }
+            DownloadIssue::Retry { counter } => write!(f, "block {:02X} retried", counter),
+            DownloadIssue::OutOfOrder { counter } => {
+                write!(f, "block {:02X} out of order", counter)
+            }
+            DownloadIssue::Gap { address, length } => {
+                write!(f, "gap of {} bytes at {:08X}", length, address)
+            }
+            DownloadIssue::SizeMismatch { expected, received } => {
+                write!(f, "expected {} bytes, received {}", expected, received)
+            }
+            DownloadIssue::AddressOverflow { counter } => {
+                write!(f, "block {:02X} past the end of the address space", counter)
+            }
+            DownloadIssue::MissingTransferExit => write!(f, "no transfer exit"),
+            DownloadIssue::Encoded { data_format } => {
+                write!(f, "compressed/encrypted (format {:02X})", data_format)
+            }
+        }
+    }
+}
+
+// A memory region downloaded with RequestDownload, TransferData and RequestTransferExit.
+#[derive(Debug, Clone)]
+pub struct FirmwareRegion {
+    pub channel: usize,
+    pub timestamp: f64,
+    pub address: u32,
+    pub size: usize,
+    // Block data by absolute sequence number, starting at 1
+    blocks: BTreeMap<u64, Vec<u8>>,
+    next_block: u64,
+    pub issues: Vec<DownloadIssue>,
+}
+
+impl FirmwareRegion {
+    fn new(channel: usize, timestamp: f64, data_format: u8, address: u32, size: usize) -> Self {
+        let mut issues = Vec::new();
+        if data_format != 0 {
+            issues.push(DownloadIssue::Encoded { data_format });
+        }
+        Self {
+            channel,
+            timestamp,
+            address,
+            size,
+            blocks: BTreeMap::new(),
+            next_block: 1,
+            issues,
+        }
+    }
+
+    // Length of a full block, taken from the longest block received
+    fn block_length(&self) -> usize {
+        self.blocks.values().map(|b| b.len()).max().unwrap_or(0)
+    }
+
+    // Offset of a block from the region address, if all of the block fits in the address space
+    fn block_offset(&self, sequence: u64, length: usize) -> Option<u32> {
+        let offset = (sequence - 1).checked_mul(self.block_length() as u64)?;
+        let end = (self.address as u64)
+            .checked_add(offset)?
+            .checked_add(length as u64)?;
+        match end <= 1 << 32 {
+            true => u32::try_from(offset).ok(),
+            false => None,
+        }
+    }
+
+    pub(crate) fn received(&self) -> usize {
+        self.blocks.values().map(|b| b.len()).sum()
+    }
+
+    fn block(&mut self, counter: u8, data: &[u8]) {
+        // Interpret the wrapping 8-bit counter as the sequence number closest to the next one
+        let expected = self.next_block;
+        let delta = counter.wrapping_sub(expected as u8) as i8 as i64;
+        let sequence = match expected as i64 + delta {
+            sequence if sequence >= 1 => sequence as u64,
+            _ => {
+                self.issues.push(DownloadIssue::OutOfOrder { counter });
+                return;
+            }
+        };
+
+        if self.blocks.contains_key(&sequence) {
+            self.issues.push(DownloadIssue::Retry { counter });
+        } else if sequence < expected {
+            self.issues.push(DownloadIssue::OutOfOrder { counter });
+        }
+        self.blocks.insert(sequence, data.to_vec());
+        self.next_block = self.next_block.max(sequence + 1);
+    }
+
+    fn finish(&mut self) {
+        let block_length = self.block_length();
+        let last = self.blocks.keys().next_back().copied().unwrap_or(0);
+        for sequence in 1..last {
+            if !self.blocks.contains_key(&sequence) {
+                if let Some(offset) = self.block_offset(sequence, block_length) {
+                    self.issues.push(DownloadIssue::Gap {
+                        address: self.address + offset,
+                        length: block_length,
+                    });
+                }
+            }
+        }
+        for (sequence, data) in &self.blocks {
+            if self.block_offset(*sequence, data.len()).is_none() {
+                self.issues.push(DownloadIssue::AddressOverflow {
+                    counter: *sequence as u8,
+                });
+            }
+        }
+        let received = self.received();
+        if received != self.size {
+            self.issues.push(DownloadIssue::SizeMismatch {
+                expected: self.size,
+                received,
+            });
+        }
+    }
+
+    // Contiguous runs of received data with their offsets from the region address. Blocks past
+    // the end of the address space are left out.
+    fn runs(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut runs: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut next_sequence = 0;
+        for (sequence, data) in &self.blocks {
+            let offset = match self.block_offset(*sequence, data.len()) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            match runs.last_mut() {
+                Some((_, run)) if *sequence == next_sequence => run.extend_from_slice(data),
+                _ => runs.push((offset, data.clone())),
+            }
+            next_sequence = sequence + 1;
+        }
+        runs
+    }
+
+    // Contiguous runs of received data with their start addresses
+    pub(crate) fn segments(&self) -> Vec<(u32, Vec<u8>)> {
+        self.runs()
+            .into_iter()
+            .map(|(offset, data)| (self.address + offset, data))
+            .collect()
+    }
+
+    // The whole region, with missing blocks filled
+    pub(crate) fn to_bin(&self) -> Vec<u8> {
+        let mut bin = Vec::new();
+        for (offset, data) in self.runs() {
+            if bin.len() < offset as usize {
+                bin.resize(offset as usize, FILL_BYTE);
+            }
+            bin.extend_from_slice(&data);
+        }
+        bin
+    }
+
+    pub(crate) fn to_intel_hex(&self) -> String {
+        let mut hex = String::new();
+        let mut upper_address: Option<u16> = None;
+        for (address, data) in self.segments() {
+            for (index, chunk) in data.chunks(HEX_RECORD_LENGTH).enumerate() {
+                let record_address = address + (index * HEX_RECORD_LENGTH) as u32;
+                let upper = (record_address >> 16) as u16;
+                if upper_address != Some(upper) {
+                    hex_record(&mut hex, 0, 0x04, &upper.to_be_bytes());
+                    upper_address = Some(upper);
+                }
+                hex_record(&mut hex, record_address as u16, 0x00, chunk);
+            }
+        }
+        hex_record(&mut hex, 0, 0x01, &[]);
+        hex
+    }
+
+    pub(crate) fn write_bin(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_bin())?;
+        Ok(())
+    }
+
+    pub(crate) fn write_intel_hex(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_intel_hex())?;
+        Ok(())
+    }
+}
+
+fn hex_record(out: &mut String, address: u16, record_type: u8, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend_from_slice(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, b| sum.wrapping_add(*b))
+        .wrapping_neg();
+    bytes.push(checksum);
+
+    out.push(':');
+    for b in bytes {
+        let _ = write!(out, "{:02X}", b);
+    }
+    out.push('\n');
+}
+
+// Read a big-endian number of up to 4 bytes
+fn be_value(bytes: &[u8]) -> Option<u32> {
+    match bytes.len() {
+        1..=4 => Some(bytes.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32)),
+        _ => None,
+    }
+}
+
+fn request_download(
+    channel: usize,
+    pdu: &IsoTpPdu,
+    response: Option<&UdsMessage>,
+) -> Option<FirmwareRegion> {
+    // 34 dataFormatIdentifier addressAndLengthFormatIdentifier memoryAddress memorySize
+    let data = &pdu.data;
+    let data_format = *data.get(1)?;
+    let format = *data.get(2)?;
+    let address_length = (format & 0x0F) as usize;
+    let size_length = (format >> 4) as usize;
+    let address = be_value(data.get(3..3 + address_length)?)?;
+    let size = be_value(data.get(3 + address_length..3 + address_length + size_length)?)?;
+
+    let mut region =
+        FirmwareRegion::new(channel, pdu.timestamp, data_format, address, size as usize);
+    if let Some(UdsKind::NegativeResponse(nrc)) = response.map(|r| &r.kind) {
+        region.issues.push(DownloadIssue::Rejected { nrc: *nrc });
+    }
+    Some(region)
+}
+
+// Follow the download sessions in a decoded UDS conversation and collect the transferred regions.
+pub(crate) fn extract(pdus: &[IsoTpPdu], uds: &[UdsMessage]) -> Vec<FirmwareRegion> {
+    let mut regions = Vec::new();
+    // Region being downloaded on each ISO-TP channel
+    let mut open: Vec<(usize, FirmwareRegion)> = Vec::new();
+
+    for message in uds.iter().filter(|m| m.kind == UdsKind::Request) {
+        let pdu = &pdus[message.pdu];
+        let response = message.paired.map(|index| &uds[index]);
+        let position = open.iter().position(|(channel, _)| *channel == pdu.channel);
+
+        match message.service {
+            REQUEST_DOWNLOAD => {
+                if let Some(position) = position {
+                    let (_, mut region) = open.remove(position);
+                    region.issues.push(DownloadIssue::MissingTransferExit);
+                    region.finish();
+                    regions.push(region);
+                }
+                if let Some(region) = request_download(pdu.channel, pdu, response) {
+                    match region
+                        .issues
+                        .iter()
+                        .any(|i| matches!(i, DownloadIssue::Rejected { .. }))
+                    {
+                        true => regions.push(region),
+                        false => open.push((pdu.channel, region)),
+                    }
+                }
+            }
+            TRANSFER_DATA => {
+                let region = match position {
+                    Some(position) => &mut open[position].1,
+                    None => continue,
+                };
+                let counter = match pdu.data.get(1) {
+                    Some(counter) => *counter,
+                    None => continue,
+                };
+                match response.map(|r| &r.kind) {
+                    Some(UdsKind::NegativeResponse(nrc)) => {
+                        region
+                            .issues
+                            .push(DownloadIssue::BlockRejected { counter, nrc: *nrc });
+                    }
+                    _ => region.block(counter, &pdu.data[2..]),
+                }
+            }
+            REQUEST_TRANSFER_EXIT => {
+                if let Some(position) = position {
+                    let (_, mut region) = open.remove(position);
+                    region.finish();
+                    regions.push(region);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (_, mut region) in open {
+        region.issues.push(DownloadIssue::MissingTransferExit);
+        region.finish();
+        regions.push(region);
+    }
+    regions.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::isotp::Direction;
+    use crate::protocol::uds;
+
+    fn pdu(timestamp: f64, data: &[u8]) -> IsoTpPdu {
+        IsoTpPdu {
+            channel: 0,
+            direction: Direction::Tx,
+            timestamp,
+            end_timestamp: timestamp,
+            length: data.len(),
+            data: data.to_vec(),
+            rows: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn transfer(counter: u8, data: &[u8]) -> [IsoTpPdu; 2] {
+        let mut request = vec![TRANSFER_DATA, counter];
+        request.extend_from_slice(data);
+        [pdu(0.0, &request), pdu(0.0, &[0x76, counter])]
+    }
+
+    // RequestDownload of `size` bytes at `address`, the given blocks and a transfer exit
+    fn download(address: u32, size: u32, blocks: &[(u8, Vec<u8>)]) -> Vec<FirmwareRegion> {
+        let mut request = vec![REQUEST_DOWNLOAD, 0x00, 0x44];
+        request.extend_from_slice(&address.to_be_bytes());
+        request.extend_from_slice(&size.to_be_bytes());
+        let mut pdus = vec![pdu(0.0, &request), pdu(0.0, &[0x74, 0x20, 0x01, 0x02])];
+        for (counter, data) in blocks {
+            pdus.extend(transfer(*counter, data));
+        }
+        pdus.push(pdu(0.0, &[REQUEST_TRANSFER_EXIT]));
+        pdus.push(pdu(0.0, &[0x77]));
+        extract(&pdus, &uds::decode(&pdus, &[]))
+    }
+
+    #[test]
+    fn intel_hex_records() {
+        // Example record from the Intel HEX specification
+        let mut hex = String::new();
+        let data = [
+            0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01, 0x36, 0x00, 0x7E, 0xFE, 0x09, 0xD2,
+            0x19, 0x01,
+        ];
+        hex_record(&mut hex, 0x0100, 0x00, &data);
+        hex_record(&mut hex, 0, 0x04, &[0xFF, 0xFF]);
+        hex_record(&mut hex, 0, 0x01, &[]);
+        assert_eq!(
+            hex,
+            ":10010000214601360121470136007EFE09D2190140\n:02000004FFFFFC\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn complete_download() {
+        let regions = download(
+            0x0800_0000,
+            32,
+            &[(1, (0..16).collect()), (2, (16..32).collect())],
+        );
+        assert_eq!(regions.len(), 1);
+        let region = &regions[0];
+        assert!(region.issues.is_empty());
+        assert_eq!(region.segments(), vec![(0x0800_0000, (0..32).collect())]);
+        assert_eq!(region.to_bin(), (0..32).collect::<Vec<u8>>());
+        assert_eq!(
+            region.to_intel_hex(),
+            ":020000040800F2\n\
+             :10000000000102030405060708090A0B0C0D0E0F78\n\
+             :10001000101112131415161718191A1B1C1D1E1F68\n\
+             :00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn gaps_and_retries() {
+        let regions = download(
+            0x1000,
+            48,
+            &[(1, vec![1; 16]), (1, vec![1; 16]), (3, vec![3; 16])],
+        );
+        let region = &regions[0];
+        assert_eq!(
+            region.issues,
+            vec![
+                DownloadIssue::Retry { counter: 1 },
+                DownloadIssue::Gap {
+                    address: 0x1010,
+                    length: 16
+                },
+                DownloadIssue::SizeMismatch {
+                    expected: 48,
+                    received: 32
+                },
+            ]
+        );
+        let bin = region.to_bin();
+        assert_eq!(bin.len(), 48);
+        assert_eq!(&bin[16..32], &[FILL_BYTE; 16]);
+        assert_eq!(bin[32], 3);
+        assert_eq!(region.segments().len(), 2);
+    }
+
+    #[test]
+    fn counter_wraps() {
+        let blocks: Vec<(u8, Vec<u8>)> = (1..=257u32).map(|n| (n as u8, vec![n as u8])).collect();
+        let regions = download(0, 257, &blocks);
+        assert!(regions[0].issues.is_empty());
+        assert_eq!(regions[0].to_bin()[256], 1);
+    }
+
+    #[test]
+    fn address_overflow() {
+        let regions = download(0xFFFF_FFF0, 32, &[(1, vec![0xAA; 16]), (2, vec![0xBB; 16])]);
+        let region = &regions[0];
+        assert_eq!(
+            region.issues,
+            vec![DownloadIssue::AddressOverflow { counter: 2 }]
+        );
+        assert_eq!(region.segments(), vec![(0xFFFF_FFF0, vec![0xAA; 16])]);
+        assert_eq!(region.to_bin(), vec![0xAA; 16]);
+        assert_eq!(
+            region.to_intel_hex(),
+            ":02000004FFFFFC\n:10FFF000AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA61\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn rejected_and_unfinished() {
+        let request = [REQUEST_DOWNLOAD, 0x00, 0x11, 0x80, 0x10];
+        let pdus = vec![
+            pdu(0.0, &request),
+            pdu(0.1, &[0x7F, REQUEST_DOWNLOAD, 0x70]),
+            pdu(0.2, &[REQUEST_DOWNLOAD, 0x11, 0x11, 0x80, 0x10]),
+            pdu(0.3, &[0x74, 0x10, 0x10]),
+        ];
+        let regions = extract(&pdus, &uds::decode(&pdus, &[]));
+        assert_eq!(regions.len(), 2);
+        assert_eq!(
+            regions[0].issues,
+            vec![DownloadIssue::Rejected { nrc: 0x70 }]
+        );
+        assert_eq!((regions[1].address, regions[1].size), (0x80, 0x10));
+        assert_eq!(
+            regions[1].issues,
+            vec![
+                DownloadIssue::Encoded { data_format: 0x11 },
+                DownloadIssue::MissingTransferExit,
+                DownloadIssue::SizeMismatch {
+                    expected: 16,
+                    received: 0
+                },
+            ]
+        );
+    }
+}
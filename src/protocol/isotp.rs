@@ -0,0 +1,562 @@
+use std::fmt;
+
+use strum::EnumIter;
+
+use crate::message::Message;
+
+const DEFAULT_TIMEOUT_MS: f64 = 1000.0;
+
+#[derive(Debug, EnumIter, PartialEq, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub enum Addressing {
+    #[default]
+    Normal,
+    // First data byte is the target address (N_TA)
+    Extended,
+    // First data byte is the address extension (N_AE)
+    Mixed,
+}
+
+impl Addressing {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Addressing::Normal => "Normal",
+            Addressing::Extended => "Extended",
+            Addressing::Mixed => "Mixed",
+        }
+    }
+
+    fn offset(&self) -> usize {
+        match self {
+            Addressing::Normal => 0,
+            Addressing::Extended | Addressing::Mixed => 1,
+        }
+    }
+}
+
+fn default_timeout_ms() -> f64 {
+    DEFAULT_TIMEOUT_MS
+}
+
+// A pair of CAN IDs carrying one ISO-TP connection, e.g. a tester and an ECU.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct IsoTpChannel {
+    pub name: String,
+    pub tx_id: Vec<u8>,
+    pub rx_id: Vec<u8>,
+    #[serde(default)]
+    pub addressing: Addressing,
+    // Address byte expected in frames on each ID, for extended and mixed addressing
+    #[serde(default)]
+    pub tx_address: u8,
+    #[serde(default)]
+    pub rx_address: u8,
+    // Maximum gap between frames of one PDU (N_Bs / N_Cr)
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: f64,
+}
+
+impl IsoTpChannel {
+    fn direction_of(&self, message: &Message) -> Option<Direction> {
+        let offset = self.addressing.offset();
+        let address_matches = |address: u8| offset == 0 || message.data.first() == Some(&address);
+        if message.id == self.tx_id && address_matches(self.tx_address) {
+            Some(Direction::Tx)
+        } else if message.id == self.rx_id && address_matches(self.rx_address) {
+            Some(Direction::Rx)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+impl Direction {
+    fn index(&self) -> usize {
+        match self {
+            Direction::Tx => 0,
+            Direction::Rx => 1,
+        }
+    }
+
+    fn opposite(&self) -> Direction {
+        match self {
+            Direction::Tx => Direction::Rx,
+            Direction::Rx => Direction::Tx,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Direction::Tx => "Tx",
+            Direction::Rx => "Rx",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IsoTpError {
+    SequenceError { expected: u8, received: u8 },
+    Timeout { gap_ms: f64 },
+    UnexpectedConsecutiveFrame,
+    MissingFlowControl,
+    FlowControlOverflow,
+    InvalidLength,
+    // A new first or single frame arrived before this PDU was complete
+    Interrupted,
+    Incomplete { received: usize },
+}
+
+impl fmt::Display for IsoTpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IsoTpError::SequenceError { expected, received } => {
+                write!(
+                    f,
+                    "sequence error (expected {}, got {})",
+                    expected, received
+                )
+            }
+            IsoTpError::Timeout { gap_ms } => write!(f, "timeout ({:.1} ms gap)", gap_ms),
+            IsoTpError::UnexpectedConsecutiveFrame => write!(f, "unexpected consecutive frame"),
+            IsoTpError::MissingFlowControl => write!(f, "missing flow control"),
+            IsoTpError::FlowControlOverflow => write!(f, "flow control overflow"),
+            IsoTpError::InvalidLength => write!(f, "invalid length"),
+            IsoTpError::Interrupted => write!(f, "interrupted by new PDU"),
+            IsoTpError::Incomplete { received } => {
+                write!(f, "incomplete ({} bytes received)", received)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IsoTpPdu {
+    pub channel: usize,
+    pub direction: Direction,
+    pub timestamp: f64,
+    pub end_timestamp: f64,
+    pub length: usize,
+    pub data: Vec<u8>,
+    // Indices into the message list of every frame of this PDU, including flow control
+    pub rows: Vec<usize>,
+    pub errors: Vec<IsoTpError>,
+}
+
+impl IsoTpPdu {
+    fn new(channel: usize, direction: Direction, timestamp: f64, length: usize) -> Self {
+        Self {
+            channel,
+            direction,
+            timestamp,
+            end_timestamp: timestamp,
+            length,
+            data: Vec::with_capacity(length),
+            rows: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.data.len() == self.length
+    }
+}
+
+struct Transfer {
+    pdu: IsoTpPdu,
+    next_sequence: u8,
+    awaiting_flow_control: bool,
+    block_size: u8,
+    block_count: u8,
+}
+
+struct Reassembler {
+    transfers: Vec<[Option<Transfer>; 2]>,
+    pdus: Vec<IsoTpPdu>,
+}
+
+impl Reassembler {
+    fn finish(&mut self, mut transfer: Transfer, error: Option<IsoTpError>) {
+        if let Some(error) = error {
+            transfer.pdu.errors.push(error);
+        }
+        self.pdus.push(transfer.pdu);
+    }
+
+    // Close a transfer that is still in progress when a new PDU starts on the same stream
+    fn interrupt(&mut self, channel: usize, direction: Direction) {
+        if let Some(transfer) = self.transfers[channel][direction.index()].take() {
+            self.finish(transfer, Some(IsoTpError::Interrupted));
+        }
+    }
+
+    fn frame(
+        &mut self,
+        index: usize,
+        message: &Message,
+        channel_index: usize,
+        channel: &IsoTpChannel,
+        direction: Direction,
+    ) {
+        let payload = &message.data[channel.addressing.offset()..];
+        let pci = match payload.first() {
+            Some(pci) => *pci,
+            None => return,
+        };
+        let time = message.timestamp;
+        let timeout = channel.timeout_ms / 1000.0;
+        let slot = direction.index();
+
+        match pci >> 4 {
+            // Single frame
+            0 => {
+                self.interrupt(channel_index, direction);
+                let (length, start) = match (pci & 0x0F, payload.len() > 8) {
+                    (0, true) => (*payload.get(1).unwrap_or(&0) as usize, 2),
+                    (length, _) => (length as usize, 1),
+                };
+                let mut pdu = IsoTpPdu::new(channel_index, direction, time, length);
+                pdu.rows.push(index);
+                let available = &payload[start.min(payload.len())..];
+                pdu.data
+                    .extend_from_slice(&available[..length.min(available.len())]);
+                if length == 0 || !pdu.is_complete() {
+                    pdu.errors.push(IsoTpError::InvalidLength);
+                }
+                self.pdus.push(pdu);
+            }
+            // First frame
+            1 => {
+                self.interrupt(channel_index, direction);
+                let short_length =
+                    ((pci as usize & 0x0F) << 8) | *payload.get(1).unwrap_or(&0) as usize;
+                let (length, start) = match short_length {
+                    0 if payload.len() >= 6 => (
+                        u32::from_be_bytes([payload[2], payload[3], payload[4], payload[5]])
+                            as usize,
+                        6,
+                    ),
+                    length => (length, 2),
+                };
+                let mut pdu = IsoTpPdu::new(channel_index, direction, time, length);
+                pdu.rows.push(index);
+                let available = &payload[start.min(payload.len())..];
+                pdu.data
+                    .extend_from_slice(&available[..length.min(available.len())]);
+                if length == 0 {
+                    pdu.errors.push(IsoTpError::InvalidLength);
+                }
+                let transfer = Transfer {
+                    pdu,
+                    next_sequence: 1,
+                    awaiting_flow_control: true,
+                    block_size: 0,
+                    block_count: 0,
+                };
+                match transfer.pdu.is_complete() {
+                    true => self.finish(transfer, None),
+                    false => self.transfers[channel_index][slot] = Some(transfer),
+                }
+            }
+            // Consecutive frame
+            2 => {
+                let mut transfer = match self.transfers[channel_index][slot].take() {
+                    Some(transfer) => transfer,
+                    None => {
+                        let mut pdu = IsoTpPdu::new(channel_index, direction, time, 0);
+                        pdu.rows.push(index);
+                        pdu.errors.push(IsoTpError::UnexpectedConsecutiveFrame);
+                        self.pdus.push(pdu);
+                        return;
+                    }
+                };
+                let gap = time - transfer.pdu.end_timestamp;
+                if gap > timeout {
+                    transfer.pdu.errors.push(IsoTpError::Timeout {
+                        gap_ms: gap * 1000.0,
+                    });
+                }
+                if transfer.awaiting_flow_control {
+                    transfer.pdu.errors.push(IsoTpError::MissingFlowControl);
+                    transfer.awaiting_flow_control = false;
+                }
+                let sequence = pci & 0x0F;
+                if sequence != transfer.next_sequence {
+                    transfer.pdu.errors.push(IsoTpError::SequenceError {
+                        expected: transfer.next_sequence,
+                        received: sequence,
+                    });
+                }
+                transfer.next_sequence = (sequence + 1) & 0x0F;
+
+                let remaining = transfer.pdu.length - transfer.pdu.data.len();
+                let available = &payload[1..];
+                transfer
+                    .pdu
+                    .data
+                    .extend_from_slice(&available[..remaining.min(available.len())]);
+                transfer.pdu.rows.push(index);
+                transfer.pdu.end_timestamp = time;
+
+                transfer.block_count = transfer.block_count.wrapping_add(1);
+                if transfer.block_size != 0 && transfer.block_count == transfer.block_size {
+                    transfer.awaiting_flow_control = true;
+                }
+
+                match transfer.pdu.is_complete() {
+                    true => self.finish(transfer, None),
+                    false => self.transfers[channel_index][slot] = Some(transfer),
+                }
+            }
+            // Flow control, which belongs to the transfer in the opposite direction
+            3 => {
+                let other = direction.opposite().index();
+                let mut transfer = match self.transfers[channel_index][other].take() {
+                    Some(transfer) => transfer,
+                    None => return,
+                };
+                let gap = time - transfer.pdu.end_timestamp;
+                if gap > timeout {
+                    transfer.pdu.errors.push(IsoTpError::Timeout {
+                        gap_ms: gap * 1000.0,
+                    });
+                }
+                transfer.pdu.rows.push(index);
+                transfer.pdu.end_timestamp = time;
+                match pci & 0x0F {
+                    // Continue to send
+                    0 => {
+                        transfer.awaiting_flow_control = false;
+                        transfer.block_size = *payload.get(1).unwrap_or(&0);
+                        transfer.block_count = 0;
+                    }
+                    // Wait
+                    1 => {}
+                    _ => {
+                        self.finish(transfer, Some(IsoTpError::FlowControlOverflow));
+                        return;
+                    }
+                }
+                self.transfers[channel_index][other] = Some(transfer);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Reassemble the ISO-TP PDUs carried on the configured channels, in order of their first frame.
+pub(crate) fn reassemble(messages: &[Message], channels: &[IsoTpChannel]) -> Vec<IsoTpPdu> {
+    let mut reassembler = Reassembler {
+        transfers: channels.iter().map(|_| [None, None]).collect(),
+        pdus: Vec::new(),
+    };
+
+    for (index, message) in messages.iter().enumerate() {
+        for (channel_index, channel) in channels.iter().enumerate() {
+            if let Some(direction) = channel.direction_of(message) {
+                reassembler.frame(index, message, channel_index, channel, direction);
+            }
+        }
+    }
+
+    for channel in 0..channels.len() {
+        for slot in 0..2 {
+            if let Some(transfer) = reassembler.transfers[channel][slot].take() {
+                let received = transfer.pdu.data.len();
+                reassembler.finish(transfer, Some(IsoTpError::Incomplete { received }));
+            }
+        }
+    }
+
+    let mut pdus = reassembler.pdus;
+    pdus.sort_by_key(|pdu| pdu.rows.first().copied().unwrap_or(0));
+    pdus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: f64, id: u16, data: &[u8]) -> Message {
+        Message {
+            timestamp,
+            id: id.to_be_bytes().to_vec(),
+            data: data.to_vec(),
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    fn channel(addressing: Addressing) -> IsoTpChannel {
+        IsoTpChannel {
+            name: "ECU".to_string(),
+            tx_id: vec![0x07, 0xE0],
+            rx_id: vec![0x07, 0xE8],
+            addressing,
+            tx_address: 0xF1,
+            rx_address: 0x10,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+        }
+    }
+
+    // ReadDataByIdentifier VIN request and its 20 byte response
+    fn vin_exchange() -> Vec<Message> {
+        vec![
+            frame(
+                0.000,
+                0x7E0,
+                &[0x03, 0x22, 0xF1, 0x90, 0x55, 0x55, 0x55, 0x55],
+            ),
+            frame(
+                0.010,
+                0x7E8,
+                &[0x10, 0x14, 0x62, 0xF1, 0x90, 0x57, 0x30, 0x4C],
+            ),
+            frame(
+                0.011,
+                0x7E0,
+                &[0x30, 0x00, 0x00, 0x55, 0x55, 0x55, 0x55, 0x55],
+            ),
+            frame(
+                0.012,
+                0x7E8,
+                &[0x21, 0x30, 0x30, 0x30, 0x30, 0x34, 0x33, 0x32],
+            ),
+            frame(
+                0.013,
+                0x7E8,
+                &[0x22, 0x31, 0x31, 0x34, 0x30, 0x30, 0x30, 0x30],
+            ),
+        ]
+    }
+
+    #[test]
+    fn single_and_multi_frame() {
+        let pdus = reassemble(&vin_exchange(), &[channel(Addressing::Normal)]);
+        assert_eq!(pdus.len(), 2);
+        assert_eq!(pdus[0].direction, Direction::Tx);
+        assert_eq!(pdus[0].data, vec![0x22, 0xF1, 0x90]);
+        assert_eq!(pdus[0].rows, vec![0]);
+        let response = &pdus[1];
+        assert_eq!(response.direction, Direction::Rx);
+        assert_eq!(response.length, 20);
+        assert_eq!(&response.data[..3], &[0x62, 0xF1, 0x90]);
+        assert_eq!(&response.data[3..], b"W0L00004321140000");
+        assert_eq!(response.rows, vec![1, 2, 3, 4]);
+        assert_eq!((response.timestamp, response.end_timestamp), (0.010, 0.013));
+        assert!(response.errors.is_empty());
+    }
+
+    #[test]
+    fn sequence_and_timing_errors() {
+        let mut messages = vin_exchange();
+        messages[3].data[0] = 0x22;
+        messages[4].timestamp = 1.5;
+        let pdus = reassemble(&messages, &[channel(Addressing::Normal)]);
+        assert_eq!(
+            pdus[1].errors[0],
+            IsoTpError::SequenceError {
+                expected: 1,
+                received: 2
+            }
+        );
+        // The timeout is checked first, and the next frame follows on from the one received
+        assert!(matches!(pdus[1].errors[1], IsoTpError::Timeout { gap_ms } if gap_ms > 1000.0));
+        assert_eq!(
+            pdus[1].errors[2],
+            IsoTpError::SequenceError {
+                expected: 3,
+                received: 2
+            }
+        );
+    }
+
+    #[test]
+    fn flow_control_errors() {
+        let mut messages = vin_exchange();
+        messages.remove(2);
+        let pdus = reassemble(&messages, &[channel(Addressing::Normal)]);
+        assert_eq!(pdus[1].errors, vec![IsoTpError::MissingFlowControl]);
+        assert!(pdus[1].is_complete());
+
+        // A block size of one needs flow control before every consecutive frame
+        let mut messages = vin_exchange();
+        messages[2].data[1] = 1;
+        let pdus = reassemble(&messages, &[channel(Addressing::Normal)]);
+        assert_eq!(pdus[1].errors, vec![IsoTpError::MissingFlowControl]);
+
+        let mut messages = vin_exchange();
+        messages[2].data[0] = 0x32;
+        let pdus = reassemble(&messages, &[channel(Addressing::Normal)]);
+        assert_eq!(pdus[1].errors, vec![IsoTpError::FlowControlOverflow]);
+        assert_eq!(pdus[1].rows, vec![1, 2]);
+        assert_eq!(pdus[2].errors, vec![IsoTpError::UnexpectedConsecutiveFrame]);
+    }
+
+    #[test]
+    fn interrupted_and_incomplete() {
+        let mut messages = vin_exchange();
+        messages.truncate(4);
+        messages.push(frame(0.020, 0x7E8, &[0x10, 0x08, 1, 2, 3, 4, 5, 6]));
+        let pdus = reassemble(&messages, &[channel(Addressing::Normal)]);
+        assert_eq!(pdus.len(), 3);
+        assert_eq!(pdus[1].errors, vec![IsoTpError::Interrupted]);
+        assert_eq!(pdus[1].data.len(), 13);
+        assert_eq!(pdus[2].errors, vec![IsoTpError::Incomplete { received: 6 }]);
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        let messages = vec![
+            frame(0.0, 0x7E0, &[0x00, 0x55]),
+            frame(0.0, 0x7E0, &[0x05, 0x01, 0x02]),
+            frame(0.0, 0x7E0, &[0x10, 0x00, 0x00]),
+        ];
+        let pdus = reassemble(&messages, &[channel(Addressing::Normal)]);
+        assert!(pdus
+            .iter()
+            .all(|pdu| pdu.errors == vec![IsoTpError::InvalidLength]));
+        assert_eq!(pdus[1].data, vec![0x01, 0x02]);
+    }
+
+    #[test]
+    fn long_frames() {
+        // CAN FD single frame with the length in the second byte
+        let mut data = vec![0x00, 10];
+        data.extend(1..=10);
+        data.extend([0xCC; 4]);
+        // First frame with a 32-bit length
+        let first = [0x10, 0x00, 0x00, 0x00, 0x00, 0x09, 1, 2];
+        let messages = vec![
+            frame(0.0, 0x7E8, &data),
+            frame(0.1, 0x7E8, &first),
+            frame(0.2, 0x7E0, &[0x30, 0x00, 0x00]),
+            frame(0.3, 0x7E8, &[0x21, 3, 4, 5, 6, 7, 8, 9]),
+        ];
+        let pdus = reassemble(&messages, &[channel(Addressing::Normal)]);
+        assert_eq!(pdus[0].data, (1..=10).collect::<Vec<u8>>());
+        assert_eq!(pdus[1].length, 9);
+        assert_eq!(pdus[1].data, (1..=9).collect::<Vec<u8>>());
+        assert!(pdus.iter().all(|pdu| pdu.errors.is_empty()));
+    }
+
+    #[test]
+    fn extended_addressing() {
+        let messages = vec![
+            frame(0.0, 0x7E0, &[0xF1, 0x02, 0x10, 0x03]),
+            // Another tester's address
+            frame(0.0, 0x7E0, &[0xF2, 0x02, 0x10, 0x01]),
+            frame(0.1, 0x7E8, &[0x10, 0x02, 0x50, 0x03]),
+        ];
+        let pdus = reassemble(&messages, &[channel(Addressing::Extended)]);
+        assert_eq!(pdus.len(), 2);
+        assert_eq!(pdus[0].data, vec![0x10, 0x03]);
+        assert_eq!(pdus[1].direction, Direction::Rx);
+        assert_eq!(pdus[1].data, vec![0x50, 0x03]);
+    }
+}
@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::message::{is_extended_id, Message};
+use crate::signal::Signal;
+
+pub(crate) const GLOBAL_ADDRESS: u8 = 0xFF;
+const PGN_TP_CM: u32 = 0xEC00;
+const PGN_TP_DT: u32 = 0xEB00;
+const TP_RTS: u8 = 0x10;
+const TP_CTS: u8 = 0x11;
+const TP_END_OF_MESSAGE_ACK: u8 = 0x13;
+const TP_BAM: u8 = 0x20;
+const TP_ABORT: u8 = 0xFF;
+const TP_PACKET_LENGTH: usize = 7;
+// T1 between broadcast packets, T2/T3 while waiting for the other side of a connection
+const BAM_TIMEOUT_MS: f64 = 750.0;
+const CMDT_TIMEOUT_MS: f64 = 1250.0;
+
+// The fields of a 29-bit J1939 identifier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct J1939Id {
+    pub priority: u8,
+    pub pgn: u32,
+    pub source: u8,
+    // Global for broadcast (PDU2) PGNs
+    pub destination: u8,
+}
+
+impl J1939Id {
+    pub(crate) fn from_id(id: u32) -> Self {
+        let pdu_format = (id >> 16) & 0xFF;
+        let pdu_specific = ((id >> 8) & 0xFF) as u8;
+        let data_page = (id >> 24) & 0x03;
+        let (pgn, destination) = match pdu_format < 240 {
+            true => ((data_page << 16) | (pdu_format << 8), pdu_specific),
+            false => (
+                (data_page << 16) | (pdu_format << 8) | pdu_specific as u32,
+                GLOBAL_ADDRESS,
+            ),
+        };
+        Self {
+            priority: ((id >> 26) & 0x07) as u8,
+            pgn,
+            source: (id & 0xFF) as u8,
+            destination,
+        }
+    }
+
+    // Only extended identifiers carry J1939 fields
+    pub(crate) fn from_message(message: &Message) -> Option<Self> {
+        match is_extended_id(&message.id) {
+            true => Some(J1939Id::from_id(message.id_u32()?)),
+            false => None,
+        }
+    }
+}
+
+// A parameter (SPN) within a PGN, as bits counted from the least significant bit of byte 1.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct SpnDefinition {
+    pub spn: u32,
+    pub name: String,
+    pub start_bit: u16,
+    pub length: u16,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default)]
+    pub unit: String,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct PgnDefinition {
+    pub pgn: u32,
+    pub name: String,
+    #[serde(default)]
+    pub spns: Vec<SpnDefinition>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DefinitionFile {
+    pgns: Vec<PgnDefinition>,
+}
+
+// Read PGN definitions from a JSON file of the form {"pgns": [{"pgn": 61444, "name": "EEC1",
+// "spns": [{"spn": 190, "name": "Engine Speed", "start_bit": 24, "length": 16, ...}]}]}
+pub(crate) fn read_definitions(path: &Path) -> Result<Vec<PgnDefinition>, Box<dyn Error>> {
+    let file = fs::File::open(path)?;
+    let definitions: DefinitionFile = serde_json::from_reader(&file)?;
+    Ok(definitions.pgns)
+}
+
+pub(crate) fn find_definition(definitions: &[PgnDefinition], pgn: u32) -> Option<&PgnDefinition> {
+    definitions.iter().find(|definition| definition.pgn == pgn)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpnState {
+    Value(f64),
+    Error,
+    NotAvailable,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpnValue {
+    pub name: String,
+    pub state: SpnState,
+    pub unit: String,
+}
+
+impl fmt::Display for SpnValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.state {
+            SpnState::Value(value) if self.unit.is_empty() => write!(f, "{}: {}", self.name, value),
+            SpnState::Value(value) => write!(f, "{}: {} {}", self.name, value, self.unit),
+            SpnState::Error => write!(f, "{}: error", self.name),
+            SpnState::NotAvailable => write!(f, "{}: n/a", self.name),
+        }
+    }
+}
+
+impl SpnDefinition {
+    fn decode(&self, data: &[u8]) -> Option<SpnValue> {
+        let signal = Signal {
+            start_bit: self.start_bit,
+            length: self.length,
+            ..Default::default()
+        };
+        let raw = signal.raw(data)?;
+        // The top byte (or the top two bits of short parameters) flags error and n/a values
+        let (indicator, error, not_available) = match self.length {
+            length if length >= 8 => (raw >> (length - 8), 0xFE, 0xFF),
+            length if length >= 2 => (raw >> (length - 2), 0b10, 0b11),
+            _ => (raw, u64::MAX, u64::MAX),
+        };
+        let state = match indicator {
+            i if i == not_available => SpnState::NotAvailable,
+            i if i == error => SpnState::Error,
+            _ => SpnState::Value(raw as f64 * self.scale + self.offset),
+        };
+        Some(SpnValue {
+            name: self.name.clone(),
+            state,
+            unit: self.unit.clone(),
+        })
+    }
+}
+
+impl PgnDefinition {
+    pub(crate) fn decode(&self, data: &[u8]) -> Vec<SpnValue> {
+        self.spns
+            .iter()
+            .filter_map(|spn| spn.decode(data))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportKind {
+    Bam,
+    Cmdt,
+}
+
+impl TransportKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TransportKind::Bam => "BAM",
+            TransportKind::Cmdt => "CMDT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportError {
+    SequenceError { expected: u8, received: u8 },
+    Timeout { gap_ms: f64 },
+    MissingClearToSend,
+    UnexpectedData,
+    Aborted { reason: u8 },
+    Interrupted,
+    Incomplete { received: usize },
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::SequenceError { expected, received } => {
+                write!(
+                    f,
+                    "sequence error (expected {}, got {})",
+                    expected, received
+                )
+            }
+            TransportError::Timeout { gap_ms } => write!(f, "timeout ({:.1} ms gap)", gap_ms),
+            TransportError::MissingClearToSend => write!(f, "data without clear to send"),
+            TransportError::UnexpectedData => write!(f, "unexpected data packet"),
+            TransportError::Aborted { reason } => write!(f, "aborted (reason {})", reason),
+            TransportError::Interrupted => write!(f, "interrupted by new transfer"),
+            TransportError::Incomplete { received } => {
+                write!(f, "incomplete ({} bytes received)", received)
+            }
+        }
+    }
+}
+
+// A multi-packet message reassembled from TP.CM and TP.DT frames.
+#[derive(Debug, Clone)]
+pub struct J1939Transfer {
+    pub kind: TransportKind,
+    pub pgn: u32,
+    pub source: u8,
+    pub destination: u8,
+    pub timestamp: f64,
+    pub end_timestamp: f64,
+    pub length: usize,
+    pub data: Vec<u8>,
+    // Indices into the message list of every frame of this transfer
+    pub rows: Vec<usize>,
+    pub errors: Vec<TransportError>,
+    packets: Vec<bool>,
+    // Packets still allowed by the last clear to send
+    cleared: u8,
+}
+
+impl J1939Transfer {
+    fn new(kind: TransportKind, id: &J1939Id, control: &[u8], timestamp: f64) -> Self {
+        let length = u16::from_le_bytes([control[1], control[2]]) as usize;
+        let packets = control[3] as usize;
+        Self {
+            kind,
+            pgn: u32::from_le_bytes([control[5], control[6], control[7], 0]),
+            source: id.source,
+            destination: id.destination,
+            timestamp,
+            end_timestamp: timestamp,
+            length,
+            data: vec![0; packets * TP_PACKET_LENGTH],
+            rows: Vec::new(),
+            errors: Vec::new(),
+            packets: vec![false; packets],
+            cleared: 0,
+        }
+    }
+
+    pub(crate) fn received(&self) -> usize {
+        let bytes = self.packets.iter().filter(|p| **p).count() * TP_PACKET_LENGTH;
+        bytes.min(self.length)
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        !self.packets.is_empty() && self.packets.iter().all(|p| *p)
+    }
+
+    fn timeout_ms(&self) -> f64 {
+        match self.kind {
+            TransportKind::Bam => BAM_TIMEOUT_MS,
+            TransportKind::Cmdt => CMDT_TIMEOUT_MS,
+        }
+    }
+
+    fn check_gap(&mut self, time: f64) {
+        let gap_ms = (time - self.end_timestamp) * 1000.0;
+        if gap_ms > self.timeout_ms() {
+            self.errors.push(TransportError::Timeout { gap_ms });
+        }
+    }
+}
+
+#[derive(Default)]
+struct Reassembler {
+    // Open transfers by sender and receiver address
+    transfers: HashMap<(u8, u8), J1939Transfer>,
+    finished: Vec<J1939Transfer>,
+}
+
+impl Reassembler {
+    fn finish(&mut self, mut transfer: J1939Transfer, error: Option<TransportError>) {
+        if let Some(error) = error {
+            transfer.errors.push(error);
+        }
+        // Aborted transfers already say why they are incomplete
+        let aborted = matches!(transfer.errors.last(), Some(TransportError::Aborted { .. }));
+        if !transfer.is_complete() && !aborted {
+            let received = transfer.received();
+            transfer
+                .errors
+                .push(TransportError::Incomplete { received });
+        }
+        transfer.data.truncate(transfer.length);
+        self.finished.push(transfer);
+    }
+
+    fn control(&mut self, index: usize, message: &Message, id: &J1939Id) {
+        let control = &message.data;
+        if control.len() < 8 {
+            return;
+        }
+        let time = message.timestamp;
+        let key = (id.source, id.destination);
+        // Responses come from the receiver of the transfer
+        let reverse_key = (id.destination, id.source);
+
+        match control[0] {
+            TP_BAM | TP_RTS => {
+                let kind = match control[0] {
+                    TP_BAM => TransportKind::Bam,
+                    _ => TransportKind::Cmdt,
+                };
+                if let Some(previous) = self.transfers.remove(&key) {
+                    self.finish(previous, Some(TransportError::Interrupted));
+                }
+                let mut transfer = J1939Transfer::new(kind, id, control, time);
+                transfer.rows.push(index);
+                self.transfers.insert(key, transfer);
+            }
+            TP_CTS => {
+                if let Some(transfer) = self.transfers.get_mut(&reverse_key) {
+                    transfer.check_gap(time);
+                    transfer.rows.push(index);
+                    transfer.end_timestamp = time;
+                    transfer.cleared = control[1];
+                }
+            }
+            TP_END_OF_MESSAGE_ACK => {
+                if let Some(mut transfer) = self.transfers.remove(&reverse_key) {
+                    transfer.rows.push(index);
+                    transfer.end_timestamp = time;
+                    self.finish(transfer, None);
+                }
+            }
+            TP_ABORT => {
+                let transfer = match self.transfers.remove(&key) {
+                    Some(transfer) => Some(transfer),
+                    None => self.transfers.remove(&reverse_key),
+                };
+                if let Some(mut transfer) = transfer {
+                    transfer.rows.push(index);
+                    transfer.end_timestamp = time;
+                    self.finish(
+                        transfer,
+                        Some(TransportError::Aborted { reason: control[1] }),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn data(&mut self, index: usize, message: &Message, id: &J1939Id) -> Option<()> {
+        let key = (id.source, id.destination);
+        let transfer = self.transfers.get_mut(&key)?;
+        transfer.check_gap(message.timestamp);
+        transfer.rows.push(index);
+        transfer.end_timestamp = message.timestamp;
+
+        let sequence = *message.data.first()?;
+        let packet = (sequence as usize).wrapping_sub(1);
+        if packet >= transfer.packets.len() {
+            transfer.errors.push(TransportError::UnexpectedData);
+            return None;
+        }
+        if transfer.kind == TransportKind::Cmdt {
+            match transfer.cleared {
+                0 if !transfer
+                    .errors
+                    .contains(&TransportError::MissingClearToSend) =>
+                {
+                    transfer.errors.push(TransportError::MissingClearToSend)
+                }
+                0 => {}
+                cleared => transfer.cleared = cleared - 1,
+            }
+        }
+        // Retransmitted packets after a clear to send may repeat earlier sequence numbers
+        let expected = transfer.packets.iter().take_while(|p| **p).count() + 1;
+        if sequence as usize != expected && !transfer.packets[packet] {
+            transfer.errors.push(TransportError::SequenceError {
+                expected: expected as u8,
+                received: sequence,
+            });
+        }
+        let payload = &message.data[1..message.data.len().min(1 + TP_PACKET_LENGTH)];
+        let offset = packet * TP_PACKET_LENGTH;
+        transfer.data[offset..offset + payload.len()].copy_from_slice(payload);
+        transfer.packets[packet] = true;
+
+        // A broadcast is done with its last packet, a connection with the receiver's acknowledge
+        if transfer.kind == TransportKind::Bam && transfer.is_complete() {
+            let transfer = self.transfers.remove(&key)?;
+            self.finish(transfer, None);
+        }
+        Some(())
+    }
+}
+
+// Reassemble the J1939 transport protocol transfers in a capture, in order of their first frame.
+pub(crate) fn reassemble(messages: &[Message]) -> Vec<J1939Transfer> {
+    let mut reassembler = Reassembler::default();
+
+    for (index, message) in messages.iter().enumerate() {
+        let id = match J1939Id::from_message(message) {
+            Some(id) => id,
+            None => continue,
+        };
+        match id.pgn {
+            PGN_TP_CM => reassembler.control(index, message, &id),
+            PGN_TP_DT => {
+                reassembler.data(index, message, &id);
+            }
+            _ => {}
+        }
+    }
+
+    let open: Vec<J1939Transfer> = reassembler.transfers.drain().map(|(_, t)| t).collect();
+    for transfer in open {
+        reassembler.finish(transfer, None);
+    }
+
+    let mut transfers = reassembler.finished;
+    transfers.sort_by_key(|transfer| transfer.rows.first().copied().unwrap_or(0));
+    transfers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: f64, id: u32, data: &[u8]) -> Message {
+        Message {
+            timestamp,
+            id: id.to_be_bytes().to_vec(),
+            data: data.to_vec(),
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    #[test]
+    fn identifiers() {
+        let id = J1939Id::from_id(0x18FE_F100);
+        assert_eq!((id.priority, id.pgn, id.source), (6, 0xFEF1, 0x00));
+        assert_eq!(id.destination, GLOBAL_ADDRESS);
+        let id = J1939Id::from_id(0x18EA_2117);
+        assert_eq!((id.pgn, id.source, id.destination), (0xEA00, 0x17, 0x21));
+        let id = J1939Id::from_id(0x0DF0_0403);
+        assert_eq!((id.priority, id.pgn), (3, 0x1_F004));
+
+        // Extended IDs of any value, but not base IDs
+        let message = frame(0.0, 0x0000_0123, &[]);
+        assert_eq!(J1939Id::from_message(&message).unwrap().source, 0x23);
+        let message = Message {
+            id: vec![0x01, 0x23],
+            ..message
+        };
+        assert!(J1939Id::from_message(&message).is_none());
+    }
+
+    #[test]
+    fn spn_values() {
+        // EEC1 engine speed, 0.125 rpm per bit
+        let speed = SpnDefinition {
+            spn: 190,
+            name: "Engine Speed".to_string(),
+            start_bit: 24,
+            length: 16,
+            scale: 0.125,
+            offset: 0.0,
+            unit: "rpm".to_string(),
+        };
+        let pgn = PgnDefinition {
+            pgn: 0xF004,
+            name: "EEC1".to_string(),
+            spns: vec![speed],
+        };
+        let values = pgn.decode(&[0xFF, 0xFF, 0xFF, 0x40, 0x1F, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(values[0].state, SpnState::Value(1000.0));
+        assert_eq!(values[0].to_string(), "Engine Speed: 1000 rpm");
+        let values = pgn.decode(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(values[0].state, SpnState::NotAvailable);
+        let values = pgn.decode(&[0xFF, 0xFF, 0xFF, 0x00, 0xFE, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(values[0].state, SpnState::Error);
+        // Too short for the parameter
+        assert!(pgn.decode(&[0xFF, 0xFF, 0xFF, 0x40]).is_empty());
+
+        let switch = SpnDefinition {
+            spn: 70,
+            name: "Parking Brake".to_string(),
+            start_bit: 2,
+            length: 2,
+            scale: 1.0,
+            offset: 0.0,
+            unit: String::new(),
+        };
+        assert_eq!(
+            switch.decode(&[0b0100]).unwrap().state,
+            SpnState::Value(1.0)
+        );
+        assert_eq!(switch.decode(&[0b1000]).unwrap().state, SpnState::Error);
+        assert_eq!(
+            switch.decode(&[0b1100]).unwrap().state,
+            SpnState::NotAvailable
+        );
+    }
+
+    // DM1 with two active DTCs, broadcast by address 0x00
+    fn bam() -> Vec<Message> {
+        vec![
+            frame(
+                0.00,
+                0x18EC_FF00,
+                &[0x20, 0x0E, 0x00, 0x02, 0xFF, 0xCA, 0xFE, 0x00],
+            ),
+            frame(
+                0.05,
+                0x18EB_FF00,
+                &[0x01, 0x04, 0xFF, 0x64, 0x00, 0x03, 0x01, 0x6E],
+            ),
+            frame(
+                0.10,
+                0x18EB_FF00,
+                &[0x02, 0x00, 0x04, 0x02, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+        ]
+    }
+
+    #[test]
+    fn broadcast() {
+        let transfers = reassemble(&bam());
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!(transfer.kind, TransportKind::Bam);
+        assert_eq!(transfer.pgn, 0xFECA);
+        assert_eq!(
+            (transfer.source, transfer.destination),
+            (0x00, GLOBAL_ADDRESS)
+        );
+        assert_eq!(
+            transfer.data,
+            vec![
+                0x04, 0xFF, 0x64, 0x00, 0x03, 0x01, 0x6E, 0x00, 0x04, 0x02, 0xFF, 0xFF, 0xFF, 0xFF
+            ]
+        );
+        assert_eq!(transfer.rows, vec![0, 1, 2]);
+        assert!(transfer.errors.is_empty());
+    }
+
+    #[test]
+    fn broadcast_errors() {
+        let mut messages = bam();
+        messages[2].timestamp = 1.0;
+        messages[2].data[0] = 0x03;
+        let transfers = reassemble(&messages);
+        assert!(
+            matches!(transfers[0].errors[0], TransportError::Timeout { gap_ms } if gap_ms > 900.0)
+        );
+        assert_eq!(transfers[0].errors[1], TransportError::UnexpectedData);
+        assert_eq!(
+            transfers[0].errors[2],
+            TransportError::Incomplete { received: 7 }
+        );
+
+        // A new announcement before the last packet
+        let mut messages = bam();
+        messages.insert(2, messages[0].clone());
+        let transfers = reassemble(&messages);
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(
+            transfers[0].errors,
+            vec![
+                TransportError::Interrupted,
+                TransportError::Incomplete { received: 7 }
+            ]
+        );
+        assert_eq!(
+            transfers[1].errors,
+            vec![
+                TransportError::SequenceError {
+                    expected: 1,
+                    received: 2
+                },
+                TransportError::Incomplete { received: 7 }
+            ]
+        );
+    }
+
+    // 9 bytes of PGN 0xEF00 from 0x00 to 0x21, with clear to send and acknowledge
+    fn connection() -> Vec<Message> {
+        vec![
+            frame(
+                0.00,
+                0x1CEC_2100,
+                &[0x10, 0x09, 0x00, 0x02, 0x02, 0x00, 0xEF, 0x00],
+            ),
+            frame(
+                0.01,
+                0x1CEC_0021,
+                &[0x11, 0x02, 0x01, 0xFF, 0xFF, 0x00, 0xEF, 0x00],
+            ),
+            frame(0.02, 0x1CEB_2100, &[0x01, 1, 2, 3, 4, 5, 6, 7]),
+            frame(
+                0.03,
+                0x1CEB_2100,
+                &[0x02, 8, 9, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+            frame(
+                0.04,
+                0x1CEC_0021,
+                &[0x13, 0x09, 0x00, 0x02, 0xFF, 0x00, 0xEF, 0x00],
+            ),
+        ]
+    }
+
+    #[test]
+    fn connection_mode() {
+        let transfers = reassemble(&connection());
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!(transfer.kind, TransportKind::Cmdt);
+        assert_eq!(
+            (transfer.pgn, transfer.source, transfer.destination),
+            (0xEF00, 0x00, 0x21)
+        );
+        assert_eq!(transfer.data, (1..=9).collect::<Vec<u8>>());
+        assert_eq!(transfer.rows, vec![0, 1, 2, 3, 4]);
+        assert!(transfer.errors.is_empty());
+    }
+
+    #[test]
+    fn connection_errors() {
+        let mut messages = connection();
+        messages.remove(1);
+        let transfers = reassemble(&messages);
+        assert_eq!(
+            transfers[0].errors,
+            vec![TransportError::MissingClearToSend]
+        );
+
+        let mut messages = connection();
+        messages[4] = frame(
+            0.04,
+            0x1CEC_0021,
+            &[0xFF, 0x03, 0xFF, 0xFF, 0xFF, 0x00, 0xEF, 0x00],
+        );
+        let transfers = reassemble(&messages);
+        assert_eq!(
+            transfers[0].errors,
+            vec![TransportError::Aborted { reason: 3 }]
+        );
+
+        // The sender never gets an acknowledge
+        let mut messages = connection();
+        messages.pop();
+        messages[3].timestamp = 2.0;
+        let transfers = reassemble(&messages);
+        assert!(matches!(
+            transfers[0].errors[0],
+            TransportError::Timeout { .. }
+        ));
+        assert_eq!(transfers[0].errors.len(), 1);
+        assert!(transfers[0].is_complete());
+    }
+}
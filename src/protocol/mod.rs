@@ -0,0 +1,8 @@
+pub(crate) mod canopen;
+pub(crate) mod firmware;
+pub(crate) mod isotp;
+pub(crate) mod j1939;
+pub(crate) mod nmea2000;
+pub(crate) mod obd;
+pub(crate) mod uds;
+pub(crate) mod xcp;
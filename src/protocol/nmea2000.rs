@@ -0,0 +1,510 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::message::Message;
+use crate::protocol::j1939::{J1939Id, SpnState, SpnValue};
+use crate::signal::{Signal, ValueType};
+
+const FIRST_FRAME_LENGTH: usize = 6;
+const FRAME_LENGTH: usize = 7;
+// The frame counter is 5 bits, which limits a fast packet to 223 bytes
+const MAX_FRAMES: usize = 32;
+
+// Standard PGNs that use fast-packet framing, for PGNs the definition file doesn't cover
+const FAST_PACKET_PGNS: [u32; 44] = [
+    126208, 126464, 126720, 126983, 126984, 126985, 126986, 126987, 126988, 126996, 126998, 127233,
+    127237, 127489, 127496, 127497, 127498, 127503, 127504, 127506, 127507, 127509, 127510, 127511,
+    127512, 127513, 127514, 128275, 128520, 129029, 129038, 129039, 129040, 129041, 129044, 129045,
+    129284, 129285, 129540, 129794, 129809, 129810, 130577, 130578,
+];
+
+// A field within a PGN, as bits counted from the least significant bit of byte 1.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct FieldDefinition {
+    pub name: String,
+    pub start_bit: u16,
+    pub length: u16,
+    #[serde(default)]
+    pub signed: bool,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default)]
+    pub unit: String,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct Nmea2000Definition {
+    pub pgn: u32,
+    pub name: String,
+    // Overrides the built-in list of fast-packet PGNs
+    #[serde(default)]
+    pub fast_packet: Option<bool>,
+    #[serde(default)]
+    pub fields: Vec<FieldDefinition>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DefinitionFile {
+    pgns: Vec<Nmea2000Definition>,
+}
+
+// Read PGN definitions from a JSON file of the form {"pgns": [{"pgn": 129025, "name": "Position",
+// "fields": [{"name": "Latitude", "start_bit": 0, "length": 32, "signed": true, ...}]}]}
+pub(crate) fn read_definitions(path: &Path) -> Result<Vec<Nmea2000Definition>, Box<dyn Error>> {
+    let file = fs::File::open(path)?;
+    let definitions: DefinitionFile = serde_json::from_reader(&file)?;
+    Ok(definitions.pgns)
+}
+
+pub(crate) fn find_definition(
+    definitions: &[Nmea2000Definition],
+    pgn: u32,
+) -> Option<&Nmea2000Definition> {
+    definitions.iter().find(|definition| definition.pgn == pgn)
+}
+
+pub(crate) fn is_fast_packet(definitions: &[Nmea2000Definition], pgn: u32) -> bool {
+    match find_definition(definitions, pgn).and_then(|d| d.fast_packet) {
+        Some(fast_packet) => fast_packet,
+        None => FAST_PACKET_PGNS.contains(&pgn),
+    }
+}
+
+impl FieldDefinition {
+    fn decode(&self, data: &[u8]) -> Option<SpnValue> {
+        let signal = Signal {
+            start_bit: self.start_bit,
+            length: self.length,
+            value_type: match self.signed {
+                true => ValueType::Signed,
+                false => ValueType::Unsigned,
+            },
+            ..Default::default()
+        };
+        let raw = signal.raw(data)?;
+        // The highest value flags n/a and the one below it out of range, where signed fields
+        // only count the positive values
+        let max = match (self.signed, self.length) {
+            (_, length) if length < 2 => u64::MAX,
+            (true, length) => (1u64 << (length - 1)) - 1,
+            (false, 64) => u64::MAX,
+            (false, length) => (1u64 << length) - 1,
+        };
+        let state = match raw {
+            raw if raw == max => SpnState::NotAvailable,
+            raw if raw == max - 1 => SpnState::Error,
+            _ => SpnState::Value(signal.raw_value(data)? * self.scale + self.offset),
+        };
+        Some(SpnValue {
+            name: self.name.clone(),
+            state,
+            unit: self.unit.clone(),
+        })
+    }
+}
+
+impl Nmea2000Definition {
+    pub(crate) fn decode(&self, data: &[u8]) -> Vec<SpnValue> {
+        self.fields
+            .iter()
+            .filter_map(|field| field.decode(data))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FastPacketError {
+    OutOfOrder { frame: u8, after: u8 },
+    MissingFirstFrame,
+    Missing { frames: Vec<u8> },
+    // A new packet reused the sequence ID before this one was complete
+    Interrupted,
+    InvalidLength,
+}
+
+impl fmt::Display for FastPacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FastPacketError::OutOfOrder { frame, after } => {
+                write!(f, "frame {} out of order (after {})", frame, after)
+            }
+            FastPacketError::MissingFirstFrame => write!(f, "missing first frame"),
+            FastPacketError::Missing { frames } => {
+                let frames = frames
+                    .iter()
+                    .map(|frame| frame.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "missing frames {}", frames)
+            }
+            FastPacketError::Interrupted => write!(f, "interrupted by new packet"),
+            FastPacketError::InvalidLength => write!(f, "invalid length"),
+        }
+    }
+}
+
+// A message reassembled from the frames of one fast packet.
+#[derive(Debug, Clone)]
+pub struct FastPacket {
+    pub pgn: u32,
+    pub source: u8,
+    pub destination: u8,
+    pub sequence: u8,
+    pub timestamp: f64,
+    pub end_timestamp: f64,
+    // From the first frame, if it was seen
+    pub length: Option<usize>,
+    pub data: Vec<u8>,
+    // Indices into the message list of every frame of this packet
+    pub rows: Vec<usize>,
+    pub errors: Vec<FastPacketError>,
+    // Payload by frame counter
+    frames: BTreeMap<u8, Vec<u8>>,
+}
+
+impl FastPacket {
+    fn new(id: &J1939Id, sequence: u8, timestamp: f64) -> Self {
+        Self {
+            pgn: id.pgn,
+            source: id.source,
+            destination: id.destination,
+            sequence,
+            timestamp,
+            end_timestamp: timestamp,
+            length: None,
+            data: Vec::new(),
+            rows: Vec::new(),
+            errors: Vec::new(),
+            frames: BTreeMap::new(),
+        }
+    }
+
+    fn frame_count(&self) -> Option<usize> {
+        let length = self.length?;
+        let rest = length.saturating_sub(FIRST_FRAME_LENGTH);
+        Some(1 + (rest + FRAME_LENGTH - 1) / FRAME_LENGTH)
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        match self.frame_count() {
+            Some(count) => (0..count).all(|frame| self.frames.contains_key(&(frame as u8))),
+            None => false,
+        }
+    }
+
+    fn add_frame(&mut self, index: usize, message: &Message) {
+        let counter = message.data[0] & 0x1F;
+        if let Some(last) = self.frames.keys().next_back().copied() {
+            if counter < last {
+                self.errors.push(FastPacketError::OutOfOrder {
+                    frame: counter,
+                    after: last,
+                });
+            }
+        }
+        let payload = match counter {
+            0 => {
+                let length = *message.data.get(1).unwrap_or(&0) as usize;
+                if length == 0 || length > FIRST_FRAME_LENGTH + (MAX_FRAMES - 1) * FRAME_LENGTH {
+                    self.errors.push(FastPacketError::InvalidLength);
+                }
+                self.length = Some(length);
+                message.data.get(2..).unwrap_or(&[])
+            }
+            _ => &message.data[1..],
+        };
+        self.frames.insert(counter, payload.to_vec());
+        self.rows.push(index);
+        self.end_timestamp = message.timestamp;
+    }
+
+    // Put the payload together and note what is missing
+    fn finish(&mut self) {
+        let count = match self.frame_count() {
+            Some(count) => count,
+            None => {
+                self.errors.push(FastPacketError::MissingFirstFrame);
+                self.frames
+                    .keys()
+                    .next_back()
+                    .map_or(0, |last| *last as usize + 1)
+            }
+        };
+        let missing: Vec<u8> = (0..count as u8)
+            .filter(|frame| !self.frames.contains_key(frame))
+            .filter(|frame| *frame != 0 || self.length.is_some())
+            .collect();
+        if !missing.is_empty() {
+            self.errors
+                .push(FastPacketError::Missing { frames: missing });
+        }
+        for (frame, payload) in &self.frames {
+            let offset = match frame {
+                0 => 0,
+                frame => FIRST_FRAME_LENGTH + (*frame as usize - 1) * FRAME_LENGTH,
+            };
+            if self.data.len() < offset + payload.len() {
+                self.data.resize(offset + payload.len(), 0xFF);
+            }
+            self.data[offset..offset + payload.len()].copy_from_slice(payload);
+        }
+        if let Some(length) = self.length {
+            self.data.truncate(length);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Reassembler {
+    // Open packets by source, PGN and sequence ID
+    packets: HashMap<(u8, u32, u8), FastPacket>,
+    finished: Vec<FastPacket>,
+}
+
+impl Reassembler {
+    fn finish(&mut self, mut packet: FastPacket) {
+        packet.finish();
+        self.finished.push(packet);
+    }
+
+    fn frame(&mut self, index: usize, message: &Message, id: &J1939Id) {
+        let header = match message.data.first() {
+            Some(header) => *header,
+            None => return,
+        };
+        let sequence = header >> 5;
+        let counter = header & 0x1F;
+        let key = (id.source, id.pgn, sequence);
+
+        // A frame we already have means the sequence ID has come around again
+        let repeated = self
+            .packets
+            .get(&key)
+            .map(|packet| packet.frames.contains_key(&counter));
+        if repeated == Some(true) {
+            let mut packet = self.packets.remove(&key).unwrap();
+            packet.errors.push(FastPacketError::Interrupted);
+            self.finish(packet);
+        }
+
+        let packet = self
+            .packets
+            .entry(key)
+            .or_insert_with(|| FastPacket::new(id, sequence, message.timestamp));
+        packet.add_frame(index, message);
+        if packet.is_complete() {
+            let packet = self.packets.remove(&key).unwrap();
+            self.finish(packet);
+        }
+    }
+}
+
+// Reassemble the fast packets in a capture, in order of their first frame.
+pub(crate) fn reassemble(
+    messages: &[Message],
+    definitions: &[Nmea2000Definition],
+) -> Vec<FastPacket> {
+    let mut reassembler = Reassembler::default();
+
+    for (index, message) in messages.iter().enumerate() {
+        match J1939Id::from_message(message) {
+            Some(id) if is_fast_packet(definitions, id.pgn) => {
+                reassembler.frame(index, message, &id)
+            }
+            _ => {}
+        }
+    }
+
+    let open: Vec<FastPacket> = reassembler.packets.drain().map(|(_, p)| p).collect();
+    for packet in open {
+        reassembler.finish(packet);
+    }
+
+    let mut packets = reassembler.finished;
+    packets.sort_by_key(|packet| packet.rows.first().copied().unwrap_or(0));
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Priority 3, PGN 129029 (GNSS position data) from address 0x10
+    const GNSS_POSITION: u32 = 0x0DF8_0510;
+
+    fn frame(id: u32, data: &[u8]) -> Message {
+        Message {
+            timestamp: 0.0,
+            id: id.to_be_bytes().to_vec(),
+            data: data.to_vec(),
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    // The frames of a 43 byte fast packet with bytes 0, 1, 2, ...
+    fn frames(sequence: u8) -> Vec<Message> {
+        let payload: Vec<u8> = (0..43).collect();
+        let mut first = vec![sequence << 5, 43];
+        first.extend_from_slice(&payload[..FIRST_FRAME_LENGTH]);
+        let mut messages = vec![frame(GNSS_POSITION, &first)];
+        for (counter, chunk) in payload[FIRST_FRAME_LENGTH..]
+            .chunks(FRAME_LENGTH)
+            .enumerate()
+        {
+            let mut data = vec![sequence << 5 | (counter as u8 + 1)];
+            data.extend_from_slice(chunk);
+            data.resize(8, 0xFF);
+            messages.push(frame(GNSS_POSITION, &data));
+        }
+        messages
+    }
+
+    #[test]
+    fn complete_packet() {
+        let messages = frames(2);
+        assert_eq!(messages.len(), 7);
+        let packets = reassemble(&messages, &[]);
+        assert_eq!(packets.len(), 1);
+        let packet = &packets[0];
+        assert_eq!(
+            (packet.pgn, packet.source, packet.sequence),
+            (129029, 0x10, 2)
+        );
+        assert_eq!(packet.length, Some(43));
+        assert_eq!(packet.data, (0..43).collect::<Vec<u8>>());
+        assert_eq!(packet.rows, (0..7).collect::<Vec<usize>>());
+        assert!(packet.errors.is_empty());
+    }
+
+    #[test]
+    fn interleaved_sequences() {
+        let mut messages = Vec::new();
+        for (a, b) in frames(1).into_iter().zip(frames(2)) {
+            messages.push(a);
+            messages.push(b);
+        }
+        let packets = reassemble(&messages, &[]);
+        assert_eq!(packets.len(), 2);
+        assert!(packets
+            .iter()
+            .all(|p| p.is_complete() && p.errors.is_empty()));
+        assert_eq!(packets[1].rows, vec![1, 3, 5, 7, 9, 11, 13]);
+    }
+
+    #[test]
+    fn out_of_order_frames() {
+        let mut messages = frames(0);
+        messages.swap(1, 2);
+        let packets = reassemble(&messages, &[]);
+        assert_eq!(
+            packets[0].errors,
+            vec![FastPacketError::OutOfOrder { frame: 1, after: 2 }]
+        );
+        assert_eq!(packets[0].data, (0..43).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn missing_frames() {
+        let mut messages = frames(0);
+        messages.remove(3);
+        let packets = reassemble(&messages, &[]);
+        assert_eq!(
+            packets[0].errors,
+            vec![FastPacketError::Missing { frames: vec![3] }]
+        );
+        assert_eq!(&packets[0].data[20..27], &[0xFF; 7]);
+        assert_eq!(packets[0].data.len(), 43);
+
+        let messages = frames(0)[1..3].to_vec();
+        let packets = reassemble(&messages, &[]);
+        assert_eq!(packets[0].length, None);
+        assert_eq!(packets[0].errors, vec![FastPacketError::MissingFirstFrame]);
+        assert_eq!(packets[0].data.len(), FIRST_FRAME_LENGTH + 2 * FRAME_LENGTH);
+    }
+
+    #[test]
+    fn interrupted_and_invalid() {
+        let mut messages = frames(0)[..3].to_vec();
+        messages.extend(frames(0));
+        let packets = reassemble(&messages, &[]);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(
+            packets[0].errors,
+            vec![
+                FastPacketError::Interrupted,
+                FastPacketError::Missing {
+                    frames: vec![3, 4, 5, 6]
+                }
+            ]
+        );
+        assert!(packets[1].errors.is_empty());
+
+        let packets = reassemble(&[frame(GNSS_POSITION, &[0x00, 0x00, 1, 2])], &[]);
+        assert_eq!(packets[0].errors, vec![FastPacketError::InvalidLength]);
+    }
+
+    #[test]
+    fn fast_packet_pgns() {
+        // Rapid position updates are single frame
+        let position = [frame(0x09F8_0110, &[0x20, 0x08, 1, 2, 3, 4, 5, 6])];
+        assert!(reassemble(&position, &[]).is_empty());
+        let definitions = vec![Nmea2000Definition {
+            pgn: 129025,
+            name: "Position, Rapid Update".to_string(),
+            fast_packet: Some(true),
+            fields: Vec::new(),
+        }];
+        assert!(is_fast_packet(&definitions, 129025));
+        assert!(!is_fast_packet(&definitions, 129026));
+        assert!(is_fast_packet(&definitions, 129029));
+        assert_eq!(reassemble(&position, &definitions).len(), 1);
+    }
+
+    #[test]
+    fn field_values() {
+        let field = |start_bit, length, signed, scale| FieldDefinition {
+            name: "Field".to_string(),
+            start_bit,
+            length,
+            signed,
+            scale,
+            offset: 0.0,
+            unit: String::new(),
+        };
+        let heading = field(8, 16, false, 0.0001);
+        let value = heading.decode(&[0, 0x10, 0x27]).unwrap();
+        assert_eq!(value.state, SpnState::Value(1.0));
+        assert_eq!(
+            heading.decode(&[0, 0xFF, 0xFF]).unwrap().state,
+            SpnState::NotAvailable
+        );
+        assert_eq!(
+            heading.decode(&[0, 0xFE, 0xFF]).unwrap().state,
+            SpnState::Error
+        );
+
+        let deviation = field(0, 16, true, 0.0001);
+        let value = deviation.decode(&[0xF0, 0xD8]).unwrap();
+        assert_eq!(value.state, SpnState::Value(-1.0));
+        assert_eq!(
+            deviation.decode(&[0xFF, 0x7F]).unwrap().state,
+            SpnState::NotAvailable
+        );
+        assert_eq!(
+            deviation.decode(&[0xFE, 0x7F]).unwrap().state,
+            SpnState::Error
+        );
+        assert!(deviation.decode(&[0xFF]).is_none());
+    }
+}
@@ -0,0 +1,647 @@
+use crate::protocol::isotp::{Direction, IsoTpChannel, IsoTpPdu};
+use crate::protocol::uds::nrc_name;
+use crate::util::{bytes_to_string, hex_to_str};
+
+const FUNCTIONAL_REQUEST_ID: u16 = 0x7DF;
+const PHYSICAL_REQUEST_ID: u16 = 0x7E0;
+const RESPONSE_ID: u16 = 0x7E8;
+const ECU_COUNT: u16 = 8;
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+
+// ISO-TP channels of the 11-bit OBD-II IDs: one per ECU, with the functional request ID last.
+// Functional requests are always single frames, so that channel has no receive ID.
+pub(crate) fn channels() -> Vec<IsoTpChannel> {
+    let mut channels: Vec<IsoTpChannel> = (0..ECU_COUNT)
+        .map(|ecu| IsoTpChannel {
+            name: format!("ECU {}", ecu + 1),
+            tx_id: (PHYSICAL_REQUEST_ID + ecu).to_be_bytes().to_vec(),
+            rx_id: (RESPONSE_ID + ecu).to_be_bytes().to_vec(),
+            addressing: Default::default(),
+            tx_address: 0,
+            rx_address: 0,
+            timeout_ms: 1000.0,
+        })
+        .collect();
+    channels.push(IsoTpChannel {
+        name: "Functional".to_string(),
+        tx_id: FUNCTIONAL_REQUEST_ID.to_be_bytes().to_vec(),
+        rx_id: Vec::new(),
+        addressing: Default::default(),
+        tx_address: 0,
+        rx_address: 0,
+        timeout_ms: 1000.0,
+    });
+    channels
+}
+
+// CAN ID a PDU on one of the OBD-II channels was sent on
+fn sender_id(pdu: &IsoTpPdu) -> u16 {
+    let ecu = pdu.channel as u16;
+    match (ecu < ECU_COUNT, pdu.direction) {
+        (true, Direction::Tx) => PHYSICAL_REQUEST_ID + ecu,
+        (true, Direction::Rx) => RESPONSE_ID + ecu,
+        (false, _) => FUNCTIONAL_REQUEST_ID,
+    }
+}
+
+pub(crate) fn mode_name(mode: u8) -> Option<&'static str> {
+    let name = match mode {
+        0x01 => "Current data",
+        0x02 => "Freeze frame data",
+        0x03 => "Stored DTCs",
+        0x09 => "Vehicle information",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// P/C/B/U code from the two bytes of a DTC
+pub(crate) fn dtc_string(high: u8, low: u8) -> String {
+    let system = ['P', 'C', 'B', 'U'][(high >> 6) as usize];
+    format!(
+        "{}{}{:X}{:02X}",
+        system,
+        (high >> 4) & 0x03,
+        high & 0x0F,
+        low
+    )
+}
+
+// Round to two decimals, so that scaled values don't show float noise
+fn format_value(value: f64) -> String {
+    ((value * 100.0).round() / 100.0).to_string()
+}
+
+// PIDs 00, 20, 40, ... report which of the next 32 PIDs are supported
+fn supported_pids(base: u8, data: &[u8]) -> String {
+    let mask = data
+        .iter()
+        .take(4)
+        .fold(0u32, |acc, b| (acc << 8) | *b as u32);
+    let pids = (0..32u32)
+        .filter(|bit| mask & (0x8000_0000 >> bit) != 0)
+        .map(|bit| format!("{:02X}", base as u32 + bit + 1))
+        .collect::<Vec<_>>();
+    match pids.is_empty() {
+        true => "none".to_string(),
+        false => pids.join(" "),
+    }
+}
+
+enum Formula {
+    // Big-endian value of all data bytes, times scale plus offset
+    Linear {
+        scale: f64,
+        offset: f64,
+        unit: &'static str,
+    },
+    Supported,
+    MonitorStatus,
+    Dtc,
+    // Voltage in A, short term fuel trim in B
+    OxygenSensor,
+    Hex,
+}
+
+struct PidDefinition {
+    name: &'static str,
+    length: usize,
+    formula: Formula,
+}
+
+fn linear(
+    name: &'static str,
+    length: usize,
+    scale: f64,
+    offset: f64,
+    unit: &'static str,
+) -> PidDefinition {
+    PidDefinition {
+        name,
+        length,
+        formula: Formula::Linear {
+            scale,
+            offset,
+            unit,
+        },
+    }
+}
+
+fn other(name: &'static str, length: usize, formula: Formula) -> PidDefinition {
+    PidDefinition {
+        name,
+        length,
+        formula,
+    }
+}
+
+// Mode 01/02 PIDs with the formulas of SAE J1979
+fn pid_definition(pid: u8) -> Option<PidDefinition> {
+    let percent = 100.0 / 255.0;
+    let trim = 100.0 / 128.0;
+    let definition = match pid {
+        0x00 | 0x20 | 0x40 | 0x60 | 0x80 | 0xA0 | 0xC0 => {
+            other("Supported PIDs", 4, Formula::Supported)
+        }
+        0x01 => other("Monitor status", 4, Formula::MonitorStatus),
+        0x02 => other("Freeze frame DTC", 2, Formula::Dtc),
+        0x03 => other("Fuel system status", 2, Formula::Hex),
+        0x04 => linear("Engine load", 1, percent, 0.0, "%"),
+        0x05 => linear("Coolant temperature", 1, 1.0, -40.0, "°C"),
+        0x06 => linear("Short term fuel trim bank 1", 1, trim, -100.0, "%"),
+        0x07 => linear("Long term fuel trim bank 1", 1, trim, -100.0, "%"),
+        0x08 => linear("Short term fuel trim bank 2", 1, trim, -100.0, "%"),
+        0x09 => linear("Long term fuel trim bank 2", 1, trim, -100.0, "%"),
+        0x0A => linear("Fuel pressure", 1, 3.0, 0.0, "kPa"),
+        0x0B => linear("Intake manifold pressure", 1, 1.0, 0.0, "kPa"),
+        0x0C => linear("Engine speed", 2, 0.25, 0.0, "rpm"),
+        0x0D => linear("Vehicle speed", 1, 1.0, 0.0, "km/h"),
+        0x0E => linear("Timing advance", 1, 0.5, -64.0, "°"),
+        0x0F => linear("Intake air temperature", 1, 1.0, -40.0, "°C"),
+        0x10 => linear("MAF air flow rate", 2, 0.01, 0.0, "g/s"),
+        0x11 => linear("Throttle position", 1, percent, 0.0, "%"),
+        0x12 => other("Commanded secondary air status", 1, Formula::Hex),
+        0x13 => other("Oxygen sensors present", 1, Formula::Hex),
+        0x14 => other("Oxygen sensor 1", 2, Formula::OxygenSensor),
+        0x15 => other("Oxygen sensor 2", 2, Formula::OxygenSensor),
+        0x16 => other("Oxygen sensor 3", 2, Formula::OxygenSensor),
+        0x17 => other("Oxygen sensor 4", 2, Formula::OxygenSensor),
+        0x18 => other("Oxygen sensor 5", 2, Formula::OxygenSensor),
+        0x19 => other("Oxygen sensor 6", 2, Formula::OxygenSensor),
+        0x1A => other("Oxygen sensor 7", 2, Formula::OxygenSensor),
+        0x1B => other("Oxygen sensor 8", 2, Formula::OxygenSensor),
+        0x1C => linear("OBD standard", 1, 1.0, 0.0, ""),
+        0x1F => linear("Run time since engine start", 2, 1.0, 0.0, "s"),
+        0x21 => linear("Distance with MIL on", 2, 1.0, 0.0, "km"),
+        0x22 => linear("Fuel rail pressure", 2, 0.079, 0.0, "kPa"),
+        0x23 => linear("Fuel rail gauge pressure", 2, 10.0, 0.0, "kPa"),
+        0x2C => linear("Commanded EGR", 1, percent, 0.0, "%"),
+        0x2D => linear("EGR error", 1, trim, -100.0, "%"),
+        0x2E => linear("Commanded evaporative purge", 1, percent, 0.0, "%"),
+        0x2F => linear("Fuel tank level", 1, percent, 0.0, "%"),
+        0x30 => linear("Warm-ups since codes cleared", 1, 1.0, 0.0, ""),
+        0x31 => linear("Distance since codes cleared", 2, 1.0, 0.0, "km"),
+        0x33 => linear("Barometric pressure", 1, 1.0, 0.0, "kPa"),
+        0x42 => linear("Control module voltage", 2, 0.001, 0.0, "V"),
+        0x43 => linear("Absolute load", 2, percent, 0.0, "%"),
+        0x44 => linear("Commanded air-fuel ratio", 2, 2.0 / 65536.0, 0.0, ""),
+        0x45 => linear("Relative throttle position", 1, percent, 0.0, "%"),
+        0x46 => linear("Ambient air temperature", 1, 1.0, -40.0, "°C"),
+        0x47 => linear("Absolute throttle position B", 1, percent, 0.0, "%"),
+        0x48 => linear("Absolute throttle position C", 1, percent, 0.0, "%"),
+        0x49 => linear("Accelerator pedal position D", 1, percent, 0.0, "%"),
+        0x4A => linear("Accelerator pedal position E", 1, percent, 0.0, "%"),
+        0x4B => linear("Accelerator pedal position F", 1, percent, 0.0, "%"),
+        0x4C => linear("Commanded throttle actuator", 1, percent, 0.0, "%"),
+        0x4D => linear("Time run with MIL on", 2, 1.0, 0.0, "min"),
+        0x4E => linear("Time since codes cleared", 2, 1.0, 0.0, "min"),
+        0x51 => other("Fuel type", 1, Formula::Hex),
+        0x52 => linear("Ethanol fuel", 1, percent, 0.0, "%"),
+        0x5A => linear("Relative accelerator pedal position", 1, percent, 0.0, "%"),
+        0x5B => linear("Hybrid battery pack remaining life", 1, percent, 0.0, "%"),
+        0x5C => linear("Engine oil temperature", 1, 1.0, -40.0, "°C"),
+        0x5D => linear("Fuel injection timing", 2, 1.0 / 128.0, -210.0, "°"),
+        0x5E => linear("Engine fuel rate", 2, 0.05, 0.0, "L/h"),
+        0x61 => linear("Driver's demand engine torque", 1, 1.0, -125.0, "%"),
+        0x62 => linear("Actual engine torque", 1, 1.0, -125.0, "%"),
+        0x63 => linear("Engine reference torque", 2, 1.0, 0.0, "Nm"),
+        0xA6 => linear("Odometer", 4, 0.1, 0.0, "km"),
+        _ => return None,
+    };
+    Some(definition)
+}
+
+fn pid_value(pid: u8, definition: &PidDefinition, data: &[u8]) -> String {
+    match &definition.formula {
+        Formula::Linear {
+            scale,
+            offset,
+            unit,
+        } => {
+            let raw = data.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+            let value = format_value(raw as f64 * scale + offset);
+            match unit.is_empty() {
+                true => value,
+                false => format!("{} {}", value, unit),
+            }
+        }
+        Formula::Supported => supported_pids(pid, data),
+        Formula::MonitorStatus => {
+            let mil = match data[0] & 0x80 != 0 {
+                true => "on",
+                false => "off",
+            };
+            format!("MIL {}, {} DTCs", mil, data[0] & 0x7F)
+        }
+        Formula::Dtc => dtc_string(data[0], data[1]),
+        Formula::OxygenSensor => {
+            let voltage = format!("{} V", format_value(data[0] as f64 / 200.0));
+            match data[1] {
+                0xFF => voltage,
+                trim => format!(
+                    "{}, trim {} %",
+                    voltage,
+                    format_value(trim as f64 * 100.0 / 128.0 - 100.0)
+                ),
+            }
+        }
+        Formula::Hex => hex_to_str(data),
+    }
+}
+
+fn pid_name(pid: u8) -> String {
+    match pid_definition(pid) {
+        Some(definition) => definition.name.to_string(),
+        None => format!("PID {:02X}", pid),
+    }
+}
+
+// Each PID in a mode 01 or 02 response is followed by its data, and in mode 02 by the frame
+// number before the data. The length of unknown PIDs isn't known, so decoding stops there.
+fn decode_pid_values(mut params: &[u8], freeze_frame: bool) -> Vec<String> {
+    let mut values = Vec::new();
+    while let Some(pid) = params.first().copied() {
+        let header = 1 + freeze_frame as usize;
+        let definition = match pid_definition(pid) {
+            Some(definition) if params.len() >= header + definition.length => definition,
+            _ => {
+                values.push(format!("PID {:02X}: [{}]", pid, hex_to_str(&params[1..])));
+                break;
+            }
+        };
+        let data = &params[header..header + definition.length];
+        let value = pid_value(pid, &definition, data);
+        values.push(match freeze_frame {
+            true => format!("{} (frame {}): {}", definition.name, params[1], value),
+            false => format!("{}: {}", definition.name, value),
+        });
+        params = &params[header + definition.length..];
+    }
+    values
+}
+
+fn decode_pid_request(params: &[u8], freeze_frame: bool) -> Vec<String> {
+    match freeze_frame {
+        true => params
+            .chunks(2)
+            .map(|chunk| match chunk {
+                [pid, frame] => format!("{} (frame {})", pid_name(*pid), frame),
+                _ => pid_name(chunk[0]),
+            })
+            .collect(),
+        false => params.iter().map(|pid| pid_name(*pid)).collect(),
+    }
+}
+
+// DTCs are two bytes each. On CAN the list is preceded by a count, which makes its length odd;
+// older protocols pad with 0000 instead.
+fn decode_dtcs(params: &[u8]) -> String {
+    let dtcs = match params.len() % 2 {
+        1 => &params[1..],
+        _ => params,
+    };
+    let dtcs = dtcs
+        .chunks_exact(2)
+        .filter(|dtc| dtc != &[0, 0])
+        .map(|dtc| dtc_string(dtc[0], dtc[1]))
+        .collect::<Vec<_>>();
+    match dtcs.is_empty() {
+        true => "no DTCs".to_string(),
+        false => dtcs.join(", "),
+    }
+}
+
+fn info_type_name(info_type: u8) -> String {
+    let name = match info_type {
+        0x00 | 0x20 | 0x40 | 0x60 | 0x80 | 0xA0 | 0xC0 => "Supported info types",
+        0x01 => "VIN message count",
+        0x02 => "VIN",
+        0x03 => "Calibration ID message count",
+        0x04 => "Calibration ID",
+        0x05 => "CVN message count",
+        0x06 => "Calibration verification number",
+        0x08 => "In-use performance tracking",
+        0x09 => "ECU name message count",
+        0x0A => "ECU name",
+        0x0B => "In-use performance tracking",
+        _ => return format!("Info type {:02X}", info_type),
+    };
+    name.to_string()
+}
+
+// Text fields are padded with zero bytes to a fixed length
+fn padded_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    bytes_to_string(&bytes[..end])
+}
+
+// Mode 09 responses carry the info type, then on CAN a count of data items. A VIN arrives in one
+// multi-frame response, which ISO-TP has already put together.
+fn decode_vehicle_info(info_type: u8, params: &[u8]) -> String {
+    if info_type & 0x1F == 0 {
+        return supported_pids(info_type, params);
+    }
+    // The VIN is 17 characters, with or without the count byte
+    let items = match (info_type, params.len()) {
+        (0x02, 17) => params,
+        _ => params.get(1..).unwrap_or(&[]),
+    };
+    match info_type {
+        0x01 | 0x03 | 0x05 | 0x07 | 0x09 => match params.first() {
+            Some(count) => count.to_string(),
+            None => String::new(),
+        },
+        0x02 => bytes_to_string(items),
+        0x04 => items
+            .chunks(16)
+            .map(padded_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        0x06 => items
+            .chunks(4)
+            .map(hex_to_str)
+            .collect::<Vec<_>>()
+            .join(", "),
+        0x0A => padded_string(items),
+        _ => hex_to_str(items),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObdKind {
+    Request,
+    Response,
+    NegativeResponse(u8),
+}
+
+impl ObdKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ObdKind::Request => "Request",
+            ObdKind::Response => "Response",
+            ObdKind::NegativeResponse(_) => "Negative",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObdMessage {
+    // Index of the PDU this was decoded from
+    pub pdu: usize,
+    pub id: u16,
+    pub kind: ObdKind,
+    pub mode: u8,
+    pub description: String,
+}
+
+fn decode_pdu(index: usize, pdu: &IsoTpPdu) -> Option<ObdMessage> {
+    let data = &pdu.data;
+    let first = *data.first()?;
+    let (kind, mode, params) = if first == NEGATIVE_RESPONSE {
+        let mode = *data.get(1)?;
+        (ObdKind::NegativeResponse(*data.get(2)?), mode, &data[3..])
+    } else if mode_name(first).is_some() {
+        (ObdKind::Request, first, &data[1..])
+    } else if first >= POSITIVE_RESPONSE_OFFSET {
+        (
+            ObdKind::Response,
+            first - POSITIVE_RESPONSE_OFFSET,
+            &data[1..],
+        )
+    } else {
+        return None;
+    };
+    // Other modes share these IDs with UDS, which uses higher service IDs
+    let mode_text = mode_name(mode)?;
+
+    let details = match (&kind, mode) {
+        (ObdKind::NegativeResponse(nrc), _) => format!("{} ({:02X})", nrc_name(*nrc), nrc),
+        (ObdKind::Request, 0x01 | 0x02) => decode_pid_request(params, mode == 0x02).join(", "),
+        (ObdKind::Response, 0x01 | 0x02) => decode_pid_values(params, mode == 0x02).join(", "),
+        (ObdKind::Request, 0x09) => match params.first() {
+            Some(info_type) => info_type_name(*info_type),
+            None => String::new(),
+        },
+        (ObdKind::Response, 0x09) => match params.split_first() {
+            Some((info_type, items)) => format!(
+                "{}: {}",
+                info_type_name(*info_type),
+                decode_vehicle_info(*info_type, items)
+            ),
+            None => String::new(),
+        },
+        (ObdKind::Response, 0x03) => decode_dtcs(params),
+        _ => String::new(),
+    };
+    let description = match details.is_empty() {
+        true => mode_text.to_string(),
+        false => format!("{}: {}", mode_text, details),
+    };
+
+    Some(ObdMessage {
+        pdu: index,
+        id: sender_id(pdu),
+        kind,
+        mode,
+        description,
+    })
+}
+
+// Decode the OBD-II requests and responses in PDUs reassembled on the channels above.
+pub(crate) fn decode(pdus: &[IsoTpPdu]) -> Vec<ObdMessage> {
+    pdus.iter()
+        .enumerate()
+        .filter(|(_, pdu)| pdu.is_complete())
+        .filter_map(|(index, pdu)| decode_pdu(index, pdu))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+    use crate::protocol::isotp::reassemble;
+
+    fn frame(id: u16, data: &[u8]) -> Message {
+        Message {
+            timestamp: 0.0,
+            id: id.to_be_bytes().to_vec(),
+            data: data.to_vec(),
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    fn messages(frames: &[(u16, &[u8])]) -> Vec<ObdMessage> {
+        let messages: Vec<_> = frames.iter().map(|(id, data)| frame(*id, data)).collect();
+        decode(&reassemble(&messages, &channels()))
+    }
+
+    fn descriptions(frames: &[(u16, &[u8])]) -> Vec<String> {
+        messages(frames)
+            .into_iter()
+            .map(|message| message.description)
+            .collect()
+    }
+
+    // Single frame responses of the first ECU to mode 01 requests
+    fn current_data(params: &[u8]) -> String {
+        let mut data = vec![params.len() as u8 + 1, 0x41];
+        data.extend(params);
+        descriptions(&[(RESPONSE_ID, &data)]).remove(0)
+    }
+
+    #[test]
+    fn pid_formulas() {
+        assert_eq!(
+            current_data(&[0x0C, 0x1A, 0xF8]),
+            "Current data: Engine speed: 1726 rpm"
+        );
+        assert_eq!(
+            current_data(&[0x05, 0x7B]),
+            "Current data: Coolant temperature: 83 °C"
+        );
+        assert_eq!(
+            current_data(&[0x04, 0x80]),
+            "Current data: Engine load: 50.2 %"
+        );
+        assert_eq!(
+            current_data(&[0x06, 0x90]),
+            "Current data: Short term fuel trim bank 1: 12.5 %"
+        );
+        assert_eq!(
+            current_data(&[0x10, 0x01, 0xF4]),
+            "Current data: MAF air flow rate: 5 g/s"
+        );
+        assert_eq!(
+            current_data(&[0xA6, 0x00, 0x01, 0xE2, 0x40]),
+            "Current data: Odometer: 12345.6 km"
+        );
+        assert_eq!(
+            current_data(&[0x14, 0x5A, 0x80]),
+            "Current data: Oxygen sensor 1: 0.45 V, trim 0 %"
+        );
+        assert_eq!(
+            current_data(&[0x14, 0x5A, 0xFF]),
+            "Current data: Oxygen sensor 1: 0.45 V"
+        );
+        assert_eq!(
+            current_data(&[0x01, 0x83, 0x07, 0xE5, 0x00]),
+            "Current data: Monitor status: MIL on, 3 DTCs"
+        );
+        assert_eq!(
+            current_data(&[0x00, 0xBE, 0x1F, 0xA8, 0x13]),
+            "Current data: Supported PIDs: 01 03 04 05 06 07 0C 0D 0E 0F 10 11 13 15 1C 1F 20"
+        );
+        // Several PIDs in one response, up to one whose length isn't known
+        assert_eq!(
+            current_data(&[0x0C, 0x1A, 0xF8, 0x0D, 0x3C, 0xFE, 0x01]),
+            "Current data: Engine speed: 1726 rpm, Vehicle speed: 60 km/h, PID FE: [01]"
+        );
+    }
+
+    #[test]
+    fn requests() {
+        assert_eq!(
+            descriptions(&[
+                (FUNCTIONAL_REQUEST_ID, &[0x03, 0x01, 0x0C, 0x0D]),
+                (PHYSICAL_REQUEST_ID, &[0x03, 0x02, 0x0C, 0x00]),
+                (RESPONSE_ID + 1, &[0x05, 0x42, 0x0C, 0x00, 0x1A, 0xF8]),
+            ]),
+            vec![
+                "Current data: Engine speed, Vehicle speed",
+                "Freeze frame data: Engine speed (frame 0)",
+                "Freeze frame data: Engine speed (frame 0): 1726 rpm",
+            ]
+        );
+        let ids: Vec<_> = messages(&[
+            (FUNCTIONAL_REQUEST_ID, &[0x02, 0x01, 0x0D]),
+            (RESPONSE_ID + 1, &[0x03, 0x41, 0x0D, 0x3C]),
+        ])
+        .iter()
+        .map(|message| message.id)
+        .collect();
+        assert_eq!(ids, vec![0x7DF, 0x7E9]);
+    }
+
+    #[test]
+    fn dtcs() {
+        assert_eq!(dtc_string(0x01, 0x33), "P0133");
+        assert_eq!(dtc_string(0x21, 0x35), "P2135");
+        assert_eq!(dtc_string(0x43, 0x00), "C0300");
+        assert_eq!(dtc_string(0x81, 0x23), "B0123");
+        assert_eq!(dtc_string(0xC1, 0x00), "U0100");
+        assert_eq!(dtc_string(0x3A, 0xBC), "P3ABC");
+
+        // The count on CAN, padding on older protocols and no DTCs
+        assert_eq!(decode_dtcs(&[0x02, 0x01, 0x33, 0xC1, 0x00]), "P0133, U0100");
+        assert_eq!(decode_dtcs(&[0x01, 0x33, 0x00, 0x00]), "P0133");
+        assert_eq!(decode_dtcs(&[0x00]), "no DTCs");
+        assert_eq!(
+            descriptions(&[(RESPONSE_ID, &[0x04, 0x43, 0x01, 0x01, 0x33])]),
+            vec!["Stored DTCs: P0133"]
+        );
+        assert_eq!(
+            current_data(&[0x02, 0x01, 0x33]),
+            "Current data: Freeze frame DTC: P0133"
+        );
+    }
+
+    #[test]
+    fn vin() {
+        let decoded = messages(&[
+            (FUNCTIONAL_REQUEST_ID, &[0x02, 0x09, 0x02]),
+            (
+                RESPONSE_ID,
+                &[0x10, 0x14, 0x49, 0x02, 0x01, b'1', b'D', b'4'],
+            ),
+            (PHYSICAL_REQUEST_ID, &[0x30, 0x00, 0x00]),
+            (
+                RESPONSE_ID,
+                &[0x21, b'G', b'P', b'0', b'0', b'R', b'5', b'5'],
+            ),
+            (
+                RESPONSE_ID,
+                &[0x22, b'B', b'1', b'2', b'3', b'4', b'5', b'6'],
+            ),
+        ]);
+        let decoded: Vec<_> = decoded
+            .iter()
+            .map(|message| (message.kind.clone(), message.description.as_str()))
+            .collect();
+        assert_eq!(
+            decoded,
+            vec![
+                (ObdKind::Request, "Vehicle information: VIN"),
+                (
+                    ObdKind::Response,
+                    "Vehicle information: VIN: 1D4GP00R55B123456"
+                ),
+            ]
+        );
+        // Without the count byte
+        assert_eq!(
+            decode_vehicle_info(0x02, b"1D4GP00R55B123456"),
+            "1D4GP00R55B123456"
+        );
+        assert_eq!(decode_vehicle_info(0x0A, b"\x01ECM\0-EngineControl"), "ECM");
+    }
+
+    #[test]
+    fn negative_responses() {
+        let decoded = messages(&[
+            (RESPONSE_ID, &[0x03, 0x7F, 0x01, 0x12]),
+            // UDS services on the same IDs
+            (RESPONSE_ID, &[0x03, 0x7F, 0x22, 0x31]),
+        ]);
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].kind, ObdKind::NegativeResponse(0x12));
+        assert_eq!(decoded[0].mode, 0x01);
+        assert_eq!(
+            decoded[0].description,
+            "Current data: subFunctionNotSupported (12)"
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use crate::protocol::isotp::IsoTpPdu;
+use crate::util::hex_to_str;
+
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+const RESPONSE_PENDING: u8 = 0x78;
+
+// User-supplied name for a data identifier, used by the *DataByIdentifier services.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct DidName {
+    pub did: u16,
+    pub name: String,
+}
+
+pub(crate) fn service_name(sid: u8) -> Option<&'static str> {
+    let name = match sid {
+        0x10 => "DiagnosticSessionControl",
+        0x11 => "ECUReset",
+        0x14 => "ClearDiagnosticInformation",
+        0x19 => "ReadDTCInformation",
+        0x22 => "ReadDataByIdentifier",
+        0x23 => "ReadMemoryByAddress",
+        0x24 => "ReadScalingDataByIdentifier",
+        0x27 => "SecurityAccess",
+        0x28 => "CommunicationControl",
+        0x29 => "Authentication",
+        0x2A => "ReadDataByPeriodicIdentifier",
+        0x2C => "DynamicallyDefineDataIdentifier",
+        0x2E => "WriteDataByIdentifier",
+        0x2F => "InputOutputControlByIdentifier",
+        0x31 => "RoutineControl",
+        0x34 => "RequestDownload",
+        0x35 => "RequestUpload",
+        0x36 => "TransferData",
+        0x37 => "RequestTransferExit",
+        0x38 => "RequestFileTransfer",
+        0x3D => "WriteMemoryByAddress",
+        0x3E => "TesterPresent",
+        0x83 => "AccessTimingParameter",
+        0x84 => "SecuredDataTransmission",
+        0x85 => "ControlDTCSetting",
+        0x86 => "ResponseOnEvent",
+        0x87 => "LinkControl",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// Services whose first parameter byte is a sub-function
+fn has_sub_function(sid: u8) -> bool {
+    matches!(
+        sid,
+        0x10 | 0x11 | 0x19 | 0x27 | 0x28 | 0x29 | 0x2C | 0x31 | 0x3E | 0x83 | 0x85 | 0x86 | 0x87
+    )
+}
+
+pub(crate) fn sub_function_name(sid: u8, sub_function: u8) -> Option<String> {
+    let name = match (sid, sub_function) {
+        (0x10, 0x01) => "defaultSession",
+        (0x10, 0x02) => "programmingSession",
+        (0x10, 0x03) => "extendedDiagnosticSession",
+        (0x10, 0x04) => "safetySystemDiagnosticSession",
+        (0x11, 0x01) => "hardReset",
+        (0x11, 0x02) => "keyOffOnReset",
+        (0x11, 0x03) => "softReset",
+        (0x11, 0x04) => "enableRapidPowerShutDown",
+        (0x11, 0x05) => "disableRapidPowerShutDown",
+        (0x19, 0x01) => "reportNumberOfDTCByStatusMask",
+        (0x19, 0x02) => "reportDTCByStatusMask",
+        (0x19, 0x03) => "reportDTCSnapshotIdentification",
+        (0x19, 0x04) => "reportDTCSnapshotRecordByDTCNumber",
+        (0x19, 0x06) => "reportDTCExtDataRecordByDTCNumber",
+        (0x19, 0x0A) => "reportSupportedDTC",
+        // 0x00 and 0x7F are reserved
+        (0x27, level @ 0x01..=0x7E) => {
+            return Some(match level % 2 {
+                1 => format!("requestSeed (level {})", level),
+                _ => format!("sendKey (level {})", level - 1),
+            })
+        }
+        (0x28, 0x00) => "enableRxAndTx",
+        (0x28, 0x01) => "enableRxAndDisableTx",
+        (0x28, 0x02) => "disableRxAndEnableTx",
+        (0x28, 0x03) => "disableRxAndTx",
+        (0x31, 0x01) => "startRoutine",
+        (0x31, 0x02) => "stopRoutine",
+        (0x31, 0x03) => "requestRoutineResults",
+        (0x3E, 0x00) => "zeroSubFunction",
+        (0x85, 0x01) => "on",
+        (0x85, 0x02) => "off",
+        (0x87, 0x01) => "verifyModeTransitionWithFixedParameter",
+        (0x87, 0x02) => "verifyModeTransitionWithSpecificParameter",
+        (0x87, 0x03) => "transitionMode",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+pub(crate) fn nrc_name(nrc: u8) -> &'static str {
+    match nrc {
+        0x10 => "generalReject",
+        0x11 => "serviceNotSupported",
+        0x12 => "subFunctionNotSupported",
+        0x13 => "incorrectMessageLengthOrInvalidFormat",
+        0x14 => "responseTooLong",
+        0x21 => "busyRepeatRequest",
+        0x22 => "conditionsNotCorrect",
+        0x24 => "requestSequenceError",
+        0x25 => "noResponseFromSubnetComponent",
+        0x26 => "failurePreventsExecutionOfRequestedAction",
+        0x31 => "requestOutOfRange",
+        0x33 => "securityAccessDenied",
+        0x35 => "invalidKey",
+        0x36 => "exceedNumberOfAttempts",
+        0x37 => "requiredTimeDelayNotExpired",
+        0x70 => "uploadDownloadNotAccepted",
+        0x71 => "transferDataSuspended",
+        0x72 => "generalProgrammingFailure",
+        0x73 => "wrongBlockSequenceCounter",
+        0x78 => "requestCorrectlyReceived-ResponsePending",
+        0x7E => "subFunctionNotSupportedInActiveSession",
+        0x7F => "serviceNotSupportedInActiveSession",
+        0x81 => "rpmTooHigh",
+        0x82 => "rpmTooLow",
+        0x83 => "engineIsRunning",
+        0x84 => "engineIsNotRunning",
+        0x85 => "engineRunTimeTooLow",
+        0x86 => "temperatureTooHigh",
+        0x87 => "temperatureTooLow",
+        0x88 => "vehicleSpeedTooHigh",
+        0x89 => "vehicleSpeedTooLow",
+        0x8A => "throttle/PedalTooHigh",
+        0x8B => "throttle/PedalTooLow",
+        0x8C => "transmissionRangeNotInNeutral",
+        0x8D => "transmissionRangeNotInGear",
+        0x8F => "brakeSwitch(es)NotClosed",
+        0x90 => "shifterLeverNotInPark",
+        0x91 => "torqueConverterClutchLocked",
+        0x92 => "voltageTooHigh",
+        0x93 => "voltageTooLow",
+        _ => "unknown",
+    }
+}
+
+fn standard_did_name(did: u16) -> Option<&'static str> {
+    let name = match did {
+        0xF186 => "ActiveDiagnosticSession",
+        0xF187 => "SparePartNumber",
+        0xF188 => "ECUSoftwareNumber",
+        0xF189 => "ECUSoftwareVersionNumber",
+        0xF18A => "SystemSupplierIdentifier",
+        0xF18B => "ECUManufacturingDate",
+        0xF18C => "ECUSerialNumber",
+        0xF190 => "VIN",
+        0xF191 => "ECUHardwareNumber",
+        0xF192 => "SystemSupplierECUHardwareNumber",
+        0xF193 => "SystemSupplierECUHardwareVersionNumber",
+        0xF194 => "SystemSupplierECUSoftwareNumber",
+        0xF195 => "SystemSupplierECUSoftwareVersionNumber",
+        0xF197 => "SystemNameOrEngineType",
+        0xF198 => "RepairShopCodeOrTesterSerialNumber",
+        0xF199 => "ProgrammingDate",
+        0xF19E => "ODXFile",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn did_string(did: u16, did_names: &[DidName]) -> String {
+    let name = did_names
+        .iter()
+        .find(|entry| entry.did == did)
+        .map(|entry| entry.name.as_str())
+        .or_else(|| standard_did_name(did));
+    match name {
+        Some(name) => format!("{:04X} ({})", did, name),
+        None => format!("{:04X}", did),
+    }
+}
+
+// Services that address data by 16-bit identifier in requests and positive responses
+fn has_did(sid: u8) -> bool {
+    matches!(sid, 0x22 | 0x24 | 0x2E | 0x2F)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UdsKind {
+    Request,
+    PositiveResponse,
+    NegativeResponse(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct UdsMessage {
+    // Index of the PDU this was decoded from
+    pub pdu: usize,
+    pub kind: UdsKind,
+    // Request service ID, also for responses
+    pub service: u8,
+    pub sub_function: Option<u8>,
+    pub suppress_positive_response: bool,
+    pub description: String,
+    // Index of the matching request or final response
+    pub paired: Option<usize>,
+    pub latency_ms: Option<f64>,
+}
+
+impl UdsMessage {
+    pub(crate) fn is_response(&self) -> bool {
+        self.kind != UdsKind::Request
+    }
+}
+
+fn is_request_sid(sid: u8) -> bool {
+    service_name(sid).is_some()
+}
+
+fn decode_pdu(index: usize, pdu: &IsoTpPdu, did_names: &[DidName]) -> Option<UdsMessage> {
+    let data = &pdu.data;
+    let first = *data.first()?;
+
+    let (kind, service, params) = if first == NEGATIVE_RESPONSE {
+        let service = *data.get(1)?;
+        let nrc = *data.get(2)?;
+        (UdsKind::NegativeResponse(nrc), service, &data[3..])
+    } else if is_request_sid(first) {
+        (UdsKind::Request, first, &data[1..])
+    } else if first >= POSITIVE_RESPONSE_OFFSET && is_request_sid(first - POSITIVE_RESPONSE_OFFSET)
+    {
+        (
+            UdsKind::PositiveResponse,
+            first - POSITIVE_RESPONSE_OFFSET,
+            &data[1..],
+        )
+    } else {
+        return None;
+    };
+
+    let service_text = service_name(service).unwrap_or("Unknown");
+    let mut message = UdsMessage {
+        pdu: index,
+        kind: kind.clone(),
+        service,
+        sub_function: None,
+        suppress_positive_response: false,
+        description: String::new(),
+        paired: None,
+        latency_ms: None,
+    };
+
+    if let UdsKind::NegativeResponse(nrc) = kind {
+        message.description = format!(
+            "{} negative response: {} ({:02X})",
+            service_text,
+            nrc_name(nrc),
+            nrc
+        );
+        return Some(message);
+    }
+
+    let mut parts = vec![match kind {
+        UdsKind::Request => service_text.to_string(),
+        _ => format!("{} response", service_text),
+    }];
+    let mut params = params;
+
+    if has_sub_function(service) {
+        if let Some(sub_function) = params.first() {
+            let value = sub_function & !SUPPRESS_POSITIVE_RESPONSE;
+            message.sub_function = Some(value);
+            message.suppress_positive_response =
+                kind == UdsKind::Request && sub_function & SUPPRESS_POSITIVE_RESPONSE != 0;
+            parts.push(sub_function_name(service, value).unwrap_or(format!("{:02X}", value)));
+            if message.suppress_positive_response {
+                parts.push("(suppress response)".to_string());
+            }
+            params = &params[1..];
+        }
+    }
+
+    if has_did(service) && params.len() >= 2 {
+        parts.push(did_string(
+            u16::from_be_bytes([params[0], params[1]]),
+            did_names,
+        ));
+        params = &params[2..];
+    } else if service == 0x31 && params.len() >= 2 {
+        parts.push(format!("routine {:02X}{:02X}", params[0], params[1]));
+        params = &params[2..];
+    }
+
+    if !params.is_empty() {
+        parts.push(format!("[{}]", hex_to_str(params)));
+    }
+    message.description = parts.join(" ");
+    Some(message)
+}
+
+// Decode the UDS messages in a list of PDUs and pair each request with its response.
+pub(crate) fn decode(pdus: &[IsoTpPdu], did_names: &[DidName]) -> Vec<UdsMessage> {
+    let mut messages: Vec<UdsMessage> = pdus
+        .iter()
+        .enumerate()
+        .filter(|(_, pdu)| pdu.is_complete())
+        .filter_map(|(index, pdu)| decode_pdu(index, pdu, did_names))
+        .collect();
+
+    // Outstanding request for each ISO-TP channel
+    let mut pending: Vec<(usize, usize)> = Vec::new();
+    for index in 0..messages.len() {
+        let channel = pdus[messages[index].pdu].channel;
+        let request = pending
+            .iter()
+            .position(|(pending_channel, _)| *pending_channel == channel);
+
+        if !messages[index].is_response() {
+            match request {
+                Some(position) => pending[position].1 = index,
+                None => pending.push((channel, index)),
+            }
+            continue;
+        }
+
+        let position = match request {
+            Some(position) if messages[pending[position].1].service == messages[index].service => {
+                position
+            }
+            _ => continue,
+        };
+        let request_index = pending[position].1;
+        let latency = (pdus[messages[index].pdu].timestamp
+            - pdus[messages[request_index].pdu].end_timestamp)
+            * 1000.0;
+        messages[index].paired = Some(request_index);
+        messages[index].latency_ms = Some(latency);
+        if messages[index].kind != UdsKind::NegativeResponse(RESPONSE_PENDING) {
+            messages[request_index].paired = Some(index);
+            messages[request_index].latency_ms = Some(latency);
+            pending.remove(position);
+        }
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::isotp::Direction;
+
+    fn pdu(channel: usize, timestamp: f64, data: &[u8]) -> IsoTpPdu {
+        IsoTpPdu {
+            channel,
+            direction: Direction::Tx,
+            timestamp,
+            end_timestamp: timestamp,
+            length: data.len(),
+            data: data.to_vec(),
+            rows: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn security_access_levels() {
+        assert_eq!(sub_function_name(0x27, 0x00), None);
+        assert_eq!(
+            sub_function_name(0x27, 0x01),
+            Some("requestSeed (level 1)".to_string())
+        );
+        assert_eq!(
+            sub_function_name(0x27, 0x02),
+            Some("sendKey (level 1)".to_string())
+        );
+        assert_eq!(
+            sub_function_name(0x27, 0x7E),
+            Some("sendKey (level 125)".to_string())
+        );
+        assert_eq!(sub_function_name(0x27, 0x7F), None);
+    }
+
+    #[test]
+    fn suppressed_reserved_sub_function() {
+        let messages = decode(&[pdu(0, 0.0, &[0x27, 0x80])], &[]);
+        assert_eq!(messages[0].sub_function, Some(0x00));
+        assert!(messages[0].suppress_positive_response);
+        assert_eq!(
+            messages[0].description,
+            "SecurityAccess 00 (suppress response)"
+        );
+    }
+
+    #[test]
+    fn descriptions() {
+        let did_names = vec![DidName {
+            did: 0x0102,
+            name: "Odometer".to_string(),
+        }];
+        let pdus = vec![
+            pdu(0, 0.0, &[0x10, 0x03]),
+            pdu(0, 0.0, &[0x22, 0xF1, 0x90]),
+            pdu(0, 0.0, &[0x62, 0x01, 0x02, 0x00, 0x10]),
+            pdu(0, 0.0, &[0x31, 0x01, 0xFF, 0x00, 0xAA]),
+            pdu(0, 0.0, &[0x7F, 0x27, 0x35]),
+        ];
+        let descriptions: Vec<String> = decode(&pdus, &did_names)
+            .into_iter()
+            .map(|m| m.description)
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                "DiagnosticSessionControl extendedDiagnosticSession",
+                "ReadDataByIdentifier F190 (VIN)",
+                "ReadDataByIdentifier response 0102 (Odometer) [00 10]",
+                "RoutineControl startRoutine routine FF00 [AA]",
+                "SecurityAccess negative response: invalidKey (35)",
+            ]
+        );
+    }
+
+    #[test]
+    fn pairing() {
+        let pdus = vec![
+            pdu(0, 0.000, &[0x31, 0x01, 0xFF, 0x00]),
+            // Other channels keep their own pending requests
+            pdu(1, 0.005, &[0x3E, 0x00]),
+            pdu(0, 0.010, &[0x7F, 0x31, 0x78]),
+            pdu(0, 0.250, &[0x71, 0x01, 0xFF, 0x00]),
+            pdu(1, 0.300, &[0x7E, 0x00]),
+            // Not a response to the pending request
+            pdu(0, 0.400, &[0x50, 0x01]),
+        ];
+        let messages = decode(&pdus, &[]);
+        assert_eq!(messages[0].paired, Some(3));
+        assert_eq!(messages[2].paired, Some(0));
+        assert_eq!(messages[3].paired, Some(0));
+        assert!((messages[3].latency_ms.unwrap() - 250.0).abs() < 1e-9);
+        assert_eq!(messages[1].paired, Some(4));
+        assert_eq!(messages[5].paired, None);
+    }
+}
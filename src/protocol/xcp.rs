@@ -0,0 +1,1208 @@
+use strum::EnumIter;
+
+use crate::message::Message;
+use crate::signal::ByteOrder;
+use crate::util::{bytes_to_string, hex_to_str};
+
+const XCP_RES: u8 = 0xFF;
+const XCP_ERR: u8 = 0xFE;
+const XCP_EV: u8 = 0xFD;
+const XCP_SERV: u8 = 0xFC;
+const CCP_CRM: u8 = 0xFF;
+const CCP_EVENT: u8 = 0xFE;
+// DAQ list mode bit that adds a timestamp to the first ODT of each sample
+const XCP_DAQ_TIMESTAMP: u8 = 0x10;
+// Used until GET_DAQ_RESOLUTION_INFO reports the slave's timestamp size
+const DEFAULT_TIMESTAMP_SIZE: usize = 4;
+
+#[derive(
+    Debug, EnumIter, PartialEq, serde::Serialize, serde::Deserialize, Default, Clone, Copy,
+)]
+pub enum XcpProtocol {
+    #[default]
+    Xcp,
+    Ccp,
+}
+
+impl XcpProtocol {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            XcpProtocol::Xcp => "XCP",
+            XcpProtocol::Ccp => "CCP",
+        }
+    }
+}
+
+// The pair of CAN IDs used by one XCP or CCP master and slave.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct XcpChannel {
+    pub name: String,
+    pub protocol: XcpProtocol,
+    pub master_id: Vec<u8>,
+    pub slave_id: Vec<u8>,
+    // Byte order of the slave's parameters and measurements. XCP slaves report it when
+    // connecting, which takes precedence.
+    #[serde(default)]
+    pub byte_order: ByteOrder,
+}
+
+fn xcp_command_name(command: u8) -> Option<&'static str> {
+    let name = match command {
+        0xFF => "CONNECT",
+        0xFE => "DISCONNECT",
+        0xFD => "GET_STATUS",
+        0xFC => "SYNCH",
+        0xFB => "GET_COMM_MODE_INFO",
+        0xFA => "GET_ID",
+        0xF9 => "SET_REQUEST",
+        0xF8 => "GET_SEED",
+        0xF7 => "UNLOCK",
+        0xF6 => "SET_MTA",
+        0xF5 => "UPLOAD",
+        0xF4 => "SHORT_UPLOAD",
+        0xF3 => "BUILD_CHECKSUM",
+        0xF2 => "TRANSPORT_LAYER_CMD",
+        0xF1 => "USER_CMD",
+        0xF0 => "DOWNLOAD",
+        0xEF => "DOWNLOAD_NEXT",
+        0xEE => "DOWNLOAD_MAX",
+        0xED => "SHORT_DOWNLOAD",
+        0xEC => "MODIFY_BITS",
+        0xEB => "SET_CAL_PAGE",
+        0xEA => "GET_CAL_PAGE",
+        0xE9 => "GET_PAG_PROCESSOR_INFO",
+        0xE8 => "GET_SEGMENT_INFO",
+        0xE7 => "GET_PAGE_INFO",
+        0xE6 => "SET_SEGMENT_MODE",
+        0xE5 => "GET_SEGMENT_MODE",
+        0xE4 => "COPY_CAL_PAGE",
+        0xE3 => "CLEAR_DAQ_LIST",
+        0xE2 => "SET_DAQ_PTR",
+        0xE1 => "WRITE_DAQ",
+        0xE0 => "SET_DAQ_LIST_MODE",
+        0xDF => "GET_DAQ_LIST_MODE",
+        0xDE => "START_STOP_DAQ_LIST",
+        0xDD => "START_STOP_SYNCH",
+        0xDC => "GET_DAQ_CLOCK",
+        0xDB => "READ_DAQ",
+        0xDA => "GET_DAQ_PROCESSOR_INFO",
+        0xD9 => "GET_DAQ_RESOLUTION_INFO",
+        0xD8 => "GET_DAQ_LIST_INFO",
+        0xD7 => "GET_DAQ_EVENT_INFO",
+        0xD6 => "FREE_DAQ",
+        0xD5 => "ALLOC_DAQ",
+        0xD4 => "ALLOC_ODT",
+        0xD3 => "ALLOC_ODT_ENTRY",
+        0xD2 => "PROGRAM_START",
+        0xD1 => "PROGRAM_CLEAR",
+        0xD0 => "PROGRAM",
+        0xCF => "PROGRAM_RESET",
+        0xCE => "GET_PGM_PROCESSOR_INFO",
+        0xCD => "GET_SECTOR_INFO",
+        0xCC => "PROGRAM_PREPARE",
+        0xCB => "PROGRAM_FORMAT",
+        0xCA => "PROGRAM_NEXT",
+        0xC9 => "PROGRAM_MAX",
+        0xC8 => "PROGRAM_VERIFY",
+        0xC7 => "WRITE_DAQ_MULTIPLE",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn xcp_error_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "ERR_CMD_SYNCH",
+        0x10 => "ERR_CMD_BUSY",
+        0x11 => "ERR_DAQ_ACTIVE",
+        0x12 => "ERR_PGM_ACTIVE",
+        0x20 => "ERR_CMD_UNKNOWN",
+        0x21 => "ERR_CMD_SYNTAX",
+        0x22 => "ERR_OUT_OF_RANGE",
+        0x23 => "ERR_WRITE_PROTECTED",
+        0x24 => "ERR_ACCESS_DENIED",
+        0x25 => "ERR_ACCESS_LOCKED",
+        0x26 => "ERR_PAGE_NOT_VALID",
+        0x27 => "ERR_MODE_NOT_VALID",
+        0x28 => "ERR_SEGMENT_NOT_VALID",
+        0x29 => "ERR_SEQUENCE",
+        0x2A => "ERR_DAQ_CONFIG",
+        0x30 => "ERR_MEMORY_OVERFLOW",
+        0x31 => "ERR_GENERIC",
+        0x32 => "ERR_VERIFY",
+        0x33 => "ERR_RESOURCE_TEMPORARY_NOT_ACCESSIBLE",
+        _ => "unknown",
+    }
+}
+
+fn xcp_event_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "EV_RESUME_MODE",
+        0x01 => "EV_CLEAR_DAQ",
+        0x02 => "EV_STORE_DAQ",
+        0x03 => "EV_STORE_CAL",
+        0x05 => "EV_CMD_PENDING",
+        0x06 => "EV_DAQ_OVERLOAD",
+        0x07 => "EV_SESSION_TERMINATED",
+        0x08 => "EV_TIME_SYNC",
+        0x09 => "EV_STIM_TIMEOUT",
+        0x0A => "EV_SLEEP",
+        0x0B => "EV_WAKE_UP",
+        0xFE => "EV_USER",
+        0xFF => "EV_TRANSPORT",
+        _ => "unknown",
+    }
+}
+
+fn ccp_command_name(command: u8) -> Option<&'static str> {
+    let name = match command {
+        0x01 => "CONNECT",
+        0x02 => "SET_MTA",
+        0x03 => "DNLOAD",
+        0x04 => "UPLOAD",
+        0x05 => "TEST",
+        0x06 => "START_STOP",
+        0x07 => "DISCONNECT",
+        0x08 => "START_STOP_ALL",
+        0x09 => "GET_ACTIVE_CAL_PAGE",
+        0x0C => "SET_S_STATUS",
+        0x0D => "GET_S_STATUS",
+        0x0E => "BUILD_CHKSUM",
+        0x0F => "SHORT_UP",
+        0x10 => "CLEAR_MEMORY",
+        0x11 => "SELECT_CAL_PAGE",
+        0x12 => "GET_SEED",
+        0x13 => "UNLOCK",
+        0x14 => "GET_DAQ_SIZE",
+        0x15 => "SET_DAQ_PTR",
+        0x16 => "WRITE_DAQ",
+        0x17 => "EXCHANGE_ID",
+        0x18 => "PROGRAM",
+        0x19 => "MOVE",
+        0x1B => "GET_CCP_VERSION",
+        0x20 => "DIAG_SERVICE",
+        0x21 => "ACTION_SERVICE",
+        0x22 => "PROGRAM_6",
+        0x23 => "DNLOAD_6",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn ccp_error_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "acknowledge",
+        0x01 => "DAQ processor overload",
+        0x10 => "command processor busy",
+        0x11 => "DAQ processor busy",
+        0x12 => "internal timeout",
+        0x18 => "key request",
+        0x19 => "session status request",
+        0x20 => "cold start request",
+        0x21 => "cal. data init. request",
+        0x22 => "DAQ list init. request",
+        0x23 => "code update request",
+        0x30 => "unknown command",
+        0x31 => "command syntax",
+        0x32 => "parameter(s) out of range",
+        0x33 => "access denied",
+        0x34 => "overload",
+        0x35 => "access locked",
+        0x36 => "resource/function not available",
+        _ => "unknown",
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XcpSender {
+    Master,
+    Slave,
+}
+
+impl XcpSender {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            XcpSender::Master => "Master",
+            XcpSender::Slave => "Slave",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XcpKind {
+    Command,
+    Response,
+    Error(u8),
+    Event,
+    Service,
+    Daq,
+}
+
+impl XcpKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            XcpKind::Command => "Command",
+            XcpKind::Response => "Response",
+            XcpKind::Error(_) => "Error",
+            XcpKind::Event => "Event",
+            XcpKind::Service => "Service",
+            XcpKind::Daq => "DAQ",
+        }
+    }
+}
+
+// One decoded XCP or CCP frame.
+#[derive(Debug, Clone)]
+pub struct XcpPacket {
+    pub channel: usize,
+    pub row: usize,
+    pub timestamp: f64,
+    pub sender: XcpSender,
+    pub kind: XcpKind,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct OdtEntry {
+    address: u32,
+    extension: u8,
+    // In bytes; entries that were allocated but never written have no size
+    size: u8,
+    // Bit within the element for single-bit measurements
+    bit_offset: Option<u8>,
+}
+
+impl OdtEntry {
+    fn text(&self) -> String {
+        match self.extension {
+            0 => format!("{:08X}", self.address),
+            extension => format!("{:02X}:{:08X}", extension, self.address),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct DaqList {
+    odts: Vec<Vec<OdtEntry>>,
+    // PID of the first ODT, from the slave's answer when the list is started or sized
+    first_pid: Option<u8>,
+    timestamp: bool,
+}
+
+// How XCP DTOs identify their DAQ list and ODT
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum Identification {
+    #[default]
+    Absolute,
+    RelativeByte,
+    RelativeWord,
+    RelativeWordAligned,
+}
+
+// DAQ configuration and protocol state of one channel, built up from the commands seen.
+#[derive(Default)]
+struct Session {
+    byte_order: ByteOrder,
+    lists: Vec<DaqList>,
+    // List, ODT and entry written by the next WRITE_DAQ
+    pointer: (usize, usize, usize),
+    // The last command, which gives meaning to the response
+    pending: Option<Vec<u8>>,
+    identification: Identification,
+    timestamp_size: usize,
+}
+
+impl Session {
+    fn new(byte_order: ByteOrder) -> Self {
+        Self {
+            byte_order,
+            timestamp_size: DEFAULT_TIMESTAMP_SIZE,
+            ..Default::default()
+        }
+    }
+
+    fn number(&self, data: &[u8], start: usize, size: usize) -> Option<u64> {
+        let bytes = data.get(start..start + size)?.iter();
+        let value = match self.byte_order {
+            ByteOrder::Intel => bytes.rev().fold(0u64, |acc, b| (acc << 8) | *b as u64),
+            ByteOrder::Motorola => bytes.fold(0u64, |acc, b| (acc << 8) | *b as u64),
+        };
+        Some(value)
+    }
+
+    fn list_mut(&mut self, daq: usize) -> &mut DaqList {
+        if self.lists.len() <= daq {
+            self.lists.resize(daq + 1, DaqList::default());
+        }
+        &mut self.lists[daq]
+    }
+
+    fn odt_mut(&mut self, daq: usize, odt: usize) -> &mut Vec<OdtEntry> {
+        let list = self.list_mut(daq);
+        if list.odts.len() <= odt {
+            list.odts.resize(odt + 1, Vec::new());
+        }
+        &mut list.odts[odt]
+    }
+
+    fn write_entry(&mut self, entry: OdtEntry) {
+        let (daq, odt, index) = self.pointer;
+        let entries = self.odt_mut(daq, odt);
+        if entries.len() <= index {
+            entries.resize(index + 1, OdtEntry::default());
+        }
+        entries[index] = entry;
+        self.pointer.2 += 1;
+    }
+
+    // List and ODT of an absolute PID. Without a first PID from the slave, ODTs are assumed to
+    // be numbered consecutively over all lists.
+    fn odt_of_pid(&self, pid: u8) -> Option<(usize, usize)> {
+        let mut next_pid = 0usize;
+        for (daq, list) in self.lists.iter().enumerate() {
+            let first = list.first_pid.map_or(next_pid, |first| first as usize);
+            if (first..first + list.odts.len()).contains(&(pid as usize)) {
+                return Some((daq, pid as usize - first));
+            }
+            next_pid = first + list.odts.len();
+        }
+        None
+    }
+
+    // "DAQ 0 ODT 1: 20001000=12, ..." from the entries configured for this ODT
+    fn decode_odt(&self, daq: usize, odt: usize, data: &[u8]) -> String {
+        let list = self.lists.get(daq);
+        let entries = match list.and_then(|list| list.odts.get(odt)) {
+            Some(entries) => entries,
+            None => return format!("DAQ {} ODT {}: [{}]", daq, odt, hex_to_str(data)),
+        };
+        let mut position = 0;
+        let mut parts = Vec::new();
+        if odt == 0 && matches!(list, Some(list) if list.timestamp) {
+            if let Some(timestamp) = self.number(data, 0, self.timestamp_size) {
+                parts.push(format!("timestamp {}", timestamp));
+            }
+            position = self.timestamp_size;
+        }
+        for entry in entries.iter().filter(|entry| entry.size > 0) {
+            let value = match self.number(data, position, entry.size as usize) {
+                Some(value) => value,
+                None => {
+                    parts.push(format!("{}=?", entry.text()));
+                    break;
+                }
+            };
+            let value = match entry.bit_offset {
+                Some(bit) => (value >> bit) & 1,
+                None => value,
+            };
+            parts.push(format!("{}={}", entry.text(), value));
+            position += entry.size as usize;
+        }
+        format!("DAQ {} ODT {}: {}", daq, odt, parts.join(", "))
+    }
+
+    fn xcp_command(&mut self, data: &[u8]) -> String {
+        self.pending = Some(data.to_vec());
+        let name = match xcp_command_name(data[0]) {
+            Some(name) => name.to_string(),
+            None => format!("command {:02X}", data[0]),
+        };
+        match self.xcp_command_details(data) {
+            Some(details) => format!("{} {}", name, details),
+            None if data.len() > 1 => format!("{} [{}]", name, hex_to_str(&data[1..])),
+            None => name,
+        }
+    }
+
+    fn xcp_command_details(&mut self, data: &[u8]) -> Option<String> {
+        match data[0] {
+            // CONNECT
+            0xFF => Some(format!("mode {}", data.get(1)?)),
+            // SET_MTA
+            0xF6 => Some(format!(
+                "{:02X}:{:08X}",
+                data.get(3)?,
+                self.number(data, 4, 4)?
+            )),
+            // UPLOAD
+            0xF5 => Some(format!("{} bytes", data.get(1)?)),
+            // SHORT_UPLOAD
+            0xF4 => Some(format!(
+                "{} bytes from {:02X}:{:08X}",
+                data.get(1)?,
+                data.get(3)?,
+                self.number(data, 4, 4)?
+            )),
+            // DOWNLOAD
+            0xF0 => Some(format!(
+                "{} bytes [{}]",
+                data.get(1)?,
+                hex_to_str(&data[2..])
+            )),
+            // SHORT_DOWNLOAD
+            0xED => Some(format!(
+                "{} bytes to {:02X}:{:08X} [{}]",
+                data.get(1)?,
+                data.get(3)?,
+                self.number(data, 4, 4)?,
+                hex_to_str(data.get(8..).unwrap_or(&[]))
+            )),
+            // FREE_DAQ
+            0xD6 => {
+                self.lists.clear();
+                Some("all lists".to_string())
+            }
+            // ALLOC_DAQ
+            0xD5 => {
+                let count = self.number(data, 2, 2)? as usize;
+                self.lists = vec![DaqList::default(); count];
+                Some(format!("{} lists", count))
+            }
+            // ALLOC_ODT
+            0xD4 => {
+                let daq = self.number(data, 2, 2)? as usize;
+                let count = *data.get(4)? as usize;
+                self.list_mut(daq).odts = vec![Vec::new(); count];
+                Some(format!("DAQ {}: {} ODTs", daq, count))
+            }
+            // ALLOC_ODT_ENTRY
+            0xD3 => {
+                let daq = self.number(data, 2, 2)? as usize;
+                let odt = *data.get(4)? as usize;
+                let count = *data.get(5)? as usize;
+                *self.odt_mut(daq, odt) = vec![OdtEntry::default(); count];
+                Some(format!("DAQ {} ODT {}: {} entries", daq, odt, count))
+            }
+            // SET_DAQ_PTR
+            0xE2 => {
+                let daq = self.number(data, 2, 2)? as usize;
+                let odt = *data.get(4)? as usize;
+                let entry = *data.get(5)? as usize;
+                self.pointer = (daq, odt, entry);
+                Some(format!("DAQ {} ODT {} entry {}", daq, odt, entry))
+            }
+            // WRITE_DAQ
+            0xE1 => {
+                let entry = OdtEntry {
+                    bit_offset: Some(*data.get(1)?).filter(|bit| *bit != 0xFF),
+                    size: *data.get(2)?,
+                    extension: *data.get(3)?,
+                    address: self.number(data, 4, 4)? as u32,
+                };
+                let text = format!("{} bytes at {}", entry.size, entry.text());
+                self.write_entry(entry);
+                Some(text)
+            }
+            // WRITE_DAQ_MULTIPLE
+            0xC7 => {
+                let count = *data.get(1)? as usize;
+                let mut texts = Vec::new();
+                for element in 0..count {
+                    let start = 2 + element * 8;
+                    let entry = OdtEntry {
+                        bit_offset: Some(*data.get(start)?).filter(|bit| *bit != 0xFF),
+                        size: *data.get(start + 1)?,
+                        address: self.number(data, start + 2, 4)? as u32,
+                        extension: *data.get(start + 6)?,
+                    };
+                    texts.push(format!("{} bytes at {}", entry.size, entry.text()));
+                    self.write_entry(entry);
+                }
+                Some(texts.join(", "))
+            }
+            // SET_DAQ_LIST_MODE
+            0xE0 => {
+                let mode = *data.get(1)?;
+                let daq = self.number(data, 2, 2)? as usize;
+                let event = self.number(data, 4, 2)?;
+                self.list_mut(daq).timestamp = mode & XCP_DAQ_TIMESTAMP != 0;
+                Some(format!("DAQ {}: mode {:02X}, event {}", daq, mode, event))
+            }
+            // START_STOP_DAQ_LIST
+            0xDE => {
+                let mode = match data.get(1)? {
+                    0 => "stop",
+                    1 => "start",
+                    2 => "select",
+                    _ => "?",
+                };
+                Some(format!("{} DAQ {}", mode, self.number(data, 2, 2)?))
+            }
+            // START_STOP_SYNCH
+            0xDD => Some(
+                match data.get(1)? {
+                    0 => "stop all",
+                    1 => "start selected",
+                    2 => "stop selected",
+                    _ => "?",
+                }
+                .to_string(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn xcp_response(&mut self, data: &[u8]) -> String {
+        let pending = self.pending.take();
+        let command = pending
+            .as_ref()
+            .and_then(|command| command.first().copied());
+        let name = command
+            .and_then(xcp_command_name)
+            .unwrap_or("unknown command");
+        let details = match command {
+            Some(command) => self.xcp_response_details(command, pending.as_deref(), data),
+            None => None,
+        };
+        match details {
+            Some(details) => format!("{} response: {}", name, details),
+            None if data.len() > 1 => {
+                format!("{} response [{}]", name, hex_to_str(&data[1..]))
+            }
+            None => format!("{} response", name),
+        }
+    }
+
+    fn xcp_response_details(
+        &mut self,
+        command: u8,
+        request: Option<&[u8]>,
+        data: &[u8],
+    ) -> Option<String> {
+        match command {
+            // CONNECT, which tells the byte order of everything that follows
+            0xFF => {
+                let comm_mode = *data.get(2)?;
+                self.byte_order = match comm_mode & 0x01 {
+                    0 => ByteOrder::Intel,
+                    _ => ByteOrder::Motorola,
+                };
+                Some(format!(
+                    "resources {:02X}, {}, max CTO {}, max DTO {}",
+                    data.get(1)?,
+                    self.byte_order.name(),
+                    data.get(3)?,
+                    self.number(data, 4, 2)?
+                ))
+            }
+            // UPLOAD, SHORT_UPLOAD
+            0xF5 | 0xF4 => Some(format!("[{}]", hex_to_str(&data[1..]))),
+            // GET_DAQ_PROCESSOR_INFO
+            0xDA => {
+                let key = *data.get(7)?;
+                self.identification = match key >> 6 {
+                    0 => Identification::Absolute,
+                    1 => Identification::RelativeByte,
+                    2 => Identification::RelativeWord,
+                    _ => Identification::RelativeWordAligned,
+                };
+                Some(format!(
+                    "max DAQ {}, max event {}, key {:02X}",
+                    self.number(data, 2, 2)?,
+                    self.number(data, 4, 2)?,
+                    key
+                ))
+            }
+            // GET_DAQ_RESOLUTION_INFO
+            0xD9 => {
+                let timestamp_mode = *data.get(5)?;
+                self.timestamp_size = (timestamp_mode & 0x07) as usize;
+                Some(format!("timestamp size {}", self.timestamp_size))
+            }
+            // GET_DAQ_CLOCK
+            0xDC => Some(format!("timestamp {}", self.number(data, 4, 4)?)),
+            // START_STOP_DAQ_LIST, which assigns the list its first PID when starting it
+            0xDE => {
+                let request = request?;
+                let daq = self.number(request, 2, 2)? as usize;
+                let first_pid = *data.get(1)?;
+                if request.get(1) != Some(&0) {
+                    self.list_mut(daq).first_pid = Some(first_pid);
+                }
+                Some(format!("DAQ {} first PID {}", daq, first_pid))
+            }
+            _ => None,
+        }
+    }
+
+    fn xcp_daq(&self, data: &[u8]) -> String {
+        let (daq, odt, header) = match self.identification {
+            Identification::Absolute => match self.odt_of_pid(data[0]) {
+                Some((daq, odt)) => (daq, odt, 1),
+                None => return format!("DAQ PID {}: [{}]", data[0], hex_to_str(&data[1..])),
+            },
+            Identification::RelativeByte => {
+                (*data.get(1).unwrap_or(&0) as usize, data[0] as usize, 2)
+            }
+            Identification::RelativeWord => (
+                self.number(data, 1, 2).unwrap_or(0) as usize,
+                data[0] as usize,
+                3,
+            ),
+            Identification::RelativeWordAligned => (
+                self.number(data, 2, 2).unwrap_or(0) as usize,
+                data[0] as usize,
+                4,
+            ),
+        };
+        self.decode_odt(daq, odt, data.get(header..).unwrap_or(&[]))
+    }
+
+    fn xcp_frame(&mut self, sender: XcpSender, data: &[u8]) -> Option<(XcpKind, String)> {
+        let pid = *data.first()?;
+        if sender == XcpSender::Master {
+            return Some((XcpKind::Command, self.xcp_command(data)));
+        }
+        let packet = match pid {
+            XCP_RES => (XcpKind::Response, self.xcp_response(data)),
+            XCP_ERR => {
+                let code = *data.get(1)?;
+                let name = self
+                    .pending
+                    .take()
+                    .and_then(|command| xcp_command_name(command[0]))
+                    .unwrap_or("unknown command");
+                let text = format!("{} error: {} ({:02X})", name, xcp_error_name(code), code);
+                (XcpKind::Error(code), text)
+            }
+            XCP_EV => {
+                let code = *data.get(1)?;
+                (
+                    XcpKind::Event,
+                    format!("{} ({:02X})", xcp_event_name(code), code),
+                )
+            }
+            XCP_SERV => {
+                let text = match data.get(1)? {
+                    0 => "SERV_RESET".to_string(),
+                    1 => format!("SERV_TEXT: {}", bytes_to_string(&data[2..])),
+                    code => format!("service {:02X}", code),
+                };
+                (XcpKind::Service, text)
+            }
+            _ => (XcpKind::Daq, self.xcp_daq(data)),
+        };
+        Some(packet)
+    }
+
+    fn ccp_command(&mut self, data: &[u8]) -> String {
+        self.pending = Some(data.to_vec());
+        let name = match ccp_command_name(data[0]) {
+            Some(name) => name.to_string(),
+            None => format!("command {:02X}", data[0]),
+        };
+        let counter = data
+            .get(1)
+            .map_or(String::new(), |counter| format!(" #{}", counter));
+        match self.ccp_command_details(data) {
+            Some(details) => format!("{}{} {}", name, counter, details),
+            None if data.len() > 2 => format!("{}{} [{}]", name, counter, hex_to_str(&data[2..])),
+            None => name + &counter,
+        }
+    }
+
+    fn ccp_command_details(&mut self, data: &[u8]) -> Option<String> {
+        match data[0] {
+            // CONNECT, with the station address always in Intel byte order
+            0x01 => Some(format!(
+                "station {:04X}",
+                u16::from_le_bytes([*data.get(2)?, *data.get(3)?])
+            )),
+            // SET_MTA
+            0x02 => Some(format!(
+                "MTA{} {:02X}:{:08X}",
+                data.get(2)?,
+                data.get(3)?,
+                self.number(data, 4, 4)?
+            )),
+            // DNLOAD
+            0x03 => Some(format!(
+                "{} bytes [{}]",
+                data.get(2)?,
+                hex_to_str(data.get(3..).unwrap_or(&[]))
+            )),
+            // UPLOAD
+            0x04 => Some(format!("{} bytes", data.get(2)?)),
+            // SHORT_UP
+            0x0F => Some(format!(
+                "{} bytes from {:02X}:{:08X}",
+                data.get(2)?,
+                data.get(3)?,
+                self.number(data, 4, 4)?
+            )),
+            // GET_DAQ_SIZE, which also clears the list
+            0x14 => {
+                let daq = *data.get(2)? as usize;
+                *self.list_mut(daq) = DaqList::default();
+                Some(format!("DAQ {}", daq))
+            }
+            // SET_DAQ_PTR
+            0x15 => {
+                let daq = *data.get(2)? as usize;
+                let odt = *data.get(3)? as usize;
+                let entry = *data.get(4)? as usize;
+                self.pointer = (daq, odt, entry);
+                Some(format!("DAQ {} ODT {} element {}", daq, odt, entry))
+            }
+            // WRITE_DAQ
+            0x16 => {
+                let entry = OdtEntry {
+                    size: *data.get(2)?,
+                    extension: *data.get(3)?,
+                    address: self.number(data, 4, 4)? as u32,
+                    bit_offset: None,
+                };
+                let text = format!("{} bytes at {}", entry.size, entry.text());
+                self.write_entry(entry);
+                Some(text)
+            }
+            // START_STOP
+            0x06 => {
+                let mode = match data.get(2)? {
+                    0 => "stop",
+                    1 => "start",
+                    2 => "prepare",
+                    _ => "?",
+                };
+                let daq = *data.get(3)? as usize;
+                let last_odt = *data.get(4)? as usize;
+                self.odt_mut(daq, last_odt);
+                Some(format!(
+                    "{} DAQ {} up to ODT {}, event {}",
+                    mode,
+                    daq,
+                    last_odt,
+                    data.get(5)?
+                ))
+            }
+            // START_STOP_ALL
+            0x08 => Some(
+                match data.get(2)? {
+                    0 => "stop",
+                    _ => "start",
+                }
+                .to_string(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn ccp_response(&mut self, data: &[u8]) -> Option<(XcpKind, String)> {
+        let code = *data.get(1)?;
+        let counter = *data.get(2)?;
+        let pending = self.pending.take();
+        let command = pending
+            .as_ref()
+            .and_then(|command| command.first().copied());
+        let name = command
+            .and_then(ccp_command_name)
+            .unwrap_or("unknown command");
+        if code != 0 {
+            let text = format!(
+                "{} #{} error: {} ({:02X})",
+                name,
+                counter,
+                ccp_error_name(code),
+                code
+            );
+            return Some((XcpKind::Error(code), text));
+        }
+        let params = data.get(3..).unwrap_or(&[]);
+        let details = match (command, pending.as_deref()) {
+            // GET_DAQ_SIZE, answered with the list size and its first PID
+            (Some(0x14), Some(request)) => {
+                let daq = *request.get(2)? as usize;
+                let size = *params.first()? as usize;
+                let first_pid = *params.get(1)?;
+                let list = self.list_mut(daq);
+                list.odts = vec![Vec::new(); size];
+                list.first_pid = Some(first_pid);
+                format!("DAQ {}: {} ODTs, first PID {}", daq, size, first_pid)
+            }
+            // UPLOAD, SHORT_UP
+            (Some(0x04 | 0x0F), Some(request)) => {
+                let size = (*request.get(2)? as usize).min(params.len());
+                format!("[{}]", hex_to_str(&params[..size]))
+            }
+            // GET_CCP_VERSION
+            (Some(0x1B), _) => format!("version {}.{}", params.first()?, params.get(1)?),
+            _ => String::new(),
+        };
+        let text = match details.is_empty() {
+            true => format!("{} #{} response", name, counter),
+            false => format!("{} #{} response: {}", name, counter, details),
+        };
+        Some((XcpKind::Response, text))
+    }
+
+    fn ccp_frame(&mut self, sender: XcpSender, data: &[u8]) -> Option<(XcpKind, String)> {
+        let pid = *data.first()?;
+        if sender == XcpSender::Master {
+            return Some((XcpKind::Command, self.ccp_command(data)));
+        }
+        match pid {
+            CCP_CRM => self.ccp_response(data),
+            CCP_EVENT => {
+                let code = *data.get(1)?;
+                Some((
+                    XcpKind::Event,
+                    format!("{} ({:02X})", ccp_error_name(code), code),
+                ))
+            }
+            pid => {
+                let text = match self.odt_of_pid(pid) {
+                    Some((daq, odt)) => self.decode_odt(daq, odt, &data[1..]),
+                    None => format!("DAQ PID {}: [{}]", pid, hex_to_str(&data[1..])),
+                };
+                Some((XcpKind::Daq, text))
+            }
+        }
+    }
+}
+
+// Decode the XCP and CCP frames on the configured channels, following the DAQ configuration
+// so that measurement frames can be split into their ODT entries.
+pub(crate) fn decode(messages: &[Message], channels: &[XcpChannel]) -> Vec<XcpPacket> {
+    let mut sessions: Vec<Session> = channels
+        .iter()
+        .map(|channel| Session::new(channel.byte_order.clone()))
+        .collect();
+    let mut packets = Vec::new();
+
+    for (row, message) in messages.iter().enumerate() {
+        for (index, channel) in channels.iter().enumerate() {
+            let sender = if message.id == channel.master_id {
+                XcpSender::Master
+            } else if message.id == channel.slave_id {
+                XcpSender::Slave
+            } else {
+                continue;
+            };
+            let session = &mut sessions[index];
+            let decoded = match channel.protocol {
+                XcpProtocol::Xcp => session.xcp_frame(sender, &message.data),
+                XcpProtocol::Ccp => session.ccp_frame(sender, &message.data),
+            };
+            if let Some((kind, description)) = decoded {
+                packets.push(XcpPacket {
+                    channel: index,
+                    row,
+                    timestamp: message.timestamp,
+                    sender,
+                    kind,
+                    description,
+                });
+            }
+        }
+    }
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: u16 = 0x7E0;
+    const SLAVE: u16 = 0x7E1;
+
+    fn channel(protocol: XcpProtocol) -> XcpChannel {
+        XcpChannel {
+            name: "ECU".to_string(),
+            protocol,
+            master_id: MASTER.to_be_bytes().to_vec(),
+            slave_id: SLAVE.to_be_bytes().to_vec(),
+            byte_order: ByteOrder::Intel,
+        }
+    }
+
+    fn frame(id: u16, data: &[u8]) -> Message {
+        Message {
+            timestamp: 0.0,
+            id: id.to_be_bytes().to_vec(),
+            data: data.to_vec(),
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        }
+    }
+
+    fn descriptions(protocol: XcpProtocol, frames: &[(u16, &[u8])]) -> Vec<String> {
+        let messages: Vec<_> = frames.iter().map(|(id, data)| frame(*id, data)).collect();
+        decode(&messages, &[channel(protocol)])
+            .into_iter()
+            .map(|packet| packet.description)
+            .collect()
+    }
+
+    #[test]
+    fn connect_byte_order() {
+        let upload: &[u8] = &[0xF4, 4, 0, 0, 0x20, 0x00, 0x10, 0x00];
+        let packets = descriptions(
+            XcpProtocol::Xcp,
+            &[
+                (MASTER, upload),
+                (MASTER, &[0xFF, 0x00]),
+                // Motorola byte order in COMM_MODE_BASIC
+                (SLAVE, &[0xFF, 0x15, 0x01, 0x08, 0x00, 0x08, 0x01, 0x01]),
+                (MASTER, upload),
+                (SLAVE, &[0xFF, 0x01, 0x02, 0x03, 0x04]),
+            ],
+        );
+        assert_eq!(
+            packets,
+            vec![
+                "SHORT_UPLOAD 4 bytes from 00:00100020",
+                "CONNECT mode 0",
+                "CONNECT response: resources 15, Motorola (big endian), max CTO 8, max DTO 8",
+                "SHORT_UPLOAD 4 bytes from 00:20001000",
+                "SHORT_UPLOAD response: [01 02 03 04]",
+            ]
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let packets = descriptions(
+            XcpProtocol::Xcp,
+            &[
+                (MASTER, &[0xF6, 0, 0, 0x01, 0x00, 0x10, 0x00, 0x20]),
+                (MASTER, &[0xF5, 4]),
+                (MASTER, &[0xF0, 2, 0xAA, 0xBB]),
+                (MASTER, &[0xED, 1, 0, 0, 0x00, 0x10, 0x00, 0x20]),
+                (MASTER, &[0xDE, 1, 0x02, 0x00]),
+                (MASTER, &[0xDD, 0]),
+                (MASTER, &[0xFA, 1]),
+                (MASTER, &[0x80, 1, 2]),
+                (MASTER, &[0xFD]),
+            ],
+        );
+        assert_eq!(
+            packets,
+            vec![
+                "SET_MTA 01:20001000",
+                "UPLOAD 4 bytes",
+                "DOWNLOAD 2 bytes [AA BB]",
+                "SHORT_DOWNLOAD 1 bytes to 00:20001000 []",
+                "START_STOP_DAQ_LIST start DAQ 2",
+                "START_STOP_SYNCH stop all",
+                "GET_ID [01]",
+                "command 80 [01 02]",
+                "GET_STATUS",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_and_events() {
+        assert_eq!(xcp_error_name(0x10), "ERR_CMD_BUSY");
+        assert_eq!(xcp_error_name(0x20), "ERR_CMD_UNKNOWN");
+        assert_eq!(xcp_error_name(0x2A), "ERR_DAQ_CONFIG");
+        assert_eq!(
+            xcp_error_name(0x33),
+            "ERR_RESOURCE_TEMPORARY_NOT_ACCESSIBLE"
+        );
+        assert_eq!(xcp_error_name(0x34), "unknown");
+        assert_eq!(ccp_error_name(0x33), "access denied");
+
+        let messages = [
+            frame(MASTER, &[0xF5, 4]),
+            frame(SLAVE, &[0xFE, 0x25]),
+            frame(SLAVE, &[0xFE, 0x20]),
+            frame(SLAVE, &[0xFD, 0x06]),
+            frame(SLAVE, &[0xFC, 0x01, b'o', b'k', 0]),
+        ];
+        let packets = decode(&messages, &[channel(XcpProtocol::Xcp)]);
+        let kinds: Vec<_> = packets.iter().map(|packet| packet.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                XcpKind::Command,
+                XcpKind::Error(0x25),
+                XcpKind::Error(0x20),
+                XcpKind::Event,
+                XcpKind::Service,
+            ]
+        );
+        let packets: Vec<_> = packets
+            .into_iter()
+            .map(|packet| packet.description)
+            .collect();
+        assert_eq!(
+            packets[1..],
+            [
+                "UPLOAD error: ERR_ACCESS_LOCKED (25)",
+                // The error answered the pending command
+                "unknown command error: ERR_CMD_UNKNOWN (20)",
+                "EV_DAQ_OVERLOAD (06)",
+                "SERV_TEXT: ok_",
+            ]
+        );
+    }
+
+    // Two lists: DAQ 0 with a timestamped ODT of a word and a bit and an ODT of a double word,
+    // DAQ 1 with one byte
+    const DAQ_CONFIGURATION: [&[u8]; 15] = [
+        &[0xD5, 0, 2, 0],
+        &[0xD4, 0, 0, 0, 2],
+        &[0xD4, 0, 1, 0, 1],
+        &[0xE2, 0, 0, 0, 0, 0],
+        &[0xE1, 0xFF, 2, 0, 0x00, 0x10, 0x00, 0x20],
+        &[0xE1, 3, 1, 0, 0x04, 0x10, 0x00, 0x20],
+        &[0xE2, 0, 0, 0, 1, 0],
+        &[0xE1, 0xFF, 4, 1, 0x00, 0x20, 0x00, 0x20],
+        &[0xE2, 0, 1, 0, 0, 0],
+        &[0xE1, 0xFF, 1, 0, 0x00, 0x30, 0x00, 0x20],
+        &[0xE0, 0x10, 0, 0, 1, 0],
+        &[0xDE, 2, 0, 0],
+        &[0xDE, 1, 1, 0],
+        &[0xDD, 1],
+        &[0xDA],
+    ];
+
+    #[test]
+    fn absolute_daq() {
+        let mut frames: Vec<(u16, &[u8])> = Vec::new();
+        for command in DAQ_CONFIGURATION {
+            frames.push((MASTER, command));
+            frames.push((SLAVE, &[0xFF]));
+        }
+        // DAQ 0 didn't get a first PID, so its ODTs are numbered from 0; DAQ 1 starts at 5
+        frames[25].1 = &[0xFF, 5];
+        frames.extend([
+            (SLAVE, &[0x00, 0x10, 0x27, 0x00, 0x00, 0x34, 0x12, 0x08][..]),
+            (SLAVE, &[0x01, 0x78, 0x56, 0x34, 0x12]),
+            (SLAVE, &[0x01, 0x78]),
+            (SLAVE, &[0x05, 0x2A]),
+            (SLAVE, &[0x02, 0x2A]),
+        ]);
+        let packets = descriptions(XcpProtocol::Xcp, &frames);
+        assert_eq!(packets[0], "ALLOC_DAQ 2 lists");
+        assert_eq!(packets[1], "ALLOC_DAQ response");
+        assert_eq!(packets[2], "ALLOC_ODT DAQ 0: 2 ODTs");
+        assert_eq!(packets[6], "SET_DAQ_PTR DAQ 0 ODT 0 entry 0");
+        assert_eq!(packets[8], "WRITE_DAQ 2 bytes at 20001000");
+        assert_eq!(packets[14], "WRITE_DAQ 4 bytes at 01:20002000");
+        assert_eq!(packets[20], "SET_DAQ_LIST_MODE DAQ 0: mode 10, event 1");
+        assert_eq!(packets[22], "START_STOP_DAQ_LIST select DAQ 0");
+        assert_eq!(
+            packets[25],
+            "START_STOP_DAQ_LIST response: DAQ 1 first PID 5"
+        );
+        assert_eq!(
+            packets[30..],
+            [
+                "DAQ 0 ODT 0: timestamp 10000, 20001000=4660, 20001004=1",
+                "DAQ 0 ODT 1: 01:20002000=305419896",
+                "DAQ 0 ODT 1: 01:20002000=?",
+                "DAQ 1 ODT 0: 20003000=42",
+                "DAQ PID 2: [2A]",
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_daq() {
+        let mut frames: Vec<(u16, &[u8])> = Vec::new();
+        for command in &DAQ_CONFIGURATION[..11] {
+            frames.push((MASTER, command));
+        }
+        frames.extend([
+            // Two byte timestamps
+            (MASTER, &[0xD9][..]),
+            (SLAVE, &[0xFF, 0, 0, 0, 0, 0x02, 1, 0]),
+            // Relative ODT numbers with a byte, a word and an aligned word for the list
+            (MASTER, &[0xDA]),
+            (SLAVE, &[0xFF, 0, 2, 0, 1, 0, 0, 0x40]),
+            (SLAVE, &[0x00, 0x00, 0x10, 0x27, 0x34, 0x12, 0x00]),
+            (SLAVE, &[0x00, 0x01, 0x2A]),
+            (MASTER, &[0xDA]),
+            (SLAVE, &[0xFF, 0, 2, 0, 1, 0, 0, 0x80]),
+            (SLAVE, &[0x01, 0x00, 0x00, 0x78, 0x56, 0x34, 0x12]),
+            (MASTER, &[0xDA]),
+            (SLAVE, &[0xFF, 0, 2, 0, 1, 0, 0, 0xC0]),
+            (SLAVE, &[0x00, 0x00, 0x01, 0x00, 0x2A]),
+        ]);
+        let packets = descriptions(XcpProtocol::Xcp, &frames);
+        assert_eq!(
+            packets[11..],
+            [
+                "GET_DAQ_RESOLUTION_INFO",
+                "GET_DAQ_RESOLUTION_INFO response: timestamp size 2",
+                "GET_DAQ_PROCESSOR_INFO",
+                "GET_DAQ_PROCESSOR_INFO response: max DAQ 2, max event 1, key 40",
+                "DAQ 0 ODT 0: timestamp 10000, 20001000=4660, 20001004=0",
+                "DAQ 1 ODT 0: 20003000=42",
+                "GET_DAQ_PROCESSOR_INFO",
+                "GET_DAQ_PROCESSOR_INFO response: max DAQ 2, max event 1, key 80",
+                "DAQ 0 ODT 1: 01:20002000=305419896",
+                "GET_DAQ_PROCESSOR_INFO",
+                "GET_DAQ_PROCESSOR_INFO response: max DAQ 2, max event 1, key C0",
+                "DAQ 1 ODT 0: 20003000=42",
+            ]
+        );
+    }
+
+    #[test]
+    fn ccp_daq() {
+        let packets = descriptions(
+            XcpProtocol::Ccp,
+            &[
+                (MASTER, &[0x01, 1, 0x34, 0x12]),
+                (SLAVE, &[0xFF, 0, 1]),
+                (MASTER, &[0x14, 2, 0, 0, 0, 0, 0, 0]),
+                (SLAVE, &[0xFF, 0, 2, 3, 0x10]),
+                (MASTER, &[0x15, 3, 0, 1, 0]),
+                (MASTER, &[0x16, 4, 2, 0, 0x00, 0x20, 0x00, 0x00]),
+                (SLAVE, &[0xFF, 0, 4]),
+                (MASTER, &[0x06, 5, 1, 0, 2, 1]),
+                (SLAVE, &[0x11, 0xE8, 0x03]),
+                (SLAVE, &[0x20, 0x01]),
+                (MASTER, &[0x0F, 6, 4, 0, 0x00, 0x20, 0x00, 0x00]),
+                (SLAVE, &[0xFF, 0x33, 6]),
+                (MASTER, &[0x04, 7, 2]),
+                (SLAVE, &[0xFF, 0, 7, 0xAA, 0xBB, 0xCC]),
+            ],
+        );
+        assert_eq!(
+            packets,
+            vec![
+                "CONNECT #1 station 1234",
+                "CONNECT #1 response",
+                "GET_DAQ_SIZE #2 DAQ 0",
+                "GET_DAQ_SIZE #2 response: DAQ 0: 3 ODTs, first PID 16",
+                "SET_DAQ_PTR #3 DAQ 0 ODT 1 element 0",
+                "WRITE_DAQ #4 2 bytes at 00002000",
+                "WRITE_DAQ #4 response",
+                "START_STOP #5 start DAQ 0 up to ODT 2, event 1",
+                "DAQ 0 ODT 1: 00002000=1000",
+                "DAQ PID 32: [01]",
+                "SHORT_UP #6 4 bytes from 00:00002000",
+                "SHORT_UP #6 error: access denied (33)",
+                "UPLOAD #7 2 bytes",
+                "UPLOAD #7 response: [AA BB]",
+            ]
+        );
+    }
+
+    #[test]
+    fn pid_fallback() {
+        let list = |odts: usize, first_pid: Option<u8>| DaqList {
+            odts: vec![Vec::new(); odts],
+            first_pid,
+            timestamp: false,
+        };
+        let session = Session {
+            lists: vec![list(2, None), list(3, Some(10)), list(1, None)],
+            ..Session::new(ByteOrder::Intel)
+        };
+        assert_eq!(session.odt_of_pid(0), Some((0, 0)));
+        assert_eq!(session.odt_of_pid(1), Some((0, 1)));
+        assert_eq!(session.odt_of_pid(2), None);
+        assert_eq!(session.odt_of_pid(12), Some((1, 2)));
+        // Numbered on from the previous list's first PID
+        assert_eq!(session.odt_of_pid(13), Some((2, 0)));
+        assert_eq!(session.odt_of_pid(14), None);
+        assert_eq!(Session::new(ByteOrder::Intel).odt_of_pid(0), None);
+    }
+}
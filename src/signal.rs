@@ -0,0 +1,470 @@
+use strum::EnumIter;
+
+use crate::message::Message;
+use crate::util::parse_number;
+use crate::value_table::{find_table, ValueTable};
+
+#[derive(Debug, EnumIter, PartialEq, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub enum ByteOrder {
+    #[default]
+    Intel,
+    Motorola,
+}
+
+impl ByteOrder {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ByteOrder::Intel => "Intel (little endian)",
+            ByteOrder::Motorola => "Motorola (big endian)",
+        }
+    }
+}
+
+#[derive(Debug, EnumIter, PartialEq, serde::Serialize, serde::Deserialize, Default, Clone)]
+pub enum ValueType {
+    #[default]
+    Unsigned,
+    Signed,
+    Float,
+}
+
+impl ValueType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ValueType::Unsigned => "Unsigned",
+            ValueType::Signed => "Signed",
+            ValueType::Float => "Float",
+        }
+    }
+}
+
+// A bit field within the message payload, numbered as in DBC files: Intel signals start at
+// their least significant bit, Motorola signals at their most significant bit.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct Signal {
+    pub start_bit: u16,
+    pub length: u16,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    pub scale: f64,
+    pub offset: f64,
+    pub unit: String,
+    #[serde(default)]
+    pub value_table: Option<String>,
+    #[serde(default)]
+    pub multiplexor: Option<Multiplexor>,
+}
+
+impl Default for Signal {
+    fn default() -> Self {
+        Self {
+            start_bit: 0,
+            length: 8,
+            byte_order: ByteOrder::default(),
+            value_type: ValueType::default(),
+            scale: 1.0,
+            offset: 0.0,
+            unit: String::new(),
+            value_table: None,
+            multiplexor: None,
+        }
+    }
+}
+
+impl Signal {
+    pub(crate) fn is_valid(&self) -> bool {
+        match self.value_type {
+            ValueType::Float => self.length == 32 || self.length == 64,
+            _ => self.length > 0 && self.length <= 64,
+        }
+    }
+
+    // Whether the signal is present in this payload, i.e. all of its multiplexor conditions hold.
+    pub(crate) fn is_active(&self, data: &[u8]) -> bool {
+        match &self.multiplexor {
+            None => true,
+            Some(mux) => mux.matches(data),
+        }
+    }
+
+    fn bit(data: &[u8], bit: usize) -> Option<u64> {
+        data.get(bit / 8)
+            .map(|byte| ((byte >> (bit % 8)) & 1) as u64)
+    }
+
+    // Extract the raw bits of the signal, or None if the payload is too short.
+    pub(crate) fn raw(&self, data: &[u8]) -> Option<u64> {
+        if !self.is_valid() {
+            return None;
+        }
+        let mut raw = 0u64;
+        match self.byte_order {
+            ByteOrder::Intel => {
+                for i in 0..self.length as usize {
+                    raw |= Signal::bit(data, self.start_bit as usize + i)? << i;
+                }
+            }
+            ByteOrder::Motorola => {
+                let mut pos = self.start_bit as usize;
+                for _ in 0..self.length {
+                    raw = (raw << 1) | Signal::bit(data, pos)?;
+                    // Walk from MSB to LSB within a byte, then on to the MSB of the next byte
+                    pos = match pos % 8 {
+                        0 => pos + 15,
+                        _ => pos - 1,
+                    };
+                }
+            }
+        }
+        Some(raw)
+    }
+
+    // Decode the raw bits as the configured type, before scaling.
+    pub(crate) fn raw_value(&self, data: &[u8]) -> Option<f64> {
+        let raw = self.raw(data)?;
+        let value = match self.value_type {
+            ValueType::Unsigned => raw as f64,
+            ValueType::Signed => {
+                let shift = 64 - self.length as u32;
+                ((raw << shift) as i64 >> shift) as f64
+            }
+            ValueType::Float => match self.length {
+                32 => f32::from_bits(raw as u32) as f64,
+                _ => f64::from_bits(raw),
+            },
+        };
+        Some(value)
+    }
+
+    pub(crate) fn decode(&self, message: &Message, tables: &[ValueTable]) -> Option<SignalValue> {
+        let raw = self.raw_value(&message.data)?;
+        let named = self
+            .value_table
+            .as_ref()
+            .and_then(|name| find_table(tables, name))
+            .map(|table| match table.lookup(raw) {
+                Some(name) => NamedValue::Defined(name.clone()),
+                None => NamedValue::Undefined,
+            });
+        // Scaled integers are only as precise as the scale and offset
+        let decimals = match self.value_type {
+            ValueType::Float => None,
+            _ => Some(decimals(self.scale).max(decimals(self.offset))),
+        };
+        Some(SignalValue {
+            raw,
+            physical: raw * self.scale + self.offset,
+            decimals,
+            unit: self.unit.clone(),
+            named,
+        })
+    }
+
+    pub(crate) fn description(&self) -> String {
+        let order = match self.byte_order {
+            ByteOrder::Intel => "LE",
+            ByteOrder::Motorola => "BE",
+        };
+        let description = format!(
+            "bits {}|{} {} {}",
+            self.start_bit,
+            self.length,
+            order,
+            self.value_type.name()
+        );
+        match &self.multiplexor {
+            Some(mux) => format!("{} if {}", description, mux.description()),
+            None => description,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct MuxRange {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl MuxRange {
+    pub(crate) fn contains(&self, value: u64) -> bool {
+        self.min <= value && value <= self.max
+    }
+
+    // Parse selector values written as e.g. `3` or `1-3, 5`.
+    pub(crate) fn parse_list(s: &str) -> Option<Vec<MuxRange>> {
+        let mut ranges = Vec::new();
+        for item in s.split(',') {
+            let range = match item.split_once('-') {
+                Some((min, max)) => MuxRange {
+                    min: parse_number(min)?,
+                    max: parse_number(max)?,
+                },
+                None => {
+                    let value = parse_number(item)?;
+                    MuxRange {
+                        min: value,
+                        max: value,
+                    }
+                }
+            };
+            if range.min > range.max {
+                return None;
+            }
+            ranges.push(range);
+        }
+        Some(ranges)
+    }
+
+    pub(crate) fn list_string(ranges: &[MuxRange]) -> String {
+        ranges
+            .iter()
+            .map(|range| match range.min == range.max {
+                true => range.min.to_string(),
+                false => format!("{}-{}", range.min, range.max),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// Makes a signal conditional on a selector field. The selector can itself be multiplexed,
+// which gives the nested (extended) multiplexing of DBC files.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct Multiplexor {
+    pub name: String,
+    pub selector: Box<Signal>,
+    pub values: Vec<MuxRange>,
+}
+
+impl Multiplexor {
+    pub(crate) fn matches(&self, data: &[u8]) -> bool {
+        if !self.selector.is_active(data) {
+            return false;
+        }
+        match self.selector.raw(data) {
+            Some(value) => self.values.iter().any(|range| range.contains(value)),
+            None => false,
+        }
+    }
+
+    pub(crate) fn description(&self) -> String {
+        format!("{}={}", self.name, MuxRange::list_string(&self.values))
+    }
+}
+
+pub(crate) enum NamedValue {
+    Defined(String),
+    // The signal has a value table, but it has no entry for this value
+    Undefined,
+}
+
+pub(crate) struct SignalValue {
+    pub raw: f64,
+    pub physical: f64,
+    // Decimals the physical value is shown with, or all of them
+    pub decimals: Option<usize>,
+    pub unit: String,
+    pub named: Option<NamedValue>,
+}
+
+impl SignalValue {
+    pub(crate) fn is_undefined(&self) -> bool {
+        matches!(self.named, Some(NamedValue::Undefined))
+    }
+}
+
+impl std::fmt::Display for SignalValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(NamedValue::Defined(name)) = &self.named {
+            return write!(f, "{} ({})", name, self.raw);
+        }
+        let physical = match self.decimals {
+            Some(decimals) => format!("{:.*}", decimals, self.physical),
+            None => self.physical.to_string(),
+        };
+        match self.unit.is_empty() {
+            true => write!(f, "{}", physical),
+            false => write!(f, "{} {}", physical, self.unit),
+        }
+    }
+}
+
+// Decimals in the shortest representation of a value, e.g. 1 for 0.1
+fn decimals(value: f64) -> usize {
+    value
+        .to_string()
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(start_bit: u16, length: u16, byte_order: ByteOrder) -> Signal {
+        Signal {
+            start_bit,
+            length,
+            byte_order,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn intel() {
+        let s = signal(0, 16, ByteOrder::Intel);
+        assert_eq!(s.raw(&[0x34, 0x12]), Some(0x1234));
+        let s = signal(4, 8, ByteOrder::Intel);
+        assert_eq!(s.raw(&[0xA0, 0x0B]), Some(0xBA));
+        let s = signal(63, 1, ByteOrder::Intel);
+        assert_eq!(s.raw(&[0, 0, 0, 0, 0, 0, 0, 0x80]), Some(1));
+        // Payload too short
+        assert_eq!(signal(0, 16, ByteOrder::Intel).raw(&[0x01]), None);
+    }
+
+    #[test]
+    fn motorola() {
+        let s = signal(7, 16, ByteOrder::Motorola);
+        assert_eq!(s.raw(&[0x12, 0x34]), Some(0x1234));
+        let s = signal(3, 8, ByteOrder::Motorola);
+        assert_eq!(s.raw(&[0x0A, 0xB0]), Some(0xAB));
+        let s = signal(39, 32, ByteOrder::Motorola);
+        assert_eq!(
+            s.raw(&[0, 0, 0, 0, 0xDE, 0xAD, 0xBE, 0xEF]),
+            Some(0xDEAD_BEEF)
+        );
+        assert_eq!(signal(7, 16, ByteOrder::Motorola).raw(&[0x12]), None);
+    }
+
+    #[test]
+    fn value_types() {
+        let mut s = signal(0, 8, ByteOrder::Intel);
+        s.value_type = ValueType::Signed;
+        assert_eq!(s.raw_value(&[0xFF]), Some(-1.0));
+        s.length = 12;
+        assert_eq!(s.raw_value(&[0x00, 0x08]), Some(-2048.0));
+        assert_eq!(s.raw_value(&[0xFF, 0x07]), Some(2047.0));
+
+        let mut s = signal(0, 32, ByteOrder::Intel);
+        s.value_type = ValueType::Float;
+        assert_eq!(s.raw_value(&1.5f32.to_le_bytes()), Some(1.5));
+        s.length = 64;
+        assert_eq!(s.raw_value(&(-0.25f64).to_le_bytes()), Some(-0.25));
+        // Floats are 32 or 64 bits
+        s.length = 16;
+        assert_eq!(s.raw_value(&[0; 8]), None);
+    }
+
+    #[test]
+    fn decode() {
+        let message = Message {
+            timestamp: 0.0,
+            id: vec![0x01, 0x00],
+            data: vec![100, 2],
+            crc: Vec::new(),
+            ack: true,
+            speed: String::new(),
+        };
+        let tables = vec![ValueTable {
+            name: "Gear".to_string(),
+            entries: ValueTable::parse_entries("0=Park; 1=Drive").unwrap(),
+        }];
+        let s = Signal {
+            scale: 0.5,
+            offset: -40.0,
+            unit: "degC".to_string(),
+            ..signal(0, 8, ByteOrder::Intel)
+        };
+        let value = s.decode(&message, &tables).unwrap();
+        assert_eq!((value.raw, value.physical), (100.0, 10.0));
+        assert!(value.named.is_none());
+        assert_eq!(value.to_string(), "10.0 degC");
+
+        // Shown as precise as the scale, not the nearest float
+        let tenths = Signal {
+            scale: 0.1,
+            ..signal(0, 8, ByteOrder::Intel)
+        };
+        let message = Message {
+            data: vec![3],
+            ..message.clone()
+        };
+        let value = tenths.decode(&message, &tables).unwrap();
+        assert_ne!(value.physical, 0.3);
+        assert_eq!(value.to_string(), "0.3");
+        let offset = Signal {
+            offset: -0.25,
+            ..tenths
+        };
+        assert_eq!(
+            offset.decode(&message, &tables).unwrap().to_string(),
+            "0.05"
+        );
+        let float = Signal {
+            value_type: ValueType::Float,
+            ..signal(0, 32, ByteOrder::Intel)
+        };
+        let message = Message {
+            data: 0.1f32.to_le_bytes().to_vec(),
+            ..message
+        };
+        let value = float.decode(&message, &tables).unwrap();
+        assert_eq!(value.to_string(), (0.1f32 as f64).to_string());
+
+        let gear = Signal {
+            value_table: Some("Gear".to_string()),
+            ..signal(8, 8, ByteOrder::Intel)
+        };
+        let value = gear.decode(&message, &tables).unwrap();
+        assert!(matches!(value.named, Some(NamedValue::Undefined)));
+        let message = Message {
+            data: vec![0, 1],
+            ..message
+        };
+        let value = gear.decode(&message, &tables).unwrap();
+        assert!(matches!(value.named, Some(NamedValue::Defined(name)) if name == "Drive"));
+    }
+
+    #[test]
+    fn multiplexing() {
+        let page = Multiplexor {
+            name: "Page".to_string(),
+            selector: Box::new(signal(0, 4, ByteOrder::Intel)),
+            values: MuxRange::parse_list("1-2, 5").unwrap(),
+        };
+        let sub = Multiplexor {
+            name: "Sub".to_string(),
+            selector: Box::new(Signal {
+                multiplexor: Some(page.clone()),
+                ..signal(4, 4, ByteOrder::Intel)
+            }),
+            values: MuxRange::parse_list("3").unwrap(),
+        };
+        let s = Signal {
+            multiplexor: Some(sub),
+            ..signal(8, 8, ByteOrder::Intel)
+        };
+        assert!(s.is_active(&[0x31, 0]));
+        assert!(s.is_active(&[0x35, 0]));
+        // Nested selector matches, but its own selector doesn't
+        assert!(!s.is_active(&[0x33, 0]));
+        assert!(!s.is_active(&[0x41, 0]));
+        assert!(!s.is_active(&[]));
+        assert_eq!(s.description(), "bits 8|8 LE Unsigned if Sub=3");
+        assert_eq!(page.description(), "Page=1-2, 5");
+    }
+
+    #[test]
+    fn parse_mux_ranges() {
+        assert_eq!(
+            MuxRange::parse_list(" 3, 0x10-0x1F "),
+            Some(vec![
+                MuxRange { min: 3, max: 3 },
+                MuxRange { min: 16, max: 31 }
+            ])
+        );
+        assert_eq!(MuxRange::parse_list("3-1"), None);
+        assert_eq!(MuxRange::parse_list("1,"), None);
+        assert_eq!(MuxRange::parse_list(""), None);
+    }
+}
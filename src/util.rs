@@ -28,16 +28,18 @@ pub fn bytes_to_string(bytes: &[u8]) -> String {
     s
 }
 
-pub fn empty_vec_as_none<T>(vec: Vec<T>) -> Option<Vec<T>> {
-    match vec.is_empty() {
-        true => None,
-        false => Some(vec),
-    }
-}
-
 pub fn empty_str_as_none(s: String) -> Option<String> {
     match s.is_empty() {
         true => None,
         false => Some(s),
     }
 }
+
+// Parse a decimal or 0x-prefixed hexadecimal number.
+pub fn parse_number(s: &str) -> Option<u64> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -0,0 +1,135 @@
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct ValueTableEntry {
+    pub value: i64,
+    pub name: String,
+}
+
+// Named raw values of an enumerated signal, e.g. 0=Off; 1=Standby; 2=Run.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct ValueTable {
+    pub name: String,
+    pub entries: Vec<ValueTableEntry>,
+}
+
+fn parse_value(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => s.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+impl ValueTable {
+    pub(crate) fn lookup(&self, raw: f64) -> Option<&String> {
+        if raw.fract() != 0.0 {
+            return None;
+        }
+        self.entries
+            .iter()
+            .find(|entry| entry.value == raw as i64)
+            .map(|entry| &entry.name)
+    }
+
+    // Parse entries written as `value=name`, separated by semicolons or new lines.
+    pub(crate) fn parse_entries(s: &str) -> Option<Vec<ValueTableEntry>> {
+        let mut entries = Vec::new();
+        for item in s.split([';', '\n']) {
+            if item.trim().is_empty() {
+                continue;
+            }
+            let (value, name) = item.split_once('=')?;
+            let name = name.trim();
+            if name.is_empty() {
+                return None;
+            }
+            entries.push(ValueTableEntry {
+                value: parse_value(value)?,
+                name: name.to_string(),
+            });
+        }
+        match entries.is_empty() {
+            true => None,
+            false => Some(entries),
+        }
+    }
+
+    pub(crate) fn entries_string(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| format!("{}={}", entry.value, entry.name))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+pub(crate) fn find_table<'a>(tables: &'a [ValueTable], name: &str) -> Option<&'a ValueTable> {
+    tables.iter().find(|table| table.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(value: i64, name: &str) -> ValueTableEntry {
+        ValueTableEntry {
+            value,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_entries() {
+        assert_eq!(
+            ValueTable::parse_entries("0=Off; 1=Standby;2 = Run\n0x0F=Fault;"),
+            Some(vec![
+                entry(0, "Off"),
+                entry(1, "Standby"),
+                entry(2, "Run"),
+                entry(15, "Fault"),
+            ])
+        );
+        assert_eq!(
+            ValueTable::parse_entries("-1=Invalid; -0x10=Low"),
+            Some(vec![entry(-1, "Invalid"), entry(-16, "Low")])
+        );
+        // Names may contain '='
+        assert_eq!(
+            ValueTable::parse_entries("3=a=b"),
+            Some(vec![entry(3, "a=b")])
+        );
+    }
+
+    #[test]
+    fn parse_entries_rejects_invalid() {
+        assert_eq!(ValueTable::parse_entries(""), None);
+        assert_eq!(ValueTable::parse_entries(" ; \n"), None);
+        assert_eq!(ValueTable::parse_entries("0=Off; 1"), None);
+        assert_eq!(ValueTable::parse_entries("1="), None);
+        assert_eq!(ValueTable::parse_entries("x=Off"), None);
+        assert_eq!(ValueTable::parse_entries("0xG=Off"), None);
+    }
+
+    #[test]
+    fn lookup_and_round_trip() {
+        let table = ValueTable {
+            name: "State".to_string(),
+            entries: ValueTable::parse_entries("0=Off; 2=Run").unwrap(),
+        };
+        assert_eq!(table.lookup(2.0), Some(&"Run".to_string()));
+        assert_eq!(table.lookup(1.0), None);
+        assert_eq!(table.lookup(2.5), None);
+        assert_eq!(table.entries_string(), "0=Off; 2=Run");
+        assert_eq!(
+            ValueTable::parse_entries(&table.entries_string()),
+            Some(table.entries.clone())
+        );
+        let tables = [table];
+        assert_eq!(find_table(&tables, "State"), Some(&tables[0]));
+        assert_eq!(find_table(&tables, "Mode"), None);
+    }
+}
@@ -0,0 +1,387 @@
+use std::error::Error;
+
+// Just enough of the zip format to read the entries of an archive, stored or deflated.
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054B50;
+const CENTRAL_DIRECTORY_ENTRY: u32 = 0x02014B50;
+const LOCAL_HEADER: u32 = 0x04034B50;
+const END_OF_CENTRAL_DIRECTORY_LENGTH: usize = 22;
+const LOCAL_HEADER_LENGTH: usize = 30;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+pub(crate) struct ZipEntry {
+    pub name: String,
+    method: u16,
+    offset: usize,
+    compressed_size: usize,
+    size: usize,
+}
+
+pub(crate) struct ZipArchive<'a> {
+    data: &'a [u8],
+    pub entries: Vec<ZipEntry>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => Err("unexpected end of archive".into()),
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err("unexpected end of archive".into()),
+    }
+}
+
+impl<'a> ZipArchive<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        // The end of central directory record is followed by a comment of up to 64k
+        let end = (0..=data.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_LENGTH))
+            .rev()
+            .take(0x10000 + END_OF_CENTRAL_DIRECTORY_LENGTH)
+            .find(|offset| u32_at(data, *offset).ok() == Some(END_OF_CENTRAL_DIRECTORY))
+            .ok_or("not a zip archive")?;
+        let count = u16_at(data, end + 10)? as usize;
+        let mut offset = u32_at(data, end + 16)? as usize;
+
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            if u32_at(data, offset)? != CENTRAL_DIRECTORY_ENTRY {
+                return Err("invalid central directory".into());
+            }
+            let name_length = u16_at(data, offset + 28)? as usize;
+            let extra_length = u16_at(data, offset + 30)? as usize;
+            let comment_length = u16_at(data, offset + 32)? as usize;
+            let name = data
+                .get(offset + 46..offset + 46 + name_length)
+                .ok_or("unexpected end of archive")?;
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).to_string(),
+                method: u16_at(data, offset + 10)?,
+                compressed_size: u32_at(data, offset + 20)? as usize,
+                size: u32_at(data, offset + 24)? as usize,
+                offset: u32_at(data, offset + 42)? as usize,
+            });
+            offset += 46 + name_length + extra_length + comment_length;
+        }
+        Ok(Self { data, entries })
+    }
+
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| format!("{} not found in archive", name))?;
+        if u32_at(self.data, entry.offset)? != LOCAL_HEADER {
+            return Err(format!("invalid header for {}", name).into());
+        }
+        let name_length = u16_at(self.data, entry.offset + 26)? as usize;
+        let extra_length = u16_at(self.data, entry.offset + 28)? as usize;
+        let start = entry.offset + LOCAL_HEADER_LENGTH + name_length + extra_length;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .ok_or("unexpected end of archive")?;
+        match entry.method {
+            STORED => Ok(compressed.to_vec()),
+            DEFLATED => inflate(compressed, entry.size),
+            method => Err(format!("unsupported compression method {}", method).into()),
+        }
+    }
+}
+
+// Base values and extra bits of the length and distance codes
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order the code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const MAX_BITS: usize = 15;
+
+struct BitInput<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitInput<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, Box<dyn Error>> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or("unexpected end of compressed data")?;
+            self.buffer |= (byte as u32) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << count) - 1);
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// Canonical Huffman code as the number of codes of each length and the symbols in code order
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; MAX_BITS + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, input: &mut BitInput) -> Result<u16, Box<dyn Error>> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_BITS {
+            code |= input.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".into())
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(input: &mut BitInput) -> Result<(Huffman, Huffman), Box<dyn Error>> {
+    let literals = input.bits(5)? as usize + 257;
+    let distances = input.bits(5)? as usize + 1;
+    let code_lengths = input.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+        lengths[*index] = input.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match code_length_code.decode(input)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(previous) => (*previous, 3 + input.bits(2)?),
+                None => return Err("repeated length with no previous length".into()),
+            },
+            17 => (0, 3 + input.bits(3)?),
+            _ => (0, 11 + input.bits(7)?),
+        };
+        lengths.resize(lengths.len() + repeat as usize, length);
+    }
+    if lengths.len() > literals + distances {
+        return Err("too many code lengths".into());
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+fn inflate_block(
+    input: &mut BitInput,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let symbol = literals.decode(input)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err("invalid length code".into());
+                }
+                let length =
+                    LENGTH_BASE[index] as usize + input.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(input)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err("invalid distance code".into());
+                }
+                let distance = DISTANCE_BASE[index] as usize
+                    + input.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err("distance too far back".into());
+                }
+                let start = output.len() - distance;
+                for offset in 0..length {
+                    output.push(output[start + offset]);
+                }
+            }
+        }
+    }
+}
+
+// Decompress raw DEFLATE data
+pub(crate) fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut input = BitInput {
+        data,
+        position: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut output = Vec::with_capacity(size);
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => {
+                input.align();
+                let start = input.position;
+                let length = u16_at(data, start)? as usize;
+                let block = data
+                    .get(start + 4..start + 4 + length)
+                    .ok_or("unexpected end of compressed data")?;
+                output.extend_from_slice(block);
+                input.position = start + 4 + length;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut input, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut input)?;
+                inflate_block(&mut input, &mut output, &literals, &distances)?;
+            }
+            _ => return Err("invalid block type".into()),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A sigrok session written with Python's zipfile rather than recorded by sigrok: a stored
+    // version entry, metadata deflated with dynamic codes, the first logic chunk deflated with
+    // fixed codes and the second one stored
+    pub(crate) const SESSION: &str = "\
+        504b0304140000000000000021000dbed51a01000000010000000700000076657273696f6e32504b03041400\
+        000008000000210030e95e5b7e00000094000000080000006d657461646174612d8bb10ac2301040f7fb8afc\
+        80a50938661017171da443a114b9c6331cc65c48d20efd7a4b757cbcf7061f64c23042619fe5ad16ca8525da\
+        b63936066078d2c28e941ec161aa73a61707b2413cbb83862a15834a59262ad640c14f0a94b192d5ea7a59ff\
+        1e236e836d610fb53d9f6e8f7bff23b353d7c31cb9165eb713be504b0304140000000800000021004deedfc9\
+        1100000020000000090000006c6f6769632d312d3163660602464646666626540012078a0100504b03041400\
+        0000000000002100775f70ee2000000020000000090000006c6f6769632d312d320202020202020202020202\
+        020203030303030303030303030303030303030303504b01021403140000000000000021000dbed51a010000\
+        000100000007000000000000000000000080010000000076657273696f6e504b010214031400000008000000\
+        210030e95e5b7e000000940000000800000000000000000000008001260000006d65746164617461504b0102\
+        1403140000000800000021004deedfc911000000200000000900000000000000000000008001ca0000006c6f\
+        6769632d312d31504b0102140314000000000000002100775f70ee2000000020000000090000000000000000\
+        0000008001020100006c6f6769632d312d32504b05060000000004000400d9000000490100000000";
+
+    #[test]
+    fn entries() {
+        let data = hex::decode(SESSION).unwrap();
+        let archive = ZipArchive::new(&data).unwrap();
+        let names: Vec<&str> = archive.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["version", "metadata", "logic-1-1", "logic-1-2"]);
+        assert_eq!(archive.read("version").unwrap(), b"2");
+        let metadata = String::from_utf8(archive.read("metadata").unwrap()).unwrap();
+        assert!(metadata.starts_with("[global]\n"));
+        assert!(metadata.contains("samplerate=1 MHz\n"));
+        assert_eq!(metadata.len(), archive.entries[1].size);
+        let first = archive.read("logic-1-1").unwrap();
+        assert_eq!(first.len(), 32);
+        assert_eq!(&first[..12], &[3, 3, 3, 3, 3, 1, 1, 1, 3, 3, 2, 2]);
+        assert_eq!(archive.read("logic-1-2").unwrap()[13..], [3; 19]);
+        assert_eq!(
+            archive.read("logic-1-3").unwrap_err().to_string(),
+            "logic-1-3 not found in archive"
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let data = hex::decode(SESSION).unwrap();
+        let error = ZipArchive::new(&data[..data.len() - 30]).err().unwrap();
+        assert_eq!(error.to_string(), "not a zip archive");
+        // The central directory points past the end of the data
+        let mut moved = data[..0x150].to_vec();
+        moved.extend_from_slice(&data[data.len() - END_OF_CENTRAL_DIRECTORY_LENGTH..]);
+        let error = ZipArchive::new(&moved).err().unwrap();
+        assert_eq!(error.to_string(), "unexpected end of archive");
+
+        let compressed = hex::decode("4b4c4a4ec44000").unwrap();
+        assert_eq!(inflate(&compressed, 21).unwrap(), b"abcabcabcabcabcabcabc");
+        let error = inflate(&compressed[..4], 21).unwrap_err();
+        assert_eq!(error.to_string(), "unexpected end of compressed data");
+    }
+
+    #[test]
+    fn stored_blocks() {
+        let compressed = hex::decode("010600f9ff736967726f6b").unwrap();
+        assert_eq!(inflate(&compressed, 6).unwrap(), b"sigrok");
+        let error = inflate(&compressed[..8], 6).unwrap_err();
+        assert_eq!(error.to_string(), "unexpected end of compressed data");
+        // Block type 3 is reserved
+        assert_eq!(
+            inflate(&[0x07], 0).unwrap_err().to_string(),
+            "invalid block type"
+        );
+    }
+}